@@ -0,0 +1,541 @@
+# kpass' messages in English, which other languages fall back to.
+# Translations (`locales/<language>.ftl`) use the same IDs.
+
+## Saving
+
+pending-changes = Pending changes:
+saving = Saving...
+saved = Saved.
+save-failed = Couldn't save { $database }: { $error }
+try-again = Try again?
+changes-pending = The changes aren't saved yet; they will be after the next action.
+nothing-to-undo = Nothing to undo in this session.
+undo-confirm = Undo the change to { $database } saved at { $time }?
+
+## Picking entries and databases
+
+select-entry = Select entry
+sorted-by = Sorted by { $order } (select to change)
+all-databases = All databases
+search-in = Search in:
+add-to = Add to
+edit-in = Edit in
+group-in = Group in
+change-key-of = Change key of
+group = Group
+new-kind = New:
+ok = Ok?
+
+## Entries
+
+no-password = { $entry } has no password.
+no-notes = { $entry } has no notes.
+edit-notes = Edit them?
+edit-notes-help = Otherwise they're opened read-only
+requires-master-password = Requires the master password.
+rotate-password = Password { $warning }, time to rotate it.
+copied = Copied to clipboard!
+copied-field = Copied { $field } to clipboard!
+copy-field = Copy:
+copy-field-help = Type to filter, Esc when done
+
+## Entry details
+
+label-colors = Colors:
+label-username = Username:
+label-url = Url:
+label-other-url = Other URL:
+label-host = Host:
+label-passkey = Passkey:
+label-files = Files:
+label-expires = Expires:
+label-created = Created:
+label-modified = Modified:
+label-last-accessed = Last accessed:
+label-password = Password:
+
+## Actions in the session's menu
+
+action-search = Search
+action-show-password = Show password
+action-show-qr = Show QR
+action-view-notes-in-editor = View notes in editor
+action-open-url = Open URL
+action-auto-type = Auto-type
+action-auto-type-into-window = Auto-type into window
+action-check-reachable = Check reachable
+action-edit = Edit
+action-bulk-edit = Bulk edit
+action-new = New
+action-duplicate = Duplicate
+action-rename-move-group = Rename/move group
+action-change-master-key = Change master key
+action-undo = Undo
+action-switch-database = Switch database
+action-reveal-session = Reveal session
+action-quit = Quit
+
+## Actions in the launcher menu
+
+menu-copy-password = Copy password
+menu-auto-type = Auto-type
+menu-copy-username = Copy username
+menu-copy-otp = Copy OTP
+
+## Entry orders
+
+order-database-order = database order
+order-title = title
+order-last-modified = last modified
+order-group = group
+order-most-used = most used
+
+## Fields to copy
+
+field-username = Username
+field-password = Password
+field-url = URL
+field-otp-code = OTP code
+field-notes = Notes
+field-done = Done
+
+## Prompting for values
+
+between = Between { $min } and { $max }
+enter-a-number = Please enter a number
+must-be-between = Must be between { $min } and { $max }
+expires = Expires:
+expires-never = Never
+expires-in-days = In a number of days
+expires-on-date = On a date
+expires-in = Expires in (days):
+expires-on = Expires on:
+
+## Editing entries
+
+invalid-field = Invalid { $field } of { $entry }: { $value }
+edit-title = Title:
+edit-username = UserName:
+edit-url = URL:
+edit-url-help = Leave empty to remove the URL
+other-urls = Other URLs:
+remove-url = Remove { $url }
+add-url = Add URL
+done = Done
+no-custom-fields = No custom fields.
+protected-fields = Protected fields:
+not-text-kept-protected = { $field } isn't text, keeping it protected.
+insert-snippet = Insert snippet:
+no-snippet = (none)
+edit-notes-field = Notes:
+autotype-sequence = Auto-type sequence:
+autotype-sequence-help = e.g. {"{"}USERNAME{"}"}{"{"}TAB{"}"}{"{"}PASSWORD{"}"}{"{"}ENTER{"}"}, {"{"}DELAY 500{"}"}, {"{"}S:Field{"}"}
+autotype-start-delay = Delay before typing (ms):
+autotype-key-delay = Delay between keystrokes (ms):
+edit-host = Host:
+edit-host-help = Leave empty to remove the host
+edit-port = Port:
+edit-location = Location:
+edit-location-help = e.g. a datacenter or region
+require-master-password = Always require the full master password?
+require-master-password-help = The quick password won't do, and the agent won't serve it
+foreground-color = Foreground color:
+background-color = Background color:
+color-help = #rrggbb, or leave empty for none
+favorite = Favorite?
+favorite-help = Favorites are listed first
+generate = Generate
+generate-pronounceable = Generate pronounceable
+enter-manually = Enter manually
+customize-generation = Customize generation?
+password-generated = Password generated.
+strength = Strength: { $strength }
+confirm-password = Confirm:
+use-anyway = Use anyway?
+copy-of = { $title } - Copy
+keep-history = Keep the history?
+generate-new-password = Generate a new password?
+
+## Parts of entries to edit
+
+part-title = Title
+part-username = UserName
+part-url = URL
+part-other-urls = Other URLs
+part-notes = Notes
+part-protected-fields = Protected fields
+part-password = Password
+part-auto-type-sequence = Auto-type sequence
+part-auto-type-delays = Auto-type delays
+part-expiry = Expiry
+part-host = Host
+part-favorite = Favorite
+part-icon = Icon
+part-colors = Colors
+part-require-master-password = Require master password
+part-done = Done
+
+## Unlocking
+
+password-for = Password for { $database }:
+password-for-user = Password for { $user } on { $host }:
+full-password = Full password:
+incorrect-password = Incorrect password.
+open-failed = Failed to open database. Wrong password?
+wrong-password = Wrong password, try again
+quick-pass = Quick Pass:
+quick-pass-incorrect = Quick Pass was incorrect.
+cache-decrypt-failed = Couldn't decrypt the password cache: { $error }
+cache-corrupt = The quick password cache is corrupt (e.g. from an interrupted write); removed it.
+cache-stale = { $error } (was the master password changed?); removed it.
+granted-password-wrong = The granted password doesn't open { $database }.
+
+## Saving changes made elsewhere
+
+changed-on-disk = The database was changed by something else since it was opened.
+save = Save
+merge-into-it = Merge my changes into it
+overwrite-it = Overwrite it
+cancel = Cancel
+merge-summary = { $added } entries added, { $updated } updated.
+merge-conflict = Changed in both, kept { $kept }: { $entry }
+kept-ours = ours
+kept-theirs = theirs
+
+## Commands
+
+no-agent = No agent running.
+agent-locked = Agent locked.
+agent-already-unlocked = The agent is already unlocked.
+agent-unlocked = Agent unlocked.
+granted = Paired devices can unlock the agent's database for { $minutes } minutes.
+export-passphrase = Export passphrase:
+export-cancelled = Export cancelled.
+leaving-out-denied = Leaving out { $group }, which denies export.
+some-require-master-password = Some entries require the master password.
+exported-to = Exported to { $path }.
+checking-otp = Checking { $count } entries with OTP...
+save-confirm = Save?
+nothing-to-compact = Nothing to compact.
+compact-summary = This removes { $revisions } history revisions (keeping up to { $keep } per entry, from the last { $max_days } days) and { $deleted } records of deleted entries (older than { $deleted_days } days).
+compact-confirm = Compact?
+compacted = Compacted: { $before } KiB → { $after } KiB.
+nothing-to-rotate = No passwords to rotate.
+rotate-confirm = Rotate these { $count } passwords?
+rotated = Rotated. Now change them on these sites:
+nothing-expires = Nothing expires within { $days } days.
+database-parses = The database decrypts and parses.
+restored = Restored.
+shared = Shared '{ $entry }' to { $path }.
+received = Received '{ $entry }'.
+stored-file = Stored { $file } in '{ $entry }'.
+wrote-file = Wrote { $file } to { $path }.
+imported-passkey = Imported the passkey for { $entry }.
+delete-passkey = Delete the passkey for { $site } ({ $username }) from { $entry }? It can't be used to sign in anymore.
+saved-passkey-in-history = Saved. The passkey is kept in the entry's history.
+no-passkeys = No passkeys.
+nothing-to-change = Nothing to change.
+apply-confirm = Apply changes to { $count } entries?
+ssh-key-added = Added the key to ssh-agent.
+
+## The interactive session
+
+usage-read-failed = Couldn't read usage: { $error }
+usage-record-failed = Couldn't record usage: { $error }
+breach-audit-read-failed = Couldn't read the last breach audit: { $error }
+reload-failed = Couldn't check { $database } for changes: { $error }
+unknown-action = Unknown action in the config: { $action }
+read-only = { $database } is open read-only.
+weak-password = Weak password: { $strength }
+invalid-otp = Invalid OTP: { $error }
+copy = Copy
+reveal = Reveal
+no-url = { $entry } has no URL.
+opened = Opened { $url }
+typing-in = Typing into the focused window in { $seconds }...
+typed = Typed.
+type-into = Type into
+no-host = { $entry } has no host.
+connecting = Connecting to { $host }...
+reachable = Reachable ({ $ms } ms).
+unreachable = Unreachable: { $error }
+
+## Generating passwords
+
+length = Length:
+options = Options:
+characters-numbers = Numbers
+characters-lowercase-letters = Lowercase letters
+characters-uppercase-letters = Uppercase letters
+characters-symbols = Symbols
+characters-spaces = Spaces
+characters-exclude-similar-characters = Exclude similar characters
+select-character-class = Select at least one character class
+below-min-entropy = This is below the recommended minimum of { $bits } bits.
+preview-use-it = Use it
+preview-regenerate = Regenerate
+preview-change-length = Change length
+preview-change-characters = Change characters
+syllables = Syllables:
+capital-and-digit = Add a capital letter and digit?
+
+## Creating databases and changing keys
+
+kdf = Key derivation function
+kdf-argon2id = Argon2id (recommended)
+kdf-aes = AES-KDF (for compatibility with old clients)
+new-master-password = New master password:
+require-key-file = Also require a key file?
+key-file-path = Key file (created if missing):
+generated-key-file = Generated key file { $path }.
+keep-key-file-safe = Keep a copy of it somewhere safe: without it the database can't be opened.
+creating = Creating...
+created = Created { $path }.
+add-key-file = kpass only asks for the password, so add the key file to `key_files` in the config:
+key-file = Key file
+keep-key-file = Keep the key file
+change-key-file = Use a different key file
+remove-key-file = Remove the key file
+saved-with-backup = Saved. The previous version was backed up (see `kpass restore-backup`).
+recovery-codes-invalidated = Recovery codes no longer work; generate new ones with `kpass recovery <database>`.
+update-key-files = Update `key_files` in the config to use the new key file:
+remove-from-key-files = Remove the database from `key_files` in the config.
+
+## Audits
+
+continue = Continue?
+no-urls = No entries have URLs.
+url-check-requests = This will make a HEAD request to each of { $count } URLs.
+url-check-no-credentials = No credentials are sent, but the sites (and your network) will see the requests.
+urls-resolved = All URLs resolved.
+dead-urls = { $count } entries have URLs that no longer resolve.
+tag-them = Tag them as '{ $tag }'?
+same-password = Same password in { $count } entries:
+need-attention = { $count } entries need attention.
+edit-them-now = Edit them now?
+searching = Searching { $path }...
+hibp-sends-prefixes = This sends the first 5 characters of each password's SHA-1 hash to { $api }.
+hibp-keeps-passwords = Passwords (and full hashes) never leave this machine.
+
+## Bulk edits
+
+entries = Entries:
+entries-help = Space to select, type to filter, Enter when done
+no-entries-selected = No entries selected.
+for-entries = For { $count } entries:
+bulk-move-to-group = Move to group
+bulk-add-tag = Add tag
+bulk-remove-tag = Remove tag
+bulk-delete = Delete
+bulk-set-expiry = Set expiry
+moved-entries = Moved { $count } entries to { $group }.
+tag = Tag:
+tagged-entries = Tagged { $count } entries with { $tag }.
+no-tags = The entries have no tags.
+untagged-entries = Removed { $tag } from { $count } entries.
+delete-entries = Delete { $count } entries?
+deleted-entries = Deleted { $count } entries ({ $recycled } moved to the recycle bin).
+changed-expiry = Changed the expiry of { $count } entries.
+
+## Importing
+
+import-into = Import into:
+another-database = Another database...
+database-path = Database path:
+no-such-file = No such file
+no-downloads-dir = Couldn't find the downloads dir.
+looking-for-exports = Looking for exports in { $dir }...
+no-exports-found = No exports found. Export your passwords (as Bitwarden JSON, browser CSV or KeePass XML) and run this again, or import a file directly:
+found-exports = Found { $count } exports.
+import = Import:
+export-in-plain-text = { $path } holds all its passwords in plain text.
+delete-it = Delete it?
+deleted = Deleted.
+skipping-cards = Skipping { $count } cards and identities.
+decrypting-with-gpg = Decrypting { $count } files with gpg...
+reading-as = Reading as { $format } export.
+reading-from-vault = Reading { $path } from { $address } (KV version { $version })...
+import-confirm = Import?
+entries-to-import = { $count } entries to import:
+and-more = ...and { $count } more
+skipping-tokens = Skipping { $count } HOTP and Steam tokens.
+create-new-entry = (create a new entry)
+entry-for-token = Entry for { $issuer } ({ $account }):
+tokens-already-imported = All tokens are already in the database.
+tokens-to-import = { $count } tokens to import:
+token-replaces = replace OTP of { $entry }
+token-adds = add to { $entry }
+token-new-entry = new entry
+
+## Recovery codes and shares
+
+confirm-full-password = Confirm full password:
+recovery-codes-intro = Recovery codes are an alternative way to unlock your database.
+recovery-codes-sensitive = Anyone holding a single code and the database file can recover your master password, so a code is as sensitive as the password itself.
+recovery-codes-offline = Store them offline (e.g. printed, in a safe), never next to the database.
+recovery-codes-replaced = Any previously generated codes will be invalidated.
+generate-recovery-codes = Generate recovery codes?
+generating-codes = Generating codes...
+recovery-codes-use = Each code can be used once, with `kpass recover <database>`.
+recovery-code = Recovery code:
+recovered = Recovered. Please set a new password.
+new-password = New password:
+saved-recovery-codes-used = Saved. Recovery codes have been used up; generate new ones with `kpass recovery <database>`.
+shares-unlock = Any { $threshold } of these { $count } shares, with the database file, unlock it.
+shares-keep-apart = Give them to different people or keep them in different places.
+shares-invalidated = Changing the master password makes them useless.
+split-password = Split the master password?
+shares-use = Unlock with any { $threshold } of them with `--from-shares`.
+share = Share:
+share-of = Share { $number } of { $threshold }:
+share-of-other-password = This share is of a different password.
+share-entered = That share was already entered.
+
+## Renaming and moving groups
+
+audit-rule-refers = Audit rule '{ $rule }' refers to { $old }; update its `group` to { $new } in the config.
+rename-or-move = Rename or move?
+rename = Rename
+move = Move
+new-name = New name:
+move-into = Move into:
+move-affects = /{ $old } -> /{ $new } affects { $entries } entries in { $subgroups } subgroups.
+
+## Backups, exports and sharing
+
+restore-backup-from = Restore backup from
+replace-with-backup = Replace the database with the backup from { $time }?
+export-unencrypted = This will write ALL passwords to an unencrypted file.
+export-access = Anyone who gets hold of the file (or a printout) has access to everything.
+export-print-and-delete = Print the file and then securely delete it; don't keep it on disk.
+export-phrase = export my secrets
+type-to-confirm = Type "{ $phrase }" to confirm:
+passphrase = Passphrase:
+generate-passphrase = Generate a passphrase?
+send-passphrase-separately = Send the passphrase separately from the file (e.g. over a call or another app).
+
+## Showing secrets
+
+show-on-screen = Show { $what } on screen?
+show-on-screen-help = Make sure nobody is looking over your shoulder
+press-enter-to-hide = Press Enter to hide it (hidden after { $seconds } seconds).
+the-password = the password
+the-qr-code = the QR code
+the-field = the { $field }
+no-password-or-otp = { $entry } has no password or OTP.
+show-as-qr = Show as QR:
+qr-password = Password
+qr-wifi-network = WiFi network
+qr-otp-otpauth-uri = OTP (otpauth:// URI)
+wifi-ssid = Network name (SSID):
+
+## Templates and duplicates
+
+leave-empty-to-skip = Leave empty to skip
+value-from = { $value } (from { $entry })
+no-duplicates = No duplicates found.
+found-duplicates = Found { $count } sets of likely duplicates.
+merge-into = Merge into { $entry }
+skip = Skip
+stop = Stop
+merge-confirm = Merge?
+merged-entries = Merged { $count } entries into { $entry }.
+
+## Key derivation and upgrades
+
+kdf-current = Current: { $settings }
+target-unlock-time = Target unlock time (ms):
+kdf-memory = Memory (MiB):
+benchmarking = Benchmarking...
+kdf-over-target = Even { $iterations } iterations take longer than the target; consider less memory.
+kdf-suggested = Suggested: { $settings } (about { $ms } ms to unlock)
+kdf-unchanged = The database already uses these settings.
+kdf-confirm = Rewrite the database with these settings?
+up-to-date = The database is up to date already. See `kpass kdf` to tune its KDF.
+upgraded = Upgraded: { $settings }
+upgrade-breaks-old-clients = Clients that only read the old format (e.g. KeePass 2.34 and older) can't open it afterwards.
+upgrade-confirm = Upgrade the database?
+saved-original-kept = Saved. The original is kept as { $path }.
+
+## Pairing devices
+
+waiting-to-pair = Waiting for a device to pair with; run `kpass pair { $host }` on it.
+pairing-code = Pairing code:
+same-code = Does { $device } show the same code?
+waiting-to-confirm = Waiting for { $device } to confirm...
+paired = Paired { $database } with { $device }.
+enable-pairing-unlock = Set `pairing_unlock = true` in the config to unlock with the password from paired devices.
+unpaired = Unpaired { $device }.
+pair-again = { $error }; pair it again.
+granting = Paired devices can unlock { $database } for { $minutes } minutes.
+unlocked-on = Unlocked { $database } on { $device }.
+refused-request = Refused a request from { $address }: { $error }
+pairings-read-failed = Couldn't read pairings: { $error }
+unlocked-by = Unlocked by { $device }.
+unlock-with-failed = Couldn't unlock with { $device }: { $error }
+
+## Opening databases in the session
+
+open-elsewhere = { $database } is open in another kpass instance ({ $holder }).
+open-elsewhere-overwrites = Changes made in both could overwrite each other.
+open-read-only = Open read-only?
+watch-failed = Can't watch { $database } for changes: { $error }
+reloaded = Reloaded { $database }, which changed on disk.
+reload-changed-failed = { $database } changed on disk, but couldn't be reloaded: { $error }
+icon = Icon:
+
+## Remote databases
+
+upload-pending = The last changes to { $url } weren't uploaded; they will be on the next save.
+downloading = Downloading { $url }...
+changed-remotely = { $url } changed remotely, downloading it again...
+uploading = Uploading to { $url }...
+
+## Background services
+
+agent-listening = Agent listening on { $path }
+agent-idle = Agent idle, shutting down.
+refused-other-user = Refused a connection from another user.
+request-failed = Failed to handle request: { $error }
+grant-expired = The grant expired.
+answer-failed = Failed to answer a paired device: { $error }
+activity-record-failed = Couldn't record activity: { $error }
+api-not-local = Listening on { $address }, which isn't only reachable from this machine.
+api-listening = API listening on http://{ $address }
+api-token-in = The token is in { $path }
+api-idle = API idle, shutting down.
+secret-service-serving = Serving org.freedesktop.secrets.
+allow-browser = Allow a browser extension to access { $database }?
+associated = Associated { $client } with { $database }.
+installed = Installed { $path }.
+notify-failed = Failed to notify: { $error }
+copied-password-of = Copied the password of { $entry }.
+
+## Sessions
+
+idle-locked = Locked after { $minutes } minutes without input.
+idle-quitting = No input for { $minutes } minutes, quitting.
+restart-failed = Couldn't restart kpass ({ $error }), so quitting instead.
+reveal-started = Secrets will be shown for { $minutes } minutes.
+reveal-ended = Secrets are masked again.
+reveal-expired = Reveal session over, secrets are masked again.
+locked-out = Too many failed attempts; locked for { $seconds }s.
+locked-out-cache-removed = { $attempts } failed attempts; removed the cached password and locked for { $seconds }s.
+sandbox-blocks-push = git_push is on, but --sandbox blocks pushing.
+sandbox-create-failed = Couldn't create { $path }, so it won't be accessible: { $error }
+sandbox-missing = { $path } doesn't exist, so it won't be accessible.
+sandbox-partial = Sandbox only partially enforced; your kernel lacks some Landlock features.
+sandbox-unsupported = Sandbox not enforced; your kernel doesn't support Landlock.
+
+## History and checks
+
+git-committed = Committed to git.
+git-pushed = Pushed.
+git-commit-failed = Couldn't commit the database: { $error }
+no-differences = No differences.
+no-changelog = No changes recorded. Enable `changelog` in the config to record them.
+no-journal = No changes recorded. Enable `journal` in the config to record them.
+no-changes-in-period = No changes in this period.
+hibp-resuming = Resuming: { $checked } of { $count } passwords already checked.
+repaired = Repaired { $repaired } of { $count } problems.
+repairable = { $repairable } of { $count } problems can be repaired with --repair.
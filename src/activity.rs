@@ -0,0 +1,223 @@
+//! A log of which entries were used (viewed, copied, typed),
+//! shown as a summary the next time the database is unlocked.
+//!
+//! The log is kept in the config dir, encrypted with the local key
+//! (see `cache::key`), one per database.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use cocoon::Cocoon;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{cache, config};
+
+/// How many events the summary lists before eliding the rest.
+const SUMMARY_LEN: usize = 20;
+
+/// Serializes access to the log, e.g. between the agent's connections.
+static LOG: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize)]
+struct Event {
+    time: NaiveDateTime,
+    action: String,
+    entry: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Log {
+    unlocked: NaiveDateTime,
+    events: Vec<Event>,
+}
+
+/// The log lives in the config dir, named after a hash of the database path.
+fn log_path(db_path: &Path) -> Result<PathBuf> {
+    let db_path = db_path.canonicalize()?;
+    let hash = Sha256::digest(db_path.as_os_str().as_encoded_bytes());
+    let name: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("activity")
+        .join(name))
+}
+
+fn read(path: &Path, key: &[u8]) -> Result<Option<Log>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = Cocoon::new(key)
+        .parse(&mut File::open(path)?)
+        .map_err(|err| anyhow!("Couldn't read activity log: {:?}", err))?;
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+fn write(path: &Path, key: &[u8], log: &Log) -> Result<()> {
+    fs::create_dir_all(path.parent().expect("Log is in the activity dir"))?;
+    let data = serde_json::to_vec(log)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    Cocoon::new(key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write activity log: {:?}", err))?;
+    Ok(())
+}
+
+/// Record that an entry was used, e.g. `copied`.
+pub fn record(db_path: &Path, action: &str, entry: &str) -> Result<()> {
+    if !config::get().activity_log {
+        return Ok(());
+    }
+    let _guard = LOG.lock().unwrap();
+    let path = log_path(db_path)?;
+    let key = cache::key()?;
+    let mut log = read(&path, &key)?.unwrap_or_else(|| Log {
+        unlocked: Local::now().naive_local(),
+        events: vec![],
+    });
+    log.events.push(Event {
+        time: Local::now().naive_local(),
+        action: action.to_string(),
+        entry: entry.to_string(),
+    });
+    write(&path, &key, &log)
+}
+
+/// What was used since the log was started, most recent last.
+fn summary(log: &Log) -> Vec<String> {
+    let since = log.unlocked.format("%Y-%m-%d %H:%M");
+    if log.events.is_empty() {
+        return vec![format!(
+            "No entries used since the last unlock ({}).",
+            since
+        )];
+    }
+    let mut lines = vec![format!(
+        "{} uses of entries since the last unlock ({}):",
+        log.events.len(),
+        since
+    )];
+    let skipped = log.events.len().saturating_sub(SUMMARY_LEN);
+    for event in &log.events[skipped..] {
+        lines.push(format!(
+            "  {} {} {}",
+            event.time.format("%Y-%m-%d %H:%M"),
+            event.action,
+            event.entry
+        ));
+    }
+    if skipped > 0 {
+        lines.push(format!("  ...and {} earlier.", skipped));
+    }
+    lines
+}
+
+/// Show what was used since the previous unlock, then start a fresh log.
+pub fn summarize(db_path: &Path) -> Result<()> {
+    if !config::get().activity_log {
+        return Ok(());
+    }
+    let _guard = LOG.lock().unwrap();
+    let path = log_path(db_path)?;
+    let key = cache::key()?;
+    if let Some(log) = read(&path, &key)? {
+        for line in summary(&log) {
+            info!("{}", line);
+        }
+    }
+    write(
+        &path,
+        &key,
+        &Log {
+            unlocked: Local::now().naive_local(),
+            events: vec![],
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(hour, 30, 0)
+            .unwrap()
+    }
+
+    fn event(action: &str, entry: &str) -> Event {
+        Event {
+            time: at(12),
+            action: action.to_string(),
+            entry: entry.to_string(),
+        }
+    }
+
+    #[test]
+    fn summarizes_recent_uses() {
+        let mut log = Log {
+            unlocked: at(9),
+            events: vec![],
+        };
+        assert_eq!(
+            summary(&log),
+            ["No entries used since the last unlock (2024-05-01 09:30)."]
+        );
+        log.events.push(event("copied", "Work/GitHub"));
+        log.events.push(event("typed", "Email"));
+        assert_eq!(
+            summary(&log),
+            [
+                "2 uses of entries since the last unlock (2024-05-01 09:30):",
+                "  2024-05-01 12:30 copied Work/GitHub",
+                "  2024-05-01 12:30 typed Email",
+            ]
+        );
+    }
+
+    #[test]
+    fn elides_earlier_uses() {
+        let log = Log {
+            unlocked: at(9),
+            events: (0..SUMMARY_LEN + 5)
+                .map(|i| event("viewed", &i.to_string()))
+                .collect(),
+        };
+        let lines = summary(&log);
+        assert_eq!(lines.len(), SUMMARY_LEN + 2);
+        assert_eq!(lines[1], "  2024-05-01 12:30 viewed 5");
+        assert_eq!(lines.last().unwrap(), "  ...and 5 earlier.");
+    }
+
+    #[test]
+    fn keeps_the_log_encrypted() {
+        let dir = std::env::temp_dir().join(format!("kpass-test-activity-{}", std::process::id()));
+        let path = dir.join("log");
+        assert!(read(&path, &[1; 32]).unwrap().is_none());
+        let log = Log {
+            unlocked: at(9),
+            events: vec![event("copied", "Work/GitHub")],
+        };
+        write(&path, &[1; 32], &log).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert!(!contents.windows(6).any(|window| window == b"GitHub"));
+        let read_back = read(&path, &[1; 32]).unwrap().unwrap();
+        assert_eq!(read_back.events[0].entry, "Work/GitHub");
+        assert!(read(&path, &[2; 32]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
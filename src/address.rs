@@ -0,0 +1,414 @@
+//! Addressing entries and groups from the command line, the same way
+//! in every command:
+//!
+//! - `group/subgroup/Title`: a title, optionally qualified by (the end of)
+//!   its group path. A leading `/` anchors the path at the root.
+//! - `uuid:<uuid>`: exactly one entry or group, however it's named.
+//! - `url:<url>`: the entry whose URL best matches the URL's host (see `domain`).
+//!
+//! Names containing `/` or `\` escape them as `\/` and `\\`.
+//! Matching is case-insensitive.
+
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, Result};
+use keepass::{
+    db::{Entry as KEntry, Group, Node},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{domain, entries_with_paths, error::Error};
+
+enum Address {
+    Uuid(Uuid),
+    /// A host.
+    Url(String),
+    Path {
+        /// Group names, ending with the entry's title (or the group's name).
+        names: Vec<String>,
+        /// Whether the path starts at the root,
+        /// rather than matching the end of the group path.
+        absolute: bool,
+    },
+}
+impl Address {
+    pub fn parse(query: &str) -> Result<Self> {
+        if let Some(uuid) = query.strip_prefix("uuid:") {
+            let uuid =
+                Uuid::parse_str(uuid.trim()).map_err(|_| anyhow!("Invalid UUID: '{}'", uuid))?;
+            return Ok(Address::Uuid(uuid));
+        }
+        if let Some(url) = query.strip_prefix("url:") {
+            let host =
+                domain::host_of(url.trim()).ok_or_else(|| anyhow!("Invalid URL: '{}'", url))?;
+            return Ok(Address::Url(host));
+        }
+        let absolute = query.starts_with('/');
+        let names = split(query);
+        Ok(Address::Path { names, absolute })
+    }
+
+    /// Whether a group path matches the address's groups.
+    fn in_groups(&self, groups: &[String], path: &[String]) -> bool {
+        let Address::Path { absolute, .. } = self else {
+            return false;
+        };
+        let matches = |skip: usize| {
+            path[skip..]
+                .iter()
+                .zip(groups)
+                .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+        };
+        if *absolute {
+            path.len() == groups.len() && matches(0)
+        } else {
+            path.len() >= groups.len() && matches(path.len() - groups.len())
+        }
+    }
+}
+
+/// Split a path into names at unescaped slashes, unescaping them.
+/// Leading and trailing slashes are ignored.
+pub fn split(path: &str) -> Vec<String> {
+    let mut names = vec![String::new()];
+    let mut chars = path.trim_start_matches('/').chars();
+    while let Some(c) = chars.next() {
+        let name = names.last_mut().expect("There is always a name");
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('/' | '\\')) => name.push(c),
+                // Other backslashes are taken literally.
+                Some(c) => name.extend(['\\', c]),
+                None => name.push('\\'),
+            },
+            '/' => names.push(String::new()),
+            c => name.push(c),
+        }
+    }
+    // Allow a trailing slash (e.g. on groups).
+    if names.len() > 1 && names.last().is_some_and(String::is_empty) {
+        names.pop();
+    }
+    if names == [""] {
+        names.clear();
+    }
+    names
+}
+
+/// Format a path (group names and a title) as an address, escaping names.
+pub fn format(path: &[String]) -> String {
+    path.iter()
+        .map(|name| name.replace('\\', "\\\\").replace('/', "\\/"))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A match, for listing ambiguous ones.
+struct Match<'a>(&'a [String], &'a Uuid);
+impl Display for Match<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (uuid:{})", format(self.0), self.1)
+    }
+}
+
+fn ambiguous(kind: &str, query: &str, matches: &[Match]) -> anyhow::Error {
+    let matches: Vec<String> = matches.iter().map(Match::to_string).collect();
+    Error::Ambiguous(format!(
+        "Multiple {} match '{}', qualify it with the group or use its UUID: {}",
+        kind,
+        query,
+        matches.join(", ")
+    ))
+    .into()
+}
+
+/// Find the entries matching `query` (see the module docs), with their
+/// paths (ending with their titles). Exact titles are preferred over
+/// substrings.
+pub fn find_entries<'a>(db: &'a Database, query: &str) -> Result<Vec<(Vec<String>, &'a KEntry)>> {
+    let address = Address::parse(query)?;
+    let entries: Vec<_> = entries_with_paths(&db.root)
+        .into_iter()
+        .map(|(mut path, entry)| {
+            path.push(entry.get_title().unwrap_or("").to_string());
+            (path, entry)
+        })
+        .collect();
+
+    let matches: Vec<&(Vec<String>, &KEntry)> = match &address {
+        Address::Uuid(uuid) => entries.iter().filter(|(_, e)| e.uuid == *uuid).collect(),
+        Address::Url(host) => {
+            let ranked: Vec<_> = entries
+                .iter()
+                .filter_map(|m| Some((domain::rank(m.1, host)?, m)))
+                .collect();
+            let best = ranked.iter().map(|(rank, _)| *rank).max();
+            ranked
+                .into_iter()
+                .filter(|(rank, _)| Some(*rank) == best)
+                .map(|(_, m)| m)
+                .collect()
+        }
+        Address::Path { names, .. } => {
+            let Some((title, groups)) = names.split_last() else {
+                return Err(anyhow!("Please name an entry"));
+            };
+            let title = title.to_lowercase();
+            let candidates = entries.iter().filter(|(path, _)| {
+                let (_, entry_groups) = path.split_last().expect("Paths end in a title");
+                address.in_groups(groups, entry_groups)
+            });
+            let entry_title = |path: &[String]| path[path.len() - 1].to_lowercase();
+            let exact: Vec<_> = candidates
+                .clone()
+                .filter(|(path, _)| entry_title(path) == title)
+                .collect();
+            if exact.is_empty() {
+                candidates
+                    .filter(|(path, _)| entry_title(path).contains(&title))
+                    .collect()
+            } else {
+                exact
+            }
+        }
+    };
+    Ok(matches.into_iter().cloned().collect())
+}
+
+/// Find the entry at `query` (see the module docs). An exact title is
+/// preferred, otherwise the title must be a substring of exactly one entry's.
+pub fn find_entry<'a>(db: &'a Database, query: &str) -> Result<&'a KEntry> {
+    let matches = find_entries(db, query)?;
+    match matches.as_slice() {
+        [(_, entry)] => Ok(entry),
+        [] => Err(Error::NotFound(format!("No entry matches '{}'", query)).into()),
+        _ => {
+            let matches: Vec<_> = matches.iter().map(|(p, e)| Match(p, &e.uuid)).collect();
+            Err(ambiguous("entries", query, &matches))
+        }
+    }
+}
+
+/// All groups below `group`, with their paths.
+fn groups_with_paths(group: &Group) -> Vec<(Vec<String>, &Group)> {
+    fn collect<'a>(
+        group: &'a Group,
+        path: &mut Vec<String>,
+        out: &mut Vec<(Vec<String>, &'a Group)>,
+    ) {
+        for node in &group.children {
+            if let Node::Group(child) = node {
+                path.push(child.name.clone());
+                out.push((path.clone(), child));
+                collect(child, path, out);
+                path.pop();
+            }
+        }
+    }
+
+    let mut out = vec![];
+    collect(group, &mut vec![], &mut out);
+    out
+}
+
+/// Find the group at `query` (see the module docs), returning it with its path.
+/// No query (or `/`) is the root group.
+pub fn find_group<'a>(db: &'a Database, query: Option<&str>) -> Result<(&'a Group, Vec<String>)> {
+    let Some(query) = query else {
+        return Ok((&db.root, vec![]));
+    };
+    let address = Address::parse(query)?;
+    let groups = groups_with_paths(&db.root);
+    let matches: Vec<_> = match &address {
+        Address::Uuid(uuid) if db.root.uuid == *uuid => return Ok((&db.root, vec![])),
+        Address::Uuid(uuid) => groups.iter().filter(|(_, g)| g.uuid == *uuid).collect(),
+        Address::Url(_) => return Err(anyhow!("URLs only address entries")),
+        Address::Path { names, .. } if names.is_empty() => return Ok((&db.root, vec![])),
+        Address::Path { names, .. } => groups
+            .iter()
+            .filter(|(path, _)| address.in_groups(names, path))
+            .collect(),
+    };
+    match matches.as_slice() {
+        [(path, group)] => Ok((group, path.clone())),
+        [] => Err(Error::NotFound(format!("No group matches '{}'", query)).into()),
+        _ => {
+            let matches: Vec<_> = matches.iter().map(|(p, g)| Match(p, &g.uuid)).collect();
+            Err(ambiguous("groups", query, &matches))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, url: &str) -> KEntry {
+        test_util::entry(&[("Title", title), ("URL", url)])
+    }
+
+    fn group(name: &str, children: Vec<Node>) -> Node {
+        let mut group = Group::new(name);
+        group.children = children;
+        Node::Group(group)
+    }
+
+    /// ```text
+    /// Work/GitHub, Work/Mail/Webmail, Work/a\/b
+    /// Personal/GitHub, Personal/GitHub Enterprise, Personal/Mail/Webmail
+    /// Bank
+    /// ```
+    fn db() -> Database {
+        let mut db = Database::new(Default::default());
+        db.root.children = vec![
+            group(
+                "Work",
+                vec![
+                    entry("GitHub", "https://github.com").into(),
+                    group(
+                        "Mail",
+                        vec![entry("Webmail", "https://mail.work.com").into()],
+                    ),
+                    entry("a/b", "").into(),
+                ],
+            ),
+            group(
+                "Personal",
+                vec![
+                    entry("GitHub", "https://github.com/login").into(),
+                    entry("GitHub Enterprise", "https://git.example.com").into(),
+                    group(
+                        "Mail",
+                        vec![entry("Webmail", "https://mail.example.com").into()],
+                    ),
+                ],
+            ),
+            entry("Bank", "https://accounts.bank.com").into(),
+        ];
+        db
+    }
+
+    fn found(db: &Database, query: &str) -> String {
+        let uuid = find_entry(db, query).unwrap().uuid;
+        let (path, _) = find_entries(db, &format!("uuid:{}", uuid))
+            .unwrap()
+            .remove(0);
+        format(&path)
+    }
+
+    fn error(result: Result<impl Sized>) -> Option<Error> {
+        result.err()?.downcast().ok()
+    }
+
+    #[test]
+    fn splits_paths() {
+        assert_eq!(split("a/b/c"), ["a", "b", "c"]);
+        assert_eq!(split("/a/b/"), ["a", "b"]);
+        assert_eq!(split(r"a\/b/c\\d"), [r"a/b", r"c\d"]);
+        assert_eq!(split(r"a\nb\"), [r"a\nb\"]);
+        assert_eq!(split("a//b"), ["a", "", "b"]);
+        assert!(split("").is_empty());
+        assert!(split("/").is_empty());
+    }
+
+    #[test]
+    fn formats_paths() {
+        let path = ["Work".to_string(), r"a/b\c".to_string()];
+        assert_eq!(format(&path), r"Work/a\/b\\c");
+        assert_eq!(split(&format(&path)), path);
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert!(matches!(
+            Address::parse("uuid:67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            Address::Uuid(_)
+        ));
+        assert!(Address::parse("uuid:nope").is_err());
+        let Address::Url(host) = Address::parse("url:https://Me@Example.com:8080/x").unwrap()
+        else {
+            panic!("Not a URL");
+        };
+        assert_eq!(host, "example.com");
+        let Address::Path { names, absolute } = Address::parse("/Work/GitHub").unwrap() else {
+            panic!("Not a path");
+        };
+        assert_eq!(names, ["Work", "GitHub"]);
+        assert!(absolute);
+    }
+
+    #[test]
+    fn finds_entries_by_path() {
+        let db = db();
+        assert_eq!(found(&db, "Bank"), "Bank");
+        assert_eq!(found(&db, "work/github"), "Work/GitHub");
+        assert_eq!(found(&db, "Personal/Mail/Webmail"), "Personal/Mail/Webmail");
+        assert_eq!(found(&db, "/Work/Mail/Webmail"), "Work/Mail/Webmail");
+        assert_eq!(found(&db, r"a\/b"), r"Work/a\/b");
+        // Exact titles are preferred over substrings.
+        assert_eq!(found(&db, "Personal/GitHub"), "Personal/GitHub");
+        assert_eq!(found(&db, "Enterprise"), "Personal/GitHub Enterprise");
+    }
+
+    #[test]
+    fn reports_ambiguous_and_missing_entries() {
+        let db = db();
+        assert!(matches!(
+            error(find_entry(&db, "GitHub")),
+            Some(Error::Ambiguous(_))
+        ));
+        assert!(matches!(
+            error(find_entry(&db, "Mail/Webmail")),
+            Some(Error::Ambiguous(_))
+        ));
+        assert!(matches!(
+            error(find_entry(&db, "Nothing")),
+            Some(Error::NotFound(_))
+        ));
+        // Absolute paths start at the root.
+        assert!(matches!(
+            error(find_entry(&db, "/Mail/Webmail")),
+            Some(Error::NotFound(_))
+        ));
+        assert!(find_entry(&db, "/").is_err());
+    }
+
+    #[test]
+    fn finds_entries_by_url() {
+        let db = db();
+        assert_eq!(found(&db, "url:mail.example.com"), "Personal/Mail/Webmail");
+        assert_eq!(found(&db, "url:https://bank.com/"), "Bank");
+        assert!(matches!(
+            error(find_entry(&db, "url:github.com")),
+            Some(Error::Ambiguous(_))
+        ));
+        assert!(matches!(
+            error(find_entry(&db, "url:nowhere.org")),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn finds_groups() {
+        let db = db();
+        let path = |query| find_group(&db, query).unwrap().1;
+        assert!(path(None).is_empty());
+        assert!(path(Some("/")).is_empty());
+        assert_eq!(path(Some("work")), ["Work"]);
+        assert_eq!(path(Some("Work/Mail/")), ["Work", "Mail"]);
+        let root = format!("uuid:{}", db.root.uuid);
+        assert!(path(Some(&root)).is_empty());
+        assert!(matches!(
+            error(find_group(&db, Some("Mail"))),
+            Some(Error::Ambiguous(_))
+        ));
+        assert!(matches!(
+            error(find_group(&db, Some("/Mail"))),
+            Some(Error::NotFound(_))
+        ));
+        assert!(find_group(&db, Some("url:github.com")).is_err());
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    borrow::Cow,
+    env, fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use keepass::{db::Entry as KEntry, Database};
+use zeroize::Zeroizing;
+
+use crate::{
+    activity, address, autotype, database_key, entries_with_paths, error, error::Error, favorites,
+    lockout, notify, otp::Totp, pairing, protect, reference, sandbox, secret, secret::Secret, ssh,
+    usage,
+};
+
+/// How long the agent waits between polls for new connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a client may take to send a request or read a response
+/// before its connection is dropped, and how long clients wait for an answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many clients can be connected at once.
+const MAX_CLIENTS: usize = 16;
+
+/// State shared between the connection threads.
+/// Reads of the database can happen concurrently, while locking and
+/// unlocking it are serialized by the lock.
+struct State<'a> {
+    /// `None` while locked (see `kpass lock`).
+    db: RwLock<Option<Database>>,
+    /// The master password, kept for grants (see `pairing`). `None` while locked.
+    password: Mutex<Option<Secret>>,
+    /// Paired devices' requests are answered while a grant lasts.
+    grant: Mutex<Option<pairing::Grant>>,
+    db_path: &'a Path,
+    /// Held while recording a request's use of an entry, as the files
+    /// it's recorded in are read, changed and written back.
+    records: Mutex<()>,
+    last_active: Mutex<Instant>,
+    clients: AtomicUsize,
+}
+impl State<'_> {
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_active.lock().unwrap().elapsed()
+    }
+}
+
+/// Path to the agent's socket, in a directory only the user can
+/// access (see `private_dir`).
+/// Prefers `$XDG_RUNTIME_DIR` as it's private to the user.
+pub fn socket_path() -> PathBuf {
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("kpass"),
+        None => PathBuf::from(format!("/tmp/kpass-{}", crate::uid())),
+    };
+    dir.join("agent.sock")
+}
+
+/// The user ID of the process at the other end of the socket.
+fn peer_uid(stream: &UnixStream) -> Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes, and `len` is the size of `cred`.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(cred.uid)
+}
+
+/// Keep the decrypted database in memory and answer
+/// queries over a Unix socket until the agent has been
+/// idle for `idle_timeout`.
+pub fn serve(db: Database, password: Secret, db_path: &Path, idle_timeout: Duration) -> Result<()> {
+    secret::lock_all();
+
+    let path = socket_path();
+    if let Some(dir) = path.parent() {
+        crate::private_dir(dir)?;
+    }
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!("An agent is already running at {}", path.display()));
+        }
+
+        // Stale socket from an agent that didn't shut down cleanly.
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    info!("{}", tr!("agent-listening", path = path.display()));
+
+    let state = State {
+        db: RwLock::new(Some(db)),
+        password: Mutex::new(Some(password)),
+        grant: Mutex::new(None),
+        db_path,
+        last_active: Mutex::new(Instant::now()),
+        records: Mutex::new(()),
+        clients: AtomicUsize::new(0),
+    };
+    let result = thread::scope(|scope| loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                // The directory keeps others out, but check anyway.
+                if peer_uid(&stream).ok() != Some(crate::uid()) {
+                    warn!("{}", tr!("refused-other-user"));
+                    continue;
+                }
+                state.touch();
+                if state.clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                    state.clients.fetch_sub(1, Ordering::SeqCst);
+                    let _ = stream.write_all(b"err 1 Agent busy\n");
+                    continue;
+                }
+                let state = &state;
+                scope.spawn(move || {
+                    if let Err(err) = handle(state, stream) {
+                        warn!("{}", tr!("request-failed", error = err));
+                    }
+                    state.clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                let connected = state.clients.load(Ordering::SeqCst) > 0;
+                let granting = poll_grant(&state);
+                if !connected && !granting && state.idle_for() >= idle_timeout {
+                    info!("{}", tr!("agent-idle"));
+                    notify::notify("kpass locked", "The agent was idle and has shut down.");
+                    break Ok(());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) => break Err(err),
+        }
+    });
+
+    fs::remove_file(&path)?;
+    Ok(result?)
+}
+
+/// Handle a client's requests, in order, until it disconnects
+/// (which also cancels any queued requests) or times out.
+/// Requests are a single line of the form `<command> <query>`,
+/// responses are a single line of the form `ok <value>` (hex-encoded,
+/// as values may span lines or end in spaces) or
+/// `err <exit code> <message>` (see `error::exit_code`).
+fn handle(state: &State, stream: UnixStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    loop {
+        // Unlock requests carry the password.
+        let mut line = Zeroizing::new(String::new());
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(())
+            }
+            Err(err) => return Err(err.into()),
+        }
+        state.touch();
+
+        let (command, query) = line
+            .trim_end()
+            .split_once(' ')
+            .unwrap_or((line.trim_end(), ""));
+        let answer = match command {
+            "lock" | "unlock" | "status" | "grant" => control(state, command, query),
+            _ => {
+                let db = state
+                    .db
+                    .read()
+                    .map_err(|_| anyhow!("Database lock poisoned"))?;
+                match db.as_ref() {
+                    Some(db) => {
+                        let answer = answer(db, command, query);
+                        if answer.is_ok() {
+                            let _records = state.records.lock().unwrap();
+                            record_use(state.db_path, db, command, query);
+                        }
+                        answer
+                    }
+                    None => Err(anyhow!(
+                        "The agent is locked. Unlock it with `kpass unlock`."
+                    )),
+                }
+            }
+        };
+        let response = Zeroizing::new(match answer {
+            Ok(value) => format!("ok {}\n", hex::encode(Zeroizing::new(value))),
+            Err(err) => format!("err {} {}\n", error::exit_code(&err), err),
+        });
+        writer.write_all(response.as_bytes())?;
+    }
+}
+
+/// Answer paired devices' requests while a grant lasts (see `pairing`),
+/// returning whether it does.
+fn poll_grant(state: &State) -> bool {
+    let mut grant = state.grant.lock().unwrap();
+    let Some(active) = grant.as_ref() else {
+        return false;
+    };
+    if !active.is_active() {
+        *grant = None;
+        info!("{}", tr!("grant-expired"));
+        return false;
+    }
+    if let Some(password) = state.password.lock().unwrap().as_ref() {
+        if let Err(err) = active.poll(password) {
+            warn!("{}", tr!("answer-failed", error = err));
+        }
+    }
+    true
+}
+
+/// Lock the agent, dropping the database (and its key) from memory,
+/// unlock it again with the password (hex-encoded), tell which
+/// of the two it is (`locked` or `unlocked`, and the database's path),
+/// or let paired devices unlock the database for a number of minutes.
+fn control(state: &State, command: &str, query: &str) -> Result<String> {
+    // Opened before taking the lock, as failures are delayed (see `lockout`).
+    let opened = match command {
+        "unlock" => Some(open(state.db_path, query)?),
+        _ => None,
+    };
+    if command == "grant" {
+        sandbox::needs_network("Granting")?;
+        let minutes: u64 = query
+            .parse()
+            .map_err(|_| anyhow!("Malformed grant request"))?;
+        let password = state.password.lock().unwrap();
+        let password = password
+            .as_ref()
+            .ok_or_else(|| anyhow!("The agent is locked. Unlock it with `kpass unlock`."))?;
+        // Replaces any current grant, freeing the port first.
+        let mut grant = state.grant.lock().unwrap();
+        *grant = None;
+        *grant = Some(pairing::grant(
+            state.db_path,
+            password,
+            Duration::from_secs(minutes * 60),
+        )?);
+        return Ok(String::new());
+    }
+    let mut db = state
+        .db
+        .write()
+        .map_err(|_| anyhow!("Database lock poisoned"))?;
+    match command {
+        "lock" => {
+            *state.grant.lock().unwrap() = None;
+            *state.password.lock().unwrap() = None;
+            if db.take().is_some() {
+                info!("{}", tr!("agent-locked"));
+            }
+            Ok(String::new())
+        }
+        "unlock" => {
+            let (opened, password) = opened.expect("Opened before unlocking");
+            *db = Some(opened);
+            *state.password.lock().unwrap() = Some(password);
+            info!("{}", tr!("agent-unlocked"));
+            Ok(String::new())
+        }
+        _ => {
+            let status = if db.is_some() { "unlocked" } else { "locked" };
+            Ok(format!("{} {}", status, state.db_path.display()))
+        }
+    }
+}
+
+/// Open the database with the password (hex-encoded), counting
+/// failures towards the lockout like any other unlock.
+fn open(db_path: &Path, query: &str) -> Result<(Database, Secret)> {
+    lockout::check()?;
+    let pass = Zeroizing::new(hex::decode(query).map_err(|_| anyhow!("Malformed unlock request"))?);
+    let opened = match std::str::from_utf8(&pass) {
+        Ok(pass) => {
+            let pass = Secret::new(pass.to_string());
+            let key = database_key(db_path, &pass)?;
+            Database::open(&mut fs::File::open(db_path)?, key)
+                .ok()
+                .map(|db| (db, pass))
+        }
+        Err(_) => None,
+    };
+    match opened {
+        Some(opened) => {
+            lockout::reset()?;
+            Ok(opened)
+        }
+        None => {
+            lockout::record_failure()?;
+            Err(anyhow!("Incorrect password"))
+        }
+    }
+}
+
+fn answer(db: &Database, command: &str, query: &str) -> Result<String> {
+    match command {
+        "get" => {
+            let entry = find_released(db, query)?;
+            entry
+                .get_password()
+                .map(|pw| pw.to_string())
+                .ok_or_else(|| anyhow!("Entry has no password"))
+        }
+        "user" => {
+            let entry = find_released(db, query)?;
+            Ok(entry.get_username().unwrap_or("").to_string())
+        }
+        "url" => {
+            let entry = find_released(db, query)?;
+            entry
+                .get_url()
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("Entry has no URL"))
+        }
+        "path" => {
+            let entry = address::find_entry(db, query)?;
+            let (mut path, entry) = entries_with_paths(&db.root)
+                .into_iter()
+                .find(|(_, e)| e.uuid == entry.uuid)
+                .ok_or_else(|| Error::NotFound(format!("No entry matches '{}'", query)))?;
+            path.push(entry.get_title().unwrap_or("").to_string());
+            Ok(address::format(&path).replace(['\t', '\n'], " "))
+        }
+        "list" | "favorites" => {
+            let mut entries = entries_with_paths(&db.root);
+            // Favorites first (or only them).
+            entries.sort_by_key(|(_, entry)| !favorites::is_favorite(entry));
+            let paths: Vec<_> = entries
+                .into_iter()
+                .filter(|(_, entry)| command == "list" || favorites::is_favorite(entry))
+                .map(|(mut path, entry)| {
+                    path.push(entry.get_title().unwrap_or("").to_string());
+                    address::format(&path).replace(['\t', '\n'], " ")
+                })
+                .collect();
+            Ok(paths.join("\t"))
+        }
+        "autotype" => {
+            let entry = find_released(db, query)?;
+            autotype::expand_fields(&autotype::sequence_with_delays(&entry), &entry)
+        }
+        "otp" => {
+            let entry = find_released(db, query)?;
+            let uri = entry
+                .get_raw_otp_value()
+                .ok_or_else(|| anyhow!("Entry has no OTP"))?;
+            Totp::parse(uri)?.code_now()
+        }
+        "ssh-key" => {
+            let entry = find_released(db, query)?;
+            let (key, passphrase) =
+                ssh::key_of(&entry).ok_or_else(|| anyhow!("Entry has no SSH private key"))?;
+            Ok(ssh::encode(key, passphrase))
+        }
+        "field" => {
+            // `<field>\t<query>`.
+            let (field, query) = query
+                .split_once('\t')
+                .ok_or_else(|| anyhow!("Malformed field request"))?;
+            let entry = find_released(db, query)?;
+            entry
+                .get(field)
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("Entry has no {} field", field))
+        }
+        _ => Err(anyhow!("Unknown command: {}", command)),
+    }
+}
+
+/// Find the entry at `query` (see `address`), with references resolved,
+/// unless it requires the full master password, which the agent can't ask for.
+fn find_released<'a>(db: &'a Database, query: &str) -> Result<Cow<'a, KEntry>> {
+    let entry = address::find_entry(db, query)?;
+    if protect::is_required(entry) {
+        return Err(anyhow!(
+            "Entry requires the master password; open it in kpass instead"
+        ));
+    }
+    Ok(reference::resolved(db, entry))
+}
+
+/// Record the use of an entry's secrets in the activity log
+/// and its usage (for the picker's order).
+fn record_use(db_path: &Path, db: &Database, command: &str, query: &str) {
+    let action = match command {
+        "get" => "fetched password",
+        "otp" => "fetched OTP",
+        "autotype" => "typed",
+        "ssh-key" => "added SSH key",
+        "field" => "fetched field",
+        _ => return,
+    };
+    let query = query.split_once('\t').map_or(query, |(_, query)| query);
+    let Ok(entry) = address::find_entry(db, query) else {
+        return;
+    };
+    let title = entry.get_title().unwrap_or("(no title)");
+    if let Err(err) = activity::record(db_path, action, title) {
+        warn!("{}", tr!("activity-record-failed", error = err));
+    }
+    if let Err(err) = usage::record(db_path, entry.get_uuid()) {
+        warn!("{}", tr!("usage-record-failed", error = err));
+    }
+}
+
+/// Send a request to a running agent and return its answer.
+pub fn request(command: &str, query: &str) -> Result<Secret> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|_| anyhow!("No agent running. Start one with `kpass agent <database>`."))?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    writeln!(stream, "{} {}", command, query)?;
+
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new(&stream).read_line(&mut line)?;
+    parse_response(&line)
+}
+
+/// Parse the agent's response to a request (see `handle`).
+fn parse_response(line: &str) -> Result<Secret> {
+    let line = line.trim_end();
+    match line.split_once(' ').unwrap_or((line, "")) {
+        ("ok", value) => {
+            let value = Zeroizing::new(
+                hex::decode(value).map_err(|_| anyhow!("Malformed response from agent"))?,
+            );
+            let value = std::str::from_utf8(&value)
+                .map_err(|_| anyhow!("Malformed response from agent"))?;
+            Ok(Secret::new(value.to_string()))
+        }
+        ("err", msg) => {
+            let (code, msg) = msg.split_once(' ').unwrap_or(("1", msg));
+            match code
+                .parse()
+                .ok()
+                .and_then(|code| Error::from_exit_code(code, msg))
+            {
+                Some(err) => Err(err.into()),
+                None => Err(anyhow!("{}", msg)),
+            }
+        }
+        _ => Err(anyhow!("Malformed response from agent")),
+    }
+}
+
+/// Ask a running agent for a field of the entry at `query`, e.g. `API token`.
+pub fn request_field(query: &str, field: &str) -> Result<Secret> {
+    request("field", &format!("{}\t{}", field, query))
+}
+
+/// Whether the running agent is unlocked, and its database,
+/// or `None` if no agent is running.
+pub fn status() -> Result<Option<(bool, PathBuf)>> {
+    if UnixStream::connect(socket_path()).is_err() {
+        return Ok(None);
+    }
+    let status = request("status", "")?;
+    let (status, path) = status
+        .expose()
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("Malformed response from agent"))?;
+    Ok(Some((status == "unlocked", PathBuf::from(path))))
+}
+
+/// Have the running agent drop its database from memory until unlocked.
+pub fn lock() -> Result<()> {
+    request("lock", "")?;
+    Ok(())
+}
+
+/// Have the running agent let paired devices unlock its database
+/// for `minutes` (see `pairing`).
+pub fn grant(minutes: u64) -> Result<()> {
+    request("grant", &minutes.to_string())?;
+    Ok(())
+}
+
+/// Unlock the running agent with the master password.
+pub fn unlock(pass: &Secret) -> Result<()> {
+    let hex = Zeroizing::new(hex::encode(pass.expose()));
+    request("unlock", &hex)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn peer_uid_is_ours() {
+        let (a, b) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&a).unwrap(), crate::uid());
+        assert_eq!(peer_uid(&b).unwrap(), crate::uid());
+    }
+
+    #[test]
+    fn answers_keep_spaces_and_newlines() {
+        let mut db = Database::new(Default::default());
+        db.root.add_child(test_util::entry(&[
+            ("Title", "GitHub"),
+            ("UserName", "alice \nbob  "),
+        ]));
+        let state = State {
+            db: RwLock::new(Some(db)),
+            password: Mutex::new(None),
+            grant: Mutex::new(None),
+            db_path: Path::new("/kpass-test/agent.kdbx"),
+            records: Mutex::new(()),
+            last_active: Mutex::new(Instant::now()),
+            clients: AtomicUsize::new(0),
+        };
+        let (server, mut client) = UnixStream::pair().unwrap();
+        thread::scope(|scope| {
+            scope.spawn(|| handle(&state, server).unwrap());
+            writeln!(client, "user GitHub").unwrap();
+            writeln!(client, "user GitLab").unwrap();
+            client.shutdown(std::net::Shutdown::Write).unwrap();
+            let mut lines = BufReader::new(&client).lines();
+
+            let answer = parse_response(&lines.next().unwrap().unwrap()).unwrap();
+            assert_eq!(answer.expose(), "alice \nbob  ");
+            let err = parse_response(&lines.next().unwrap().unwrap()).unwrap_err();
+            assert!(err.to_string().contains("GitLab"), "{}", err);
+        });
+    }
+}
@@ -0,0 +1,520 @@
+//! A local HTTP API over the unlocked database (`kpass serve`),
+//! so other tools and scripts can fetch credentials without
+//! shelling out to kpass and re-prompting for the master password.
+//!
+//! Requests must carry the token the server writes to its token file
+//! (see `token_path`) as `Authorization: Bearer <token>`.
+//!
+//! - `GET /entries`: all entries, without secrets; `?q=` filters them by
+//!   title, path, username or URL.
+//! - `GET /entries/<address>`: one entry (see `address`), with its secrets.
+//! - `POST /entries`: create an entry from a JSON object with the fields
+//!   of `kpass apply`'s specs.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Take, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use keepass::{db::Entry as KEntry, Database, DatabaseKey};
+use serde_json::{json, Value as Json};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use crate::{
+    activity, address, agent, apply, changelog, entries_with_paths, error::Error, notify,
+    otp::Totp, protect, record_opened, reference, save_db_unattended, secret, usage,
+};
+
+/// Where the server listens unless `--listen` is given.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7844";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_CLIENTS: usize = 16;
+
+/// Request bodies are small; anything larger is refused.
+const MAX_BODY: usize = 64 * 1024;
+
+/// As are the request line and headers together.
+const MAX_HEAD: u64 = 8192;
+
+/// An error response with its HTTP status.
+#[derive(Debug)]
+struct Status(u16, String);
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.1)
+    }
+}
+impl std::error::Error for Status {}
+
+fn status(code: u16, err: impl std::fmt::Display) -> anyhow::Error {
+    Status(code, err.to_string()).into()
+}
+
+struct State<'a> {
+    db: RwLock<Database>,
+    key: DatabaseKey,
+    db_path: &'a Path,
+    token: Zeroizing<String>,
+    last_active: Mutex<Instant>,
+    clients: AtomicUsize,
+}
+impl State<'_> {
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_active.lock().unwrap().elapsed()
+    }
+}
+
+/// Path to the file with the server's token, next to the agent's socket
+/// in a directory only the user can access (see `agent::socket_path`).
+pub fn token_path() -> PathBuf {
+    agent::socket_path().with_file_name("api.token")
+}
+
+/// Serve the API on `listen` until it has been idle for `idle_timeout`.
+/// The database must be locked (see `lock_db`), as entries can be created.
+pub fn serve(
+    db: Database,
+    key: DatabaseKey,
+    db_path: &Path,
+    listen: &str,
+    idle_timeout: Duration,
+) -> Result<()> {
+    secret::lock_all();
+
+    let addr: SocketAddr = listen
+        .parse()
+        .map_err(|_| anyhow!("Invalid address to listen on: {}", listen))?;
+    if !addr.ip().is_loopback() {
+        warn!("{}", tr!("api-not-local", address = addr.ip()));
+    }
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+
+    let token = Zeroizing::new(hex::encode(rand::random::<[u8; 32]>()));
+    let path = token_path();
+    if let Some(dir) = path.parent() {
+        crate::private_dir(dir)?;
+    }
+    let _ = fs::remove_file(&path);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?
+        .write_all(token.as_bytes())?;
+    info!("{}", tr!("api-listening", address = addr));
+    info!("{}", tr!("api-token-in", path = path.display()));
+
+    let state = State {
+        db: RwLock::new(db),
+        key,
+        db_path,
+        token,
+        last_active: Mutex::new(Instant::now()),
+        clients: AtomicUsize::new(0),
+    };
+    let result = thread::scope(|scope| loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                state.touch();
+                if state.clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                    state.clients.fetch_sub(1, Ordering::SeqCst);
+                    let _ = respond(&stream, 503, &json!({ "error": "Server busy" }));
+                    continue;
+                }
+                let state = &state;
+                scope.spawn(move || {
+                    if let Err(err) = handle(state, stream) {
+                        warn!("{}", tr!("request-failed", error = err));
+                    }
+                    state.clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                let connected = state.clients.load(Ordering::SeqCst) > 0;
+                if !connected && state.idle_for() >= idle_timeout {
+                    info!("{}", tr!("api-idle"));
+                    notify::notify("kpass locked", "The API was idle and has shut down.");
+                    break Ok(());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) => break Err(err),
+        }
+    });
+
+    fs::remove_file(&path)?;
+    Ok(result?)
+}
+
+/// An HTTP request, as far as the API needs it.
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    token: Option<Zeroizing<String>>,
+    body: Zeroizing<Vec<u8>>,
+}
+
+/// Read a line of the request line and headers, refusing them
+/// once they're larger than `MAX_HEAD`.
+fn read_head_line(head: &mut Take<&mut BufReader<&TcpStream>>, line: &mut String) -> Result<usize> {
+    let len = head.read_line(line)?;
+    if head.limit() == 0 && !line.ends_with('\n') {
+        return Err(status(431, "Request headers too large"));
+    }
+    Ok(len)
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    // Read before authenticating, so capped.
+    let mut head = (&mut reader).take(MAX_HEAD);
+    let mut line = String::new();
+    read_head_line(&mut head, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(status(400, "Malformed request"));
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        token: None,
+        body: Zeroizing::new(vec![]),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut header = Zeroizing::new(String::new());
+        if read_head_line(&mut head, &mut header)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(status(400, "Malformed header"));
+        };
+        let value = value.trim();
+        match name.to_lowercase().as_str() {
+            "content-length" => {
+                content_length = value
+                    .parse()
+                    .map_err(|_| status(400, "Invalid Content-Length"))?
+            }
+            "authorization" => {
+                request.token = value
+                    .strip_prefix("Bearer ")
+                    .map(|token| Zeroizing::new(token.trim().to_string()))
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(status(413, "Request body too large"));
+    }
+    request.body = Zeroizing::new(vec![0; content_length]);
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+fn respond(mut stream: &TcpStream, code: u16, body: &Json) -> Result<()> {
+    let reason = match code {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let body = Zeroizing::new(serde_json::to_vec(body)?);
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code,
+        reason,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+/// Answer one request per connection.
+fn handle(state: &State, stream: TcpStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let response = read_request(&stream).and_then(|request| {
+        let authorized = request
+            .token
+            .as_ref()
+            .is_some_and(|token| bool::from(token.as_bytes().ct_eq(state.token.as_bytes())));
+        if !authorized {
+            return Err(status(401, "Missing or wrong token"));
+        }
+        answer(state, &request)
+    });
+    match response {
+        Ok((code, body)) => respond(&stream, code, &body),
+        Err(err) => {
+            let code = err.downcast_ref::<Status>().map_or(500, |status| status.0);
+            respond(&stream, code, &json!({ "error": err.to_string() }))
+        }
+    }
+}
+
+fn answer(state: &State, request: &Request) -> Result<(u16, Json)> {
+    let route = request.path.trim_end_matches('/');
+    match (request.method.as_str(), route) {
+        ("GET", "/entries") => {
+            let query = request
+                .query
+                .as_deref()
+                .and_then(|query| param(query, "q"))
+                .transpose()?;
+            let db = state
+                .db
+                .read()
+                .map_err(|_| anyhow!("Database lock poisoned"))?;
+            Ok((200, json!(list(&db, query.as_deref()))))
+        }
+        ("GET", _) if route.starts_with("/entries/") => {
+            let query = percent_decode(&route["/entries/".len()..])?;
+            let db = state
+                .db
+                .read()
+                .map_err(|_| anyhow!("Database lock poisoned"))?;
+            let entry = address::find_entry(&db, &query).map_err(|err| status(404, err))?;
+            if protect::is_required(entry) {
+                return Err(status(
+                    403,
+                    "Entry requires the master password; open it in kpass instead",
+                ));
+            }
+            let title = entry.get_title().unwrap_or("(no title)");
+            if let Err(err) = activity::record(state.db_path, "fetched password", title) {
+                warn!("{}", tr!("activity-record-failed", error = err));
+            }
+            if let Err(err) = usage::record(state.db_path, entry.get_uuid()) {
+                warn!("{}", tr!("usage-record-failed", error = err));
+            }
+            Ok((200, details(&reference::resolved(&db, entry))))
+        }
+        ("POST", "/entries") => {
+            let spec: apply::EntrySpec =
+                serde_json::from_slice(&request.body).map_err(|err| status(400, err))?;
+            let mut db = state
+                .db
+                .write()
+                .map_err(|_| anyhow!("Database lock poisoned"))?;
+            // Only keep the entry once it's saved.
+            let mut updated = db.clone();
+            let change = apply::create(&mut updated, &spec).map_err(|err| status(409, err))?;
+            if let Err(err) = save_db_unattended(&mut updated, &state.key, state.db_path) {
+                if !matches!(err.downcast_ref::<Error>(), Some(Error::Changed)) {
+                    return Err(err);
+                }
+                // Load the other changes, so the request can be retried on top of them.
+                *db = reopen(state)?;
+                return Err(status(409, format!("{} Reloaded it; try again.", err)));
+            }
+            *db = updated;
+            let entry = address::find_entry(&db, &format!("/{}", change.path))?;
+            Ok((201, summary(&change.path, entry)))
+        }
+        (_, "/entries") => Err(status(405, "Method not allowed")),
+        _ => Err(status(404, "Not found")),
+    }
+}
+
+/// The database as it is on disk now.
+fn reopen(state: &State) -> Result<Database> {
+    let db = Database::open(&mut File::open(state.db_path)?, state.key.clone())
+        .map_err(|err| anyhow!("Couldn't reload the database: {:?}", err))?;
+    record_opened(state.db_path)?;
+    changelog::snapshot(state.db_path, &db);
+    Ok(db)
+}
+
+/// An entry without its secrets.
+fn summary(path: &str, entry: &KEntry) -> Json {
+    json!({
+        "uuid": entry.uuid.to_string(),
+        "path": path,
+        "title": entry.get_title().unwrap_or(""),
+        "username": entry.get_username().unwrap_or(""),
+        "url": entry.get_url().unwrap_or(""),
+    })
+}
+
+/// An entry with all its fields, and its current OTP code if it has one.
+fn details(entry: &KEntry) -> Json {
+    let fields: serde_json::Map<_, _> = entry
+        .fields
+        .keys()
+        .filter_map(|name| Some((name.clone(), entry.get(name)?.into())))
+        .collect();
+    let otp = entry
+        .get_raw_otp_value()
+        .and_then(|uri| Totp::parse(uri).ok()?.code_now().ok());
+    json!({
+        "uuid": entry.uuid.to_string(),
+        "title": entry.get_title().unwrap_or(""),
+        "username": entry.get_username().unwrap_or(""),
+        "password": entry.get_password().unwrap_or(""),
+        "url": entry.get_url().unwrap_or(""),
+        "otp": otp,
+        "tags": entry.tags,
+        "fields": fields,
+    })
+}
+
+/// All entries, or those whose title, path, username or URL contain `query`.
+fn list(db: &Database, query: Option<&str>) -> Vec<Json> {
+    let query = query.map(str::to_lowercase);
+    entries_with_paths(&db.root)
+        .into_iter()
+        .filter_map(|(mut path, entry)| {
+            path.push(entry.get_title().unwrap_or("").to_string());
+            let path = address::format(&path);
+            let matches = query.as_ref().is_none_or(|query| {
+                [Some(path.as_str()), entry.get_username(), entry.get_url()]
+                    .into_iter()
+                    .flatten()
+                    .any(|text| text.to_lowercase().contains(query))
+            });
+            matches.then(|| summary(&path, entry))
+        })
+        .collect()
+}
+
+/// The value of a parameter in a query string.
+fn param(query: &str, name: &str) -> Option<Result<String>> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| percent_decode(&value.replace('+', " ")))
+    })
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| status(400, "Invalid percent-encoding"))?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| status(400, "Invalid UTF-8 in the URL"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send `data` to the server and read the request it gets.
+    fn read_sent(data: Vec<u8>) -> Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            // The server may stop reading early.
+            let _ = stream.write_all(&data);
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let request = read_request(&stream);
+        drop(stream);
+        client.join().unwrap();
+        request
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = read_sent(
+            b"POST /entries?q=x HTTP/1.1\r\nAuthorization: Bearer abc\r\nContent-Length: 2\r\n\r\n{}"
+                .to_vec(),
+        )
+        .unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/entries")
+        );
+        assert_eq!(request.query.as_deref(), Some("q=x"));
+        assert_eq!(request.token.as_deref().map(String::as_str), Some("abc"));
+        assert_eq!(*request.body, b"{}");
+    }
+
+    #[test]
+    fn refuses_large_headers() {
+        let mut data = b"GET /entries HTTP/1.1\r\nX-Padding: ".to_vec();
+        data.extend(vec![b'a'; MAX_HEAD as usize]);
+        data.extend(b"\r\n\r\n");
+        let err = read_sent(data).err().unwrap();
+        assert_eq!(err.downcast_ref::<Status>().unwrap().0, 431);
+
+        let line = [b"GET /".as_slice(), &[b'a'; MAX_HEAD as usize]].concat();
+        let err = read_sent(line).err().unwrap();
+        assert_eq!(err.downcast_ref::<Status>().unwrap().0, 431);
+    }
+
+    #[test]
+    fn percent_decode_decodes_escapes() {
+        assert_eq!(percent_decode("Work%2FGitHub").unwrap(), "Work/GitHub");
+        assert_eq!(percent_decode("a%20b%2fc").unwrap(), "a b/c");
+        assert_eq!(percent_decode("plain").unwrap(), "plain");
+        assert_eq!(percent_decode("caf%C3%A9").unwrap(), "café");
+    }
+
+    #[test]
+    fn percent_decode_rejects_bad_input() {
+        for text in ["%", "%2", "%zz", "a%g1", "%FF"] {
+            let err = percent_decode(text).unwrap_err();
+            assert_eq!(err.downcast_ref::<Status>().unwrap().0, 400, "{}", text);
+        }
+    }
+
+    #[test]
+    fn param_finds_and_decodes() {
+        let query = "x=1&q=git+hub%21&empty";
+        assert_eq!(param(query, "q").unwrap().unwrap(), "git hub!");
+        assert_eq!(param(query, "empty").unwrap().unwrap(), "");
+        assert!(param(query, "missing").is_none());
+    }
+}
@@ -0,0 +1,349 @@
+//! Creating or updating many entries at once from a spec file
+//! (`kpass apply`), e.g. to provision service accounts.
+//!
+//! Entries are matched by group path and title. Fields the spec
+//! leaves out are left as they are, so applying a spec again
+//! changes nothing.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use keepass::{
+    db::{Entry as KEntry, Node, Times, Value},
+    Database,
+};
+use serde::Deserialize;
+
+use crate::{address, error::Error, generate, import::get_or_create_group};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default, rename = "entry")]
+    entries: Vec<EntrySpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntrySpec {
+    title: String,
+    /// Group path, e.g. `Work/Service accounts` (see `address`).
+    #[serde(default)]
+    group: String,
+    username: Option<String>,
+    url: Option<String>,
+    notes: Option<String>,
+    password: Option<String>,
+    /// Generate a password if the entry doesn't have one yet.
+    #[serde(default)]
+    generate_password: bool,
+    password_length: Option<usize>,
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+impl EntrySpec {
+    /// The entry's address (see `address`).
+    fn path(&self) -> String {
+        let mut path = address::split(&self.group);
+        path.push(self.title.clone());
+        address::format(&path)
+    }
+}
+
+/// A change to one entry, for the preview.
+pub struct Change {
+    pub path: String,
+    pub added: bool,
+    /// What changed, e.g. `username: old → new`.
+    pub fields: Vec<String>,
+}
+
+/// Read the entry specs from a TOML file.
+pub fn read(path: &Path) -> Result<Vec<EntrySpec>> {
+    let spec: Spec = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|err| anyhow!("Invalid spec {}: {}", path.display(), err))?;
+    Ok(spec.entries)
+}
+
+/// Set a field, describing the change if there is one. Secret fields'
+/// values aren't shown.
+fn set(entry: &mut KEntry, field: &str, label: &str, value: Value, changes: &mut Vec<String>) {
+    let secret = matches!(value, Value::Protected(_));
+    let show = |value: Option<&str>| match value {
+        _ if secret => "***".to_string(),
+        Some(value) => format!("{:?}", value),
+        None => "(none)".to_string(),
+    };
+    let old = entry.get(field).map(str::to_string);
+    let new = match &value {
+        Value::Unprotected(value) => Some(value.clone()),
+        Value::Protected(value) => Some(String::from_utf8_lossy(value.unsecure()).into_owned()),
+        _ => None,
+    };
+    if old == new {
+        return;
+    }
+    changes.push(match old {
+        None => format!("{}: {}", label, show(new.as_deref())),
+        Some(old) => format!("{}: {} → {}", label, show(Some(&old)), show(new.as_deref())),
+    });
+    entry.fields.insert(field.to_string(), value);
+}
+
+/// Apply a spec to one entry, returning what changed.
+fn apply_entry(entry: &mut KEntry, spec: &EntrySpec) -> Result<Vec<String>> {
+    let mut changes = vec![];
+    let unprotected = |value: &str| Value::Unprotected(value.to_string());
+    let protected = |value: &str| Value::Protected(value.as_bytes().into());
+    // Entries are found by title, so it's only set on new ones.
+    set(
+        entry,
+        "Title",
+        "title",
+        unprotected(&spec.title),
+        &mut vec![],
+    );
+    if let Some(username) = &spec.username {
+        set(
+            entry,
+            "UserName",
+            "username",
+            unprotected(username),
+            &mut changes,
+        );
+    }
+    if let Some(url) = &spec.url {
+        set(entry, "URL", "URL", unprotected(url), &mut changes);
+    }
+    if let Some(notes) = &spec.notes {
+        set(entry, "Notes", "notes", unprotected(notes), &mut changes);
+    }
+    if let Some(password) = &spec.password {
+        set(
+            entry,
+            "Password",
+            "password",
+            protected(password),
+            &mut changes,
+        );
+    } else if spec.generate_password && entry.get_password().unwrap_or("").is_empty() {
+        let mut pg = generate::default_generator();
+        if let Some(length) = spec.password_length {
+            pg.length = length;
+        }
+        let password = pg.generate_one().map_err(Error::Generator)?;
+        entry
+            .fields
+            .insert("Password".to_string(), protected(&password));
+        changes.push("password: (generated)".to_string());
+    }
+    if let Some(tags) = &spec.tags {
+        if entry.tags != *tags {
+            changes.push(format!("tags: {}", tags.join(", ")));
+            entry.tags = tags.clone();
+        }
+    }
+    for (name, value) in &spec.fields {
+        set(entry, name, name, unprotected(value), &mut changes);
+    }
+    Ok(changes)
+}
+
+/// Apply the entry specs to the database, returning the changes.
+pub fn apply(db: &mut Database, specs: &[EntrySpec]) -> Result<Vec<Change>> {
+    let mut changes = vec![];
+    for spec in specs {
+        let group_path = address::split(&spec.group);
+        let path = spec.path();
+        let group = get_or_create_group(&mut db.root, &group_path);
+        let mut matches = group.children.iter_mut().filter_map(|node| match node {
+            Node::Entry(entry) if entry.get_title() == Some(spec.title.as_str()) => Some(entry),
+            _ => None,
+        });
+        let existing = matches.next();
+        if matches.next().is_some() {
+            return Err(anyhow!("Multiple entries are at {}", path));
+        }
+
+        match existing {
+            Some(entry) => {
+                let before = entry.clone();
+                let fields = apply_entry(entry, spec)?;
+                if !fields.is_empty() {
+                    // Keep the previous version, as when editing.
+                    entry
+                        .history
+                        .get_or_insert_with(Default::default)
+                        .add_entry(before);
+                    entry.times.set_last_modification(Times::now());
+                    changes.push(Change {
+                        path,
+                        added: false,
+                        fields,
+                    });
+                }
+            }
+            None => {
+                let mut entry = KEntry::new();
+                let fields = apply_entry(&mut entry, spec)?;
+                group.add_child(entry);
+                changes.push(Change {
+                    path,
+                    added: true,
+                    fields,
+                });
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Create the entry a spec describes, failing if there's one at its path already.
+pub fn create(db: &mut Database, spec: &EntrySpec) -> Result<Change> {
+    let mut updated = db.clone();
+    match apply(&mut updated, std::slice::from_ref(spec))?.pop() {
+        Some(change) if change.added => {
+            *db = updated;
+            Ok(change)
+        }
+        _ => Err(anyhow!("An entry already exists at {}", spec.path())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries_with_paths;
+
+    fn specs(toml: &str) -> Vec<EntrySpec> {
+        toml::from_str::<Spec>(toml).unwrap().entries
+    }
+
+    const SPEC: &str = r#"
+        [[entry]]
+        title = "deploy"
+        group = "Work/Service accounts"
+        username = "deploy-bot"
+        password = "hunter2"
+        tags = ["ci"]
+        fields = { Region = "eu-west-1" }
+
+        [[entry]]
+        title = "backup"
+        group = "Work/Service accounts"
+        generate_password = true
+        password_length = 40
+    "#;
+
+    #[test]
+    fn reads_specs() {
+        let path = crate::import::temp_file("spec.toml", SPEC);
+        let specs = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].path(), "Work/Service accounts/deploy");
+        assert!(toml::from_str::<Spec>("[[entry]]\ntitle = \"x\"\npasword = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn creates_entries() {
+        let mut db = Database::new(Default::default());
+        let changes = apply(&mut db, &specs(SPEC)).unwrap();
+        assert!(changes.iter().all(|change| change.added));
+        assert_eq!(
+            changes[0].fields,
+            [
+                "username: \"deploy-bot\"",
+                "password: ***",
+                "tags: ci",
+                "Region: \"eu-west-1\""
+            ]
+        );
+        assert_eq!(changes[1].fields, ["password: (generated)"]);
+
+        let entries = entries_with_paths(&db.root);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, ["Work", "Service accounts"]);
+        let (deploy, backup) = (entries[0].1, entries[1].1);
+        assert_eq!(deploy.get_password(), Some("hunter2"));
+        assert_eq!(deploy.get("Region"), Some("eu-west-1"));
+        assert_eq!(backup.get_password().unwrap().len(), 40);
+    }
+
+    #[test]
+    fn applying_again_changes_nothing() {
+        let mut db = Database::new(Default::default());
+        apply(&mut db, &specs(SPEC)).unwrap();
+        let generated = entries_with_paths(&db.root)[1]
+            .1
+            .get_password()
+            .map(String::from);
+        assert!(apply(&mut db, &specs(SPEC)).unwrap().is_empty());
+        // Generated passwords are kept.
+        assert_eq!(
+            entries_with_paths(&db.root)[1]
+                .1
+                .get_password()
+                .map(String::from),
+            generated
+        );
+    }
+
+    #[test]
+    fn updates_keep_history() {
+        let mut db = Database::new(Default::default());
+        apply(&mut db, &specs(SPEC)).unwrap();
+        let updated = specs(
+            r#"
+            [[entry]]
+            title = "deploy"
+            group = "Work/Service accounts"
+            username = "deployer"
+            password = "hunter3"
+            "#,
+        );
+        let changes = apply(&mut db, &updated).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].added);
+        assert_eq!(
+            changes[0].fields,
+            [
+                "username: \"deploy-bot\" → \"deployer\"",
+                "password: *** → ***"
+            ]
+        );
+        let deploy = entries_with_paths(&db.root)[0].1;
+        // Left out of the spec, so left as it was.
+        assert_eq!(deploy.get("Region"), Some("eu-west-1"));
+        let history = deploy.history.as_ref().unwrap().get_entries();
+        assert_eq!(history[0].get_password(), Some("hunter2"));
+    }
+
+    #[test]
+    fn refuses_ambiguous_entries() {
+        let mut db = Database::new(Default::default());
+        let spec = specs("[[entry]]\ntitle = \"deploy\"");
+        let mut entry = KEntry::new();
+        entry.fields.insert(
+            "Title".to_string(),
+            Value::Unprotected("deploy".to_string()),
+        );
+        db.root.add_child(entry.clone());
+        entry.uuid = uuid::Uuid::new_v4();
+        db.root.add_child(entry);
+        assert!(apply(&mut db, &spec).is_err());
+    }
+
+    #[test]
+    fn creates_only_new_entries() {
+        let mut db = Database::new(Default::default());
+        let spec = &specs(SPEC)[0];
+        let change = create(&mut db, spec).unwrap();
+        assert!(change.added);
+        assert_eq!(change.path, "Work/Service accounts/deploy");
+        assert!(create(&mut db, spec).is_err());
+        assert_eq!(entries_with_paths(&db.root).len(), 1);
+    }
+}
@@ -0,0 +1,511 @@
+//! Checks over the whole database that flag entries needing attention.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use inquire::Confirm;
+use keepass::{db::Entry as KEntry, Database};
+use serde::Deserialize;
+
+use crate::{
+    config, edit_entry, entries, entries_with_paths, hibp, index::Index, otp::Totp, sandbox,
+    strength::Strength, theme, Entry,
+};
+
+/// Tag added to entries whose URL no longer resolves.
+pub const DEAD_URL_TAG: &str = "dead-url";
+
+const URL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Check whether a URL still resolves. Only a `HEAD` request is made,
+/// without any credentials. Any HTTP response (even an error status)
+/// means the service still exists.
+fn check_url(agent: &ureq::Agent, url: &str) -> Result<(), String> {
+    match agent.head(url).call() {
+        Ok(_) | Err(ureq::Error::Status(..)) => Ok(()),
+        Err(ureq::Error::Transport(err)) => Err(err.to_string()),
+    }
+}
+
+/// Check every entry's URL, offering to tag the ones that
+/// no longer resolve as candidates for archiving.
+/// Returns whether the database was modified.
+pub fn check_urls(db: &mut Database) -> Result<bool> {
+    let mut by_url: HashMap<String, Vec<uuid::Uuid>> = HashMap::new();
+    for entry in entries(db) {
+        if let Some(url) = entry.get_url().filter(|u| u.starts_with("http")) {
+            by_url.entry(url.to_string()).or_default().push(entry.uuid);
+        }
+    }
+    if by_url.is_empty() {
+        info!("{}", tr!("no-urls"));
+        return Ok(false);
+    }
+    sandbox::needs_network("Checking URLs")?;
+
+    warn!("{}", tr!("url-check-requests", count = by_url.len()));
+    warn!("{}", tr!("url-check-no-credentials"));
+    let proceed = Confirm::new(&tr!("continue"))
+        .with_default(false)
+        .prompt()?;
+    if !proceed {
+        return Ok(false);
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(URL_TIMEOUT)
+        .redirects(5)
+        .build();
+    let mut dead = vec![];
+    for (url, uuids) in by_url {
+        if let Err(err) = check_url(&agent, &url) {
+            warn!("{}: {}", url, err);
+            dead.extend(uuids);
+        }
+    }
+    if dead.is_empty() {
+        info!("{}", tr!("urls-resolved"));
+        return Ok(false);
+    }
+
+    info!("{}", tr!("dead-urls", count = dead.len()));
+    let tag = Confirm::new(&tr!("tag-them", tag = DEAD_URL_TAG))
+        .with_default(true)
+        .prompt()?;
+    if !tag {
+        return Ok(false);
+    }
+    let index = Index::build(db);
+    for uuid in dead {
+        let entry = index.entry_mut(db, &uuid).expect("Entry exists");
+        if !entry.tags.iter().any(|t| t == DEAD_URL_TAG) {
+            entry.tags.push(DEAD_URL_TAG.to_string());
+        }
+    }
+    Ok(true)
+}
+
+/// Path of the entry, e.g. `Work/Servers/db1`.
+fn entry_path(group: &[String], entry: &KEntry) -> String {
+    let mut path = group.to_vec();
+    path.push(Entry(entry).to_string());
+    path.join("/")
+}
+
+/// Check for passwords shared by several entries, shorter than the configured
+/// minimum, or very weak, offering to edit each offending entry.
+/// Returns whether the database was modified.
+pub fn check_passwords(db: &mut Database) -> Result<bool> {
+    let min_length = config::get().min_password_length;
+    let mut by_password: HashMap<&str, Vec<(uuid::Uuid, String)>> = HashMap::new();
+    let mut short = vec![];
+    let mut weak = vec![];
+    for (group, entry) in entries_with_paths(&db.root) {
+        let Some(password) = entry.get_password().filter(|p| !p.is_empty()) else {
+            continue;
+        };
+        let path = entry_path(&group, entry);
+        let len = password.chars().count();
+        if len < min_length {
+            short.push((entry.uuid, format!("{}: {} chars", path, len)));
+        }
+        let strength = Strength::of(password);
+        if strength.is_very_weak() {
+            weak.push((entry.uuid, format!("{}: {}", path, strength)));
+        }
+        by_password
+            .entry(password)
+            .or_default()
+            .push((entry.uuid, path));
+    }
+    let mut reused: Vec<_> = by_password
+        .into_values()
+        .filter(|entries| entries.len() > 1)
+        .collect();
+    reused.sort_by_key(|entries| Reverse(entries.len()));
+
+    let mut offenders = vec![];
+    let mut add_offender = |uuid: uuid::Uuid| {
+        if !offenders.contains(&uuid) {
+            offenders.push(uuid);
+        }
+    };
+    if !reused.is_empty() {
+        println!("-- reused --");
+        for entries in &reused {
+            warn!("{}", tr!("same-password", count = entries.len()));
+            for (uuid, path) in entries {
+                warn!("  {}", path);
+                add_offender(*uuid);
+            }
+        }
+    }
+    for (heading, found) in [
+        (format!("shorter than {}", min_length), &short),
+        ("weak".into(), &weak),
+    ] {
+        if found.is_empty() {
+            continue;
+        }
+        println!("-- {} --", heading);
+        for (uuid, message) in found {
+            warn!("{}", message);
+            add_offender(*uuid);
+        }
+    }
+    if offenders.is_empty() {
+        println!("{}", theme::good("> No problems found."));
+        return Ok(false);
+    }
+
+    info!("{}", tr!("need-attention", count = offenders.len()));
+    let edit = Confirm::new(&tr!("edit-them-now"))
+        .with_default(false)
+        .prompt()?;
+    if !edit {
+        return Ok(false);
+    }
+    let index = Index::build(db);
+    for uuid in offenders {
+        let entry = index.entry_mut(db, &uuid).expect("Entry exists");
+        println!("-- {} --", Entry(entry));
+        edit_entry(entry)?;
+    }
+    Ok(true)
+}
+
+/// Check every password against Have I Been Pwned: the online API
+/// (after confirmation), or a downloaded `corpus` of hashes.
+/// Returns `None` if the user declines the online check.
+pub fn check_breaches(
+    db: &Database,
+    db_path: &Path,
+    corpus: Option<&Path>,
+) -> Result<Option<Vec<Finding>>> {
+    let hashed: Vec<(String, String, String)> = entries_with_paths(&db.root)
+        .into_iter()
+        .filter_map(|(group, entry)| {
+            let password = entry.get_password().filter(|p| !p.is_empty())?;
+            Some((
+                entry_path(&group, entry),
+                entry.uuid.to_string(),
+                hibp::sha1_hex(password),
+            ))
+        })
+        .collect();
+    let hashes: HashSet<String> = hashed.iter().map(|(_, _, hash)| hash.clone()).collect();
+
+    let seen = match corpus {
+        Some(corpus) => {
+            info!("{}", tr!("searching", path = corpus.display()));
+            hibp::offline(corpus, &hashes)?
+        }
+        None => {
+            sandbox::needs_network("Checking passwords online")?;
+            warn!("{}", tr!("hibp-sends-prefixes", api = hibp::RANGE_API));
+            warn!("{}", tr!("hibp-keeps-passwords"));
+            let proceed = Confirm::new(&tr!("continue"))
+                .with_default(false)
+                .prompt()?;
+            if !proceed {
+                return Ok(None);
+            }
+            hibp::online(db_path, &hashes)?
+        }
+    };
+
+    // Kept for flagging breached entries in the picker.
+    hibp::save_breached(
+        db_path,
+        hashed
+            .iter()
+            .filter(|(_, _, hash)| seen.contains_key(hash))
+            .map(|(_, uuid, _)| uuid.clone())
+            .collect(),
+    )?;
+
+    let findings = hashed
+        .into_iter()
+        .filter_map(|(entry, _, hash)| {
+            let count = seen.get(&hash)?;
+            Some(Finding {
+                rule: "breached".to_string(),
+                entry,
+                message: format!("Password seen {} times in data breaches", count),
+            })
+        })
+        .collect();
+    Ok(Some(findings))
+}
+
+/// A problem found by a rule.
+pub struct Finding {
+    pub rule: String,
+    /// Path of the entry, e.g. `Work/Servers/db1`.
+    pub entry: String,
+    pub message: String,
+}
+
+/// A check applied to every entry.
+pub trait Rule {
+    fn name(&self) -> &str;
+
+    /// Check an entry (in the group at `group`), describing the problem if there is one.
+    fn check(&self, entry: &KEntry, group: &[String]) -> Option<String>;
+}
+
+struct MissingPassword;
+impl Rule for MissingPassword {
+    fn name(&self) -> &str {
+        "missing-password"
+    }
+
+    fn check(&self, entry: &KEntry, _group: &[String]) -> Option<String> {
+        let missing = entry.get_password().unwrap_or("").is_empty();
+        missing.then(|| "No password".to_string())
+    }
+}
+
+struct Expired;
+impl Rule for Expired {
+    fn name(&self) -> &str {
+        "expired"
+    }
+
+    fn check(&self, entry: &KEntry, _group: &[String]) -> Option<String> {
+        let expiry = entry.times.get_expiry().filter(|_| entry.times.expires)?;
+        (*expiry < Utc::now().naive_utc()).then(|| format!("Expired on {}", expiry.date()))
+    }
+}
+
+/// OTP settings that can't generate (correct) codes,
+/// e.g. a corrupted secret or an unsupported algorithm.
+pub struct OtpSettings;
+impl Rule for OtpSettings {
+    fn name(&self) -> &str {
+        "otp"
+    }
+
+    fn check(&self, entry: &KEntry, _group: &[String]) -> Option<String> {
+        let Some(uri) = entry.get_raw_otp_value() else {
+            // KeePass 2 keeps TOTP settings in separate fields instead.
+            let keepass_fields = entry.fields.keys().any(|k| k.starts_with("TimeOtp-"));
+            return keepass_fields
+                .then(|| "TOTP is in KeePass' TimeOtp-* fields, which kpass doesn't read".into());
+        };
+        match Totp::parse(uri).and_then(|totp| totp.check()) {
+            Ok(()) => None,
+            Err(err) => Some(err.to_string()),
+        }
+    }
+}
+
+/// A rule defined in the config, e.g.:
+///
+/// ```toml
+/// [[audit_rules]]
+/// name = "finance-rotation"
+/// group = "Finance"
+/// max_age_days = 180
+/// ```
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RuleConfig {
+    pub name: String,
+
+    /// Only check entries in this group (or its subgroups), e.g. `Work/Corp`.
+    pub group: Option<String>,
+
+    /// Only check entries with this tag.
+    pub tag: Option<String>,
+
+    /// Minimum password length.
+    pub min_length: Option<usize>,
+
+    /// Tags entries must have.
+    pub require_tags: Vec<String>,
+
+    /// Maximum days since the password was last changed.
+    pub max_age_days: Option<i64>,
+}
+impl RuleConfig {
+    pub(crate) fn applies(&self, entry: &KEntry, group: &[String]) -> bool {
+        let in_group = self.group.as_ref().is_none_or(|wanted| {
+            let wanted: Vec<_> = wanted.split('/').filter(|p| !p.is_empty()).collect();
+            wanted.len() <= group.len()
+                && wanted
+                    .iter()
+                    .zip(group)
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b))
+        });
+        let has_tag = self.tag.as_ref().is_none_or(|tag| entry.tags.contains(tag));
+        in_group && has_tag
+    }
+}
+impl Rule for RuleConfig {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn check(&self, entry: &KEntry, group: &[String]) -> Option<String> {
+        if !self.applies(entry, group) {
+            return None;
+        }
+
+        let mut problems = vec![];
+        let password = entry.get_password().unwrap_or("");
+        if let Some(min) = self.min_length {
+            let len = password.chars().count();
+            if len < min {
+                problems.push(format!("Password has {} chars, needs {}", len, min));
+            }
+        }
+        for tag in &self.require_tags {
+            if !entry.tags.contains(tag) {
+                problems.push(format!("Missing tag '{}'", tag));
+            }
+        }
+        if let (Some(max), Some(changed)) = (self.max_age_days, password_changed(entry)) {
+            let age = (Utc::now().naive_utc() - changed).num_days();
+            if age > max {
+                problems.push(format!("Password is {} days old, max is {}", age, max));
+            }
+        }
+        Some(problems.join("; ")).filter(|p| !p.is_empty())
+    }
+}
+
+/// When the entry's password was last changed, going by its history.
+pub(crate) fn password_changed(entry: &KEntry) -> Option<NaiveDateTime> {
+    let password = entry.get_password();
+    let mut changed = entry.times.get_last_modification();
+    if let Some(history) = &entry.history {
+        // Histories are in file order, which differs between clients.
+        let mut revisions: Vec<&KEntry> = history.get_entries().iter().collect();
+        revisions.sort_by_key(|old| Reverse(old.times.get_last_modification()));
+        for old in revisions {
+            if old.get_password() != password {
+                break;
+            }
+            changed = old.times.get_last_modification().or(changed);
+        }
+    }
+    changed.copied()
+}
+
+/// The built-in rules followed by those defined in the config.
+pub fn rules() -> Vec<Box<dyn Rule>> {
+    let mut rules: Vec<Box<dyn Rule>> = vec![Box::new(MissingPassword), Box::new(Expired)];
+    for rule in &config::get().audit_rules {
+        rules.push(Box::new(rule.clone()));
+    }
+    rules
+}
+
+/// Check every entry against the rules.
+pub fn run_rules(db: &Database, rules: &[Box<dyn Rule>]) -> Vec<Finding> {
+    let mut findings = vec![];
+    for (group, entry) in entries_with_paths(&db.root) {
+        for rule in rules {
+            if let Some(message) = rule.check(entry, &group) {
+                findings.push(Finding {
+                    rule: rule.name().to_string(),
+                    entry: entry_path(&group, entry),
+                    message,
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Print the findings, grouped by rule.
+pub fn print_report(findings: &[Finding]) {
+    if findings.is_empty() {
+        println!("{}", theme::good("> No problems found."));
+        return;
+    }
+    let mut rules: Vec<&str> = vec![];
+    for finding in findings {
+        if !rules.contains(&finding.rule.as_str()) {
+            rules.push(&finding.rule);
+        }
+    }
+    for rule in rules {
+        println!("{}", theme::heading(&format!("-- {} --", rule)));
+        for finding in findings.iter().filter(|f| f.rule == rule) {
+            warn!("{}: {}", finding.entry, finding.message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::{History, Times};
+
+    use super::*;
+    use crate::test_util;
+
+    fn revision(password: &str, days_ago: i64) -> KEntry {
+        let mut entry = test_util::entry(&[("Password", password)]);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    /// An entry with the password, and `revisions` in that order.
+    fn with_history(password: &str, revisions: Vec<KEntry>) -> KEntry {
+        let mut history = History::default();
+        for old in revisions.into_iter().rev() {
+            history.add_entry(old);
+        }
+        let mut entry = revision(password, 0);
+        entry.history = Some(history);
+        entry
+    }
+
+    fn days_ago(time: NaiveDateTime) -> i64 {
+        (Utc::now().naive_utc() - time).num_days()
+    }
+
+    #[test]
+    fn password_changed_with_oldest_first_history() {
+        // Changed 20 days ago, then other fields were edited.
+        let entry = with_history(
+            "new",
+            vec![
+                revision("old", 50),
+                revision("new", 20),
+                revision("new", 10),
+            ],
+        );
+        assert_eq!(days_ago(password_changed(&entry).unwrap()), 20);
+    }
+
+    #[test]
+    fn password_changed_with_newest_first_history() {
+        let entry = with_history(
+            "new",
+            vec![
+                revision("new", 10),
+                revision("new", 20),
+                revision("old", 50),
+            ],
+        );
+        assert_eq!(days_ago(password_changed(&entry).unwrap()), 20);
+    }
+
+    #[test]
+    fn password_changed_without_history() {
+        let entry = revision("pw", 3);
+        assert_eq!(days_ago(password_changed(&entry).unwrap()), 3);
+    }
+}
@@ -0,0 +1,811 @@
+//! Type credentials into the focused window with a
+//! virtual keyboard (`wtype`, falling back to `ydotool`).
+//!
+//! What gets typed is described by KeePass auto-type sequences,
+//! e.g. `{USERNAME}{TAB}{PASSWORD}{ENTER}`.
+
+use std::{
+    env,
+    fmt::{Display, Formatter},
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use keepass::db::{Entry as KEntry, Value};
+use rand::Rng;
+use serde_json::Value as Json;
+use zeroize::Zeroize;
+
+use crate::{config, otp::Totp};
+
+/// Used when neither the entry nor its group specify a sequence.
+pub const DEFAULT_SEQUENCE: &str = "{USERNAME}{TAB}{PASSWORD}{ENTER}";
+
+/// Custom data keys for per-entry delays, in milliseconds:
+/// before typing starts (for pages that drop the first keystrokes)
+/// and between keystrokes.
+pub const START_DELAY_KEY: &str = "kpass.autotype.start-delay";
+pub const KEY_DELAY_KEY: &str = "kpass.autotype.key-delay";
+
+#[derive(Clone, Copy)]
+pub enum Key {
+    Tab,
+    Enter,
+    Space,
+    Backspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    F(u8),
+    /// A character, only used when combined with modifiers (e.g. `^a`).
+    Char(char),
+}
+impl Key {
+    /// Parse a KeePass key placeholder, e.g. `TAB` or `F5`.
+    fn parse(name: &str) -> Option<Self> {
+        let key = match name {
+            "TAB" => Key::Tab,
+            "ENTER" => Key::Enter,
+            "SPACE" => Key::Space,
+            "BACKSPACE" | "BS" | "BKSP" => Key::Backspace,
+            "DELETE" | "DEL" => Key::Delete,
+            "ESC" => Key::Escape,
+            "UP" => Key::Up,
+            "DOWN" => Key::Down,
+            "LEFT" => Key::Left,
+            "RIGHT" => Key::Right,
+            "HOME" => Key::Home,
+            "END" => Key::End,
+            "PGUP" => Key::PageUp,
+            "PGDN" => Key::PageDown,
+            "INSERT" | "INS" => Key::Insert,
+            f if f.starts_with('F') => match f[1..].parse() {
+                Ok(n @ 1..=12) => Key::F(n),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// Name as understood by `wtype -k`.
+    fn wtype_name(&self) -> String {
+        let name = match self {
+            Key::Tab => "Tab",
+            Key::Enter => "Return",
+            Key::Space => "space",
+            Key::Backspace => "BackSpace",
+            Key::Delete => "Delete",
+            Key::Escape => "Escape",
+            Key::Up => "Up",
+            Key::Down => "Down",
+            Key::Left => "Left",
+            Key::Right => "Right",
+            Key::Home => "Home",
+            Key::End => "End",
+            Key::PageUp => "Prior",
+            Key::PageDown => "Next",
+            Key::Insert => "Insert",
+            Key::F(n) => return format!("F{}", n),
+            Key::Char(c) => return c.to_string(),
+        };
+        name.to_string()
+    }
+
+    /// Linux input event code, as understood by `ydotool key`.
+    /// Only letters and digits are supported for characters.
+    fn keycode(&self) -> Option<u32> {
+        let code = match self {
+            Key::Tab => 15,
+            Key::Enter => 28,
+            Key::Space => 57,
+            Key::Backspace => 14,
+            Key::Delete => 111,
+            Key::Escape => 1,
+            Key::Up => 103,
+            Key::Down => 108,
+            Key::Left => 105,
+            Key::Right => 106,
+            Key::Home => 102,
+            Key::End => 107,
+            Key::PageUp => 104,
+            Key::PageDown => 109,
+            Key::Insert => 110,
+            Key::F(n @ 1..=10) => 58 + *n as u32,
+            Key::F(n) => 76 + *n as u32,
+            Key::Char(c) => {
+                let c = c.to_ascii_lowercase();
+                let rows: [(&str, u32); 4] = [
+                    ("1234567890", 2),
+                    ("qwertyuiop", 16),
+                    ("asdfghjkl", 30),
+                    ("zxcvbnm", 44),
+                ];
+                return rows
+                    .iter()
+                    .find_map(|(row, first)| row.find(c).map(|i| first + i as u32));
+            }
+        };
+        Some(code)
+    }
+}
+
+/// Modifiers, written before a key or character in a sequence,
+/// e.g. `^a` (Ctrl+A) or `+{TAB}` (Shift+Tab).
+#[derive(Clone, Copy)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+    Super,
+}
+impl Modifier {
+    fn parse(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(Modifier::Shift),
+            '^' => Some(Modifier::Ctrl),
+            '%' => Some(Modifier::Alt),
+            '@' => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+
+    /// Name as understood by `wtype -M`.
+    fn wtype_name(&self) -> &'static str {
+        match self {
+            Modifier::Shift => "shift",
+            Modifier::Ctrl => "ctrl",
+            Modifier::Alt => "alt",
+            Modifier::Super => "logo",
+        }
+    }
+
+    fn keycode(&self) -> u32 {
+        match self {
+            Modifier::Shift => 42,
+            Modifier::Ctrl => 29,
+            Modifier::Alt => 56,
+            Modifier::Super => 125,
+        }
+    }
+}
+
+pub enum Action {
+    Text(String),
+    Key(Key, Vec<Modifier>),
+    Delay(Duration),
+}
+impl Drop for Action {
+    fn drop(&mut self) {
+        if let Action::Text(text) = self {
+            text.zeroize();
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Backend {
+    Wtype,
+    Ydotool,
+}
+
+/// The auto-type sequence for an entry.
+pub fn sequence(entry: &KEntry) -> &str {
+    entry
+        .autotype
+        .as_ref()
+        .and_then(|at| at.sequence.as_deref())
+        .filter(|seq| !seq.is_empty())
+        .unwrap_or(DEFAULT_SEQUENCE)
+}
+
+/// The entry's sequence, preceded by its delays (if any).
+/// Delays in the sequence itself take precedence.
+pub fn sequence_with_delays(entry: &KEntry) -> String {
+    let delay = |key: &str| {
+        entry
+            .custom_data
+            .items
+            .get(key)
+            .and_then(|item| match &item.value {
+                Some(Value::Unprotected(ms)) => ms.parse::<u64>().ok(),
+                _ => None,
+            })
+    };
+    let mut seq = String::new();
+    if let Some(ms) = delay(START_DELAY_KEY).filter(|ms| *ms > 0) {
+        seq.push_str(&format!("{{DELAY {}}}", ms));
+    }
+    let key_delay = delay(KEY_DELAY_KEY).unwrap_or(config::get().autotype_key_delay);
+    if key_delay > 0 {
+        seq.push_str(&format!("{{DELAY={}}}", key_delay));
+    }
+    seq.push_str(sequence(entry));
+    seq
+}
+
+/// Characters with a special meaning in sequences.
+const SPECIAL_CHARS: &[char] = &['+', '^', '%', '@', '~', '(', ')', '[', ']'];
+
+/// Whether a placeholder is a braced literal character, e.g. `{+}`.
+fn is_literal(placeholder: &str) -> bool {
+    let mut chars = placeholder.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c == '{' || c == '}' || SPECIAL_CHARS.contains(&c),
+        _ => false,
+    }
+}
+
+/// Escape text so it's typed literally when part of a sequence.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '{' => escaped.push_str("{{}"),
+            '}' => escaped.push_str("{}}"),
+            '\n' => escaped.push_str("{ENTER}"),
+            c if SPECIAL_CHARS.contains(&c) => escaped.push_str(&format!("{{{}}}", c)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A placeholder that's replaced by a value from the entry.
+enum Field {
+    Named(String),
+    Totp,
+}
+impl Field {
+    fn parse(placeholder: &str) -> Option<Self> {
+        let name = match placeholder.to_uppercase().as_str() {
+            "USERNAME" => "UserName",
+            "PASSWORD" => "Password",
+            "TITLE" => "Title",
+            "URL" => "URL",
+            "NOTES" => "Notes",
+            "TOTP" => return Some(Field::Totp),
+            upper if upper.starts_with("S:") => return Some(Field::Named(placeholder[2..].into())),
+            _ => return None,
+        };
+        Some(Field::Named(name.to_string()))
+    }
+}
+
+/// Replace field placeholders in the sequence with the escaped
+/// values returned by `resolve`, leaving other placeholders in place.
+fn substitute(seq: &str, resolve: impl Fn(&Field) -> Result<String>) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = seq;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = placeholder_end(rest, start)?;
+        match Field::parse(&rest[start + 1..end]) {
+            Some(field) => expanded.push_str(&escape(&resolve(&field)?)),
+            None => expanded.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Replace field placeholders (e.g. `{USERNAME}`, `{S:Custom Field}`)
+/// with the values from the entry, leaving key and delay placeholders in place.
+pub fn expand_fields(seq: &str, entry: &KEntry) -> Result<String> {
+    substitute(seq, |field| match field {
+        Field::Named(name) => Ok(entry.get(name).unwrap_or("").to_string()),
+        Field::Totp => {
+            let uri = entry
+                .get_raw_otp_value()
+                .ok_or_else(|| anyhow!("Entry has no OTP"))?;
+            Totp::parse(uri)?.code_now()
+        }
+    })
+}
+
+/// Check that a sequence only uses supported placeholders.
+pub fn validate(seq: &str) -> Result<()> {
+    parse(&substitute(seq, |_| Ok(String::new()))?).map(|_| ())
+}
+
+/// Index of the `}` closing the placeholder opened at `start`,
+/// accounting for the escaped braces `{{}` and `{}}`.
+fn placeholder_end(seq: &str, start: usize) -> Result<usize> {
+    let after = &seq[start + 1..];
+    if after.starts_with("{}") || after.starts_with("}}") {
+        return Ok(start + 2);
+    }
+    after
+        .find('}')
+        .map(|i| start + 1 + i)
+        .ok_or_else(|| anyhow!("Unclosed placeholder in sequence"))
+}
+
+/// Parse a sequence (with fields already expanded) into actions.
+/// Supports keys like `{TAB}`, `{ENTER}`, `{ESC}`, `{UP}` or `{F5}`
+/// (optionally repeated, e.g. `{TAB 3}`), `~` for Enter,
+/// the modifiers `+` (Shift), `^` (Ctrl), `%` (Alt) and `@` (Super),
+/// `{DELAY <ms>}`, and `{DELAY=<ms>}` to delay between every keystroke.
+/// Special characters are typed literally when braced, e.g. `{+}`.
+pub fn parse(seq: &str) -> Result<Vec<Action>> {
+    let mut actions = vec![];
+    let mut text = String::new();
+    let mut key_delay = None;
+    let mut mods = vec![];
+    let mut rest = seq;
+    while let Some(c) = rest.chars().next() {
+        // Keys to press, and a literal character to type.
+        let mut keys = vec![];
+        let mut literal = None;
+        if let Some(modifier) = Modifier::parse(c) {
+            mods.push(modifier);
+            rest = &rest[1..];
+            continue;
+        } else if c == '~' {
+            keys.push(Key::Enter);
+            rest = &rest[1..];
+        } else if c == '{' {
+            let end = placeholder_end(rest, 0)?;
+            let placeholder = rest[1..end].to_uppercase();
+            rest = &rest[end + 1..];
+            match placeholder.as_str() {
+                p if is_literal(p) => {
+                    literal = p.chars().next();
+                }
+                p if p.starts_with("DELAY=") => {
+                    key_delay = Some(Duration::from_millis(p[6..].trim().parse()?));
+                }
+                p if p.starts_with("DELAY ") => {
+                    flush(&mut actions, &mut text, key_delay);
+                    actions.push(Action::Delay(Duration::from_millis(p[6..].trim().parse()?)));
+                }
+                p => {
+                    let (name, count) = match p.split_once(' ') {
+                        Some((name, count)) => (name, count.trim().parse()?),
+                        None => (p, 1),
+                    };
+                    let key = Key::parse(name)
+                        .ok_or_else(|| anyhow!("Unsupported placeholder: {{{}}}", p))?;
+                    keys.extend(std::iter::repeat_n(key, count));
+                }
+            }
+        } else {
+            literal = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        match literal {
+            Some(c) if mods.is_empty() => text.push(c),
+            Some(c) => keys.push(Key::Char(c)),
+            None => {}
+        }
+        if keys.is_empty() {
+            continue;
+        }
+        flush(&mut actions, &mut text, key_delay);
+        let mods = std::mem::take(&mut mods);
+        for key in keys {
+            push(&mut actions, Action::Key(key, mods.clone()), key_delay);
+        }
+    }
+    if !mods.is_empty() {
+        return Err(anyhow!("Modifier at the end of the sequence"));
+    }
+    flush(&mut actions, &mut text, key_delay);
+    Ok(actions)
+}
+
+/// Add any pending text as an action.
+fn flush(actions: &mut Vec<Action>, text: &mut String, key_delay: Option<Duration>) {
+    if !text.is_empty() {
+        push(actions, Action::Text(std::mem::take(text)), key_delay);
+    }
+}
+
+/// Add an action, followed by the per-keystroke delay (if any).
+fn push(actions: &mut Vec<Action>, action: Action, key_delay: Option<Duration>) {
+    actions.push(action);
+    if let Some(delay) = key_delay {
+        actions.push(Action::Delay(delay));
+    }
+}
+
+/// Type an entry according to its auto-type sequence.
+pub fn type_entry(entry: &KEntry) -> Result<()> {
+    let seq = expand_fields(&sequence_with_delays(entry), entry)?;
+    run(&parse(&seq)?)
+}
+
+pub fn run(actions: &[Action]) -> Result<()> {
+    let backend = match perform(Backend::Wtype, &Action::Text(String::new())) {
+        Ok(()) => Backend::Wtype,
+        Err(_) => Backend::Ydotool,
+    };
+    let config = config::get();
+    let cadence = config.autotype_jitter > 0 || config.autotype_chunk_size > 0;
+    for action in actions {
+        match action {
+            Action::Text(text) if cadence => type_with_cadence(backend, text)?,
+            action => perform(backend, action)?,
+        }
+    }
+    Ok(())
+}
+
+/// Split text into pieces of `n` chars.
+fn split_chars(text: &str, n: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = rest.char_indices().nth(n).map_or(rest.len(), |(i, _)| i);
+        let (piece, tail) = rest.split_at(end);
+        pieces.push(piece);
+        rest = tail;
+    }
+    pieces
+}
+
+/// Type text in chunks with pauses between them, and with random
+/// jitter between keystrokes, so it doesn't look machine-typed
+/// to pages that watch for pasting or inhumanly fast typing.
+fn type_with_cadence(backend: Backend, text: &str) -> Result<()> {
+    let config = config::get();
+    let jitter = config.autotype_jitter;
+    let chunk_size = match config.autotype_chunk_size {
+        0 => usize::MAX,
+        n => n,
+    };
+    let mut rng = rand::thread_rng();
+    let mut pause = |base: u64| {
+        let extra = if jitter > 0 {
+            rng.gen_range(0..=jitter)
+        } else {
+            0
+        };
+        thread::sleep(Duration::from_millis(base + extra));
+    };
+
+    for (i, chunk) in split_chars(text, chunk_size).into_iter().enumerate() {
+        if i > 0 {
+            pause(config.autotype_chunk_pause);
+        }
+        if jitter == 0 {
+            perform(backend, &Action::Text(chunk.to_string()))?;
+            continue;
+        }
+        for c in split_chars(chunk, 1) {
+            perform(backend, &Action::Text(c.to_string()))?;
+            pause(0);
+        }
+    }
+    Ok(())
+}
+
+fn perform(backend: Backend, action: &Action) -> Result<()> {
+    let (mut cmd, stdin) = match (backend, action) {
+        (_, Action::Delay(delay)) => {
+            thread::sleep(*delay);
+            return Ok(());
+        }
+        // Text is passed over stdin so it doesn't show up in the process list.
+        (Backend::Wtype, Action::Text(text)) => {
+            let mut cmd = Command::new("wtype");
+            cmd.arg("-");
+            (cmd, Some(text))
+        }
+        (Backend::Wtype, Action::Key(key, mods)) => {
+            let mut cmd = Command::new("wtype");
+            for modifier in mods {
+                cmd.args(["-M", modifier.wtype_name()]);
+            }
+            match key {
+                Key::Char(c) => cmd.arg(c.to_string()),
+                key => cmd.args(["-k", &key.wtype_name()]),
+            };
+            for modifier in mods.iter().rev() {
+                cmd.args(["-m", modifier.wtype_name()]);
+            }
+            (cmd, None)
+        }
+        (Backend::Ydotool, Action::Text(text)) => {
+            let mut cmd = Command::new("ydotool");
+            cmd.args(["type", "--file", "-"]);
+            (cmd, Some(text))
+        }
+        (Backend::Ydotool, Action::Key(key, mods)) => {
+            let code = key.keycode().ok_or_else(|| {
+                anyhow!(
+                    "Can't type '{}' with modifiers using ydotool",
+                    key.wtype_name()
+                )
+            })?;
+            let mut cmd = Command::new("ydotool");
+            cmd.arg("key");
+            for modifier in mods {
+                cmd.arg(format!("{}:1", modifier.keycode()));
+            }
+            cmd.args([format!("{}:1", code), format!("{}:0", code)]);
+            for modifier in mods.iter().rev() {
+                cmd.arg(format!("{}:0", modifier.keycode()));
+            }
+            (cmd, None)
+        }
+    };
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => anyhow!("Auto-type requires `wtype` or `ydotool`."),
+            _ => err.into(),
+        })?;
+    if let Some(text) = stdin {
+        child
+            .stdin
+            .take()
+            .expect("Stdin is piped")
+            .write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("Auto-type failed ({})", status));
+    }
+    Ok(())
+}
+
+/// A window that can be targeted for auto-typing.
+pub struct Window {
+    id: String,
+    label: String,
+}
+impl Display for Window {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+enum Compositor {
+    Sway,
+    Hyprland,
+}
+impl Compositor {
+    fn detect() -> Result<Self> {
+        if env::var_os("SWAYSOCK").is_some() {
+            Ok(Compositor::Sway)
+        } else if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Ok(Compositor::Hyprland)
+        } else {
+            Err(anyhow!("Picking a window requires sway or Hyprland."))
+        }
+    }
+}
+
+fn query_json(program: &str, args: &[&str]) -> Result<Json> {
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("`{}` failed ({})", program, output.status));
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// List the compositor's windows.
+pub fn list_windows() -> Result<Vec<Window>> {
+    let windows = match Compositor::detect()? {
+        Compositor::Sway => {
+            let tree = query_json("swaymsg", &["-t", "get_tree"])?;
+            let mut windows = vec![];
+            collect_sway_windows(&tree, &mut windows);
+            windows
+        }
+        Compositor::Hyprland => {
+            let clients = query_json("hyprctl", &["clients", "-j"])?;
+            clients
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|client| {
+                    let address = client["address"].as_str()?;
+                    let class = client["class"].as_str().unwrap_or("");
+                    let title = client["title"].as_str().unwrap_or("");
+                    Some(Window {
+                        id: address.to_string(),
+                        label: format!("[{}] {}", class, title),
+                    })
+                })
+                .collect()
+        }
+    };
+    Ok(windows)
+}
+
+/// Windows are the leaves of sway's tree that belong to a process.
+fn collect_sway_windows(node: &Json, windows: &mut Vec<Window>) {
+    if let (Some(id), Some(_pid)) = (node["id"].as_u64(), node["pid"].as_u64()) {
+        let app = node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .unwrap_or("");
+        let name = node["name"].as_str().unwrap_or("");
+        windows.push(Window {
+            id: id.to_string(),
+            label: format!("[{}] {}", app, name),
+        });
+    }
+    for key in ["nodes", "floating_nodes"] {
+        for child in node[key].as_array().into_iter().flatten() {
+            collect_sway_windows(child, windows);
+        }
+    }
+}
+
+/// Give a window keyboard focus.
+pub fn focus(window: &Window) -> Result<()> {
+    let status = match Compositor::detect()? {
+        Compositor::Sway => Command::new("swaymsg")
+            .arg(format!("[con_id={}] focus", window.id))
+            .stdout(Stdio::null())
+            .status()?,
+        Compositor::Hyprland => Command::new("hyprctl")
+            .args(["dispatch", "focuswindow", &format!("address:{}", window.id)])
+            .stdout(Stdio::null())
+            .status()?,
+    };
+    if !status.success() {
+        return Err(anyhow!("Failed to focus window ({})", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The actions, as e.g. `text:abc`, `key:ctrl+a` or `delay:500`.
+    fn describe(seq: &str) -> Vec<String> {
+        parse(seq)
+            .unwrap()
+            .iter()
+            .map(|action| match action {
+                Action::Text(text) => format!("text:{}", text),
+                Action::Key(key, mods) => {
+                    let mut name = String::from("key:");
+                    for modifier in mods {
+                        name.push_str(modifier.wtype_name());
+                        name.push('+');
+                    }
+                    name.push_str(&key.wtype_name());
+                    name
+                }
+                Action::Delay(delay) => format!("delay:{}", delay.as_millis()),
+            })
+            .collect()
+    }
+
+    fn expand(seq: &str) -> String {
+        substitute(seq, |field| {
+            Ok(match field {
+                Field::Named(name) if name == "UserName" => "alice".into(),
+                Field::Named(name) if name == "Password" => "p+ss{w}rd~".into(),
+                Field::Named(name) => format!("<{}>", name),
+                Field::Totp => "123456".into(),
+            })
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_the_default_sequence() {
+        assert_eq!(
+            describe(&expand(DEFAULT_SEQUENCE)),
+            ["text:alice", "key:Tab", "text:p+ss{w}rd~", "key:Return"]
+        );
+    }
+
+    #[test]
+    fn expands_fields() {
+        assert_eq!(expand("{username}/{TOTP}"), "alice/123456");
+        assert_eq!(expand("{S:Custom Field}"), "<Custom Field>");
+        assert_eq!(expand("{TITLE}{URL}{NOTES}"), "<Title><URL><Notes>");
+        assert_eq!(expand("{TAB}{DELAY 5}"), "{TAB}{DELAY 5}");
+        assert_eq!(expand("{PASSWORD}"), "p{+}ss{{}w{}}rd{~}");
+        assert_eq!(escape("a\nb"), "a{ENTER}b");
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(describe("{TAB 3}"), ["key:Tab", "key:Tab", "key:Tab"]);
+        assert_eq!(describe("a~b"), ["text:a", "key:Return", "text:b"]);
+        assert_eq!(
+            describe("{f5}{F12}{BS}"),
+            ["key:F5", "key:F12", "key:BackSpace"]
+        );
+        assert_eq!(describe("{PGUP}{INS}"), ["key:Prior", "key:Insert"]);
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        assert_eq!(describe("^a"), ["key:ctrl+a"]);
+        assert_eq!(describe("+^{TAB}x"), ["key:shift+ctrl+Tab", "text:x"]);
+        assert_eq!(describe("%@{F4}"), ["key:alt+logo+F4"]);
+        assert_eq!(describe("^{+}"), ["key:ctrl++"]);
+    }
+
+    #[test]
+    fn parses_literals() {
+        assert_eq!(describe("{+}{^}{%}{@}{~}"), ["text:+^%@~"]);
+        assert_eq!(describe("{(}{)}{[}{]}"), ["text:()[]"]);
+        assert_eq!(describe("{{}x{}}"), ["text:{x}"]);
+        assert_eq!(describe("naïve"), ["text:naïve"]);
+    }
+
+    #[test]
+    fn parses_delays() {
+        assert_eq!(describe("a{DELAY 500}b"), ["text:a", "delay:500", "text:b"]);
+        assert_eq!(
+            describe("{DELAY=20}ab{TAB}"),
+            ["text:ab", "delay:20", "key:Tab", "delay:20"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sequences() {
+        for seq in [
+            "{TAB",
+            "{FOO}",
+            "{F13}",
+            "{TAB x}",
+            "{DELAY abc}",
+            "{DELAY=}",
+            "abc^",
+        ] {
+            assert!(parse(seq).is_err(), "{}", seq);
+        }
+        assert!(validate("{USERNAME}{TAB}{S:Anything}").is_ok());
+        assert!(validate("{USERNAME}{NOPE}").is_err());
+        assert!(validate("{USERNAME").is_err());
+    }
+
+    #[test]
+    fn splits_chars() {
+        assert_eq!(split_chars("abcde", 2), ["ab", "cd", "e"]);
+        assert_eq!(split_chars("äöü", 1), ["ä", "ö", "ü"]);
+        assert!(split_chars("", 3).is_empty());
+    }
+
+    #[test]
+    fn collects_sway_windows() {
+        let tree = serde_json::json!({
+            "id": 1,
+            "nodes": [{
+                "id": 2,
+                "nodes": [
+                    {"id": 3, "pid": 10, "app_id": "firefox", "name": "Mail"},
+                ],
+                "floating_nodes": [
+                    {"id": 4, "pid": 11, "app_id": null,
+                     "window_properties": {"class": "XTerm"}, "name": "sh"},
+                ],
+            }],
+        });
+        let mut windows = vec![];
+        collect_sway_windows(&tree, &mut windows);
+        let windows: Vec<_> = windows
+            .iter()
+            .map(|window| (window.id.as_str(), window.label.as_str()))
+            .collect();
+        assert_eq!(windows, [("3", "[firefox] Mail"), ("4", "[XTerm] sh")]);
+    }
+}
@@ -0,0 +1,166 @@
+//! Timestamped backups, taken before every save.
+//! Only the most recent `backup_count` (see config) are kept.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use inquire::Confirm;
+use tracing::debug;
+
+use crate::{config, prompt};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const SUFFIX: &str = ".backup.kdbx";
+
+/// Where backups of the database go: the configured
+/// backup dir, or the database's own dir.
+fn backup_dir(db_path: &Path) -> PathBuf {
+    match &config::get().backup_dir {
+        Some(dir) => dir.clone(),
+        None => db_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+    }
+}
+
+/// Backups are named `.<db name>-<timestamp>.backup.kdbx`.
+fn prefix(db_path: &Path) -> String {
+    let stem = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    format!(".{}-", stem)
+}
+
+/// The database's backups with their timestamps, newest first.
+fn list(db_path: &Path) -> Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let dir = backup_dir(db_path);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let prefix = prefix(db_path);
+    let mut backups = vec![];
+    for item in fs::read_dir(&dir)? {
+        let path = item?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let timestamp = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(SUFFIX))
+            .and_then(|ts| NaiveDateTime::parse_from_str(ts, TIMESTAMP_FORMAT).ok());
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp, path));
+        }
+    }
+    backups.sort_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+    Ok(backups)
+}
+
+/// Back up the database, removing the oldest backups beyond the limit.
+pub fn create(db_path: &Path) -> Result<()> {
+    let dir = backup_dir(db_path);
+    fs::create_dir_all(&dir)?;
+    let timestamp = Local::now().format(TIMESTAMP_FORMAT);
+    let path = dir.join(format!("{}{}{}", prefix(db_path), timestamp, SUFFIX));
+    debug!(path = %path.display(), "Backing up");
+    fs::copy(db_path, path)?;
+
+    let count = config::get().backup_count.max(1);
+    for (_, old) in list(db_path)?.into_iter().skip(count) {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Interactively pick a backup and restore it over the database.
+/// The current version is backed up first, so this can be undone.
+pub fn restore(db_path: &Path) -> Result<()> {
+    let backups = list(db_path)?;
+    if backups.is_empty() {
+        return Err(anyhow!(
+            "No backups found in {}",
+            backup_dir(db_path).display()
+        ));
+    }
+    let options: Vec<String> = backups
+        .iter()
+        .map(|(timestamp, _)| timestamp.format("%Y-%m-%d %H:%M:%S").to_string())
+        .collect();
+    let choice = prompt::select(&tr!("restore-backup-from"), options).raw_prompt()?;
+    let confirm = Confirm::new(&tr!("replace-with-backup", time = choice.value))
+        .with_default(false)
+        .prompt()?;
+    if !confirm {
+        return Ok(());
+    }
+
+    // Copy next to the database, then rename into place atomically.
+    // The copy is made first, as backing up the current version
+    // may prune the chosen backup.
+    let (_, backup) = &backups[choice.index];
+    let name = db_path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = db_path.with_file_name(format!(".{}.tmp", name));
+    fs::copy(backup, &temp_path)?;
+    create(db_path)?;
+    fs::rename(&temp_path, db_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kpass-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lists_backups_newest_first() {
+        let dir = temp_dir("backups");
+        let db_path = dir.join("passwords.kdbx");
+        assert_eq!(prefix(&db_path), ".passwords-");
+        assert_eq!(backup_dir(Path::new("passwords.kdbx")), Path::new("."));
+        for name in [
+            ".passwords-20240101-120000.backup.kdbx",
+            ".passwords-20240301-120000.backup.kdbx",
+            ".passwords-20240201-120000.backup.kdbx",
+            // Not backups of this database.
+            ".other-20240401-120000.backup.kdbx",
+            ".passwords-latest.backup.kdbx",
+            "passwords.kdbx",
+        ] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let dates: Vec<String> = list(&db_path)
+            .unwrap()
+            .iter()
+            .map(|(timestamp, _)| timestamp.format("%Y-%m").to_string())
+            .collect();
+        assert_eq!(dates, ["2024-03", "2024-02", "2024-01"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_latest_backups() {
+        let dir = temp_dir("backup-rotation");
+        let db_path = dir.join("passwords.kdbx");
+        fs::write(&db_path, "current").unwrap();
+        let count = config::get().backup_count;
+        for day in 1..=count {
+            let name = format!(".passwords-202401{:02}-120000.backup.kdbx", day);
+            fs::write(dir.join(name), "old").unwrap();
+        }
+        create(&db_path).unwrap();
+        let backups = list(&db_path).unwrap();
+        assert_eq!(backups.len(), count);
+        assert_eq!(fs::read_to_string(&backups[0].1).unwrap(), "current");
+        // The oldest was removed.
+        assert!(!dir.join(".passwords-20240101-120000.backup.kdbx").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,840 @@
+//! A host for the KeePassXC-Browser extension (`kpass browser-host`),
+//! so it can fill in and save logins from a kpass database.
+//!
+//! The browser starts the host through a native messaging manifest (see
+//! `install`) and talks to it over stdin/stdout: each message is JSON,
+//! prefixed with its length. After exchanging keys, messages are encrypted
+//! (see `nacl`). The extension first associates with the database, which
+//! has to be confirmed, and proves the association on later requests.
+//!
+//! Associations are kept in the config dir, encrypted with the local key
+//! (see `cache::key`), one file per database.
+
+mod nacl;
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{Display, Formatter},
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use cocoon::Cocoon;
+use keepass::{
+    db::{Entry as KEntry, Group, Node, NodeRef, Times, Value},
+    Database, DatabaseKey,
+};
+use serde_json::{json, Value as Json};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::{
+    cache, config,
+    domain::{self, host_of, Rank},
+    entries_with_paths,
+    error::Error,
+    generate, get_entry_mut,
+    import::get_or_create_group,
+    lock, lock_db,
+    otp::Totp,
+    pinentry, protect, reference, save_db, tty, unlock,
+};
+use nacl::{SharedKey, NONCE_LEN};
+
+/// The name the extension looks for the host by.
+const HOST_NAME: &str = "org.keepassxc.keepassxc_browser";
+
+/// The KeePassXC version reported, which the extension enables features by.
+const VERSION: &str = "2.7.6";
+
+const FIREFOX_EXTENSION: &str = "keepassxc-browser@keepassxc.org";
+const CHROME_EXTENSIONS: &[&str] = &[
+    "chrome-extension://oboonakemofpalcgghocfoadofidjkkk/",
+    "chrome-extension://pdffhmdngciaglkoonimfcmckehcpafo/",
+];
+
+/// Where new logins are saved if the extension doesn't pick a group.
+const LOGINS_GROUP: &str = "KeePassXC-Browser Passwords";
+
+/// Messages from the extension are small; anything larger is refused.
+const MAX_MESSAGE: usize = 1024 * 1024;
+
+/// KeePassXC's error codes, which the extension shows messages for.
+const DATABASE_NOT_OPENED: u8 = 1;
+const CANNOT_DECRYPT_MESSAGE: u8 = 4;
+const ACTION_CANCELLED: u8 = 6;
+const PUBLIC_KEY_NOT_RECEIVED: u8 = 7;
+const ASSOCIATION_FAILED: u8 = 8;
+const INCORRECT_ACTION: u8 = 12;
+const NO_URL_PROVIDED: u8 = 14;
+const NO_LOGINS_FOUND: u8 = 15;
+
+/// An error reported to the extension with one of its codes.
+#[derive(Debug)]
+struct Failure(u8, &'static str);
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.1)
+    }
+}
+impl std::error::Error for Failure {}
+
+fn fail(code: u8, message: &'static str) -> anyhow::Error {
+    Failure(code, message).into()
+}
+
+/// The associations live in the config dir, named after a hash of the database path.
+fn associations_path(db_path: &Path) -> Result<PathBuf> {
+    let db_path = db_path.canonicalize()?;
+    let hash = Sha256::digest(db_path.as_os_str().as_encoded_bytes());
+    let name: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("browser")
+        .join(name))
+}
+
+/// Associated extensions' keys (base64), by their ID.
+fn read_associations(db_path: &Path) -> Result<BTreeMap<String, String>> {
+    let path = associations_path(db_path)?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let key = cache::key()?;
+    let data = Zeroizing::new(
+        Cocoon::new(&key)
+            .parse(&mut File::open(path)?)
+            .map_err(|err| anyhow!("Couldn't read browser associations: {:?}", err))?,
+    );
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn write_associations(db_path: &Path, associations: &BTreeMap<String, String>) -> Result<()> {
+    let path = associations_path(db_path)?;
+    fs::create_dir_all(path.parent().expect("Associations are in the browser dir"))?;
+    let data = serde_json::to_vec(associations)?;
+    let key = cache::key()?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    Cocoon::new(&key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write browser associations: {:?}", err))?;
+    Ok(())
+}
+
+/// Read a length-prefixed message, or `None` once the browser closes the pipe.
+fn read_message(input: &mut impl Read) -> Result<Option<Json>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(anyhow!("Message too large"));
+    }
+    let mut data = vec![0; len];
+    input.read_exact(&mut data)?;
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> Result<()> {
+    let data = serde_json::to_vec(message)?;
+    output.write_all(&(data.len() as u32).to_ne_bytes())?;
+    output.write_all(&data)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Whether any of the entry's URLs is for the host or a domain it's under.
+fn matches_host(entry: &KEntry, host: &str) -> bool {
+    domain::rank(entry, host).is_some_and(|rank| rank >= Rank::Parent)
+}
+
+/// KeePassXC's UUID format.
+fn uuid_hex(uuid: &Uuid) -> String {
+    uuid.simple().to_string()
+}
+
+/// Whether a flag is set, which the extension sends as a string or a bool.
+fn flag(value: &Json) -> bool {
+    value.as_bool().unwrap_or(false) || value.as_str() == Some("true")
+}
+
+fn field<'a>(message: &'a Json, name: &str) -> &'a str {
+    message[name].as_str().unwrap_or("")
+}
+
+fn group_json(group: &Group) -> Json {
+    let children: Vec<_> = group
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            Node::Group(child) => Some(group_json(child)),
+            Node::Entry(_) => None,
+        })
+        .collect();
+    json!({
+        "name": group.name,
+        "uuid": uuid_hex(&group.uuid),
+        "children": children,
+    })
+}
+
+fn find_group_mut<'a>(group: &'a mut Group, uuid: &Uuid) -> Option<&'a mut Group> {
+    if group.uuid == *uuid {
+        return Some(group);
+    }
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Group(child) => find_group_mut(child, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
+struct Unlocked {
+    db: Database,
+    key: DatabaseKey,
+    _lock: lock::Lock,
+}
+
+struct Host {
+    db_path: PathBuf,
+    unlocked: Option<Unlocked>,
+    key: Option<SharedKey>,
+}
+impl Host {
+    /// Handle a message, returning the response.
+    fn handle(&mut self, message: &Json) -> Json {
+        let action = field(message, "action");
+        let response = if action == "change-public-keys" {
+            self.change_public_keys(message)
+        } else {
+            self.encrypted(action, message)
+        };
+        response.unwrap_or_else(|err| {
+            let code = err.downcast_ref::<Failure>().map_or(0, |failure| failure.0);
+            if code == 0 {
+                warn!("{}: {}", action, err);
+            }
+            json!({
+                "action": action,
+                "errorCode": code.to_string(),
+                "error": err.to_string(),
+            })
+        })
+    }
+
+    fn change_public_keys(&mut self, message: &Json) -> Result<Json> {
+        let client_public = BASE64.decode(field(message, "publicKey"))?;
+        let (public, key) = SharedKey::agree(&client_public)?;
+        self.key = Some(key);
+        let nonce = BASE64.decode(field(message, "nonce"))?;
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| anyhow!("Invalid nonce"))?;
+        Ok(json!({
+            "action": "change-public-keys",
+            "version": VERSION,
+            "publicKey": BASE64.encode(public),
+            "nonce": BASE64.encode(nacl::increment(&nonce)),
+            "success": "true",
+        }))
+    }
+
+    /// Decrypt a message, answer it and encrypt the answer.
+    fn encrypted(&mut self, action: &str, message: &Json) -> Result<Json> {
+        let key = self
+            .key
+            .as_ref()
+            .ok_or_else(|| fail(PUBLIC_KEY_NOT_RECEIVED, "Public keys weren't exchanged"))?;
+        let undecryptable = || fail(CANNOT_DECRYPT_MESSAGE, "Couldn't decrypt the message");
+        let nonce: [u8; NONCE_LEN] = BASE64
+            .decode(field(message, "nonce"))
+            .ok()
+            .and_then(|nonce| nonce.try_into().ok())
+            .ok_or_else(undecryptable)?;
+        let sealed = BASE64
+            .decode(field(message, "message"))
+            .map_err(|_| undecryptable())?;
+        let request = key.open(&nonce, &sealed).map_err(|_| undecryptable())?;
+        let request: Json = serde_json::from_slice(&request).map_err(|_| undecryptable())?;
+
+        let mut response = self.answer(action, &request)?;
+        let nonce = nacl::increment(&nonce);
+        let fields = response.as_object_mut().expect("Responses are objects");
+        fields.insert("version".into(), VERSION.into());
+        fields.insert("nonce".into(), BASE64.encode(nonce).into());
+        fields.insert("success".into(), "true".into());
+        let key = self.key.as_ref().expect("Checked above");
+        let sealed = key.seal(&nonce, &Zeroizing::new(serde_json::to_vec(&response)?));
+        Ok(json!({
+            "action": action,
+            "message": BASE64.encode(sealed),
+            "nonce": BASE64.encode(nonce),
+        }))
+    }
+
+    fn answer(&mut self, action: &str, request: &Json) -> Result<Json> {
+        match action {
+            "get-databasehash" => {
+                if self.unlocked.is_none() && flag(&request["triggerUnlock"]) {
+                    self.unlock()?;
+                }
+                Ok(json!({ "hash": self.hash()? }))
+            }
+            "associate" => {
+                let hash = self.hash()?;
+                let description = tr!("allow-browser", database = self.db_path.display());
+                if !pinentry::confirm(&description)? {
+                    return Err(fail(ACTION_CANCELLED, "Association cancelled"));
+                }
+                let id = format!("kpass {}", hex::encode(rand::random::<[u8; 4]>()));
+                let mut associations = read_associations(&self.db_path)?;
+                associations.insert(id.clone(), field(request, "idKey").to_string());
+                write_associations(&self.db_path, &associations)?;
+                info!(
+                    "{}",
+                    tr!("associated", client = id, database = self.db_path.display())
+                );
+                Ok(json!({ "hash": hash, "id": id }))
+            }
+            "test-associate" => {
+                let hash = self.hash()?;
+                let id = field(request, "id");
+                self.check_request(request)?;
+                Ok(json!({ "hash": hash, "id": id }))
+            }
+            "get-logins" => {
+                self.check_request(request)?;
+                let host = host_of(field(request, "url"))
+                    .ok_or_else(|| fail(NO_URL_PROVIDED, "No URL provided"))?;
+                let entries = self.logins(&host)?;
+                if entries.is_empty() {
+                    return Err(fail(NO_LOGINS_FOUND, "No logins found"));
+                }
+                Ok(json!({
+                    "count": entries.len().to_string(),
+                    "entries": entries,
+                    "hash": self.hash()?,
+                    "id": field(request, "id"),
+                }))
+            }
+            "set-login" => {
+                self.check_request(request)?;
+                self.set_login(request)?;
+                Ok(json!({ "count": null, "entries": null, "error": "", "hash": self.hash()? }))
+            }
+            "generate-password" => {
+                let password = generate::default_generator()
+                    .generate_one()
+                    .map_err(Error::Generator)?;
+                Ok(json!({
+                    "password": password,
+                    "entries": [{ "login": "", "password": password }],
+                }))
+            }
+            "get-totp" => {
+                self.check_request(request)?;
+                let db = self.db()?;
+                let uuid = Uuid::parse_str(field(request, "uuid"))?;
+                let entry = db
+                    .root
+                    .iter()
+                    .find_map(|node| match node {
+                        NodeRef::Entry(entry) if entry.uuid == uuid => Some(entry),
+                        _ => None,
+                    })
+                    .filter(|entry| !protect::is_required(entry))
+                    .ok_or_else(|| anyhow!("No entry has that UUID"))?;
+                let uri = entry
+                    .get_raw_otp_value()
+                    .ok_or_else(|| anyhow!("The entry has no OTP"))?;
+                Ok(json!({ "totp": Totp::parse(uri)?.code_now()? }))
+            }
+            "get-database-groups" => {
+                self.check_request(request)?;
+                let db = self.db()?;
+                Ok(json!({ "groups": { "groups": [group_json(&db.root)] } }))
+            }
+            "create-new-group" => {
+                self.check_request(request)?;
+                let path = crate::address::split(field(request, "groupName"));
+                let unlocked = self.unlocked_mut()?;
+                let group = get_or_create_group(&mut unlocked.db.root, &path);
+                let response = json!({ "name": group.name, "uuid": uuid_hex(&group.uuid) });
+                self.save()?;
+                Ok(response)
+            }
+            "lock-database" => {
+                self.check_request(request)?;
+                self.unlocked = None;
+                Ok(json!({}))
+            }
+            _ => Err(fail(INCORRECT_ACTION, "Unknown action")),
+        }
+    }
+
+    fn unlock(&mut self) -> Result<()> {
+        let lock = lock_db(&self.db_path)?;
+        let (db, key) = unlock(&self.db_path)?;
+        self.unlocked = Some(Unlocked {
+            db,
+            key,
+            _lock: lock,
+        });
+        Ok(())
+    }
+
+    fn unlocked_mut(&mut self) -> Result<&mut Unlocked> {
+        self.unlocked
+            .as_mut()
+            .ok_or_else(|| fail(DATABASE_NOT_OPENED, "Database not opened"))
+    }
+
+    fn db(&self) -> Result<&Database> {
+        self.unlocked
+            .as_ref()
+            .map(|unlocked| &unlocked.db)
+            .ok_or_else(|| fail(DATABASE_NOT_OPENED, "Database not opened"))
+    }
+
+    /// The database's ID for the extension: a hash of its root group's UUID,
+    /// as KeePassXC computes it.
+    fn hash(&self) -> Result<String> {
+        let root = uuid_hex(&self.db()?.root.uuid);
+        Ok(hex::encode(Sha256::digest(root)))
+    }
+
+    /// Check that a request comes from an associated extension, by its `id`
+    /// and `key` or by any of its `keys`.
+    fn check_request(&self, request: &Json) -> Result<()> {
+        self.db()?;
+        let associations = read_associations(&self.db_path)?;
+        let associated = |id: &str, key: &str| {
+            !key.is_empty() && associations.get(id).is_some_and(|stored| stored == key)
+        };
+        let associated = match request["keys"].as_array() {
+            Some(keys) => keys
+                .iter()
+                .any(|key| associated(field(key, "id"), field(key, "key"))),
+            None => associated(field(request, "id"), field(request, "key")),
+        };
+        if !associated {
+            return Err(fail(ASSOCIATION_FAILED, "Association failed"));
+        }
+        Ok(())
+    }
+
+    /// The logins for the host. Entries requiring the master password are left out.
+    fn logins(&self, host: &str) -> Result<Vec<Json>> {
+        let db = self.db()?;
+        let root_name = db.root.name.clone();
+        Ok(entries_with_paths(&db.root)
+            .into_iter()
+            .filter(|(_, entry)| !protect::is_required(entry) && matches_host(entry, host))
+            .map(|(path, entry)| {
+                let entry = reference::resolved(db, entry);
+                let fields: Vec<_> = entry
+                    .fields
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("KPH: "))
+                    .filter_map(|(name, _)| Some(json!({ name: entry.get(name)? })))
+                    .collect();
+                let mut login = json!({
+                    "login": entry.get_username().unwrap_or(""),
+                    "name": entry.get_title().unwrap_or(""),
+                    "password": entry.get_password().unwrap_or(""),
+                    "uuid": uuid_hex(&entry.uuid),
+                    "group": path.last().unwrap_or(&root_name),
+                    "expired": "false",
+                    "skipAutoSubmit": "false",
+                    "stringFields": fields,
+                });
+                if let Some(totp) = entry
+                    .get_raw_otp_value()
+                    .and_then(|uri| Totp::parse(uri).ok()?.code_now().ok())
+                {
+                    login["totp"] = totp.into();
+                }
+                login
+            })
+            .collect())
+    }
+
+    /// Update the login with the UUID given, or else add one.
+    fn set_login(&mut self, request: &Json) -> Result<()> {
+        let url = field(request, "url");
+        let username = Value::Unprotected(field(request, "login").to_string());
+        let password = Value::Protected(field(request, "password").as_bytes().into());
+        let unlocked = self.unlocked_mut()?;
+        let existing = Uuid::parse_str(field(request, "uuid"))
+            .ok()
+            .and_then(|uuid| get_entry_mut(&mut unlocked.db, uuid.as_u128()));
+        match existing {
+            Some(entry) => {
+                if protect::is_required(entry) {
+                    return Err(anyhow!("The entry requires the master password"));
+                }
+                let before = entry.clone();
+                entry.fields.insert("UserName".to_string(), username);
+                entry.fields.insert("Password".to_string(), password);
+                entry
+                    .history
+                    .get_or_insert_with(Default::default)
+                    .add_entry(before);
+                entry.times.set_last_modification(Times::now());
+            }
+            None => {
+                let mut entry = KEntry::new();
+                let title = host_of(url).unwrap_or_else(|| url.to_string());
+                entry
+                    .fields
+                    .insert("Title".to_string(), Value::Unprotected(title));
+                entry
+                    .fields
+                    .insert("URL".to_string(), Value::Unprotected(url.to_string()));
+                entry.fields.insert("UserName".to_string(), username);
+                entry.fields.insert("Password".to_string(), password);
+                let group = match Uuid::parse_str(field(request, "groupUuid")) {
+                    Ok(uuid) => find_group_mut(&mut unlocked.db.root, &uuid),
+                    Err(_) => None,
+                };
+                match group {
+                    Some(group) => group.add_child(entry),
+                    None => get_or_create_group(&mut unlocked.db.root, &[LOGINS_GROUP.into()])
+                        .add_child(entry),
+                }
+            }
+        }
+        self.save()
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let db_path = self.db_path.clone();
+        let unlocked = self.unlocked_mut()?;
+        save_db(&mut unlocked.db, &unlocked.key, &db_path)
+    }
+}
+
+/// Serve the extension's requests for the database until the browser closes the pipe.
+pub fn serve(db_path: &Path) -> Result<()> {
+    // Messages go to the original stdout, while the rest of kpass' output
+    // (e.g. when unlocking or saving) goes to stderr, which browsers log.
+    let mut output = tty::divert_stdout()?;
+    let mut input = std::io::stdin().lock();
+    let mut host = Host {
+        db_path: db_path.to_path_buf(),
+        unlocked: None,
+        key: None,
+    };
+    while let Some(message) = read_message(&mut input)? {
+        let response = host.handle(&message);
+        write_message(&mut output, &response)?;
+    }
+    Ok(())
+}
+
+/// Where each browser looks for native messaging manifests,
+/// if the browser's config exists.
+fn manifest_dirs() -> Vec<(PathBuf, bool)> {
+    let Some(home) = env::var_os("HOME").map(PathBuf::from) else {
+        return vec![];
+    };
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+    [
+        (home.join(".mozilla"), "native-messaging-hosts", true),
+        (config.join("google-chrome"), "NativeMessagingHosts", false),
+        (config.join("chromium"), "NativeMessagingHosts", false),
+        (
+            config.join("BraveSoftware/Brave-Browser"),
+            "NativeMessagingHosts",
+            false,
+        ),
+    ]
+    .into_iter()
+    .filter(|(browser, _, _)| browser.exists())
+    .map(|(browser, dir, firefox)| (browser.join(dir), firefox))
+    .collect()
+}
+
+/// Install the native messaging manifests, so the browsers
+/// start `kpass browser-host` for the database.
+pub fn install(db_path: &Path) -> Result<()> {
+    let db_path = db_path.canonicalize()?;
+    let exe = env::current_exe()?;
+    // Browsers run the manifest's path without arguments (besides their own),
+    // so it's a script that passes the database along.
+    let script = config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("browser-host");
+    fs::create_dir_all(script.parent().expect("The script is in the config dir"))?;
+    let quote = |path: &Path| format!("'{}'", path.display().to_string().replace('\'', r"'\''"));
+    fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nexec {} browser-host {}\n",
+            quote(&exe),
+            quote(&db_path)
+        ),
+    )?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+
+    let dirs = manifest_dirs();
+    if dirs.is_empty() {
+        return Err(anyhow!("Couldn't find Firefox or a Chromium-based browser"));
+    }
+    for (dir, firefox) in dirs {
+        let mut manifest = json!({
+            "name": HOST_NAME,
+            "description": "kpass",
+            "path": script,
+            "type": "stdio",
+        });
+        if firefox {
+            manifest["allowed_extensions"] = json!([FIREFOX_EXTENSION]);
+        } else {
+            manifest["allowed_origins"] = json!(CHROME_EXTENSIONS);
+        }
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+        info!("{}", tr!("installed", path = path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto_box::{
+        aead::{Aead, OsRng},
+        PublicKey, SalsaBox, SecretKey,
+    };
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, url: &str) -> KEntry {
+        test_util::entry(&[
+            ("Title", title),
+            ("URL", url),
+            ("UserName", "alice"),
+            ("Password", "hunter2"),
+        ])
+    }
+
+    /// A host with a database unlocked.
+    fn host(name: &str) -> Host {
+        let db_path = env::temp_dir().join(format!(
+            "kpass-test-browser-{}-{}.kdbx",
+            name,
+            std::process::id()
+        ));
+        // Associations are found by the database's canonical path.
+        fs::write(&db_path, b"").unwrap();
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", "https://github.com/login"));
+        work.add_child(entry("GitLab", "https://gitlab.com"));
+        let mut protected = entry("GitHub admin", "https://github.com");
+        protect::set_required(&mut protected, true);
+        work.add_child(protected);
+        db.root.add_child(work);
+        Host {
+            unlocked: Some(Unlocked {
+                db,
+                key: DatabaseKey::new().with_password("correct horse"),
+                _lock: lock::acquire(&db_path).unwrap().unwrap(),
+            }),
+            db_path,
+            key: None,
+        }
+    }
+
+    /// The extension's side of the exchange.
+    struct Client {
+        secret: SecretKey,
+        key: Option<SalsaBox>,
+    }
+    impl Client {
+        fn new() -> Self {
+            Client {
+                secret: SecretKey::generate(&mut OsRng),
+                key: None,
+            }
+        }
+
+        fn exchange_keys(&mut self, host: &mut Host) {
+            let response = host.handle(&json!({
+                "action": "change-public-keys",
+                "publicKey": BASE64.encode(self.secret.public_key().as_bytes()),
+                "nonce": BASE64.encode([0; NONCE_LEN]),
+            }));
+            assert_eq!(response["success"], "true");
+            assert_eq!(
+                response["nonce"],
+                BASE64.encode(nacl::increment(&[0; NONCE_LEN]))
+            );
+            let public: [u8; 32] = BASE64
+                .decode(field(&response, "publicKey"))
+                .unwrap()
+                .try_into()
+                .unwrap();
+            self.key = Some(SalsaBox::new(&PublicKey::from(public), &self.secret));
+        }
+
+        /// Send an encrypted request, returning the decrypted response,
+        /// or the error response as is.
+        fn request(&self, host: &mut Host, action: &str, request: Json) -> Json {
+            let key = self.key.as_ref().unwrap();
+            let nonce = [1; NONCE_LEN];
+            let sealed = key
+                .encrypt(&nonce.into(), request.to_string().as_bytes())
+                .unwrap();
+            let response = host.handle(&json!({
+                "action": action,
+                "nonce": BASE64.encode(nonce),
+                "message": BASE64.encode(sealed),
+            }));
+            if response.get("errorCode").is_some() {
+                return response;
+            }
+            let nonce = nacl::increment(&nonce);
+            assert_eq!(response["nonce"], BASE64.encode(nonce));
+            let sealed = BASE64.decode(field(&response, "message")).unwrap();
+            let opened = key.decrypt(&nonce.into(), sealed.as_slice()).unwrap();
+            serde_json::from_slice(&opened).unwrap()
+        }
+    }
+
+    #[test]
+    fn frames_messages() {
+        let mut data = vec![];
+        write_message(&mut data, &json!({ "action": "get-databasehash" })).unwrap();
+        assert_eq!(data[..4], (data.len() as u32 - 4).to_ne_bytes());
+        let mut input = data.as_slice();
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({ "action": "get-databasehash" }))
+        );
+        // The browser closed the pipe.
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let too_large = (MAX_MESSAGE as u32 + 1).to_ne_bytes();
+        assert!(read_message(&mut too_large.as_slice()).is_err());
+        let truncated = [8, 0, 0, 0, b'{'];
+        assert!(read_message(&mut truncated.as_slice()).is_err());
+    }
+
+    #[test]
+    fn answers_encrypted_requests() {
+        let mut host = host("encrypted");
+        let mut client = Client::new();
+        client.exchange_keys(&mut host);
+
+        let response = client.request(&mut host, "get-databasehash", json!({}));
+        assert_eq!(response["success"], "true");
+        assert_eq!(response["version"], VERSION);
+        let root = uuid_hex(&host.db().unwrap().root.uuid);
+        assert_eq!(response["hash"], hex::encode(Sha256::digest(root)));
+
+        let root = group_json(&host.db().unwrap().root);
+        assert_eq!(root["children"][0]["name"], "Work");
+
+        host.unlocked = None;
+        let response = client.request(&mut host, "get-database-groups", json!({}));
+        assert_eq!(response["errorCode"], DATABASE_NOT_OPENED.to_string());
+    }
+
+    #[test]
+    fn refuses_unassociated_clients() {
+        let mut host = host("unassociated");
+        let mut client = Client::new();
+        client.exchange_keys(&mut host);
+        let uuid = uuid_hex(&host.db().unwrap().root.uuid);
+        for (action, request) in [
+            (
+                "test-associate",
+                json!({ "id": "kpass 0000", "key": "guess" }),
+            ),
+            (
+                "get-logins",
+                json!({ "url": "https://github.com", "keys": [] }),
+            ),
+            (
+                "set-login",
+                json!({ "id": "kpass 0000", "url": "https://github.com" }),
+            ),
+            ("get-totp", json!({ "uuid": uuid })),
+            ("get-database-groups", json!({})),
+            ("create-new-group", json!({ "groupName": "Stolen" })),
+            ("lock-database", json!({ "id": "kpass 0000" })),
+        ] {
+            let response = client.request(&mut host, action, request);
+            assert_eq!(
+                response["errorCode"],
+                ASSOCIATION_FAILED.to_string(),
+                "{}",
+                action
+            );
+        }
+        // Nothing was changed or locked.
+        let db = host.db().unwrap();
+        assert!(group_json(&db.root)["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|group| group["name"] != "Stolen"));
+    }
+
+    #[test]
+    fn reports_errors_with_codes() {
+        let mut host = host("errors");
+        let response = host.handle(&json!({ "action": "get-databasehash" }));
+        assert_eq!(response["errorCode"], PUBLIC_KEY_NOT_RECEIVED.to_string());
+
+        let mut client = Client::new();
+        client.exchange_keys(&mut host);
+        let response = client.request(&mut host, "delete-everything", json!({}));
+        assert_eq!(response["action"], "delete-everything");
+        assert_eq!(response["errorCode"], INCORRECT_ACTION.to_string());
+
+        let response = host.handle(&json!({
+            "action": "get-databasehash",
+            "nonce": BASE64.encode([1; NONCE_LEN]),
+            "message": BASE64.encode(b"not sealed by the client"),
+        }));
+        assert_eq!(response["errorCode"], CANNOT_DECRYPT_MESSAGE.to_string());
+    }
+
+    #[test]
+    fn finds_logins_for_the_host() {
+        let host = host("logins");
+        let logins = host.logins("gist.github.com").unwrap();
+        let names: Vec<_> = logins.iter().map(|login| &login["name"]).collect();
+        // Not the entry requiring the master password.
+        assert_eq!(names, ["GitHub"]);
+        assert_eq!(logins[0]["login"], "alice");
+        assert_eq!(logins[0]["password"], "hunter2");
+        assert_eq!(logins[0]["group"], "Work");
+        assert!(host.logins("example.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_flags() {
+        assert!(flag(&json!(true)));
+        assert!(flag(&json!("true")));
+        assert!(!flag(&json!("false")));
+        assert!(!flag(&Json::Null));
+    }
+}
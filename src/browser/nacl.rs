@@ -0,0 +1,149 @@
+//! NaCl's `crypto_box` (X25519, XSalsa20 and Poly1305),
+//! which KeePassXC-Browser encrypts its messages with.
+
+use anyhow::{anyhow, Result};
+use crypto_box::{
+    aead::{Aead, OsRng},
+    PublicKey, SalsaBox, SecretKey,
+};
+use zeroize::Zeroizing;
+
+pub const NONCE_LEN: usize = 24;
+
+/// The key shared with the extension.
+pub struct SharedKey(SalsaBox);
+impl SharedKey {
+    /// Generate a key pair and agree on a key with the extension's public key,
+    /// returning our public key with it.
+    pub fn agree(client_public: &[u8]) -> Result<(Vec<u8>, Self)> {
+        let client_public: [u8; 32] = client_public
+            .try_into()
+            .map_err(|_| anyhow!("Invalid public key"))?;
+        let private = SecretKey::generate(&mut OsRng);
+        let public = private.public_key().as_bytes().to_vec();
+        Ok((public, Self::new(&client_public.into(), &private)))
+    }
+
+    fn new(public: &PublicKey, private: &SecretKey) -> Self {
+        SharedKey(SalsaBox::new(public, private))
+    }
+
+    /// Encrypt a message, returning the tag followed by the ciphertext.
+    pub fn seal(&self, nonce: &[u8; NONCE_LEN], message: &[u8]) -> Vec<u8> {
+        self.0
+            .encrypt(nonce.into(), message)
+            .expect("Messages are far smaller than XSalsa20's limit")
+    }
+
+    /// Decrypt and authenticate a message sealed by the extension.
+    pub fn open(&self, nonce: &[u8; NONCE_LEN], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        self.0
+            .decrypt(nonce.into(), sealed)
+            .map(Zeroizing::new)
+            .map_err(|_| anyhow!("Couldn't decrypt the message"))
+    }
+}
+
+/// The nonce after `nonce`, as a little-endian number,
+/// which responses are sent with.
+pub fn increment(nonce: &[u8; NONCE_LEN]) -> [u8; NONCE_LEN] {
+    let mut next = *nonce;
+    for byte in &mut next {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vector from "Cryptography in NaCl", as in libsodium's tests.
+    const ALICE_SECRET: &str = "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+    const ALICE_PUBLIC: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+    const BOB_SECRET: &str = "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb";
+    const BOB_PUBLIC: &str = "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f";
+    const NONCE: &str = "69696ee955b62b73cd62bda875fc73d68219e0036b7a0b37";
+    const MESSAGE: &str = concat!(
+        "be075fc53c81f2d5cf141316ebeb0c7b5228c52a4c62cbd44b66849b64244ffc",
+        "e5ecbaaf33bd751a1ac728d45e6c61296cdc3c01233561f41db66cce314adb31",
+        "0e3be8250c46f06dceea3a7fa1348057e2f6556ad6b1318a024a838f21af1fde",
+        "048977eb48f59ffd4924ca1c60902e52f0a089bc76897040e082f93776384864",
+        "5e0705",
+    );
+    const SEALED: &str = concat!(
+        "f3ffc7703f9400e52a7dfb4b3d3305d98e993b9f48681273c29650ba32fc76ce",
+        "48332ea7164d96a4476fb8c531a1186ac0dfc17c98dce87b4da7f011ec48c972",
+        "71d2c20f9b928fe2270d6fb863d51738b48eeee314a7cc8ab932164548e526ae",
+        "90224368517acfeabd6bb3732bc0e9da99832b61ca01b6de56244a9e88d5f9b3",
+        "7973f622a43d14a6599b1f654cb45a74e355a5",
+    );
+
+    fn bytes<const N: usize>(text: &str) -> [u8; N] {
+        hex::decode(text).unwrap().try_into().unwrap()
+    }
+
+    fn alice_to_bob() -> SharedKey {
+        let bob: [u8; 32] = bytes(BOB_PUBLIC);
+        SharedKey::new(&bob.into(), &SecretKey::from(bytes::<32>(ALICE_SECRET)))
+    }
+
+    fn bob_to_alice() -> SharedKey {
+        let alice: [u8; 32] = bytes(ALICE_PUBLIC);
+        SharedKey::new(&alice.into(), &SecretKey::from(bytes::<32>(BOB_SECRET)))
+    }
+
+    #[test]
+    fn seals_like_nacl() {
+        let sealed = alice_to_bob().seal(&bytes(NONCE), &hex::decode(MESSAGE).unwrap());
+        assert_eq!(hex::encode(sealed), SEALED);
+    }
+
+    #[test]
+    fn opens_like_nacl() {
+        let opened = bob_to_alice()
+            .open(&bytes(NONCE), &hex::decode(SEALED).unwrap())
+            .unwrap();
+        assert_eq!(hex::encode(&*opened), MESSAGE);
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let sealed = hex::decode(SEALED).unwrap();
+        // The tag, then the ciphertext.
+        for i in [0, 15, 16, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(bob_to_alice().open(&bytes(NONCE), &tampered).is_err());
+        }
+        assert!(bob_to_alice().open(&bytes(NONCE), &sealed[..15]).is_err());
+        let other_nonce = increment(&bytes(NONCE));
+        assert!(bob_to_alice().open(&other_nonce, &sealed).is_err());
+    }
+
+    #[test]
+    fn agreed_keys_match() {
+        let client = SecretKey::generate(&mut OsRng);
+        let (public, ours) = SharedKey::agree(client.public_key().as_bytes()).unwrap();
+        let public: [u8; 32] = public.try_into().unwrap();
+        let theirs = SharedKey::new(&public.into(), &client);
+        let nonce = [7; NONCE_LEN];
+        let sealed = ours.seal(&nonce, b"hello");
+        assert_eq!(&*theirs.open(&nonce, &sealed).unwrap(), b"hello");
+        assert!(SharedKey::agree(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn increment_carries() {
+        let mut nonce = [0; NONCE_LEN];
+        assert_eq!(increment(&nonce)[0], 1);
+        nonce[0] = 0xff;
+        nonce[1] = 0xff;
+        let next = increment(&nonce);
+        assert_eq!(next[..3], [0, 0, 1]);
+        assert_eq!(increment(&[0xff; NONCE_LEN]), [0; NONCE_LEN]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Changing several entries at once (the "Bulk edit" action): pick them
+//! with space, then move them to a group, add or remove a tag, delete
+//! them or set their expiry, instead of going through each one.
+
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use inquire::{required, Confirm, Text};
+use keepass::{
+    db::{Entry as KEntry, Times},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{
+    address, delete_entry, entries, entries_with_paths, get_entry_mut, i18n,
+    import::get_or_create_group, pick_group, prompt, take_entry, Entry,
+};
+
+const MOVE: &str = "Move to group";
+const ADD_TAG: &str = "Add tag";
+const REMOVE_TAG: &str = "Remove tag";
+const DELETE: &str = "Delete";
+const SET_EXPIRY: &str = "Set expiry";
+const OPERATIONS: [&str; 5] = [MOVE, ADD_TAG, REMOVE_TAG, DELETE, SET_EXPIRY];
+
+/// Change each entry, keeping its previous version in its history
+/// if it changed.
+fn modify(db: &mut Database, uuids: &[Uuid], change: impl Fn(&mut KEntry)) -> usize {
+    let mut changed = 0;
+    for uuid in uuids {
+        let Some(entry) = get_entry_mut(db, uuid.as_u128()) else {
+            continue;
+        };
+        let before = entry.clone();
+        change(entry);
+        if *entry != before {
+            entry
+                .history
+                .get_or_insert_with(Default::default)
+                .add_entry(before);
+            entry.times.set_last_modification(Times::now());
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Pick entries and change them. Returns whether anything changed.
+pub fn run(db: &mut Database) -> Result<bool> {
+    let paths: Vec<(String, Uuid)> = entries_with_paths(&db.root)
+        .into_iter()
+        .map(|(mut path, entry)| {
+            path.push(Entry(entry).to_string());
+            (address::format(&path), entry.uuid)
+        })
+        .collect();
+    let labels = paths.iter().map(|(path, _)| path.clone()).collect();
+    let picked = prompt::multi_select(&tr!("entries"), labels)
+        .with_help_message(&tr!("entries-help"))
+        .raw_prompt()?;
+    if picked.is_empty() {
+        info!("{}", tr!("no-entries-selected"));
+        return Ok(false);
+    }
+    let uuids: Vec<Uuid> = picked.iter().map(|option| paths[option.index].1).collect();
+
+    let options = OPERATIONS.map(|op| i18n::name("bulk", op)).to_vec();
+    let op = prompt::select(&tr!("for-entries", count = uuids.len()), options).raw_prompt()?;
+    match OPERATIONS[op.index] {
+        MOVE => {
+            let path = pick_group(db)?;
+            for uuid in &uuids {
+                let mut entry =
+                    take_entry(&mut db.root, uuid).ok_or_else(|| anyhow!("Entry not found"))?;
+                entry.times.set_location_changed(Times::now());
+                get_or_create_group(&mut db.root, &path).add_child(entry);
+            }
+            info!(
+                "{}",
+                tr!(
+                    "moved-entries",
+                    count = uuids.len(),
+                    group = format!("/{}", path.join("/"))
+                )
+            );
+        }
+        ADD_TAG => {
+            let tag = Text::new(&tr!("tag"))
+                .with_validator(required!())
+                .prompt()?;
+            let changed = modify(db, &uuids, |entry| {
+                if !entry.tags.contains(&tag) {
+                    entry.tags.push(tag.clone());
+                }
+            });
+            info!("{}", tr!("tagged-entries", count = changed, tag = tag));
+        }
+        REMOVE_TAG => {
+            let tags: BTreeSet<String> = entries(db)
+                .filter(|entry| uuids.contains(&entry.uuid))
+                .flat_map(|entry| entry.tags.iter().cloned())
+                .collect();
+            if tags.is_empty() {
+                info!("{}", tr!("no-tags"));
+                return Ok(false);
+            }
+            let tag = prompt::select(&tr!("tag"), tags.into_iter().collect()).prompt()?;
+            let changed = modify(db, &uuids, |entry| entry.tags.retain(|t| *t != tag));
+            info!("{}", tr!("untagged-entries", count = changed, tag = tag));
+        }
+        DELETE => {
+            let confirm = Confirm::new(&tr!("delete-entries", count = uuids.len()))
+                .with_default(false)
+                .prompt()?;
+            if !confirm {
+                return Ok(false);
+            }
+            let mut recycled = 0;
+            for uuid in &uuids {
+                if delete_entry(db, uuid)? {
+                    recycled += 1;
+                }
+            }
+            info!(
+                "{}",
+                tr!("deleted-entries", count = uuids.len(), recycled = recycled)
+            );
+        }
+        SET_EXPIRY => {
+            let date = prompt::expiry(None)?;
+            let changed = modify(db, &uuids, |entry| match date {
+                Some(date) => {
+                    entry.times.expires = true;
+                    entry.times.set_expiry(prompt::end_of_day_utc(date));
+                }
+                None => entry.times.expires = false,
+            });
+            info!("{}", tr!("changed-expiry", count = changed));
+        }
+        _ => unreachable!(),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(tags: &[&str]) -> KEntry {
+        let mut entry = KEntry::new();
+        entry.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        entry
+    }
+
+    #[test]
+    fn keeps_history_of_changed_entries_only() {
+        let mut db = Database::new(Default::default());
+        let (tagged, untagged) = (entry(&["work"]), entry(&[]));
+        let uuids = [tagged.uuid, untagged.uuid, Uuid::new_v4()];
+        db.root.add_child(tagged);
+        db.root.add_child(untagged);
+
+        let tag = |entry: &mut KEntry| {
+            if !entry.tags.contains(&"work".to_string()) {
+                entry.tags.push("work".to_string());
+            }
+        };
+        assert_eq!(modify(&mut db, &uuids, tag), 1);
+        let entries: Vec<&KEntry> = entries(&db).collect();
+        assert!(entries.iter().all(|entry| entry.tags == ["work"]));
+        assert!(entries[0].history.is_none());
+        let history = entries[1].history.as_ref().unwrap().get_entries();
+        assert!(history[0].tags.is_empty());
+
+        assert_eq!(modify(&mut db, &uuids, tag), 0);
+        assert_eq!(modify(&mut db, &uuids, |entry| entry.tags.clear()), 2);
+    }
+}
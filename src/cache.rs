@@ -0,0 +1,238 @@
+//! Cache of non-secret metadata (titles, group paths, tags, UUIDs,
+//! expiry and whether entries are in the recycle bin),
+//! so entries can be listed (e.g. for shell completion) without unlocking.
+//!
+//! The cache is opt-in, as it reveals which accounts you have.
+//! It's encrypted with a key kept in the config dir, so the sidecar
+//! alone (e.g. synced along with the database) reveals nothing.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use cocoon::Cocoon;
+use keepass::Database;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{address, config::config_dir, filter};
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedEntry {
+    pub uuid: String,
+    pub title: String,
+    pub group: Vec<String>,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub recycled: bool,
+    /// When it expires, if it does.
+    #[serde(default)]
+    pub expires: Option<NaiveDateTime>,
+}
+impl CachedEntry {
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| expires < Utc::now().naive_utc())
+    }
+
+    /// The entry's title, prefixed by its group path.
+    pub fn path(&self) -> String {
+        let mut path = self.group.clone();
+        path.push(self.title.clone());
+        address::format(&path)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    /// Modification time and size of the database when cached,
+    /// to tell if the cache is stale.
+    db_modified: u64,
+    db_len: u64,
+    entries: Vec<CachedEntry>,
+}
+
+/// Path of the sidecar file holding the cache.
+fn sidecar_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".cache");
+    db_path.with_file_name(name)
+}
+
+/// Modification time (in seconds) and size of the database file.
+fn db_stamp(db_path: &Path) -> Result<(u64, u64)> {
+    let meta = fs::metadata(db_path)?;
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok((modified, meta.len()))
+}
+
+/// Get the key for local state (the cache and activity log),
+/// creating it if necessary.
+pub fn key() -> Result<Zeroizing<Vec<u8>>> {
+    let path = config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("cache.key");
+    let mut key = Zeroizing::new(vec![0; 32]);
+    if path.exists() {
+        File::open(&path)?.read_exact(&mut key)?;
+    } else {
+        File::open("/dev/urandom")?.read_exact(&mut key)?;
+        fs::create_dir_all(path.parent().expect("Key is in the config dir"))?;
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?
+            .write_all(&key)?;
+    }
+    Ok(key)
+}
+
+/// The metadata of all entries in the database.
+pub fn entries(db: &Database) -> Vec<CachedEntry> {
+    filter::with_recycled(db)
+        .into_iter()
+        .map(|(group, entry, recycled)| CachedEntry {
+            uuid: entry.uuid.to_string(),
+            title: entry.get_title().unwrap_or("(no title)").to_string(),
+            group,
+            tags: entry.tags.clone(),
+            recycled,
+            expires: entry
+                .times
+                .get_expiry()
+                .filter(|_| entry.times.expires)
+                .copied(),
+        })
+        .collect()
+}
+
+/// Refresh the cache for the (just saved) database.
+pub fn write(db: &Database, db_path: &Path) -> Result<()> {
+    write_with(db, db_path, &key()?)
+}
+
+fn write_with(db: &Database, db_path: &Path, key: &[u8]) -> Result<()> {
+    let (db_modified, db_len) = db_stamp(db_path)?;
+    let cache = Cache {
+        db_modified,
+        db_len,
+        entries: entries(db),
+    };
+    let data = serde_json::to_vec(&cache)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(sidecar_path(db_path))?;
+    Cocoon::new(key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write cache: {:?}", err))?;
+    Ok(())
+}
+
+/// Read the cached entries, if there's a cache
+/// and the database hasn't changed since it was written.
+pub fn read(db_path: &Path) -> Result<Option<Vec<CachedEntry>>> {
+    if !sidecar_path(db_path).exists() {
+        return Ok(None);
+    }
+    read_with(db_path, &key()?)
+}
+
+fn read_with(db_path: &Path, key: &[u8]) -> Result<Option<Vec<CachedEntry>>> {
+    let path = sidecar_path(db_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = Cocoon::new(key)
+        .parse(&mut File::open(path)?)
+        .map_err(|err| anyhow!("Couldn't read cache: {:?}", err))?;
+    let cache: Cache = serde_json::from_slice(&data)?;
+    let fresh = (cache.db_modified, cache.db_len) == db_stamp(db_path)?;
+    Ok(fresh.then_some(cache.entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::{Entry as KEntry, Group, Times};
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, expires_in_days: Option<i64>) -> KEntry {
+        let mut entry = test_util::entry(&[("Title", title)]);
+        entry.tags.push("work".to_string());
+        if let Some(days) = expires_in_days {
+            entry.times.expires = true;
+            entry
+                .times
+                .set_expiry(Times::now() + TimeDelta::try_days(days).unwrap());
+        }
+        entry
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", None));
+        work.add_child(entry("VPN", Some(-1)));
+        let mut bin = Group::new("Recycle Bin");
+        bin.add_child(entry("Old", Some(30)));
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        db.root.add_child(work);
+        db.root.add_child(bin);
+        db
+    }
+
+    #[test]
+    fn keeps_metadata_only() {
+        let entries = entries(&database());
+        let paths: Vec<String> = entries.iter().map(CachedEntry::path).collect();
+        assert_eq!(paths, ["Work/GitHub", "Work/VPN", "Recycle Bin/Old"]);
+        let flags: Vec<_> = entries
+            .iter()
+            .map(|e| (e.recycled, e.expires.is_some(), e.is_expired()))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (false, false, false),
+                (false, true, true),
+                (true, true, false)
+            ]
+        );
+        assert_eq!(entries[0].tags, ["work"]);
+    }
+
+    #[test]
+    fn is_fresh_until_the_database_changes() {
+        let db_path = crate::import::temp_file("cached.kdbx", "database");
+        let sidecar = sidecar_path(&db_path);
+        assert_eq!(
+            sidecar.file_name().unwrap().to_str().unwrap(),
+            format!("kpass-test-{}-cached.kdbx.cache", std::process::id())
+        );
+        let key = [3; 32];
+        assert!(read_with(&db_path, &key).unwrap().is_none());
+
+        write_with(&database(), &db_path, &key).unwrap();
+        let cached = fs::read(&sidecar).unwrap();
+        assert!(!cached.windows(6).any(|window| window == b"GitHub"));
+        assert_eq!(read_with(&db_path, &key).unwrap().unwrap().len(), 3);
+        assert!(read_with(&db_path, &[4; 32]).is_err());
+
+        fs::write(&db_path, "changed database").unwrap();
+        assert!(read_with(&db_path, &key).unwrap().is_none());
+        fs::remove_file(&db_path).unwrap();
+        fs::remove_file(&sidecar).unwrap();
+    }
+}
@@ -0,0 +1,557 @@
+//! A compact log of what each save changed, kept in the database's
+//! custom data so it travels with the database. Lighter than diffing backups.
+//!
+//! Changes are found by comparing against a snapshot
+//! of the entries taken when the database was opened (or last saved).
+//! The same comparison gives the plain-language summary shown before
+//! each save (see `summary`), whether or not the changelog is enabled.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use chrono::{NaiveDateTime, Utc};
+use keepass::{
+    db::{CustomDataItem, Entry as KEntry, Times, Value},
+    Database,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{config, entries_with_paths, journal, Entry};
+
+/// Custom data key the changelog is kept under.
+const CHANGELOG_KEY: &str = "kpass.changelog";
+
+/// How many changes to keep; older ones are dropped.
+const MAX_CHANGES: usize = 500;
+
+/// An entry (without history), with its path.
+type Located = (String, KEntry);
+
+/// Entries by UUID.
+type Snapshot = HashMap<Uuid, Located>;
+
+/// Snapshots of each database as of when it was opened or last saved.
+static SNAPSHOTS: Mutex<BTreeMap<PathBuf, Snapshot>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Change {
+    time: NaiveDateTime,
+    uuid: String,
+    /// Path of the entry, e.g. `Work/Servers/db1`.
+    entry: String,
+    /// `added`, `modified` or `removed`.
+    kind: String,
+    /// Names of the changed fields, for modified entries.
+    fields: Vec<String>,
+}
+
+fn current(db: &Database) -> Snapshot {
+    entries_with_paths(&db.root)
+        .into_iter()
+        .map(|(mut path, entry)| {
+            path.push(Entry(entry).to_string());
+            let mut entry = entry.clone();
+            entry.history = None;
+            (entry.uuid, (path.join("/"), entry))
+        })
+        .collect()
+}
+
+/// Remember the database's entries, to compare against on the next save.
+pub fn snapshot(db_path: &Path, db: &Database) {
+    SNAPSHOTS
+        .lock()
+        .unwrap()
+        .insert(db_path.to_path_buf(), current(db));
+}
+
+/// Names of the fields that differ between two versions of an entry.
+fn changed_fields(old: &KEntry, new: &KEntry) -> Vec<String> {
+    let mut fields: Vec<String> = old
+        .fields
+        .keys()
+        .chain(new.fields.keys())
+        .filter(|name| old.fields.get(*name) != new.fields.get(*name))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields.dedup();
+    if old.tags != new.tags {
+        fields.push("Tags".to_string());
+    }
+    if old.times.expires != new.times.expires || old.times.get_expiry() != new.times.get_expiry() {
+        fields.push("Expiry".to_string());
+    }
+    if old.autotype != new.autotype {
+        fields.push("Auto-type".to_string());
+    }
+    fields
+}
+
+/// A field's name in summaries.
+fn label(field: &str) -> &str {
+    match field {
+        "Title" => "title",
+        "UserName" => "username",
+        "Password" => "password",
+        "Notes" => "notes",
+        "otp" => "OTP",
+        "Tags" => "tags",
+        "Expiry" => "expiry",
+        "Auto-type" => "auto-type",
+        field => field,
+    }
+}
+
+/// How an entry's fields changed, e.g. `password changed, URL added`.
+fn describe(old: &KEntry, new: &KEntry) -> Vec<String> {
+    changed_fields(old, new)
+        .iter()
+        .map(|field| {
+            let verb = match (
+                old.fields.contains_key(field),
+                new.fields.contains_key(field),
+            ) {
+                (false, true) => "added",
+                (true, false) => "removed",
+                _ => "changed",
+            };
+            format!("{} {}", label(field), verb)
+        })
+        .collect()
+}
+
+/// How entries differ between two snapshots.
+struct Comparison<'a> {
+    added: Vec<&'a Located>,
+    /// The old and new versions of entries that changed or moved.
+    modified: Vec<(&'a Located, &'a Located)>,
+    removed: Vec<&'a Located>,
+}
+
+fn compare<'a>(before: &'a Snapshot, after: &'a Snapshot) -> Comparison<'a> {
+    let mut comparison = Comparison {
+        added: vec![],
+        modified: vec![],
+        removed: vec![],
+    };
+    for (uuid, new) in after {
+        match before.get(uuid) {
+            None => comparison.added.push(new),
+            Some(old) => {
+                if old.0 != new.0 || !changed_fields(&old.1, &new.1).is_empty() {
+                    comparison.modified.push((old, new));
+                }
+            }
+        }
+    }
+    for (uuid, old) in before {
+        if !after.contains_key(uuid) {
+            comparison.removed.push(old);
+        }
+    }
+    comparison.added.sort_by(|a, b| a.0.cmp(&b.0));
+    comparison.modified.sort_by(|a, b| a.1 .0.cmp(&b.1 .0));
+    comparison.removed.sort_by(|a, b| a.0.cmp(&b.0));
+    comparison
+}
+
+/// How an entry changed, including where it moved from.
+fn describe_modified(old: &Located, new: &Located) -> Vec<String> {
+    let mut changes = describe(&old.1, &new.1);
+    if old.0 != new.0 {
+        changes.push(format!("moved from {}", old.0));
+    }
+    changes
+}
+
+/// The changes, one entry per line, e.g. `~ Work/GitHub: password changed`.
+/// Only field names are shown, never their values.
+fn lines(comparison: &Comparison) -> Vec<String> {
+    let added = comparison
+        .added
+        .iter()
+        .map(|(path, _)| format!("+ {}", path));
+    let modified = comparison
+        .modified
+        .iter()
+        .map(|(old, new)| format!("~ {}: {}", new.0, describe_modified(old, new).join(", ")));
+    let removed = comparison
+        .removed
+        .iter()
+        .map(|(path, _)| format!("- {}", path));
+    added.chain(modified).chain(removed).collect()
+}
+
+/// A one-line, plain-language summary of the changes since the snapshot,
+/// e.g. `Updating 'GitHub': password changed, URL added`,
+/// or `None` if no entries changed.
+pub fn summary(db_path: &Path, db: &Database) -> Option<String> {
+    let snapshots = SNAPSHOTS.lock().unwrap();
+    let before = snapshots.get(db_path)?;
+    let after = current(db);
+    let comparison = compare(before, &after);
+
+    let title = |entry: &KEntry| format!("'{}'", Entry(entry));
+    match (
+        comparison.added.as_slice(),
+        comparison.modified.as_slice(),
+        comparison.removed.as_slice(),
+    ) {
+        ([], [], []) => None,
+        ([(_, entry)], [], []) => Some(format!("Adding {}", title(entry))),
+        ([], [], [(_, entry)]) => Some(format!("Removing {}", title(entry))),
+        ([], [(old, new)], []) => {
+            let mut changes = describe(&old.1, &new.1);
+            if old.0 != new.0 {
+                changes.push("moved".to_string());
+            }
+            Some(format!(
+                "Updating {}: {}",
+                title(&new.1),
+                changes.join(", ")
+            ))
+        }
+        _ => {
+            let total =
+                comparison.added.len() + comparison.modified.len() + comparison.removed.len();
+            Some(format!(
+                "Changing {} entries: {}",
+                total,
+                counts(&comparison)
+            ))
+        }
+    }
+}
+
+/// E.g. `2 added, 1 updated`.
+fn counts(comparison: &Comparison) -> String {
+    let counts: Vec<String> = [
+        (comparison.added.len(), "added"),
+        (comparison.modified.len(), "updated"),
+        (comparison.removed.len(), "removed"),
+    ]
+    .iter()
+    .filter(|(n, _)| *n > 0)
+    .map(|(n, verb)| format!("{} {}", n, verb))
+    .collect();
+    counts.join(", ")
+}
+
+/// Each change since the snapshot, one per line,
+/// if more than one entry changed (otherwise `summary` says it all).
+pub fn pending(db_path: &Path, db: &Database) -> Vec<String> {
+    let snapshots = SNAPSHOTS.lock().unwrap();
+    let Some(before) = snapshots.get(db_path) else {
+        return vec![];
+    };
+    let after = current(db);
+    let lines = lines(&compare(before, &after));
+    if lines.len() > 1 {
+        lines
+    } else {
+        vec![]
+    }
+}
+
+/// Print how the second database differs from the first, by entry.
+/// Entries are matched by UUID, so this is meant for copies of the
+/// same database (e.g. a backup or a synced copy).
+pub fn diff(a: &Database, b: &Database) {
+    let (before, after) = (current(a), current(b));
+    let comparison = compare(&before, &after);
+    let lines = lines(&comparison);
+    if lines.is_empty() {
+        info!("{}", tr!("no-differences"));
+        return;
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+    info!("{}.", counts(&comparison));
+}
+
+/// The changelog stored in the database, oldest first.
+fn read(db: &Database) -> Result<Vec<Change>> {
+    match db.meta.custom_data.items.get(CHANGELOG_KEY) {
+        Some(CustomDataItem {
+            value: Some(Value::Unprotected(json)),
+            ..
+        }) => Ok(serde_json::from_str(json)?),
+        _ => Ok(vec![]),
+    }
+}
+
+/// Changes a save recorded, to add to the journal once it's written.
+pub struct Recorded(Vec<Change>);
+
+/// Add the changes since the snapshot to the database's changelog.
+/// Called just before saving; once saved, add them to the journal too
+/// (see `add_to_journal`).
+pub fn record(db_path: &Path, db: &mut Database) -> Result<Recorded> {
+    let config = config::get();
+    if !config.changelog && !config.journal {
+        return Ok(Recorded(vec![]));
+    }
+    let snapshots = SNAPSHOTS.lock().unwrap();
+    let Some(before) = snapshots.get(db_path) else {
+        return Ok(Recorded(vec![]));
+    };
+    let after = current(db);
+    let time = Utc::now().naive_utc();
+    let change = |uuid: &Uuid, entry: &str, kind: &str, fields| Change {
+        time,
+        uuid: uuid.to_string(),
+        entry: entry.to_string(),
+        kind: kind.to_string(),
+        fields,
+    };
+
+    let comparison = compare(before, &after);
+    let mut changes = vec![];
+    for (path, entry) in comparison.added {
+        changes.push(change(&entry.uuid, path, "added", vec![]));
+    }
+    for (old, (path, entry)) in comparison.modified {
+        let mut fields = changed_fields(&old.1, entry);
+        if old.0 != *path {
+            fields.push("Group".to_string());
+        }
+        changes.push(change(&entry.uuid, path, "modified", fields));
+    }
+    for (path, entry) in comparison.removed {
+        changes.push(change(&entry.uuid, path, "removed", vec![]));
+    }
+    if changes.is_empty() {
+        return Ok(Recorded(vec![]));
+    }
+    changes.sort_by(|a, b| a.entry.cmp(&b.entry));
+    if !config.changelog {
+        return Ok(Recorded(changes));
+    }
+
+    let mut log = read(db)?;
+    log.extend(changes.iter().cloned());
+    let excess = log.len().saturating_sub(MAX_CHANGES);
+    log.drain(..excess);
+    db.meta.custom_data.items.insert(
+        CHANGELOG_KEY.to_string(),
+        CustomDataItem {
+            value: Some(Value::Unprotected(serde_json::to_string(&log)?)),
+            last_modification_time: Some(Times::now()),
+        },
+    );
+    Ok(Recorded(changes))
+}
+
+/// Add a save's changes to the journal (see `journal`), if it's enabled.
+/// Called once the save is written, so failed or cancelled saves leave
+/// nothing in it.
+pub fn add_to_journal(db_path: &Path, recorded: &Recorded) -> Result<()> {
+    if !config::get().journal || recorded.0.is_empty() {
+        return Ok(());
+    }
+    let records = recorded
+        .0
+        .iter()
+        .map(|change| (change.kind.as_str(), change.entry.as_str()));
+    journal::append(db_path, records)
+}
+
+/// Print the last `n` changes, newest first.
+pub fn print(db: &Database, n: usize) -> Result<()> {
+    let log = read(db)?;
+    if log.is_empty() {
+        info!("{}", tr!("no-changelog"));
+        return Ok(());
+    }
+    for change in log.iter().rev().take(n) {
+        let time = change.time.and_utc().with_timezone(&chrono::Local);
+        print!(
+            "{} {:8} {}",
+            time.format("%Y-%m-%d %H:%M"),
+            change.kind,
+            change.entry
+        );
+        if !change.fields.is_empty() {
+            print!(" ({})", change.fields.join(", "));
+        }
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::{Group, Node};
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, password: &str) -> KEntry {
+        test_util::entry_protecting(&[("Title", title), ("Password", password)], &["Password"])
+    }
+
+    fn set_password(entry: &mut KEntry, password: &str) {
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected(password.as_bytes().into()),
+        );
+    }
+
+    /// A database with a `Work` group holding `entries`.
+    fn database(entries: &[&KEntry]) -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        for entry in entries {
+            work.add_child((*entry).clone());
+        }
+        db.root.add_child(work);
+        db
+    }
+
+    fn work(db: &mut Database) -> &mut Group {
+        match &mut db.root.children[0] {
+            Node::Group(group) => group,
+            Node::Entry(_) => unreachable!(),
+        }
+    }
+
+    fn work_entry(db: &mut Database, i: usize) -> &mut KEntry {
+        match &mut work(db).children[i] {
+            Node::Entry(entry) => entry,
+            Node::Group(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn names_changed_fields() {
+        let old = entry("GitHub", "hunter2");
+        let mut new = old.clone();
+        assert!(changed_fields(&old, &new).is_empty());
+        set_password(&mut new, "hunter3");
+        new.fields.insert(
+            "URL".to_string(),
+            Value::Unprotected("https://github.com".to_string()),
+        );
+        new.tags.push("work".to_string());
+        new.times.expires = true;
+        assert_eq!(
+            changed_fields(&old, &new),
+            ["Password", "URL", "Tags", "Expiry"]
+        );
+        assert_eq!(
+            describe(&old, &new),
+            [
+                "password changed",
+                "URL added",
+                "tags changed",
+                "expiry changed"
+            ]
+        );
+        assert_eq!(describe(&new, &old)[1], "URL removed");
+    }
+
+    #[test]
+    fn summarizes_changes() {
+        let path = Path::new("/kpass-test/summary.kdbx");
+        let mut db = database(&[&entry("GitHub", "a"), &entry("GitLab", "b")]);
+        assert_eq!(summary(path, &db), None);
+        snapshot(path, &db);
+        assert_eq!(summary(path, &db), None);
+
+        let github = work_entry(&mut db, 0);
+        let old = github.clone();
+        // Only the entry matters, not its history.
+        github
+            .history
+            .get_or_insert_with(Default::default)
+            .add_entry(old);
+        assert_eq!(summary(path, &db), None);
+        set_password(work_entry(&mut db, 0), "c");
+        assert_eq!(
+            summary(path, &db).unwrap(),
+            "Updating 'GitHub': password changed"
+        );
+        snapshot(path, &db);
+
+        work(&mut db).add_child(entry("Gitea", "d"));
+        assert_eq!(summary(path, &db).unwrap(), "Adding 'Gitea'");
+        work(&mut db).children.remove(1);
+        assert_eq!(
+            summary(path, &db).unwrap(),
+            "Changing 2 entries: 1 added, 1 removed"
+        );
+        work(&mut db).children.pop();
+        assert_eq!(summary(path, &db).unwrap(), "Removing 'GitLab'");
+    }
+
+    #[test]
+    fn reads_the_stored_changelog() {
+        let mut db = Database::new(Default::default());
+        assert!(read(&db).unwrap().is_empty());
+        let json = r#"[{"time": "2024-01-02T03:04:05", "uuid": "", "entry": "Work/GitHub",
+            "kind": "modified", "fields": ["Password"]}]"#;
+        db.meta.custom_data.items.insert(
+            CHANGELOG_KEY.to_string(),
+            CustomDataItem {
+                value: Some(Value::Unprotected(json.to_string())),
+                last_modification_time: None,
+            },
+        );
+        let log = read(&db).unwrap();
+        assert_eq!(
+            (log[0].entry.as_str(), log[0].kind.as_str()),
+            ("Work/GitHub", "modified")
+        );
+        assert_eq!(log[0].fields, ["Password"]);
+    }
+
+    #[test]
+    fn compares_snapshots() {
+        let (kept, changed, removed) =
+            (entry("Kept", "a"), entry("Changed", "b"), entry("Old", "c"));
+        let before = database(&[&kept, &changed, &removed]);
+        let mut changed = changed.clone();
+        set_password(&mut changed, "d");
+        let mut after = database(&[&changed, &entry("New", "e")]);
+        // Moving an entry counts as modifying it.
+        let mut moved = Group::new("Moved");
+        moved.add_child(kept);
+        after.root.add_child(moved);
+
+        let (before, after) = (current(&before), current(&after));
+        let comparison = compare(&before, &after);
+        assert_eq!(counts(&comparison), "1 added, 2 updated, 1 removed");
+        assert_eq!(
+            lines(&comparison),
+            [
+                "+ Work/New",
+                "~ Moved/Kept: moved from Work/Kept",
+                "~ Work/Changed: password changed",
+                "- Work/Old",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_pending_changes() {
+        let path = Path::new("/kpass-test/pending.kdbx");
+        let mut db = database(&[&entry("GitHub", "a")]);
+        assert!(pending(path, &db).is_empty());
+        snapshot(path, &db);
+        // A single change is left to the summary.
+        set_password(work_entry(&mut db, 0), "b");
+        assert!(pending(path, &db).is_empty());
+        work(&mut db).add_child(entry("GitLab", "c"));
+        assert_eq!(
+            pending(path, &db),
+            ["+ Work/GitLab", "~ Work/GitHub: password changed"]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Checking a database's integrity (`kpass check`), e.g. after a sync
+//! conflict or editing it with other clients: that it decrypts and parses
+//! (unlocking it does that), that no two entries or groups share a UUID,
+//! that field references point to existing entries, that OTP URIs are
+//! valid, and that entries' custom icons and the recycle bin exist.
+//!
+//! With `repair`, what can be fixed without losing anything is: entries
+//! and groups sharing a UUID get new ones, and references to missing
+//! icons or a missing recycle bin are dropped (KeePass then uses the
+//! standard icon, or creates a new recycle bin). Broken references and
+//! OTP URIs need a look, so they're only reported.
+//!
+//! Attachments can't be checked, as `keepass` doesn't load them.
+
+use std::collections::HashSet;
+
+use keepass::{
+    db::{Entry as KEntry, Group, History, Node},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{entries_with_paths, otp, reference, theme, Entry};
+
+/// What the check found.
+#[derive(Default)]
+pub struct Report {
+    pub problems: Vec<String>,
+    /// How many of the problems can be repaired (and were, if repairing).
+    pub repairable: usize,
+}
+
+/// Give the entry a new UUID, in its history too.
+fn renew_uuid(entry: &mut KEntry) {
+    entry.uuid = Uuid::new_v4();
+    if let Some(history) = entry.history.take() {
+        let mut renewed = History::default();
+        for mut version in history.get_entries().iter().rev().cloned() {
+            version.uuid = entry.uuid;
+            renewed.add_entry(version);
+        }
+        entry.history = Some(renewed);
+    }
+}
+
+/// Find entries and groups whose UUID was already seen.
+fn check_uuids(
+    group: &mut Group,
+    path: &mut Vec<String>,
+    seen: &mut HashSet<Uuid>,
+    icons: &HashSet<Uuid>,
+    repair: bool,
+    report: &mut Report,
+) {
+    for node in &mut group.children {
+        match node {
+            Node::Group(child) => {
+                path.push(child.name.clone());
+                if !seen.insert(child.uuid) {
+                    report
+                        .problems
+                        .push(format!("Group {} shares its UUID", path.join("/")));
+                    report.repairable += 1;
+                    if repair {
+                        child.uuid = Uuid::new_v4();
+                    }
+                }
+                if child
+                    .custom_icon_uuid
+                    .is_some_and(|uuid| !icons.contains(&uuid))
+                {
+                    report
+                        .problems
+                        .push(format!("Group {} has a missing icon", path.join("/")));
+                    report.repairable += 1;
+                    if repair {
+                        child.custom_icon_uuid = None;
+                    }
+                }
+                check_uuids(child, path, seen, icons, repair, report);
+                path.pop();
+            }
+            Node::Entry(entry) => {
+                let mut name = path.clone();
+                name.push(Entry(entry).to_string());
+                let name = name.join("/");
+                if !seen.insert(entry.uuid) {
+                    report.problems.push(format!("{} shares its UUID", name));
+                    report.repairable += 1;
+                    if repair {
+                        renew_uuid(entry);
+                    }
+                }
+                if entry
+                    .custom_icon_uuid
+                    .is_some_and(|uuid| !icons.contains(&uuid))
+                {
+                    report.problems.push(format!("{} has a missing icon", name));
+                    report.repairable += 1;
+                    if repair {
+                        entry.custom_icon_uuid = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether the group is, or contains, the group with the UUID.
+fn has_group(group: &Group, uuid: &Uuid) -> bool {
+    group.uuid == *uuid
+        || group.children.iter().any(|node| match node {
+            Node::Group(child) => has_group(child, uuid),
+            Node::Entry(_) => false,
+        })
+}
+
+/// Check the database, repairing what's safe to if `repair`.
+pub fn check(db: &mut Database, repair: bool) -> Report {
+    let mut report = Report::default();
+
+    let icons: HashSet<Uuid> = db.meta.custom_icons.icons.iter().map(|i| i.uuid).collect();
+    let mut seen = HashSet::from([db.root.uuid]);
+    check_uuids(
+        &mut db.root,
+        &mut vec![],
+        &mut seen,
+        &icons,
+        repair,
+        &mut report,
+    );
+
+    if let Some(bin) = db.meta.recyclebin_uuid {
+        if !has_group(&db.root, &bin) {
+            report
+                .problems
+                .push("The recycle bin group is missing".to_string());
+            report.repairable += 1;
+            if repair {
+                db.meta.recyclebin_uuid = None;
+            }
+        }
+    }
+
+    for (mut path, entry) in entries_with_paths(&db.root) {
+        path.push(Entry(entry).to_string());
+        let name = path.join("/");
+        for broken in reference::broken(db, entry) {
+            report.problems.push(format!(
+                "{} refers to a missing entry: {{{}}}",
+                name, broken
+            ));
+        }
+        if let Some(Err(err)) = entry.get_raw_otp_value().map(otp::Totp::parse) {
+            report
+                .problems
+                .push(format!("{} has an invalid OTP URI: {}", name, err));
+        }
+    }
+    report
+}
+
+/// Print the problems found, and what was repaired.
+pub fn print(report: &Report, repair: bool) {
+    if report.problems.is_empty() {
+        println!("{}", theme::good("> No problems found."));
+        return;
+    }
+    for problem in &report.problems {
+        warn!("{}", problem);
+    }
+    if repair {
+        info!(
+            "{}",
+            tr!(
+                "repaired",
+                repaired = report.repairable,
+                count = report.problems.len()
+            )
+        );
+    } else if report.repairable > 0 {
+        info!(
+            "{}",
+            tr!(
+                "repairable",
+                repairable = report.repairable,
+                count = report.problems.len()
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::Value;
+
+    use super::*;
+    use crate::test_util::{db_with, entry};
+
+    #[test]
+    fn finds_nothing_in_a_sound_database() {
+        let github = entry(&[("Title", "GitHub"), ("Password", "hunter2")]);
+        let reference = format!("{{REF:P@I:{}}}", github.uuid.simple());
+        let deploy = entry(&[("Title", "Deploy"), ("Password", &reference)]);
+        let mut db = db_with(vec![github, deploy]);
+        let report = check(&mut db, false);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+    }
+
+    #[test]
+    fn repairs_shared_uuids() {
+        let github = entry(&[("Title", "GitHub"), ("Password", "new")]);
+        let mut old = github.clone();
+        old.fields.insert(
+            "Password".to_string(),
+            Value::Unprotected("old".to_string()),
+        );
+        let mut copy = github.clone();
+        copy.fields
+            .insert("Title".to_string(), Value::Unprotected("Copy".to_string()));
+        copy.history = Some(History::default());
+        copy.history.as_mut().unwrap().add_entry(old);
+        let mut db = db_with(vec![github.clone(), copy]);
+        let mut group = Group::new("Other");
+        group.uuid = db.root.uuid;
+        db.root.add_child(group);
+
+        let report = check(&mut db, false);
+        assert_eq!(
+            report.problems,
+            ["Work/Copy shares its UUID", "Group Other shares its UUID"]
+        );
+        assert_eq!(report.repairable, 2);
+        // Nothing changes without repairing.
+        assert_eq!(check(&mut db, false).problems.len(), 2);
+
+        check(&mut db, true);
+        assert!(check(&mut db, false).problems.is_empty());
+        let entries = entries_with_paths(&db.root);
+        let copy = entries[1].1;
+        assert_ne!(copy.uuid, github.uuid);
+        let history = copy.history.as_ref().unwrap().get_entries();
+        assert_eq!(history[0].uuid, copy.uuid);
+        assert_eq!(history[0].get_password(), Some("old"));
+    }
+
+    #[test]
+    fn drops_missing_icons_and_recycle_bin() {
+        let mut github = entry(&[("Title", "GitHub")]);
+        github.custom_icon_uuid = Some(Uuid::new_v4());
+        let mut db = db_with(vec![github]);
+        db.meta.recyclebin_uuid = Some(Uuid::new_v4());
+        let report = check(&mut db, true);
+        assert_eq!(
+            report.problems,
+            [
+                "Work/GitHub has a missing icon",
+                "The recycle bin group is missing"
+            ]
+        );
+        assert_eq!(report.repairable, 2);
+        assert_eq!(db.meta.recyclebin_uuid, None);
+        assert_eq!(entries_with_paths(&db.root)[0].1.custom_icon_uuid, None);
+    }
+
+    #[test]
+    fn reports_broken_references_and_otp() {
+        let missing = Uuid::new_v4().simple().to_string();
+        let mut db = db_with(vec![entry(&[
+            ("Title", "Deploy"),
+            ("Password", &format!("{{REF:P@I:{}}}", missing)),
+            ("otp", "otpauth://totp/x?digits=6"),
+        ])]);
+        let report = check(&mut db, true);
+        assert_eq!(report.problems.len(), 2);
+        assert_eq!(
+            report.problems[0],
+            format!(
+                "Work/Deploy refers to a missing entry: {{REF:P@I:{}}}",
+                missing
+            )
+        );
+        assert!(report.problems[1].starts_with("Work/Deploy has an invalid OTP URI"));
+        assert_eq!(report.repairable, 0);
+    }
+}
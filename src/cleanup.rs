@@ -0,0 +1,171 @@
+//! Wiping what would outlive kpass if it's killed (`SIGINT`, `SIGTERM`,
+//! `SIGHUP`) or panics mid-flow: the clipboard, if it still holds what
+//! kpass copied, and temporary files still being written. The process'
+//! memory (with the decrypted database and keys) goes back to the kernel
+//! as it exits, and never into a core dump (see `secret::disable_core_dumps`).
+//!
+//! Ctrl-C at a prompt doesn't send a signal; it quits as usual, leaving
+//! the clipboard to be pasted from.
+
+use std::{
+    fs,
+    io::Read,
+    os::fd::FromRawFd,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use wl_clipboard_rs::{
+    copy::{clear, Seat},
+    paste::ClipboardType,
+};
+
+use crate::{clipboard, config::Selection};
+
+/// Where kpass copied something to, if anywhere.
+static COPIED: Mutex<Option<Selection>> = Mutex::new(None);
+
+/// Temporary files and directories to remove.
+static TEMP_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Where the signal handler writes the signal it got, for the cleanup
+/// thread (the handler itself can't do much safely).
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+const SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Note that kpass copied something to the clipboard.
+pub fn copied(selection: Selection) {
+    let mut copied = COPIED.lock().unwrap();
+    *copied = match *copied {
+        Some(before) if before != selection => Some(Selection::Both),
+        _ => Some(selection),
+    };
+}
+
+/// Remove the file or directory if kpass is killed before `untrack`.
+pub fn track(path: &Path) {
+    TEMP_PATHS.lock().unwrap().push(path.to_path_buf());
+}
+
+pub fn untrack(path: &Path) {
+    TEMP_PATHS.lock().unwrap().retain(|p| p != path);
+}
+
+fn wipe() {
+    // Not `lock`, as a panic may have happened while these are held.
+    if let Ok(Some(selection)) = COPIED.try_lock().as_deref() {
+        for (clipboard, copied_to) in [
+            (ClipboardType::Regular, Selection::Clipboard),
+            (ClipboardType::Primary, Selection::Primary),
+        ] {
+            if [copied_to, Selection::Both].contains(selection) && clipboard::holds_copy(clipboard)
+            {
+                let _ = clear(clipboard::copy_type(copied_to), Seat::All);
+            }
+        }
+    }
+    if let Ok(paths) = TEMP_PATHS.try_lock() {
+        for path in paths.iter() {
+            if path.is_dir() {
+                let _ = fs::remove_dir_all(path);
+            } else {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Hand the signal to the thread that wipes (see `install`), as little
+/// besides `write` is async-signal-safe: no locks or allocation here.
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    // SAFETY: `write` is async-signal-safe, the atomic load is lock-free,
+    // and the buffer is a live local byte of the length passed. A write
+    // to a closed or full pipe only fails, which is ignored.
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
+    }
+}
+
+/// Wipe on signals and panics.
+pub fn install() -> Result<()> {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        wipe();
+        default_hook(info);
+    }));
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors `pipe2` writes.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(anyhow!("Couldn't create a pipe for signals"));
+    }
+    SIGNAL_PIPE.store(fds[1], Ordering::Relaxed);
+    // SAFETY: the read end was just opened and nothing else owns it.
+    let mut pipe = unsafe { fs::File::from_raw_fd(fds[0]) };
+    thread::spawn(move || {
+        let mut signal = [0u8];
+        if pipe.read_exact(&mut signal).is_ok() {
+            wipe();
+            // E.g. killed while the full-screen mode is up.
+            let _ = crossterm::terminal::disable_raw_mode();
+            std::process::exit(128 + signal[0] as i32);
+        }
+    });
+
+    for signal in SIGNALS {
+        // SAFETY: `action` is fully initialized (zeroed, then filled in)
+        // before being passed, and `on_signal` is async-signal-safe.
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as *const () as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_temp_paths() {
+        let tracked = |path: &Path| TEMP_PATHS.lock().unwrap().iter().any(|p| p == path);
+        let first = Path::new("/kpass-test/.db.kdbx.tmp");
+        let second = Path::new("/kpass-test/notes");
+        track(first);
+        track(second);
+        assert!(tracked(first) && tracked(second));
+        untrack(first);
+        assert!(!tracked(first) && tracked(second));
+        untrack(second);
+        assert!(!tracked(second));
+    }
+
+    #[test]
+    fn remembers_where_copies_went() {
+        *COPIED.lock().unwrap() = None;
+        copied(Selection::Primary);
+        assert_eq!(*COPIED.lock().unwrap(), Some(Selection::Primary));
+        copied(Selection::Primary);
+        assert_eq!(*COPIED.lock().unwrap(), Some(Selection::Primary));
+        copied(Selection::Clipboard);
+        assert_eq!(*COPIED.lock().unwrap(), Some(Selection::Both));
+        copied(Selection::Primary);
+        assert_eq!(*COPIED.lock().unwrap(), Some(Selection::Both));
+        *COPIED.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,119 @@
+//! Clearing copied secrets from the clipboard after a while
+//! (`clipboard_clear_seconds`, or an entry's `kpass:clip-timeout`), and notifying about copying and clearing,
+//! e.g. when kpass is started from a keybinding with no terminal to print to.
+//!
+//! The clipboard is only cleared if it still holds what kpass copied,
+//! compared by reading it back, so something copied since (even by
+//! another password manager) is left alone. Only a hash of what was
+//! copied is kept to compare against.
+
+use std::{
+    io::{IsTerminal, Read},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::debug;
+use wl_clipboard_rs::{copy, paste};
+use zeroize::Zeroizing;
+
+use crate::{config, config::Selection, notify, SELECTION};
+
+/// How many times kpass has copied, so a clear scheduled for an
+/// earlier copy doesn't clear a later one early.
+static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+/// The hash of what kpass copied last.
+static LAST_COPY: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+/// Whether to notify, as well as print: if configured, or
+/// there's no terminal to see what's printed in.
+fn notifying() -> bool {
+    config::get().notify_clipboard || !std::io::stdout().is_terminal()
+}
+
+/// Where to copy to: as given with `--selection`, or else configured.
+pub fn selection() -> Selection {
+    SELECTION.get().copied().unwrap_or(config::get().selection)
+}
+
+pub(crate) fn copy_type(selection: Selection) -> copy::ClipboardType {
+    match selection {
+        Selection::Clipboard => copy::ClipboardType::Regular,
+        Selection::Primary => copy::ClipboardType::Primary,
+        Selection::Both => copy::ClipboardType::Both,
+    }
+}
+
+fn hash(text: &[u8]) -> [u8; 32] {
+    Sha256::digest(text).into()
+}
+
+/// Whether the clipboard (or primary selection) still holds what kpass
+/// copied last, rather than something copied since. If it can't be read,
+/// it's left alone.
+pub(crate) fn holds_copy(clipboard: paste::ClipboardType) -> bool {
+    // Not `lock`, as this is also used when cleaning up after a panic.
+    let Ok(Some(last)) = LAST_COPY.try_lock().as_deref().copied() else {
+        return false;
+    };
+    let Ok((mut pipe, _)) =
+        paste::get_contents(clipboard, paste::Seat::Unspecified, paste::MimeType::Text)
+    else {
+        return false;
+    };
+    let mut contents = Zeroizing::new(vec![]);
+    pipe.read_to_end(&mut contents).is_ok() && hash(&contents) == last
+}
+
+/// Schedule clearing `text`, which was just copied, after `seconds`
+/// (0 for never), and notify about it. Text that can only be pasted
+/// once clears itself.
+pub fn copied(text: &str, selection: Selection, paste_once: bool, seconds: u64) {
+    let copy = COPIES.fetch_add(1, Ordering::Relaxed) + 1;
+    *LAST_COPY.lock().unwrap() = Some(hash(text.as_bytes()));
+    let clears = seconds > 0 && !paste_once;
+    if notifying() {
+        let message = if clears {
+            format!(
+                "Copied to the clipboard, clearing it in {} seconds.",
+                seconds
+            )
+        } else {
+            "Copied to the clipboard.".to_string()
+        };
+        notify::notify("kpass", &message);
+    }
+    if !clears {
+        return;
+    }
+    thread::spawn(move || {
+        thread::sleep(Duration::from_secs(seconds));
+        if COPIES.load(Ordering::Relaxed) != copy {
+            return;
+        }
+        let mut cleared = false;
+        for (clipboard, copied_to) in [
+            (paste::ClipboardType::Regular, Selection::Clipboard),
+            (paste::ClipboardType::Primary, Selection::Primary),
+        ] {
+            if ![copied_to, Selection::Both].contains(&selection) {
+                continue;
+            }
+            if holds_copy(clipboard) {
+                debug!(selection = ?copied_to, "Clearing");
+                cleared |= copy::clear(copy_type(copied_to), copy::Seat::All).is_ok();
+            } else {
+                debug!(selection = ?copied_to, "Not clearing, something else was copied since");
+            }
+        }
+        if cleared && notifying() {
+            notify::notify("kpass", "Cleared the clipboard.");
+        }
+    });
+}
@@ -0,0 +1,139 @@
+//! Entry foreground/background colors, e.g. as set in KeePassXC
+//! to mark critical credentials.
+
+use anyhow::{anyhow, Result};
+use keepass::{
+    db::{Color, Entry as KEntry, Group, History, Node},
+    Database,
+};
+
+use crate::theme;
+
+/// `text` in the entry's colors, as ANSI (24-bit) escapes.
+pub fn paint(entry: &KEntry, text: &str) -> String {
+    if !theme::enabled() || (entry.foreground_color.is_none() && entry.background_color.is_none()) {
+        return text.to_string();
+    }
+    let mut painted = String::new();
+    if let Some(Color { r, g, b }) = entry.foreground_color {
+        painted.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+    }
+    if let Some(Color { r, g, b }) = entry.background_color {
+        painted.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+    }
+    painted.push_str(text);
+    painted.push_str("\x1b[0m");
+    painted
+}
+
+/// A color as `#rrggbb`.
+pub fn hex(color: &Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// Parse a `#rrggbb` color.
+pub fn parse(value: &str) -> Result<Color> {
+    let mut color: Color = value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Colors look like #rrggbb, not {}", value))?;
+    writable(&mut color);
+    Ok(color)
+}
+
+/// keepass writes each component without zero-padding
+/// (e.g. `#ff0000` as `#ff00`), which it then can't read back.
+/// Nudge low components up so colors survive saving.
+fn writable(color: &mut Color) {
+    for c in [&mut color.r, &mut color.g, &mut color.b] {
+        *c = (*c).max(0x10);
+    }
+}
+
+fn writable_entry(entry: &mut KEntry) {
+    for color in [&mut entry.foreground_color, &mut entry.background_color]
+        .into_iter()
+        .flatten()
+    {
+        writable(color);
+    }
+}
+
+fn writable_group(group: &mut Group) {
+    for node in &mut group.children {
+        match node {
+            Node::Group(child) => writable_group(child),
+            Node::Entry(entry) => {
+                writable_entry(entry);
+                if let Some(history) = entry.history.take() {
+                    // History entries can only be added, so rebuild it.
+                    let mut fixed = History::default();
+                    for mut old in history.get_entries().iter().rev().cloned() {
+                        writable_entry(&mut old);
+                        fixed.add_entry(old);
+                    }
+                    entry.history = Some(fixed);
+                }
+            }
+        }
+    }
+}
+
+/// Make all colors in the database survive saving (see `writable`).
+pub fn make_writable(db: &mut Database) {
+    if let Some(color) = &mut db.meta.color {
+        writable(color);
+    }
+    writable_group(&mut db.root);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        let color = parse(" #80c0ff ").unwrap();
+        assert_eq!((color.r, color.g, color.b), (0x80, 0xc0, 0xff));
+        assert_eq!(hex(&color), "#80c0ff");
+        // Nudged up to survive saving.
+        assert_eq!(hex(&parse("#ff0000").unwrap()), "#ff1010");
+        for invalid in ["red", "#fff", "80c0ff", "#80c0fg"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn makes_colors_writable_throughout() {
+        let green = |g| Color { r: 1, g, b: 0 };
+        let mut entry = KEntry::new();
+        entry.foreground_color = Some(Color::default());
+        let (mut older, mut old) = (entry.clone(), entry.clone());
+        older.background_color = Some(green(0x80));
+        old.background_color = Some(green(0x90));
+        let mut history = History::default();
+        history.add_entry(older);
+        history.add_entry(old);
+        entry.history = Some(history);
+        let mut db = Database::new(Default::default());
+        db.meta.color = Some(Color::default());
+        let mut group = Group::new("Work");
+        group.add_child(entry);
+        db.root.add_child(group);
+
+        make_writable(&mut db);
+        assert_eq!(hex(db.meta.color.as_ref().unwrap()), "#101010");
+        let entry = crate::entries(&db).next().unwrap();
+        assert_eq!(hex(entry.foreground_color.as_ref().unwrap()), "#101010");
+        // In the same order, newest first.
+        let history: Vec<String> = entry
+            .history
+            .as_ref()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .map(|old| hex(old.background_color.as_ref().unwrap()))
+            .collect();
+        assert_eq!(history, ["#109010", "#108010"]);
+    }
+}
@@ -0,0 +1,204 @@
+//! Making databases smaller (`kpass compact`): years of edits pile up
+//! history revisions, and records of deleted entries kept for syncing.
+
+use std::{cmp::Reverse, collections::HashSet};
+
+use chrono::{NaiveDateTime, Utc};
+use keepass::{
+    db::{Group, History, Node},
+    Database,
+};
+
+/// What to keep.
+pub struct Limits {
+    /// History revisions per entry.
+    pub keep: usize,
+    /// Days of history.
+    pub max_days: i64,
+    /// Days to remember deleted entries and groups for, so merges
+    /// with copies that still have them delete them there too.
+    pub deleted_days: i64,
+}
+
+impl Limits {
+    /// The database's own history settings (as KeePass uses them),
+    /// or KeePass' defaults.
+    pub fn of(db: &Database) -> Self {
+        Limits {
+            keep: db.meta.history_max_items.unwrap_or(10),
+            max_days: db.meta.maintenance_history_days.unwrap_or(365) as i64,
+            deleted_days: 365,
+        }
+    }
+}
+
+/// What was removed.
+#[derive(Default)]
+pub struct Summary {
+    pub revisions: usize,
+    pub deleted_objects: usize,
+}
+
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.revisions == 0 && self.deleted_objects == 0
+    }
+}
+
+fn age_days(time: &NaiveDateTime) -> i64 {
+    (Utc::now().naive_utc() - *time).num_days()
+}
+
+/// Trim histories and forget old deletions.
+pub fn compact(db: &mut Database, limits: &Limits) -> Summary {
+    fn trim(group: &mut Group, limits: &Limits, summary: &mut Summary) {
+        for node in &mut group.children {
+            match node {
+                Node::Group(child) => trim(child, limits, summary),
+                Node::Entry(entry) => {
+                    let Some(history) = &entry.history else {
+                        continue;
+                    };
+                    // Histories are in file order, which is oldest first in
+                    // KeePass' and KeePassXC's files (and newest first in
+                    // kpass'), so go by modification time.
+                    let revisions = history.get_entries();
+                    let mut newest_first: Vec<usize> = (0..revisions.len()).collect();
+                    newest_first.sort_by_key(|&i| {
+                        Reverse(revisions[i].times.get_last_modification().copied())
+                    });
+                    let kept: HashSet<usize> = newest_first
+                        .into_iter()
+                        .filter(|&i| {
+                            revisions[i]
+                                .times
+                                .get_last_modification()
+                                .is_none_or(|modified| age_days(modified) <= limits.max_days)
+                        })
+                        .take(limits.keep)
+                        .collect();
+                    if kept.len() == revisions.len() {
+                        continue;
+                    }
+                    summary.revisions += revisions.len() - kept.len();
+                    // Keep the order. Revisions can only be added at the front.
+                    let mut trimmed = History::default();
+                    for (_, old) in revisions
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .filter(|(i, _)| kept.contains(i))
+                    {
+                        trimmed.add_entry(old.clone());
+                    }
+                    entry.history = Some(trimmed);
+                }
+            }
+        }
+    }
+
+    let mut summary = Summary::default();
+    trim(&mut db.root, limits, &mut summary);
+
+    let deleted = &mut db.deleted_objects.objects;
+    let before = deleted.len();
+    deleted.retain(|object| age_days(&object.deletion_time) <= limits.deleted_days);
+    summary.deleted_objects = before - deleted.len();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::{Entry, Times};
+
+    use super::*;
+    use crate::test_util;
+
+    fn revision(password: &str, days_ago: i64) -> Entry {
+        let mut entry = test_util::entry(&[("Password", password)]);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    /// A database with one entry, whose history is `revisions` in that order.
+    fn db_with_history(revisions: Vec<Entry>) -> Database {
+        let mut history = History::default();
+        for old in revisions.into_iter().rev() {
+            history.add_entry(old);
+        }
+        let mut entry = revision("current", 0);
+        entry.history = Some(history);
+        let mut db = Database::new(Default::default());
+        db.root.add_child(entry);
+        db
+    }
+
+    fn kept_passwords(db: &Database) -> Vec<String> {
+        let Node::Entry(entry) = &db.root.children[0] else {
+            panic!("Not an entry");
+        };
+        entry
+            .history
+            .as_ref()
+            .unwrap()
+            .get_entries()
+            .iter()
+            .map(|old| old.get_password().unwrap().to_string())
+            .collect()
+    }
+
+    fn limits(keep: usize, max_days: i64) -> Limits {
+        Limits {
+            keep,
+            max_days,
+            deleted_days: 365,
+        }
+    }
+
+    #[test]
+    fn keeps_newest_of_oldest_first_history() {
+        let mut db = db_with_history(vec![
+            revision("a", 40),
+            revision("b", 30),
+            revision("c", 20),
+            revision("d", 10),
+        ]);
+        let summary = compact(&mut db, &limits(2, 365));
+        assert_eq!(summary.revisions, 2);
+        assert_eq!(kept_passwords(&db), ["c", "d"]);
+    }
+
+    #[test]
+    fn keeps_newest_of_newest_first_history() {
+        let mut db = db_with_history(vec![
+            revision("d", 10),
+            revision("c", 20),
+            revision("b", 30),
+            revision("a", 40),
+        ]);
+        compact(&mut db, &limits(2, 365));
+        assert_eq!(kept_passwords(&db), ["d", "c"]);
+    }
+
+    #[test]
+    fn old_revisions_dont_drop_recent_ones() {
+        let mut db = db_with_history(vec![
+            revision("ancient", 800),
+            revision("old", 400),
+            revision("recent", 5),
+        ]);
+        let summary = compact(&mut db, &limits(10, 365));
+        assert_eq!(summary.revisions, 2);
+        assert_eq!(kept_passwords(&db), ["recent"]);
+    }
+
+    #[test]
+    fn leaves_histories_within_limits() {
+        let mut db = db_with_history(vec![revision("a", 20), revision("b", 10)]);
+        assert!(compact(&mut db, &limits(10, 365)).is_empty());
+        assert_eq!(kept_passwords(&db), ["a", "b"]);
+    }
+}
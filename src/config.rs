@@ -0,0 +1,409 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::{audit::RuleConfig, theme::Theme};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// User configuration, read from `~/.config/kpass/config.toml`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Generated passwords below this entropy (in bits) require confirmation.
+    pub min_entropy: f64,
+
+    /// Launcher command for `kpass menu`, e.g. `"rofi -dmenu"`.
+    pub launcher: Option<String>,
+
+    /// Named blocks of text that can be inserted when editing notes.
+    pub snippets: BTreeMap<String, String>,
+
+    /// Keep an encrypted cache of entry titles and groups,
+    /// so `kpass list` works without unlocking.
+    pub cache_metadata: bool,
+
+    /// Default delay between auto-typed keystrokes, in milliseconds.
+    /// Entries can override it.
+    pub autotype_key_delay: u64,
+
+    /// Maximum random delay added between auto-typed keystrokes,
+    /// in milliseconds, so typing looks less mechanical.
+    pub autotype_jitter: u64,
+
+    /// Auto-type text in chunks of this many characters,
+    /// pausing `autotype_chunk_pause` milliseconds in between.
+    pub autotype_chunk_size: usize,
+    pub autotype_chunk_pause: u64,
+
+    /// How many backups to keep per database.
+    pub backup_count: usize,
+
+    /// Where to keep backups (default: next to the database).
+    pub backup_dir: Option<PathBuf>,
+
+    /// Log which entries are used, to show a summary on the next unlock.
+    pub activity_log: bool,
+
+    /// Databases by name, so they can be opened by name (e.g. `kpass work`).
+    /// Running kpass without arguments opens all of them.
+    pub databases: BTreeMap<String, PathBuf>,
+
+    /// Key files required (along with the password) to unlock databases,
+    /// by database path.
+    pub key_files: BTreeMap<PathBuf, PathBuf>,
+
+    /// Default length of reveal sessions, in minutes.
+    pub reveal_minutes: u64,
+
+    /// How long "Show password" shows a password before clearing the screen.
+    pub show_seconds: u64,
+
+    /// Warn about entries expiring within this many days.
+    pub expiry_warning_days: i64,
+
+    /// Flag entries in the picker whose password hasn't changed in this
+    /// many days (0 to not flag them).
+    pub old_password_days: i64,
+
+    /// Record what each save changed in the database, for `kpass changelog`.
+    pub changelog: bool,
+
+    /// Keep a journal of what each save changed in the config dir,
+    /// for `kpass log`, see `journal`.
+    pub journal: bool,
+
+    /// Passwords shorter than this are flagged by `kpass audit passwords`.
+    pub min_password_length: usize,
+
+    /// Extra rules for `kpass audit rules`.
+    pub audit_rules: Vec<RuleConfig>,
+
+    /// How to notify about events like the agent locking:
+    /// `desktop`, `bell`, `none`, or a command to run.
+    pub notify: String,
+
+    /// Copied text can be pasted only once, then the clipboard is
+    /// empty (also set with `--paste-once`).
+    pub paste_once: bool,
+
+    /// Clear copied text from the clipboard after this
+    /// many seconds (0 to leave it), see `clipboard`.
+    pub clipboard_clear_seconds: u64,
+
+    /// Where to copy to: the regular `clipboard`, the `primary` selection
+    /// (pasted with middle-click) or `both` (also set with `--selection`).
+    pub selection: Selection,
+
+    /// Notify (see `notify`) when copying to and clearing the clipboard,
+    /// as well as when there's no terminal to print to.
+    pub notify_clipboard: bool,
+
+    /// How the entry picker is sorted (can be changed in the picker).
+    pub entry_sort: EntrySort,
+
+    /// How entries' icons are shown, see `icons`.
+    pub icons: IconStyle,
+
+    /// Colors for output, see `theme`.
+    pub theme: Theme,
+
+    /// How many options pickers show at once.
+    pub page_size: usize,
+
+    /// Move through pickers with h/j/k/l too (which then
+    /// can't be typed into their filters).
+    pub vim_keys: bool,
+
+    /// The interactive session's actions, in the order shown; actions left
+    /// out are hidden (empty for all of them), see `main::session_actions`.
+    pub actions: Vec<String>,
+
+    /// Language for the interactive UI, e.g. `de` (the locale's
+    /// language if unset), see `i18n`.
+    pub language: Option<String>,
+
+    /// Also find entries by (exact) text in their notes when searching.
+    pub search_notes: bool,
+
+    /// Show in the terminal's title whether the session is unlocked.
+    pub terminal_title: bool,
+
+    /// Lock the interactive session after this many minutes
+    /// without input (0 to never lock).
+    pub idle_lock_minutes: u64,
+
+    /// Quit if a password prompt gets no input for this many minutes, and
+    /// close the launcher of `kpass menu` (0 to wait forever).
+    pub prompt_timeout_minutes: u64,
+
+    /// After this many failed unlock attempts in a row, remove the cached
+    /// password and lock for an hour (0 to only cool down), see `lockout`.
+    pub max_unlock_attempts: u32,
+
+    /// How the cached password is encrypted: `quick` (with the quick
+    /// password), `gpg:<recipient>` or `age:<identity file>`, see `pw_cache`.
+    pub password_cache: String,
+
+    /// Before prompting for the password, ask paired devices for it,
+    /// see `pairing`.
+    pub pairing_unlock: bool,
+
+    /// Also send the summary of changes shown before each save
+    /// through the notification backend (see `notify`).
+    pub notify_changes: bool,
+
+    /// A pinentry program (e.g. `pinentry-gnome3`) to ask for
+    /// the master password with, instead of the terminal.
+    pub pinentry: Option<String>,
+
+    /// Commit the database to the git repository it's in after each save,
+    /// and push it if `git_push`, see `git`.
+    pub git_commit: bool,
+    pub git_push: bool,
+
+    /// Reload databases in the interactive session
+    /// when something else changes them, see `watch`.
+    pub reload_on_change: bool,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            min_entropy: 60.,
+            launcher: None,
+            snippets: BTreeMap::new(),
+            cache_metadata: false,
+            autotype_key_delay: 0,
+            autotype_jitter: 0,
+            autotype_chunk_size: 0,
+            autotype_chunk_pause: 300,
+            backup_count: 10,
+            backup_dir: None,
+            activity_log: true,
+            databases: BTreeMap::new(),
+            key_files: BTreeMap::new(),
+            reveal_minutes: 5,
+            show_seconds: 30,
+            expiry_warning_days: 14,
+            old_password_days: 730,
+            changelog: false,
+            journal: false,
+            min_password_length: 12,
+            audit_rules: vec![],
+            notify: "desktop".to_string(),
+            paste_once: false,
+            clipboard_clear_seconds: 0,
+            selection: Selection::Clipboard,
+            notify_clipboard: false,
+            entry_sort: EntrySort::Frecency,
+            icons: IconStyle::Emoji,
+            theme: Theme::default(),
+            page_size: 15,
+            vim_keys: false,
+            actions: vec![],
+            language: None,
+            search_notes: false,
+            terminal_title: true,
+            idle_lock_minutes: 10,
+            prompt_timeout_minutes: 5,
+            max_unlock_attempts: 0,
+            password_cache: "quick".to_string(),
+            pairing_unlock: false,
+            notify_changes: false,
+            pinentry: None,
+            git_commit: false,
+            git_push: false,
+            reload_on_change: true,
+        }
+    }
+}
+
+/// Where copied text goes.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// The regular clipboard, pasted with Ctrl+V.
+    Clipboard,
+    /// The primary selection, pasted with middle-click.
+    Primary,
+    Both,
+}
+impl Selection {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "clipboard" => Ok(Selection::Clipboard),
+            "primary" => Ok(Selection::Primary),
+            "both" => Ok(Selection::Both),
+            _ => Err(anyhow!(
+                "Unknown selection {}, expected clipboard, primary or both",
+                name
+            )),
+        }
+    }
+}
+
+/// How entries' icons are shown.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IconStyle {
+    Emoji,
+    /// Glyphs from a Nerd Font, which the terminal must use.
+    Nerd,
+    None,
+}
+
+/// Orders for the entry picker.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntrySort {
+    /// As the entries are stored in the database.
+    Database,
+    Title,
+    /// Most recently modified first.
+    Modified,
+    /// By group path, then title.
+    Group,
+    /// Most used (often and recently) first, see `usage`.
+    Frecency,
+}
+impl EntrySort {
+    /// The order after this one, to cycle through them.
+    pub fn next(self) -> Self {
+        match self {
+            EntrySort::Database => EntrySort::Title,
+            EntrySort::Title => EntrySort::Modified,
+            EntrySort::Modified => EntrySort::Group,
+            EntrySort::Group => EntrySort::Frecency,
+            EntrySort::Frecency => EntrySort::Database,
+        }
+    }
+}
+impl std::fmt::Display for EntrySort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EntrySort::Database => "database order",
+            EntrySort::Title => "title",
+            EntrySort::Modified => "last modified",
+            EntrySort::Group => "group",
+            EntrySort::Frecency => "most used",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where kpass' configuration lives.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("kpass"))
+}
+
+/// Load the config file, if there is one.
+pub fn load() -> Result<()> {
+    let path = config_dir().map(|dir| dir.join("config.toml"));
+    let config = match path.filter(|path| path.exists()) {
+        Some(path) => {
+            let contents = std::fs::read_to_string(&path)?;
+            toml::from_str(&contents)
+                .with_context(|| format!("Invalid config file: {}", path.display()))?
+        }
+        None => Config::default(),
+    };
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// The key file configured for the database, if any.
+pub fn key_file(db_path: &Path) -> Option<&'static Path> {
+    let db_path = db_path.canonicalize().ok()?;
+    get()
+        .key_files
+        .iter()
+        .find(|(db, _)| db.canonicalize().ok().as_ref() == Some(&db_path))
+        .map(|(_, key_file)| key_file.as_path())
+}
+
+/// The path of the database named `name` in the config,
+/// or else `name` itself as a path.
+pub fn database_path(name: &str) -> PathBuf {
+    match get().databases.get(name) {
+        Some(path) => path.clone(),
+        None => PathBuf::from(name),
+    }
+}
+
+/// The name the database at `db_path` has in the config, if any.
+pub fn database_name(db_path: &Path) -> Option<&'static str> {
+    let db_path = db_path.canonicalize().ok()?;
+    get()
+        .databases
+        .iter()
+        .find(|(_, path)| path.canonicalize().ok().as_ref() == Some(&db_path))
+        .map(|(name, _)| name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            min_entropy = 80
+            [snippets]
+            sig = "Regards,\nAlice"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.min_entropy, 80.);
+        assert_eq!(config.snippets["sig"], "Regards,\nAlice");
+        assert_eq!(config.backup_count, Config::default().backup_count);
+        assert!(toml::from_str::<Config>("").is_ok());
+        assert!(toml::from_str::<Config>("min_entropy = \"high\"").is_err());
+    }
+
+    #[test]
+    fn cycles_entry_sorts() {
+        let config: Config = toml::from_str(r#"entry_sort = "modified""#).unwrap();
+        assert!(config.entry_sort == EntrySort::Modified);
+        let mut sort = EntrySort::Database;
+        let mut seen = vec![];
+        for _ in 0..5 {
+            seen.push(sort.to_string());
+            sort = sort.next();
+        }
+        assert!(sort == EntrySort::Database);
+        assert_eq!(
+            seen,
+            [
+                "database order",
+                "title",
+                "last modified",
+                "group",
+                "most used"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!(Selection::parse("primary").unwrap(), Selection::Primary);
+        assert_eq!(Selection::parse("both").unwrap(), Selection::Both);
+        assert!(Selection::parse("Primary").is_err());
+        let config: Config = toml::from_str(r#"selection = "both""#).unwrap();
+        assert_eq!(config.selection, Selection::Both);
+        assert!(toml::from_str::<Config>(r#"selection = "secondary""#).is_err());
+    }
+}
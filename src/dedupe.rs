@@ -0,0 +1,391 @@
+//! `kpass dedupe`: finding entries that are likely the same account
+//! (same title and username, or the same password or URL), showing how
+//! they differ, and merging them into one, keeping every field and the
+//! history of each.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Result;
+use keepass::{
+    db::{Entry as KEntry, History, Node, Times, Value},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{address, delete_entry, get_entry_mut, prompt, theme, Entry};
+
+/// An entry outside the recycle bin, with its address.
+struct Candidate {
+    uuid: Uuid,
+    path: String,
+    entry: KEntry,
+}
+
+fn candidates(db: &Database) -> Vec<Candidate> {
+    fn walk(
+        group: &keepass::db::Group,
+        bin: Option<Uuid>,
+        path: &mut Vec<String>,
+        out: &mut Vec<Candidate>,
+    ) {
+        for node in &group.children {
+            match node {
+                Node::Group(child) if Some(child.uuid) == bin => {}
+                Node::Group(child) => {
+                    path.push(child.name.clone());
+                    walk(child, bin, path, out);
+                    path.pop();
+                }
+                Node::Entry(entry) => {
+                    path.push(Entry(entry).to_string());
+                    out.push(Candidate {
+                        uuid: entry.uuid,
+                        path: address::format(path),
+                        entry: entry.clone(),
+                    });
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    let bin = db
+        .meta
+        .recyclebin_uuid
+        .filter(|_| db.meta.recyclebin_enabled != Some(false));
+    let mut out = vec![];
+    walk(&db.root, bin, &mut vec![], &mut out);
+    out
+}
+
+/// Group the candidates (by index) that share a title and username,
+/// a password or a URL, directly or through others.
+fn groups(candidates: &[Candidate]) -> Vec<Vec<usize>> {
+    fn root(parents: &mut [usize], i: usize) -> usize {
+        let mut i = i;
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let mut parents: Vec<usize> = (0..candidates.len()).collect();
+    let mut seen: HashMap<(u8, String), usize> = HashMap::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        let entry = &candidate.entry;
+        let non_empty = |value: Option<&str>| value.filter(|v| !v.is_empty()).map(str::to_string);
+        let keys = [
+            non_empty(entry.get_title()).map(|title| {
+                let username = entry.get_username().unwrap_or_default();
+                (0, format!("{}\0{}", title.to_lowercase(), username))
+            }),
+            non_empty(entry.get_password()).map(|password| (1, password)),
+            non_empty(entry.get_url()).map(|url| (2, url.trim_end_matches('/').to_lowercase())),
+        ];
+        for key in keys.into_iter().flatten() {
+            match seen.get(&key) {
+                Some(&j) => {
+                    let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                    parents[a] = b;
+                }
+                None => {
+                    seen.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..candidates.len() {
+        groups.entry(root(&mut parents, i)).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+    groups
+}
+
+/// A field's value for showing, with protected ones masked.
+fn shown(value: &Value) -> String {
+    match value {
+        Value::Unprotected(text) => text.replace('\n', "⏎"),
+        Value::Protected(secret) if secret.unsecure().is_empty() => String::new(),
+        Value::Protected(_) => "••••••••".to_string(),
+        Value::Bytes(bytes) => format!("({} bytes)", bytes.len()),
+    }
+}
+
+fn is_empty(value: Option<&Value>) -> bool {
+    value.is_none_or(Value::is_empty)
+}
+
+/// Print the fields the entries have in common, then those that differ.
+fn print_diff(entries: &[&Candidate]) {
+    let names: BTreeSet<&String> = entries.iter().flat_map(|c| c.entry.fields.keys()).collect();
+    for name in names {
+        let shown = |value: &Value| match value {
+            Value::Protected(_) => theme::secret(&shown(value)),
+            _ => shown(value),
+        };
+        let values: Vec<Option<&Value>> =
+            entries.iter().map(|c| c.entry.fields.get(name)).collect();
+        if values.iter().all(|value| *value == values[0]) {
+            if let Some(value) = values[0].filter(|value| !value.is_empty()) {
+                println!("  = {}: {}", name, shown(value));
+            }
+            continue;
+        }
+        println!("  ≠ {}:", name);
+        for (candidate, value) in entries.iter().zip(&values) {
+            let value = value.map(shown).unwrap_or_else(|| "(missing)".to_string());
+            println!("      {}: {}", candidate.path, value);
+        }
+    }
+    let tags: Vec<String> = entries.iter().map(|c| c.entry.tags.join(", ")).collect();
+    if tags.iter().any(|t| *t != tags[0]) {
+        println!("  ≠ Tags:");
+        for (candidate, tags) in entries.iter().zip(&tags) {
+            println!("      {}: {}", candidate.path, tags);
+        }
+    }
+}
+
+/// Merge the others into the kept entry: fields it lacks are added,
+/// conflicting ones are picked, tags are combined, and the others'
+/// versions (and their histories) join its history. The others are then
+/// deleted.
+fn merge(db: &mut Database, keep: &Candidate, others: &[&Candidate]) -> Result<()> {
+    let mut merged = keep.entry.clone();
+    for other in others {
+        let mut names: Vec<&String> = other.entry.fields.keys().collect();
+        names.sort();
+        for name in names {
+            let theirs = &other.entry.fields[name];
+            if theirs.is_empty() {
+                continue;
+            }
+            let ours = merged.fields.get(name);
+            if is_empty(ours) {
+                merged.fields.insert(name.clone(), theirs.clone());
+                continue;
+            }
+            if ours == Some(theirs) || matches!(theirs, Value::Bytes(_)) {
+                continue;
+            }
+            let options = vec![
+                tr!(
+                    "value-from",
+                    value = shown(ours.unwrap()),
+                    entry = keep.path
+                ),
+                tr!("value-from", value = shown(theirs), entry = other.path),
+            ];
+            let picked = prompt::select(&format!("{}:", name), options).raw_prompt()?;
+            if picked.index == 1 {
+                merged.fields.insert(name.clone(), theirs.clone());
+            }
+        }
+        for tag in &other.entry.tags {
+            if !merged.tags.contains(tag) {
+                merged.tags.push(tag.clone());
+            }
+        }
+    }
+
+    // Every previous version, oldest first, so the newest ends up on top.
+    let mut versions: Vec<KEntry> = vec![keep.entry.clone()];
+    for entry in std::iter::once(&keep.entry).chain(others.iter().map(|c| &c.entry)) {
+        if let Some(history) = &entry.history {
+            versions.extend(history.get_entries().iter().cloned());
+        }
+    }
+    versions.extend(others.iter().map(|c| c.entry.clone()));
+    versions.sort_by_key(|version| version.times.get_last_modification().copied());
+    let mut history = History::default();
+    for mut version in versions {
+        version.uuid = keep.uuid;
+        history.add_entry(version);
+    }
+    merged.history = Some(history);
+    merged.times.set_last_modification(Times::now());
+
+    if let Some(entry) = get_entry_mut(db, keep.uuid.as_u128()) {
+        *entry = merged;
+    }
+    for other in others {
+        delete_entry(db, &other.uuid)?;
+    }
+    Ok(())
+}
+
+/// Go through the likely duplicates, merging those picked.
+/// Returns whether anything changed.
+pub fn run(db: &mut Database) -> Result<bool> {
+    let candidates = candidates(db);
+    let groups = groups(&candidates);
+    if groups.is_empty() {
+        info!("{}", tr!("no-duplicates"));
+        return Ok(false);
+    }
+    info!("{}", tr!("found-duplicates", count = groups.len()));
+
+    let mut merged = 0;
+    for (n, group) in groups.iter().enumerate() {
+        let entries: Vec<&Candidate> = group.iter().map(|&i| &candidates[i]).collect();
+        println!();
+        info!("{}/{}:", n + 1, groups.len());
+        for candidate in &entries {
+            println!("  - {}", candidate.path);
+        }
+        print_diff(&entries);
+
+        let mut options: Vec<String> = entries
+            .iter()
+            .map(|c| tr!("merge-into", entry = c.path))
+            .collect();
+        options.push(tr!("skip"));
+        options.push(tr!("stop"));
+        let picked = prompt::select(&tr!("merge-confirm"), options).raw_prompt()?;
+        if picked.index == entries.len() {
+            continue;
+        }
+        if picked.index > entries.len() {
+            break;
+        }
+        let keep = entries[picked.index];
+        let others: Vec<&Candidate> = entries
+            .iter()
+            .copied()
+            .filter(|c| c.uuid != keep.uuid)
+            .collect();
+        merge(db, keep, &others)?;
+        info!(
+            "{}",
+            tr!("merged-entries", count = others.len(), entry = keep.path)
+        );
+        merged += 1;
+    }
+    Ok(merged > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::Group;
+
+    use super::*;
+    use crate::test_util::{self, db_with};
+
+    fn entry(fields: &[(&str, &str)], days_ago: i64) -> KEntry {
+        let mut entry = test_util::entry(fields);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    fn titles(candidates: &[Candidate], groups: &[Vec<usize>]) -> Vec<Vec<String>> {
+        groups
+            .iter()
+            .map(|group| group.iter().map(|&i| candidates[i].path.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn groups_likely_duplicates() {
+        let db = db_with(vec![
+            entry(&[("Title", "GitHub"), ("UserName", "alice")], 0),
+            entry(&[("Title", "github"), ("UserName", "alice")], 0),
+            // Same URL as the next, which shares a password with the one after.
+            entry(&[("Title", "A"), ("URL", "https://example.com/")], 0),
+            entry(
+                &[
+                    ("Title", "B"),
+                    ("URL", "https://EXAMPLE.com"),
+                    ("Password", "x"),
+                ],
+                0,
+            ),
+            entry(&[("Title", "C"), ("Password", "x")], 0),
+            // Different usernames, and empty values don't count.
+            entry(&[("Title", "GitHub"), ("UserName", "bob"), ("URL", "")], 0),
+            entry(&[("Title", "D"), ("Password", "")], 0),
+            entry(&[("Title", "E"), ("Password", "")], 0),
+        ]);
+        let candidates = candidates(&db);
+        assert_eq!(
+            titles(&candidates, &groups(&candidates)),
+            [
+                vec!["Work/GitHub", "Work/github"],
+                vec!["Work/A", "Work/B", "Work/C"]
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_recycle_bin() {
+        let mut db = db_with(vec![entry(&[("Title", "GitHub")], 0)]);
+        let mut bin = Group::new("Recycle Bin");
+        bin.add_child(entry(&[("Title", "GitHub")], 0));
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        db.root.add_child(bin);
+        let candidates = candidates(&db);
+        assert_eq!(candidates.len(), 1);
+        assert!(groups(&candidates).is_empty());
+    }
+
+    #[test]
+    fn masks_protected_values() {
+        assert_eq!(shown(&Value::Unprotected("a\nb".to_string())), "a⏎b");
+        assert_eq!(
+            shown(&Value::Protected("secret".as_bytes().into())),
+            "••••••••"
+        );
+        assert_eq!(shown(&Value::Protected("".as_bytes().into())), "");
+        assert_eq!(shown(&Value::Bytes(vec![0; 3])), "(3 bytes)");
+    }
+
+    #[test]
+    fn merges_fields_tags_and_history() {
+        let mut keep = entry(
+            &[("Title", "GitHub"), ("UserName", "alice"), ("URL", "")],
+            2,
+        );
+        keep.tags.push("work".to_string());
+        let mut old = entry(&[("Title", "GitHub"), ("Password", "old")], 10);
+        old.uuid = keep.uuid;
+        keep.history = Some(History::default());
+        keep.history.as_mut().unwrap().add_entry(old);
+        let mut other = entry(
+            &[
+                ("Title", "GitHub"),
+                ("UserName", "alice"),
+                ("URL", "https://github.com"),
+                ("Password", "hunter2"),
+            ],
+            5,
+        );
+        other.tags.extend(["work".to_string(), "dev".to_string()]);
+        let mut db = db_with(vec![keep, other]);
+        let bin = Group::new("Recycle Bin");
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        db.root.add_child(bin);
+
+        let candidates = candidates(&db);
+        merge(&mut db, &candidates[0], &[&candidates[1]]).unwrap();
+        let merged = get_entry_mut(&mut db, candidates[0].uuid.as_u128()).unwrap();
+        assert_eq!(merged.get_url(), Some("https://github.com"));
+        assert_eq!(merged.get_password(), Some("hunter2"));
+        assert_eq!(merged.tags, ["work", "dev"]);
+        // Newest first, all as versions of the kept entry.
+        let history = merged.history.as_ref().unwrap().get_entries();
+        let passwords: Vec<_> = history.iter().map(|e| e.get_password()).collect();
+        assert_eq!(passwords, [None, Some("hunter2"), Some("old")]);
+        assert!(history.iter().all(|e| e.uuid == candidates[0].uuid));
+
+        // The other went to the recycle bin.
+        let remaining = crate::entries_with_paths(&db.root);
+        let paths: Vec<_> = remaining.iter().map(|(path, _)| path.join("/")).collect();
+        assert_eq!(paths, ["Work", "Recycle Bin"]);
+    }
+}
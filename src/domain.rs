@@ -0,0 +1,277 @@
+//! Finding entries for a website by its URL, e.g. for browser and
+//! launcher integrations (`kpass url`, `url:` addresses).
+//!
+//! An entry matches if any of its URLs (the `URL` field, or extra ones in
+//! fields named `URL ...` or `KP2A_URL...`) is for the same host, a domain
+//! the host is under, or the same registrable domain (e.g. `example.co.uk`
+//! for `accounts.example.co.uk`), in that order of preference.
+//!
+//! Registrable domains are found with the public suffixes below, a
+//! subset of the Public Suffix List: the common multi-label country
+//! suffixes, and hosting platforms whose subdomains belong to
+//! different people (so `me.github.io` doesn't match `you.github.io`).
+
+use keepass::db::Entry as KEntry;
+
+/// Public suffixes with more than one label.
+/// Single-label ones (`com`, `de`, ...) are implied.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    // Countries
+    "ac.uk",
+    "co.uk",
+    "gov.uk",
+    "ltd.uk",
+    "me.uk",
+    "net.uk",
+    "org.uk",
+    "plc.uk",
+    "sch.uk",
+    "com.au",
+    "edu.au",
+    "gov.au",
+    "net.au",
+    "org.au",
+    "co.nz",
+    "net.nz",
+    "org.nz",
+    "govt.nz",
+    "ac.jp",
+    "co.jp",
+    "go.jp",
+    "ne.jp",
+    "or.jp",
+    "co.kr",
+    "or.kr",
+    "go.kr",
+    "com.cn",
+    "net.cn",
+    "org.cn",
+    "gov.cn",
+    "com.hk",
+    "com.tw",
+    "com.sg",
+    "com.my",
+    "com.ph",
+    "com.vn",
+    "co.id",
+    "co.in",
+    "net.in",
+    "org.in",
+    "gov.in",
+    "co.il",
+    "org.il",
+    "co.za",
+    "org.za",
+    "gov.za",
+    "com.br",
+    "net.br",
+    "org.br",
+    "gov.br",
+    "com.ar",
+    "com.mx",
+    "com.co",
+    "com.pe",
+    "com.tr",
+    "gov.tr",
+    "com.ua",
+    "com.pl",
+    "net.pl",
+    "org.pl",
+    "com.es",
+    "co.at",
+    "or.at",
+    "gv.at",
+    // Hosting platforms
+    "github.io",
+    "gitlab.io",
+    "pages.dev",
+    "workers.dev",
+    "netlify.app",
+    "vercel.app",
+    "herokuapp.com",
+    "appspot.com",
+    "web.app",
+    "firebaseapp.com",
+    "blogspot.com",
+    "azurewebsites.net",
+    "cloudfront.net",
+    "s3.amazonaws.com",
+    "onrender.com",
+    "fly.dev",
+];
+
+/// The host of a URL, lowercased, e.g. `example.com` for
+/// `https://me@Example.com:8080/login`. The scheme may be left out.
+pub fn host_of(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    let host = host.trim_end_matches('.');
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// The registrable domain of a host: its public suffix and one more
+/// label, e.g. `example.co.uk` for `accounts.example.co.uk`.
+/// IP addresses, and hosts that are public suffixes, have none.
+pub fn base_domain(host: &str) -> Option<&str> {
+    let is_ip = host.contains(':') || host.split('.').all(|label| label.parse::<u8>().is_ok());
+    if is_ip {
+        return None;
+    }
+    let suffix = PUBLIC_SUFFIXES
+        .iter()
+        .filter(|suffix| host == **suffix || host.ends_with(&format!(".{}", suffix)))
+        .max_by_key(|suffix| suffix.len())
+        .copied()
+        .unwrap_or_else(|| host.rsplit('.').next().unwrap_or(host));
+    let rest = host.strip_suffix(suffix)?.strip_suffix('.')?;
+    let label = rest.rsplit('.').next()?;
+    Some(&host[host.len() - suffix.len() - label.len() - 1..])
+}
+
+/// How well an entry's URL matches a host, best last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    /// The same registrable domain, e.g. `example.com` for `mail.example.com`
+    /// and `accounts.example.com`.
+    BaseDomain,
+    /// A domain the host is under, e.g. `example.com` for `accounts.example.com`.
+    Parent,
+    /// The same host.
+    Host,
+}
+
+/// Whether the field holds an extra URL, as KeePassXC (`KP2A_URL`,
+/// `KP2A_URL_1`, ...) or by convention (`URL ...`) stores them.
+pub fn is_extra_url(name: &str) -> bool {
+    name.starts_with("URL ") || name.starts_with("KP2A_URL")
+}
+
+/// The entry's extra URLs (besides the `URL` field), by field name.
+pub fn extra_urls(entry: &KEntry) -> Vec<(&str, &str)> {
+    let mut urls: Vec<(&str, &str)> = entry
+        .fields
+        .keys()
+        .filter(|name| is_extra_url(name))
+        .filter_map(|name| Some((name.as_str(), entry.get(name)?)))
+        .filter(|(_, url)| !url.is_empty())
+        .collect();
+    urls.sort();
+    urls
+}
+
+/// The hosts of all of the entry's URLs.
+pub fn entry_hosts(entry: &KEntry) -> Vec<String> {
+    entry
+        .get_url()
+        .into_iter()
+        .chain(extra_urls(entry).into_iter().map(|(_, url)| url))
+        .filter_map(host_of)
+        .collect()
+}
+
+/// How well the best of the entry's URLs matches the host, if at all.
+pub fn rank(entry: &KEntry, host: &str) -> Option<Rank> {
+    let base = base_domain(host);
+    entry_hosts(entry)
+        .iter()
+        .filter_map(|entry_host| {
+            if entry_host == host {
+                Some(Rank::Host)
+            } else if host.ends_with(&format!(".{}", entry_host)) {
+                // Not for public suffixes, e.g. an entry for `co.uk`.
+                base.is_some_and(|base| base.len() <= entry_host.len())
+                    .then_some(Rank::Parent)
+            } else {
+                (base.is_some() && base_domain(entry_host) == base).then_some(Rank::BaseDomain)
+            }
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::entry;
+
+    #[test]
+    fn finds_hosts() {
+        let host = |url| host_of(url);
+        assert_eq!(
+            host("https://me@Example.com:8080/login").unwrap(),
+            "example.com"
+        );
+        assert_eq!(host("example.com/path?q#x").unwrap(), "example.com");
+        assert_eq!(host("http://example.com.").unwrap(), "example.com");
+        assert_eq!(host("http://a:b@host?x=@y").unwrap(), "host");
+        assert_eq!(host("https://[::1]:443/").unwrap(), "::1");
+        assert_eq!(host("https://192.168.1.1:80").unwrap(), "192.168.1.1");
+        assert_eq!(host("https:///path"), None);
+        assert_eq!(host(""), None);
+    }
+
+    #[test]
+    fn finds_base_domains() {
+        assert_eq!(base_domain("accounts.example.com"), Some("example.com"));
+        assert_eq!(base_domain("example.com"), Some("example.com"));
+        assert_eq!(base_domain("a.b.example.co.uk"), Some("example.co.uk"));
+        assert_eq!(base_domain("me.github.io"), Some("me.github.io"));
+        assert_eq!(
+            base_domain("bucket.s3.amazonaws.com"),
+            Some("bucket.s3.amazonaws.com")
+        );
+        assert_eq!(base_domain("co.uk"), None);
+        assert_eq!(base_domain("com"), None);
+        assert_eq!(base_domain("github.io"), None);
+        assert_eq!(base_domain("192.168.1.1"), None);
+        assert_eq!(base_domain("::1"), None);
+    }
+
+    #[test]
+    fn ranks_entries() {
+        let github = entry(&[("URL", "https://github.com/login")]);
+        assert_eq!(rank(&github, "github.com"), Some(Rank::Host));
+        assert_eq!(rank(&github, "gist.github.com"), Some(Rank::Parent));
+        assert_eq!(rank(&github, "gitlab.com"), None);
+
+        let mail = entry(&[("URL", "https://mail.example.co.uk")]);
+        assert_eq!(
+            rank(&mail, "accounts.example.co.uk"),
+            Some(Rank::BaseDomain)
+        );
+        assert_eq!(rank(&mail, "other.co.uk"), None);
+
+        // Different people's subdomains of hosting platforms.
+        let pages = entry(&[("URL", "https://me.github.io")]);
+        assert_eq!(rank(&pages, "you.github.io"), None);
+        assert_eq!(rank(&pages, "blog.me.github.io"), Some(Rank::Parent));
+
+        // Not for entries for public suffixes.
+        let suffix = entry(&[("URL", "co.uk")]);
+        assert_eq!(rank(&suffix, "example.co.uk"), None);
+    }
+
+    #[test]
+    fn ranks_by_the_best_url() {
+        let entry = entry(&[
+            ("URL", "https://example.com"),
+            ("KP2A_URL_1", "https://login.example.com"),
+            ("URL Backup", ""),
+            ("Notes", "https://login.example.com"),
+        ]);
+        assert_eq!(rank(&entry, "login.example.com"), Some(Rank::Host));
+        assert_eq!(
+            extra_urls(&entry),
+            [("KP2A_URL_1", "https://login.example.com")]
+        );
+        assert_eq!(entry_hosts(&entry), ["example.com", "login.example.com"]);
+        assert!(is_extra_url("URL 2") && !is_extra_url("URLs"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Errors worth telling apart, e.g. to recover from them in the
+//! interactive session (or in other front ends) instead of giving up,
+//! or for scripts to branch on kpass' exit code (see `exit_code`).
+//! They're returned inside `anyhow` errors, like all others, so check
+//! for them with `err.downcast_ref::<Error>()` or the helpers below.
+
+use std::fmt::{Display, Formatter};
+
+use inquire::InquireError;
+use keepass::error::DatabaseOpenError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The password (or key file) doesn't open the database,
+    /// or the database can't be read.
+    Open(DatabaseOpenError),
+    /// The cached password doesn't open the database (anymore),
+    /// e.g. as the master password was changed elsewhere.
+    StaleCache,
+    /// The password generator's settings can't make a password,
+    /// e.g. with every kind of character turned off.
+    Generator(&'static str),
+    /// The database is open read-only.
+    ReadOnly,
+    /// The database is open in another kpass instance, described.
+    Locked(String),
+    /// The user cancelled, e.g. a prompt with Esc.
+    Cancelled,
+    /// No entry (or group) matches a query, described.
+    NotFound(String),
+    /// Several entries (or groups) match a query, described.
+    Ambiguous(String),
+    /// Writing the database failed, described.
+    Save(String),
+    /// The database changed on disk since it was opened, so saving
+    /// would overwrite those changes.
+    Changed,
+}
+
+/// `kpass status`' exit code when no agent is running.
+pub const NO_AGENT: i32 = 2;
+
+/// `kpass status`' exit code when the agent is locked.
+pub const AGENT_LOCKED: i32 = 8;
+
+impl Error {
+    /// The exit code for the error. 1 is for all other errors, and
+    /// `kpass status` has its own (`NO_AGENT`, `AGENT_LOCKED`).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Open(_) | Error::StaleCache => 3,
+            Error::NotFound(_) => 4,
+            Error::Ambiguous(_) => 5,
+            Error::Save(_) | Error::Changed => 6,
+            Error::ReadOnly | Error::Locked(_) => 7,
+            Error::Generator(_) | Error::Cancelled => 1,
+        }
+    }
+
+    /// The error with the exit code, if it has its own
+    /// (e.g. as sent by the agent).
+    pub fn from_exit_code(code: i32, message: &str) -> Option<Self> {
+        match code {
+            4 => Some(Error::NotFound(message.to_string())),
+            5 => Some(Error::Ambiguous(message.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Open(err) => write!(f, "Failed to open database. Wrong password? {:?}", err),
+            Error::StaleCache => write!(f, "The cached password doesn't open the database"),
+            Error::Generator(err) => {
+                write!(f, "Can't generate a password with these settings: {}", err)
+            }
+            Error::ReadOnly => write!(f, "The database is open read-only."),
+            Error::Locked(holder) => write!(
+                f,
+                "The database is open in another kpass instance ({}).",
+                holder
+            ),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::Changed => write!(
+                f,
+                "The database was changed by something else since it was opened."
+            ),
+            Error::NotFound(message) | Error::Ambiguous(message) | Error::Save(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Whether the user cancelled, rather than something going wrong.
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<Error>(), Some(Error::Cancelled))
+        || matches!(
+            err.downcast_ref::<InquireError>(),
+            Some(InquireError::OperationCanceled)
+        )
+}
+
+/// Whether the user interrupted (Ctrl-C) a prompt, to quit.
+pub fn is_interrupted(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<InquireError>(),
+        Some(InquireError::OperationInterrupted)
+    )
+}
+
+/// The exit code for the error (see `Error::exit_code`).
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.downcast_ref::<Error>().map_or(1, Error::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes_differ_from_errors() {
+        let errors = [
+            Error::StaleCache,
+            Error::Generator(""),
+            Error::ReadOnly,
+            Error::Locked(String::new()),
+            Error::Cancelled,
+            Error::NotFound(String::new()),
+            Error::Ambiguous(String::new()),
+            Error::Save(String::new()),
+            Error::Changed,
+        ];
+        for err in &errors {
+            assert_ne!(err.exit_code(), NO_AGENT);
+            assert_ne!(err.exit_code(), AGENT_LOCKED);
+        }
+        assert_ne!(NO_AGENT, AGENT_LOCKED);
+    }
+
+    #[test]
+    fn from_exit_code_round_trips() {
+        for err in [
+            Error::NotFound("no match".to_string()),
+            Error::Ambiguous("2 matches".to_string()),
+        ] {
+            let back = Error::from_exit_code(err.exit_code(), &err.to_string()).unwrap();
+            assert_eq!(back.exit_code(), err.exit_code());
+            assert_eq!(back.to_string(), err.to_string());
+        }
+        assert!(Error::from_exit_code(1, "other").is_none());
+        assert!(Error::from_exit_code(6, "save").is_none());
+    }
+}
@@ -0,0 +1,79 @@
+//! `kpass exec`: run a command with an entry's secrets in its environment
+//! (e.g. `restic` with `RESTIC_PASSWORD`), fetched from the agent, so the
+//! command never sees the database, and the secrets never end up in the
+//! shell's environment or history.
+
+use std::process::{Command, ExitStatus};
+
+use anyhow::{anyhow, Result};
+
+use crate::agent;
+
+/// The variables set when none are given.
+pub const DEFAULT_VARS: &[(&str, &str)] = &[
+    ("KPASS_USERNAME", "UserName"),
+    ("KPASS_PASSWORD", "Password"),
+];
+
+/// Parse a `NAME=Field` variable spec.
+pub fn parse_var(spec: &str) -> Result<(String, String)> {
+    let (name, field) = spec
+        .split_once('=')
+        .filter(|(name, field)| !name.is_empty() && !field.is_empty())
+        .ok_or_else(|| anyhow!("Variables look like NAME=Field, not {}", spec))?;
+    if name.contains(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+        return Err(anyhow!("Invalid variable name: {}", name));
+    }
+    Ok((name.to_string(), field.to_string()))
+}
+
+/// Run the command with the entry's fields in the given variables,
+/// returning how it exited.
+pub fn run(query: &str, vars: &[(String, String)], command: &[String]) -> Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("Please provide a command to run after --"))?;
+    let mut values = vec![];
+    for (name, field) in vars {
+        values.push((name, agent::request_field(query, field)?));
+    }
+    let status = Command::new(program)
+        .args(args)
+        .envs(values.iter().map(|(name, value)| (name, value.expose())))
+        .status()
+        .map_err(|err| anyhow!("Couldn't run {}: {}", program, err))?;
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vars() {
+        assert_eq!(
+            parse_var("RESTIC_PASSWORD=Password").unwrap(),
+            ("RESTIC_PASSWORD".to_string(), "Password".to_string())
+        );
+        // Only the first `=` separates them.
+        assert_eq!(
+            parse_var("TOKEN=API token=v2").unwrap(),
+            ("TOKEN".to_string(), "API token=v2".to_string())
+        );
+        for spec in [
+            "PASSWORD",
+            "=Password",
+            "PASSWORD=",
+            "MY-VAR=Password",
+            "A B=x",
+        ] {
+            assert!(parse_var(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn needs_a_command() {
+        let err = run("github", &[], &[]).unwrap_err();
+        assert_eq!(err.to_string(), "Please provide a command to run after --");
+    }
+}
@@ -0,0 +1,689 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use cocoon::Cocoon;
+use inquire::{Confirm, Text};
+use keepass::{
+    db::{CustomDataItem, Entry as KEntry, Group, Node, Times, Value},
+    Database,
+};
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::{entries_with_paths, secret::Secret, share::prompt_passphrase, Entry};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #000; }
+h1 { border-bottom: 2px solid #000; }
+h2 { margin-top: 2em; page-break-after: avoid; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 1em; page-break-inside: avoid; }
+th, td { border: 1px solid #444; padding: 0.3em 0.5em; text-align: left; vertical-align: top; }
+th { width: 8em; background: #eee; }
+td.secret { font-family: monospace; font-size: 1.1em; }
+pre { margin: 0; white-space: pre-wrap; }
+";
+
+/// Walk through the confirmation steps required
+/// before exporting secrets in plain text.
+pub fn confirm_secrets_export() -> Result<bool> {
+    warn!("{}", tr!("export-unencrypted"));
+    warn!("{}", tr!("export-access"));
+    let proceed = Confirm::new(&tr!("continue"))
+        .with_default(false)
+        .prompt()?;
+    if !proceed {
+        return Ok(false);
+    }
+
+    warn!("{}", tr!("export-print-and-delete"));
+    let phrase = tr!("export-phrase");
+    let typed = Text::new(&tr!("type-to-confirm", phrase = phrase)).prompt()?;
+    Ok(typed.trim() == phrase)
+}
+
+/// Fields every entry has; anything else is a custom field.
+const STANDARD_FIELDS: &[&str] = &["Title", "UserName", "Password", "URL", "Notes", "otp"];
+
+/// Write the output file, readable only by the user, encrypting it
+/// with the passphrase if there is one. Refuses to overwrite existing files.
+fn write(path: &Path, data: &[u8], passphrase: Option<&Secret>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| anyhow!("Couldn't create {}: {}", path.display(), err))?;
+    match passphrase {
+        Some(passphrase) => Cocoon::new(passphrase.expose().as_bytes())
+            .dump(data.to_vec(), &mut file)
+            .map_err(|err| anyhow!("Couldn't encrypt export: {:?}", err))?,
+        None => file.write_all(data)?,
+    }
+    Ok(())
+}
+
+/// Decrypt an export written with `--encrypt`.
+pub fn decrypt(path: &Path) -> Result<Zeroizing<Vec<u8>>> {
+    let passphrase = prompt_passphrase(&tr!("passphrase"), false)?;
+    Ok(Zeroizing::new(
+        Cocoon::parse_only(passphrase.expose().as_bytes())
+            .parse(&mut File::open(path)?)
+            .map_err(|_| anyhow!("Wrong passphrase or not an encrypted export"))?,
+    ))
+}
+
+/// Group custom data key which, set to `deny`,
+/// keeps the group (and its subgroups) out of exports and shares.
+const EXPORT_KEY: &str = "export";
+
+fn denies_export(group: &Group) -> bool {
+    matches!(
+        group.custom_data.items.get(EXPORT_KEY),
+        Some(CustomDataItem { value: Some(Value::Unprotected(value)), .. }) if value == "deny"
+    )
+}
+
+/// Allow or deny exporting the group.
+pub fn set_export_denied(group: &mut Group, denied: bool) {
+    if denied {
+        group.custom_data.items.insert(
+            EXPORT_KEY.to_string(),
+            CustomDataItem {
+                value: Some(Value::Unprotected("deny".to_string())),
+                last_modification_time: Some(Times::now()),
+            },
+        );
+    } else {
+        group.custom_data.items.remove(EXPORT_KEY);
+    }
+}
+
+/// The path of the group at `path`, or of one of its parents,
+/// if it denies export.
+pub fn denied_at(db: &Database, path: &[String]) -> Option<String> {
+    let mut group = &db.root;
+    let mut walked = vec![];
+    for name in path {
+        if denies_export(group) {
+            break;
+        }
+        group = group.children.iter().find_map(|node| match node {
+            Node::Group(g) if &g.name == name => Some(g),
+            _ => None,
+        })?;
+        walked.push(name.as_str());
+    }
+    denies_export(group).then(|| format!("/{}", walked.join("/")))
+}
+
+/// A copy of `group` without the subgroups that deny export,
+/// along with the paths of those left out.
+pub fn without_denied(group: &Group, group_path: &[String]) -> (Group, Vec<String>) {
+    let mut kept = group.clone();
+    let mut denied = vec![];
+    kept.children = vec![];
+    for node in &group.children {
+        match node {
+            Node::Group(child) => {
+                let path = [group_path, std::slice::from_ref(&child.name)].concat();
+                if denies_export(child) {
+                    denied.push(format!("/{}", path.join("/")));
+                } else {
+                    let (child, child_denied) = without_denied(child, &path);
+                    kept.add_child(child);
+                    denied.extend(child_denied);
+                }
+            }
+            Node::Entry(entry) => kept.add_child(entry.clone()),
+        }
+    }
+    (kept, denied)
+}
+
+/// Write the HTML report.
+pub fn write_html(
+    db: &Database,
+    group: &Group,
+    group_path: &[String],
+    include_secrets: bool,
+    path: &Path,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let html = Zeroizing::new(html(db, group, group_path, include_secrets));
+    write(path, html.as_bytes(), passphrase)
+}
+
+/// An entry with all its fields, for plain text exports.
+#[derive(Serialize)]
+struct Exported {
+    group: Vec<String>,
+    title: String,
+    username: String,
+    password: String,
+    url: String,
+    notes: String,
+    otp: String,
+    tags: Vec<String>,
+    fields: BTreeMap<String, String>,
+}
+impl Exported {
+    fn new(entry: &KEntry, group: Vec<String>) -> Self {
+        let field = |key: &str| {
+            entry
+                .fields
+                .get(key)
+                .and_then(value_str)
+                .unwrap_or_default()
+        };
+        Exported {
+            group,
+            title: field("Title"),
+            username: field("UserName"),
+            password: field("Password"),
+            url: field("URL"),
+            notes: field("Notes"),
+            otp: field("otp"),
+            tags: entry.tags.clone(),
+            fields: entry
+                .fields
+                .iter()
+                .filter(|(key, _)| !STANDARD_FIELDS.contains(&key.as_str()))
+                .filter_map(|(key, val)| Some((key.clone(), value_str(val)?)))
+                .collect(),
+        }
+    }
+}
+
+/// The text of a field, if it's text at all.
+fn value_str(value: &Value) -> Option<String> {
+    match value {
+        Value::Unprotected(s) => Some(s.clone()),
+        Value::Protected(data) => std::str::from_utf8(data.unsecure()).ok().map(String::from),
+        Value::Bytes(_) => None,
+    }
+}
+
+/// All entries under `group`, including those in subgroups.
+fn collect(group: &Group, group_path: &[String]) -> Vec<Exported> {
+    entries_with_paths(group)
+        .into_iter()
+        .map(|(path, entry)| Exported::new(entry, [group_path, &path].concat()))
+        .collect()
+}
+
+/// Write all entries (including secrets) as JSON.
+pub fn write_json(
+    group: &Group,
+    group_path: &[String],
+    path: &Path,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let entries = collect(group, group_path);
+    let json = Zeroizing::new(serde_json::to_string_pretty(&entries)?);
+    write(path, json.as_bytes(), passphrase)
+}
+
+/// A path segment for a secrets manager: lowercase,
+/// with anything but letters, digits, `_` and `.` turned into `-`.
+fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Entries as secrets for secrets managers: their paths
+/// (slugged group path, then title) with their non-empty fields.
+/// Entries with the same path, or the path of a group, get a numbered suffix.
+fn secrets(
+    group: &Group,
+    group_path: &[String],
+) -> BTreeMap<Vec<String>, BTreeMap<String, String>> {
+    let entries = collect(group, group_path);
+    let mut taken: BTreeSet<Vec<String>> = BTreeSet::new();
+    for entry in &entries {
+        let group: Vec<String> = entry.group.iter().map(|name| slug(name)).collect();
+        for len in 1..=group.len() {
+            taken.insert(group[..len].to_vec());
+        }
+    }
+
+    let mut secrets = BTreeMap::new();
+    for entry in entries {
+        let mut path: Vec<String> = entry.group.iter().map(|name| slug(name)).collect();
+        let title = slug(&entry.title);
+        path.push(title.clone());
+        let mut n = 2;
+        while taken.contains(&path) {
+            *path.last_mut().expect("Path has the title") = format!("{}-{}", title, n);
+            n += 1;
+        }
+        taken.insert(path.clone());
+
+        let mut data: BTreeMap<String, String> = [
+            ("username", entry.username),
+            ("password", entry.password),
+            ("url", entry.url),
+            ("notes", entry.notes),
+            ("otp", entry.otp),
+        ]
+        .into_iter()
+        .map(|(key, val)| (key.to_string(), val))
+        .collect();
+        data.extend(entry.fields);
+        data.retain(|_, val| !val.is_empty());
+        secrets.insert(path, data);
+    }
+    secrets
+}
+
+/// Write all entries as a JSON object of HashiCorp Vault KV secrets
+/// by path, e.g. `work/servers/db-prod`, each with its fields as data.
+pub fn write_vault_kv(
+    group: &Group,
+    group_path: &[String],
+    path: &Path,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let secrets: BTreeMap<String, _> = secrets(group, group_path)
+        .into_iter()
+        .map(|(path, data)| (path.join("/"), data))
+        .collect();
+    let json = Zeroizing::new(serde_json::to_string_pretty(&secrets)?);
+    write(path, json.as_bytes(), passphrase)
+}
+
+/// Write all entries as YAML encrypted with `sops`, nested by group path
+/// and then title. sops picks the keys to encrypt with from the creation
+/// rules in `.sops.yaml` matching the output path, as for any other file;
+/// the plain text is only ever passed to it through a pipe.
+pub fn write_sops(
+    group: &Group,
+    group_path: &[String],
+    path: &Path,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let mut tree = serde_json::Map::new();
+    for (secret_path, data) in secrets(group, group_path) {
+        let (title, groups) = secret_path.split_last().expect("Path has the title");
+        let mut node = &mut tree;
+        for name in groups {
+            node = node
+                .entry(name.clone())
+                .or_insert_with(|| serde_json::Value::Object(Default::default()))
+                .as_object_mut()
+                .expect("Group paths don't collide with entries");
+        }
+        node.insert(title.clone(), serde_json::to_value(data)?);
+    }
+    let json = Zeroizing::new(serde_json::to_vec(&tree)?);
+
+    let child = Command::new("sops")
+        .args(["--encrypt", "--input-type", "json", "--output-type", "yaml"])
+        .arg("--filename-override")
+        .arg(path)
+        .arg("/dev/stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!("sops isn't installed."));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    // Written from another thread, so sops can't block on a full stdout pipe.
+    let mut stdin = child.stdin.take().expect("Stdin is piped");
+    let output = std::thread::scope(|scope| {
+        scope.spawn(move || stdin.write_all(&json));
+        child.wait_with_output()
+    })?;
+    if !output.status.success() {
+        return Err(anyhow!("sops failed with {}", output.status));
+    }
+    write(path, &output.stdout, passphrase)
+}
+
+/// Write all entries (including secrets) as CSV.
+/// The columns match those read by `kpass import csv`,
+/// followed by a column for each custom field.
+pub fn write_csv(
+    group: &Group,
+    group_path: &[String],
+    path: &Path,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let entries = collect(group, group_path);
+    let custom: BTreeSet<&String> = entries.iter().flat_map(|e| e.fields.keys()).collect();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut header: Vec<&str> = vec![
+        "group", "title", "username", "password", "url", "notes", "otp", "tags",
+    ];
+    header.extend(custom.iter().map(|key| key.as_str()));
+    writer.write_record(&header)?;
+    for entry in &entries {
+        let group = entry.group.join("/");
+        let tags = entry.tags.join(",");
+        let mut record = vec![
+            group.as_str(),
+            &entry.title,
+            &entry.username,
+            &entry.password,
+            &entry.url,
+            &entry.notes,
+            &entry.otp,
+            &tags,
+        ];
+        record.extend(
+            custom
+                .iter()
+                .map(|key| entry.fields.get(*key).map(String::as_str).unwrap_or("")),
+        );
+        writer.write_record(&record)?;
+    }
+    let csv = Zeroizing::new(writer.into_inner()?);
+    write(path, &csv, passphrase)
+}
+
+fn html(db: &Database, group: &Group, group_path: &[String], include_secrets: bool) -> String {
+    let name = db
+        .meta
+        .database_name
+        .as_deref()
+        .unwrap_or("KeePass Database");
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape(name)));
+    out.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+    out.push_str(&format!("<h1>{}</h1>\n", escape(name)));
+    out.push_str(&format!(
+        "<p>Exported {}.</p>\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    ));
+    let path: String = group_path.iter().map(|name| format!("/{}", name)).collect();
+    write_group(&mut out, group, &path, include_secrets);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn write_group(out: &mut String, group: &Group, path: &str, include_secrets: bool) {
+    let entries: Vec<_> = group
+        .children
+        .iter()
+        .filter_map(|node| match node {
+            Node::Entry(e) => Some(Entry(e)),
+            Node::Group(_) => None,
+        })
+        .collect();
+    if !entries.is_empty() {
+        let heading = if path.is_empty() { "/" } else { path };
+        out.push_str(&format!("<h2>{}</h2>\n", escape(heading)));
+    }
+    for entry in entries {
+        out.push_str("<table>\n");
+        row(out, "Title", &entry.to_string(), false);
+        if let Some(username) = entry.username() {
+            row(out, "Username", username, false);
+        }
+        if include_secrets {
+            if let Some(password) = entry.password() {
+                row(out, "Password", password, true);
+            }
+            if let Some(otp) = entry.0.get_raw_otp_value() {
+                row(out, "OTP", otp, true);
+            }
+        }
+        if let Some(url) = entry.url() {
+            row(out, "URL", url, false);
+        }
+        if let Some(notes) = entry.notes().filter(|n| !n.is_empty()) {
+            out.push_str(&format!(
+                "<tr><th>Notes</th><td><pre>{}</pre></td></tr>\n",
+                escape(notes)
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+
+    for node in &group.children {
+        if let Node::Group(child) = node {
+            let child_path = format!("{}/{}", path, child.name);
+            write_group(out, child, &child_path, include_secrets);
+        }
+    }
+}
+
+fn row(out: &mut String, label: &str, value: &str, secret: bool) {
+    let class = if secret { " class=\"secret\"" } else { "" };
+    out.push_str(&format!(
+        "<tr><th>{}</th><td{}>{}</td></tr>\n",
+        label,
+        class,
+        escape(value)
+    ));
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::MetadataExt, path::PathBuf};
+
+    use super::*;
+    use crate::test_util;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("kpass-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn entry(fields: &[(&str, &str)]) -> KEntry {
+        test_util::entry_protecting(fields, &["Password", "otp"])
+    }
+
+    /// A database with GitHub in `Work` and a server in `Work/Servers`.
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        work.add_child(entry(&[
+            ("Title", "GitHub"),
+            ("UserName", "alice"),
+            ("Password", "hunter<2>"),
+            ("URL", "https://github.com"),
+            ("otp", "otpauth://totp/x?secret=JBSWY3DP"),
+        ]));
+        let mut servers = Group::new("Servers");
+        servers.add_child(entry(&[
+            ("Title", "db & cache"),
+            ("Password", "s3cret"),
+            ("Notes", "line 1\nline 2"),
+            ("Port", "5432"),
+        ]));
+        work.add_child(servers);
+        db.root.add_child(work);
+        db
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn gates_secrets_in_html() {
+        let db = database();
+        let without = html(&db, &db.root, &[], false);
+        assert!(without.contains("<h2>/Work</h2>"));
+        assert!(without.contains("<h2>/Work/Servers</h2>"));
+        assert!(without.contains("<td>db &amp; cache</td>"));
+        assert!(without.contains("<pre>line 1\nline 2</pre>"));
+        assert!(!without.contains("hunter"));
+        assert!(!without.contains("otpauth"));
+
+        let with = html(&db, &db.root, &[], true);
+        assert!(with.contains("<td class=\"secret\">hunter&lt;2&gt;</td>"));
+        assert!(with.contains("<td class=\"secret\">otpauth://totp/x?secret=JBSWY3DP</td>"));
+    }
+
+    #[test]
+    fn exports_json() {
+        let db = database();
+        let path = temp_path("export.json");
+        write_json(&db.root, &[], &path, None).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        // Won't overwrite.
+        assert!(write_json(&db.root, &[], &path, None).is_err());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(json[0]["group"], serde_json::json!(["Work"]));
+        assert_eq!(json[0]["password"], "hunter<2>");
+        assert_eq!(json[1]["group"], serde_json::json!(["Work", "Servers"]));
+        assert_eq!(json[1]["fields"], serde_json::json!({"Port": "5432"}));
+    }
+
+    #[test]
+    fn exports_csv() {
+        let db = database();
+        let path = temp_path("export.csv");
+        let work = [String::from("Exported")];
+        write_csv(&db.root, &work, &path, None).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "group,title,username,password,url,notes,otp,tags,Port"
+        );
+        assert_eq!(
+            lines[1],
+            "Exported/Work,GitHub,alice,hunter<2>,https://github.com,,otpauth://totp/x?secret=JBSWY3DP,,"
+        );
+        assert_eq!(
+            lines[2],
+            "Exported/Work/Servers,db & cache,,s3cret,,\"line 1"
+        );
+        assert_eq!(lines[3], "line 2\",,,5432");
+    }
+
+    #[test]
+    fn encrypts_exports() {
+        let path = temp_path("export.json.enc");
+        let passphrase = Secret::new("correct horse battery".to_string());
+        write(&path, b"{\"password\": \"hunter2\"}", Some(&passphrase)).unwrap();
+        let sealed = fs::read(&path).unwrap();
+        assert!(!sealed.windows(7).any(|window| window == b"hunter2"));
+        let opened = Cocoon::parse_only(b"correct horse battery")
+            .parse(&mut File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!(opened, b"{\"password\": \"hunter2\"}");
+        assert!(Cocoon::parse_only(b"wrong")
+            .parse(&mut File::open(&path).unwrap())
+            .is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn servers(db: &mut Database) -> &mut Group {
+        let Node::Group(work) = &mut db.root.children[0] else {
+            unreachable!()
+        };
+        match &mut work.children[1] {
+            Node::Group(servers) => servers,
+            Node::Entry(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn leaves_out_groups_denying_export() {
+        let mut db = database();
+        set_export_denied(servers(&mut db), true);
+        let path = |path: &str| -> Vec<String> { path.split('/').map(String::from).collect() };
+        assert_eq!(denied_at(&db, &path("Work")), None);
+        assert_eq!(
+            denied_at(&db, &path("Work/Servers")).as_deref(),
+            Some("/Work/Servers")
+        );
+        // Subgroups and missing groups inside a denied one are denied by it.
+        assert_eq!(
+            denied_at(&db, &path("Work/Servers/Old")).as_deref(),
+            Some("/Work/Servers")
+        );
+        assert_eq!(denied_at(&db, &path("Home")), None);
+
+        let (kept, denied) = without_denied(&db.root, &[]);
+        assert_eq!(denied, ["/Work/Servers"]);
+        let titles: Vec<String> = collect(&kept, &[]).into_iter().map(|e| e.title).collect();
+        assert_eq!(titles, ["GitHub"]);
+
+        set_export_denied(servers(&mut db), false);
+        assert!(without_denied(&db.root, &[]).1.is_empty());
+    }
+
+    #[test]
+    fn slugs_names() {
+        assert_eq!(slug("Work Servers"), "work-servers");
+        assert_eq!(slug("  db & cache!  "), "db-cache");
+        assert_eq!(slug("v1.2_beta"), "v1.2_beta");
+        assert_eq!(slug("Équipe"), "équipe");
+        assert_eq!(slug("---"), "untitled");
+        assert_eq!(slug(""), "untitled");
+    }
+
+    #[test]
+    fn paths_secrets_uniquely() {
+        let mut db = database();
+        let Node::Group(work) = &mut db.root.children[0] else {
+            unreachable!()
+        };
+        // Same title as another entry, and as a group.
+        work.add_child(entry(&[("Title", "github"), ("Password", "x")]));
+        work.add_child(entry(&[("Title", "Servers"), ("Password", "y")]));
+        let secrets = secrets(&db.root, &[]);
+        let paths: Vec<String> = secrets.keys().map(|path| path.join("/")).collect();
+        assert_eq!(
+            paths,
+            [
+                "work/github",
+                "work/github-2",
+                "work/servers/db-cache",
+                "work/servers-2"
+            ]
+        );
+        let server = &secrets[&vec![
+            "work".to_string(),
+            "servers".to_string(),
+            "db-cache".to_string(),
+        ]];
+        let keys: Vec<&String> = server.keys().collect();
+        // Empty fields are left out.
+        assert_eq!(keys, ["Port", "notes", "password"]);
+    }
+}
@@ -0,0 +1,36 @@
+//! Favorite entries, listed first in the picker and in `kpass fav`.
+//! They're tagged, so other clients (e.g. KeePassXC) can show them too.
+
+use keepass::db::Entry as KEntry;
+
+pub const TAG: &str = "Favorite";
+
+pub fn is_favorite(entry: &KEntry) -> bool {
+    entry.tags.iter().any(|tag| tag == TAG)
+}
+
+pub fn set(entry: &mut KEntry, favorite: bool) {
+    entry.tags.retain(|tag| tag != TAG);
+    if favorite {
+        entry.tags.push(TAG.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_favorites() {
+        let mut entry = KEntry::new();
+        entry.tags.push("work".to_string());
+        assert!(!is_favorite(&entry));
+        set(&mut entry, true);
+        set(&mut entry, true);
+        assert!(is_favorite(&entry));
+        assert_eq!(entry.tags, ["work", TAG]);
+        set(&mut entry, false);
+        assert!(!is_favorite(&entry));
+        assert_eq!(entry.tags, ["work"]);
+    }
+}
@@ -0,0 +1,183 @@
+//! Small files (WireGuard configs, service account keys) kept encrypted
+//! in an entry (`kpass file put`/`get`). `keepass` doesn't load
+//! attachments, so each file is a protected field named `KPASS_FILE_`
+//! and the file's name, holding its contents as base64. Other clients
+//! show these as custom fields rather than attachments.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use keepass::db::{Entry as KEntry, Times, Value};
+use zeroize::Zeroizing;
+
+/// What the file fields start with.
+pub const FIELD_PREFIX: &str = "KPASS_FILE_";
+
+/// The largest file kept, as the whole database is rewritten on each save.
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// The names of the files kept in the entry.
+pub fn names(entry: &KEntry) -> Vec<&str> {
+    let mut names: Vec<&str> = entry
+        .fields
+        .keys()
+        .filter_map(|name| name.strip_prefix(FIELD_PREFIX))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Keep the file at `path` in the entry, under its file name, replacing
+/// any file of that name. Returns the name.
+pub fn put(entry: &mut KEntry, path: &Path) -> Result<String> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("{} isn't a file", path.display()))?
+        .to_string();
+    let size = fs::metadata(path)?.len();
+    if size > MAX_SIZE {
+        return Err(anyhow!(
+            "{} is too large ({} KiB, at most {} KiB)",
+            name,
+            size / 1024,
+            MAX_SIZE / 1024
+        ));
+    }
+    let contents = Zeroizing::new(fs::read(path)?);
+    let encoded = Zeroizing::new(BASE64.encode(&*contents));
+
+    let before = entry.clone();
+    entry.fields.insert(
+        format!("{}{}", FIELD_PREFIX, name),
+        Value::Protected(encoded.as_bytes().into()),
+    );
+    entry
+        .history
+        .get_or_insert_with(Default::default)
+        .add_entry(before);
+    entry.times.set_last_modification(Times::now());
+    Ok(name)
+}
+
+/// The contents of the file kept in the entry under `name`.
+pub fn get(entry: &KEntry, name: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let missing = || {
+        let names = names(entry);
+        if names.is_empty() {
+            anyhow!("The entry has no files")
+        } else {
+            anyhow!("No file '{}'; the entry has: {}", name, names.join(", "))
+        }
+    };
+    let encoded = entry
+        .get(&format!("{}{}", FIELD_PREFIX, name))
+        .ok_or_else(missing)?;
+    let contents = BASE64
+        .decode(encoded)
+        .map_err(|_| anyhow!("The file '{}' is corrupted", name))?;
+    Ok(Zeroizing::new(contents))
+}
+
+/// Write the contents to `path`, readable only by the user.
+/// An existing file isn't overwritten.
+pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| anyhow!("Couldn't create {}: {}", path.display(), err))?;
+    file.write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, os::unix::fs::MetadataExt};
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("kpass-test-files-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_files_in_fields() {
+        let dir = temp_dir("put");
+        let path = dir.join("wg0.conf");
+        fs::write(&path, b"[Interface]\nPrivateKey = abc\n").unwrap();
+        let mut entry = KEntry::new();
+        assert_eq!(put(&mut entry, &path).unwrap(), "wg0.conf");
+        assert!(matches!(
+            entry.fields.get("KPASS_FILE_wg0.conf"),
+            Some(Value::Protected(_))
+        ));
+        assert_eq!(
+            &*get(&entry, "wg0.conf").unwrap(),
+            b"[Interface]\nPrivateKey = abc\n"
+        );
+
+        // Replacing it keeps the old version in the history.
+        fs::write(&path, [0, 159, 146, 150]).unwrap();
+        put(&mut entry, &path).unwrap();
+        assert_eq!(&*get(&entry, "wg0.conf").unwrap(), &[0, 159, 146, 150]);
+        assert_eq!(entry.history.as_ref().unwrap().get_entries().len(), 2);
+
+        fs::write(dir.join("key.json"), b"{}").unwrap();
+        put(&mut entry, &dir.join("key.json")).unwrap();
+        assert_eq!(names(&entry), ["key.json", "wg0.conf"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refuses_large_files() {
+        let dir = temp_dir("large");
+        let path = dir.join("large.bin");
+        fs::write(&path, vec![0; MAX_SIZE as usize + 1]).unwrap();
+        let mut entry = KEntry::new();
+        assert!(put(&mut entry, &path).is_err());
+        assert!(names(&entry).is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn explains_missing_files() {
+        let mut entry = KEntry::new();
+        let err = get(&entry, "wg0.conf").unwrap_err();
+        assert_eq!(err.to_string(), "The entry has no files");
+
+        entry.fields.insert(
+            "KPASS_FILE_key.json".to_string(),
+            Value::Unprotected("not base64!".to_string()),
+        );
+        let err = get(&entry, "wg0.conf").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No file 'wg0.conf'; the entry has: key.json"
+        );
+        assert!(get(&entry, "key.json").is_err());
+    }
+
+    #[test]
+    fn writes_private_files() {
+        let dir = temp_dir("write");
+        let path = dir.join("wg0.conf");
+        write(&path, b"secret").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        // Not overwritten.
+        assert!(write(&path, b"other").is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
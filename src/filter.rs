@@ -0,0 +1,200 @@
+//! Narrowing `kpass list` and the entry picker to a subset of the vault,
+//! with `--group`, `--tag` and `--expired-only`, which combine. Entries
+//! in the recycle bin are left out unless `--include-recycled` is given.
+
+use std::sync::OnceLock;
+
+use keepass::{
+    db::{Entry as KEntry, Group, Node},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::Entry;
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+#[derive(Default)]
+pub struct Filter {
+    /// Only entries in this group (or its subgroups), as a path from the root.
+    pub group: Vec<String>,
+    /// Only entries with this tag.
+    pub tag: Option<String>,
+    pub include_recycled: bool,
+    pub expired_only: bool,
+}
+impl Filter {
+    /// Whether an entry passes, given its group path, tags, whether
+    /// it's in the recycle bin and whether it has expired.
+    pub fn matches(
+        &self,
+        group: &[String],
+        tags: &[String],
+        recycled: bool,
+        expired: bool,
+    ) -> bool {
+        let in_group = self.group.len() <= group.len()
+            && self
+                .group
+                .iter()
+                .zip(group)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b));
+        let tagged = self.tag.as_ref().is_none_or(|tag| tags.contains(tag));
+        in_group
+            && tagged
+            && (self.include_recycled || !recycled)
+            && (!self.expired_only || expired)
+    }
+
+    /// The database's entries that pass, with their group paths.
+    pub fn entries<'a>(&self, db: &'a Database) -> Vec<(Vec<String>, &'a KEntry)> {
+        with_recycled(db)
+            .into_iter()
+            .filter(|(group, entry, recycled)| {
+                self.matches(group, &entry.tags, *recycled, Entry(entry).is_expired())
+            })
+            .map(|(group, entry, _)| (group, entry))
+            .collect()
+    }
+}
+
+/// Set the filter given on the command line.
+pub fn set(filter: Filter) {
+    let _ = FILTER.set(filter);
+}
+
+/// The filter given on the command line (none by default).
+pub fn get() -> &'static Filter {
+    FILTER.get_or_init(Filter::default)
+}
+
+/// All of the database's entries with their group paths,
+/// and whether they're in the recycle bin.
+pub fn with_recycled(db: &Database) -> Vec<(Vec<String>, &KEntry, bool)> {
+    fn collect<'a>(
+        group: &'a Group,
+        bin: Option<Uuid>,
+        recycled: bool,
+        path: &mut Vec<String>,
+        out: &mut Vec<(Vec<String>, &'a KEntry, bool)>,
+    ) {
+        for node in &group.children {
+            match node {
+                Node::Entry(entry) => out.push((path.clone(), entry, recycled)),
+                Node::Group(child) => {
+                    path.push(child.name.clone());
+                    let recycled = recycled || Some(child.uuid) == bin;
+                    collect(child, bin, recycled, path, out);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    let bin = db
+        .meta
+        .recyclebin_uuid
+        .filter(|_| db.meta.recyclebin_enabled != Some(false));
+    let mut out = vec![];
+    collect(&db.root, bin, false, &mut vec![], &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::Times;
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, tags: &[&str], expired: bool) -> KEntry {
+        let mut entry = test_util::entry(&[("Title", title)]);
+        entry.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        if expired {
+            entry.times.expires = true;
+            entry
+                .times
+                .set_expiry(Times::now() - TimeDelta::try_days(1).unwrap());
+        }
+        entry
+    }
+
+    /// `Work/GitHub` (tagged dev), `Work/Mail/Webmail` (expired),
+    /// `Bank`, and `Recycle Bin/Old` (tagged dev).
+    fn db() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut mail = Group::new("Mail");
+        mail.add_child(entry("Webmail", &[], true));
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", &["dev"], false));
+        work.add_child(mail);
+        let mut bin = Group::new("Recycle Bin");
+        bin.add_child(entry("Old", &["dev"], false));
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        db.root.add_child(work);
+        db.root.add_child(entry("Bank", &[], false));
+        db.root.add_child(bin);
+        db
+    }
+
+    fn titles(filter: Filter, db: &Database) -> Vec<String> {
+        filter
+            .entries(db)
+            .iter()
+            .map(|(_, entry)| Entry(entry).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn leaves_out_recycled_entries() {
+        let db = db();
+        assert_eq!(
+            titles(Filter::default(), &db),
+            ["GitHub", "Webmail", "Bank"]
+        );
+        let filter = Filter {
+            include_recycled: true,
+            ..Default::default()
+        };
+        assert_eq!(titles(filter, &db), ["GitHub", "Webmail", "Bank", "Old"]);
+
+        // Unless the recycle bin is disabled.
+        let mut db = db;
+        db.meta.recyclebin_enabled = Some(false);
+        assert_eq!(titles(Filter::default(), &db).len(), 4);
+    }
+
+    #[test]
+    fn filters_by_group() {
+        let db = db();
+        let group = |path: &[&str]| Filter {
+            group: path.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+        assert_eq!(titles(group(&["work"]), &db), ["GitHub", "Webmail"]);
+        assert_eq!(titles(group(&["Work", "Mail"]), &db), ["Webmail"]);
+        assert!(titles(group(&["Mail"]), &db).is_empty());
+    }
+
+    #[test]
+    fn combines_filters() {
+        let db = db();
+        let tagged = Filter {
+            tag: Some("dev".into()),
+            ..Default::default()
+        };
+        assert_eq!(titles(tagged, &db), ["GitHub"]);
+        let expired = Filter {
+            expired_only: true,
+            ..Default::default()
+        };
+        assert_eq!(titles(expired, &db), ["Webmail"]);
+        let both = Filter {
+            tag: Some("dev".into()),
+            expired_only: true,
+            ..Default::default()
+        };
+        assert!(titles(both, &db).is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{anyhow, Result};
+use inquire::Confirm;
+use passwords::PasswordGenerator;
+use rand::{rngs::OsRng, Rng};
+
+use crate::{config, error::Error, i18n::Label, prompt, secret::Secret, strength::Strength};
+
+const NUMBERS: &str = "Numbers";
+const LOWERCASE: &str = "Lowercase letters";
+const UPPERCASE: &str = "Uppercase letters";
+const SYMBOLS: &str = "Symbols";
+const SPACES: &str = "Spaces";
+const EXCLUDE_SIMILAR: &str = "Exclude similar characters";
+
+/// Allowed lengths for generated passwords.
+const LENGTH_RANGE: std::ops::RangeInclusive<usize> = 4..=256;
+
+/// The default generator settings.
+pub fn default_generator() -> PasswordGenerator {
+    PasswordGenerator {
+        length: 12,
+        numbers: true,
+        lowercase_letters: true,
+        uppercase_letters: true,
+        symbols: true,
+        spaces: true,
+        exclude_similar_characters: false,
+        strict: true,
+    }
+}
+
+/// Size of the character pool the generator draws from.
+/// Sizes match those of the `passwords` crate's pools.
+fn pool_size(pg: &PasswordGenerator) -> usize {
+    let similar = pg.exclude_similar_characters;
+    let mut size = 0;
+    if pg.numbers {
+        size += if similar { 8 } else { 10 };
+    }
+    if pg.lowercase_letters {
+        size += if similar { 23 } else { 26 };
+    }
+    if pg.uppercase_letters {
+        size += if similar { 24 } else { 26 };
+    }
+    if pg.symbols {
+        size += if similar { 28 } else { 32 };
+    }
+    if pg.spaces {
+        size += 1;
+    }
+    size
+}
+
+/// Entropy (in bits) of a password produced by the generator.
+pub fn entropy(pg: &PasswordGenerator) -> f64 {
+    pg.length as f64 * (pool_size(pg) as f64).log2()
+}
+
+/// Prompt for the length, starting from the current one.
+fn prompt_length(pg: &mut PasswordGenerator) -> Result<()> {
+    pg.length = prompt::number_in_range(&tr!("length"), pg.length, LENGTH_RANGE)?;
+    Ok(())
+}
+
+/// Prompt for the characters to use, starting from the current ones.
+fn prompt_characters(pg: &mut PasswordGenerator) -> Result<()> {
+    let options = vec![
+        (NUMBERS, pg.numbers),
+        (LOWERCASE, pg.lowercase_letters),
+        (UPPERCASE, pg.uppercase_letters),
+        (SYMBOLS, pg.symbols),
+        (SPACES, pg.spaces),
+        (EXCLUDE_SIMILAR, pg.exclude_similar_characters),
+    ];
+    let defaults: Vec<usize> = (0..options.len()).filter(|i| options[*i].1).collect();
+    let options: Vec<Label> = options
+        .into_iter()
+        .map(|(option, _)| Label("characters", option))
+        .collect();
+    let selected: Vec<&str> = prompt::multi_select(&tr!("options"), options)
+        .with_default(&defaults)
+        .with_validator(|selected: &[inquire::list_option::ListOption<&Label>]| {
+            if selected.iter().any(|opt| opt.value.1 != EXCLUDE_SIMILAR) {
+                Ok(inquire::validator::Validation::Valid)
+            } else {
+                Ok(inquire::validator::Validation::Invalid(
+                    tr!("select-character-class").into(),
+                ))
+            }
+        })
+        .prompt()?
+        .into_iter()
+        .map(|label| label.1)
+        .collect();
+    pg.numbers = selected.contains(&NUMBERS);
+    pg.lowercase_letters = selected.contains(&LOWERCASE);
+    pg.uppercase_letters = selected.contains(&UPPERCASE);
+    pg.symbols = selected.contains(&SYMBOLS);
+    pg.spaces = selected.contains(&SPACES);
+    pg.exclude_similar_characters = selected.contains(&EXCLUDE_SIMILAR);
+    Ok(())
+}
+
+/// Report the entropy of a password about to be generated, asking for
+/// confirmation if it's below the configured minimum.
+/// Returns whether to go ahead.
+fn accept_entropy(bits: f64) -> Result<bool> {
+    println!("> Entropy: {:.1} bits", bits);
+
+    let min_entropy = config::get().min_entropy;
+    if bits < min_entropy {
+        warn!(
+            "{}",
+            tr!("below-min-entropy", bits = format!("{:.0}", min_entropy))
+        );
+        return Ok(Confirm::new(&tr!("use-anyway"))
+            .with_default(false)
+            .prompt()?);
+    }
+    Ok(true)
+}
+
+/// Show a generated password with its strength, letting the user
+/// regenerate it or change the length or characters until they accept one.
+pub fn preview(mut pg: PasswordGenerator) -> Result<Secret> {
+    const USE: &str = "Use it";
+    const REGENERATE: &str = "Regenerate";
+    const LENGTH: &str = "Change length";
+    const CHARACTERS: &str = "Change characters";
+    loop {
+        let password = match pg.generate_one() {
+            Ok(password) => Secret::new(password),
+            Err(err) => {
+                warn!("{}.", Error::Generator(err));
+                prompt_characters(&mut pg)?;
+                continue;
+            }
+        };
+        println!("> Generated: {}", password.expose());
+        println!("> Strength: {}", Strength::of(password.expose()));
+        let options = [USE, REGENERATE, LENGTH, CHARACTERS].map(|option| Label("preview", option));
+        let choice = prompt::select(&tr!("label-password"), options.to_vec()).prompt()?;
+        match choice.1 {
+            USE if accept_entropy(entropy(&pg))? => return Ok(password),
+            LENGTH => prompt_length(&mut pg)?,
+            CHARACTERS => prompt_characters(&mut pg)?,
+            _ => {}
+        }
+    }
+}
+
+/// Make `count` passwords without prompting, e.g. for `kpass gen`.
+pub fn many(pg: &PasswordGenerator, count: usize) -> Result<Vec<Secret>> {
+    if !LENGTH_RANGE.contains(&pg.length) {
+        return Err(anyhow!(
+            "The length must be from {} to {}",
+            LENGTH_RANGE.start(),
+            LENGTH_RANGE.end()
+        ));
+    }
+    let passwords = pg.generate(count).map_err(Error::Generator)?;
+    Ok(passwords.into_iter().map(Secret::new).collect())
+}
+
+/// Consonants and vowels pronounceable passwords are made of. Leaves out
+/// letters that are read or typed ambiguously (`c`, `q`, `w`, `x`, `y`).
+const CONSONANTS: &[u8] = b"bdfghjklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// Syllables are grouped into hyphenated words of this many.
+const SYLLABLES_PER_WORD: usize = 3;
+
+/// Settings for pronounceable passwords: random syllables,
+/// e.g. `tobmifa-dulvoke`, easier to type on a TV or console.
+pub struct Pronounceable {
+    pub syllables: usize,
+    /// Capitalize the first letter and end with a digit,
+    /// for sites that require them.
+    pub capital_and_digit: bool,
+}
+
+/// Number of possible syllables: consonant-vowel, or consonant-vowel-consonant.
+fn syllable_count() -> usize {
+    let cv = CONSONANTS.len() * VOWELS.len();
+    cv + cv * CONSONANTS.len()
+}
+
+impl Default for Pronounceable {
+    fn default() -> Self {
+        Pronounceable {
+            syllables: 6,
+            capital_and_digit: false,
+        }
+    }
+}
+impl Pronounceable {
+    /// Entropy (in bits) of a password produced with these settings.
+    pub fn entropy(&self) -> f64 {
+        let mut bits = self.syllables as f64 * (syllable_count() as f64).log2();
+        if self.capital_and_digit {
+            bits += 10_f64.log2();
+        }
+        bits
+    }
+
+    fn generate_one(&self) -> String {
+        let mut rng = OsRng;
+        let mut password = String::new();
+        for i in 0..self.syllables {
+            if i > 0 && i.is_multiple_of(SYLLABLES_PER_WORD) {
+                password.push('-');
+            }
+            // Uniform over all syllables, so each adds the same entropy.
+            let n = rng.gen_range(0..syllable_count());
+            let cv_count = CONSONANTS.len() * VOWELS.len();
+            let (cv, last) = (n % cv_count, n / cv_count);
+            password.push(CONSONANTS[cv / VOWELS.len()] as char);
+            password.push(VOWELS[cv % VOWELS.len()] as char);
+            if last > 0 {
+                password.push(CONSONANTS[last - 1] as char);
+            }
+        }
+        if self.capital_and_digit {
+            password[..1].make_ascii_uppercase();
+            password.push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        password
+    }
+}
+
+/// Make `count` pronounceable passwords without prompting, like `many`.
+pub fn many_pronounceable(settings: &Pronounceable, count: usize) -> Vec<Secret> {
+    (0..count)
+        .map(|_| Secret::new(settings.generate_one()))
+        .collect()
+}
+
+/// Prompt for pronounceable password settings.
+pub fn prompt_pronounceable() -> Result<Pronounceable> {
+    let mut settings = Pronounceable::default();
+    settings.syllables = prompt::number_in_range(&tr!("syllables"), settings.syllables, 2..=32)?;
+    settings.capital_and_digit = Confirm::new(&tr!("capital-and-digit"))
+        .with_default(false)
+        .prompt()?;
+    Ok(settings)
+}
+
+/// Generate a pronounceable password, reporting its entropy and
+/// asking for confirmation if it's below the configured minimum.
+/// Returns `None` if the user rejects a weak password.
+pub fn generate_pronounceable(settings: &Pronounceable) -> Result<Option<Secret>> {
+    if !accept_entropy(settings.entropy())? {
+        return Ok(None);
+    }
+    Ok(Some(Secret::new(settings.generate_one())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn pool_sizes_match_the_generator() {
+        for similar in [false, true] {
+            for (numbers, lower, upper, symbols, spaces) in [
+                (true, true, true, true, true),
+                (true, false, false, false, false),
+                (false, true, false, false, false),
+                (false, false, true, false, false),
+                (false, false, false, true, false),
+                (true, true, false, false, true),
+            ] {
+                let pg = PasswordGenerator {
+                    length: 256,
+                    numbers,
+                    lowercase_letters: lower,
+                    uppercase_letters: upper,
+                    symbols,
+                    spaces,
+                    exclude_similar_characters: similar,
+                    strict: false,
+                };
+                let drawn: HashSet<char> = pg
+                    .generate(20)
+                    .unwrap()
+                    .iter()
+                    .flat_map(|password| password.chars())
+                    .collect();
+                assert_eq!(drawn.len(), pool_size(&pg));
+            }
+        }
+    }
+
+    #[test]
+    fn computes_entropy() {
+        let pg = default_generator();
+        assert_eq!(pool_size(&pg), 95);
+        assert!((entropy(&pg) - 12.0 * 95_f64.log2()).abs() < 1e-9);
+        let digits = PasswordGenerator {
+            length: 4,
+            lowercase_letters: false,
+            uppercase_letters: false,
+            symbols: false,
+            spaces: false,
+            ..pg
+        };
+        assert!((entropy(&digits) - 4.0 * 10_f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn makes_pronounceable_passwords() {
+        assert_eq!(syllable_count(), 16 * 5 * 17);
+        let settings = Pronounceable::default();
+        for password in many_pronounceable(&settings, 50) {
+            let words: Vec<&str> = password.expose().split('-').collect();
+            assert_eq!(words.len(), 2);
+            for word in words {
+                assert!((6..=9).contains(&word.len()), "{}", word);
+                let mut chars = word.bytes();
+                assert!(CONSONANTS.contains(&chars.next().unwrap()));
+                assert!(VOWELS.contains(&chars.next().unwrap()));
+                assert!(word
+                    .bytes()
+                    .all(|c| CONSONANTS.contains(&c) || VOWELS.contains(&c)));
+            }
+        }
+        assert!((settings.entropy() - 6.0 * 1360_f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn adds_a_capital_and_digit() {
+        let settings = Pronounceable {
+            syllables: 2,
+            capital_and_digit: true,
+        };
+        let password = settings.generate_one();
+        assert!(password.starts_with(|c: char| c.is_ascii_uppercase()));
+        assert!(password.ends_with(|c: char| c.is_ascii_digit()));
+        assert!(!password.contains('-'));
+        assert!((settings.entropy() - 2.0 * 1360_f64.log2() - 10_f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn makes_many_passwords() {
+        let pg = default_generator();
+        let passwords = many(&pg, 5).unwrap();
+        assert_eq!(passwords.len(), 5);
+        assert!(passwords.iter().all(|p| p.expose().chars().count() == 12));
+        for length in [3, 257] {
+            assert!(many(&PasswordGenerator { length, ..pg }, 1).is_err());
+        }
+    }
+}
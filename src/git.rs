@@ -0,0 +1,105 @@
+//! Committing the database to the git repository it's in after each save
+//! (`git_commit` in the config), and optionally pushing it (`git_push`),
+//! for a versioned history of the encrypted file.
+//!
+//! Only the database file is committed. Failures are warned about
+//! but don't fail the save, which has happened by then.
+
+use std::{path::Path, process::Command};
+
+use anyhow::{anyhow, Result};
+
+use crate::config;
+
+/// Run git in `dir`, failing with its error output if it fails.
+fn git(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("git {} failed: {}", args[0], stderr.trim()));
+    }
+    Ok(())
+}
+
+fn try_commit(db_path: &Path, message: &str) -> Result<()> {
+    let db_path = db_path.canonicalize()?;
+    let (Some(dir), Some(name)) = (db_path.parent(), db_path.file_name()) else {
+        return Err(anyhow!("Invalid database path"));
+    };
+    let name = name.to_string_lossy();
+    if git(dir, &["rev-parse", "--is-inside-work-tree"]).is_err() {
+        return Err(anyhow!("{} isn't in a git repository", dir.display()));
+    }
+    git(dir, &["add", "--", &name])?;
+    // Nothing to commit, e.g. the file was committed already.
+    if git(dir, &["diff", "--cached", "--quiet", "--", &name]).is_ok() {
+        return Ok(());
+    }
+    git(dir, &["commit", "--quiet", "-m", message, "--", &name])?;
+    info!("{}", tr!("git-committed"));
+    if config::get().git_push {
+        git(dir, &["push", "--quiet"])?;
+        info!("{}", tr!("git-pushed"));
+    }
+    Ok(())
+}
+
+/// Commit the saved database, described by the summary of changes if there is one.
+pub fn commit(db_path: &Path, summary: Option<&str>) {
+    if !config::get().git_commit {
+        return;
+    }
+    let name = db_path.file_name().unwrap_or_default().to_string_lossy();
+    let message = summary.map_or_else(|| format!("Update {}", name), str::to_string);
+    if let Err(err) = try_commit(db_path, &message) {
+        warn!("{}", tr!("git-commit-failed", error = err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn log(dir: &Path) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["log", "--format=%s"])
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn commits_only_the_database() {
+        let dir = env::temp_dir().join(format!("kpass-test-git-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("db.kdbx");
+        fs::write(&db_path, "v1").unwrap();
+        assert!(try_commit(&db_path, "Add 'GitHub'").is_err());
+
+        git(&dir, &["init", "--quiet"]).unwrap();
+        git(&dir, &["config", "user.name", "kpass"]).unwrap();
+        git(&dir, &["config", "user.email", "kpass@example.com"]).unwrap();
+        git(&dir, &["config", "commit.gpgsign", "false"]).unwrap();
+        fs::write(dir.join("notes.txt"), "not committed").unwrap();
+        try_commit(&db_path, "Add 'GitHub'").unwrap();
+        // Unchanged.
+        try_commit(&db_path, "Nothing").unwrap();
+        fs::write(&db_path, "v2").unwrap();
+        try_commit(&db_path, "Modify 'GitHub'").unwrap();
+        assert_eq!(log(&dir), "Modify 'GitHub'\nAdd 'GitHub'\n");
+
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .args(["ls-files"])
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"db.kdbx\n");
+        let _ = fs::remove_dir_all(dir);
+    }
+}
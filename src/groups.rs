@@ -0,0 +1,210 @@
+//! Renaming and moving groups.
+
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, Text};
+use keepass::{
+    db::{Group, Node, Times},
+    Database,
+};
+
+use crate::{config, import::get_or_create_group, pick_group, prompt};
+
+/// How many entries and subgroups the group contains, recursively.
+fn count(group: &Group) -> (usize, usize) {
+    let mut counts = (0, 0);
+    for node in &group.children {
+        match node {
+            Node::Entry(_) => counts.0 += 1,
+            Node::Group(child) => {
+                let (entries, groups) = count(child);
+                counts.0 += entries;
+                counts.1 += groups + 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Remove the group at `path` from its parent.
+fn take_group(root: &mut Group, path: &[String]) -> Group {
+    let (name, parent) = path.split_last().expect("Not the root group");
+    let parent = get_or_create_group(root, parent);
+    let idx = parent
+        .children
+        .iter()
+        .position(|node| matches!(node, Node::Group(g) if &g.name == name))
+        .expect("Group exists");
+    match parent.children.remove(idx) {
+        Node::Group(group) => group,
+        Node::Entry(_) => unreachable!(),
+    }
+}
+
+fn has_child_group(group: &Group, name: &str) -> bool {
+    group
+        .children
+        .iter()
+        .any(|node| matches!(node, Node::Group(g) if g.name == name))
+}
+
+/// Move the group at `path` to `new_path`, renaming it to the last part.
+fn move_group(db: &mut Database, path: &[String], new_path: &[String]) {
+    let (name, parent) = new_path.split_last().expect("Not the root group");
+    let mut group = take_group(&mut db.root, path);
+    group.name = name.clone();
+    group.times.set_last_modification(Times::now());
+    if parent != &path[..path.len() - 1] {
+        group.times.set_location_changed(Times::now());
+    }
+    get_or_create_group(&mut db.root, parent).add_child(group);
+}
+
+/// Point out config that refers to the group's old path,
+/// as kpass doesn't rewrite the config file.
+fn warn_about_references(old: &str, new: &str) {
+    for rule in &config::get().audit_rules {
+        let group = rule.group.as_deref().unwrap_or("").trim_matches('/');
+        let refers = group == old || group.starts_with(&format!("{}/", old));
+        if refers {
+            warn!(
+                "{}",
+                tr!(
+                    "audit-rule-refers",
+                    rule = rule.name,
+                    old = group,
+                    new = new
+                )
+            );
+        }
+    }
+}
+
+/// Interactively rename or move a group, after previewing how much it affects.
+/// Returns whether the database was modified.
+pub fn rename_or_move(db: &mut Database) -> Result<bool> {
+    let path = pick_group(db)?;
+    if path.is_empty() {
+        return Err(anyhow!("The root group can't be renamed or moved."));
+    }
+    let old = path.join("/");
+    let (entries, subgroups) = count(get_or_create_group(&mut db.root, &path));
+
+    let options = vec![tr!("rename"), tr!("move")];
+    let action = prompt::select(&tr!("rename-or-move"), options).raw_prompt()?;
+    let new_path = if action.index == 0 {
+        let name = Text::new(&tr!("new-name"))
+            .with_initial_value(&path[path.len() - 1])
+            .prompt()?;
+        if name.is_empty() || name.contains('/') {
+            return Err(anyhow!("Group names can't be empty or contain '/'."));
+        }
+        let mut new_path = path[..path.len() - 1].to_vec();
+        new_path.push(name);
+        new_path
+    } else {
+        info!("{}", tr!("move-into"));
+        let mut new_path = pick_group(db)?;
+        if new_path.starts_with(&path) {
+            return Err(anyhow!("A group can't be moved into itself."));
+        }
+        new_path.push(path[path.len() - 1].clone());
+        new_path
+    };
+    if new_path == path {
+        return Ok(false);
+    }
+    let (name, parent) = new_path.split_last().expect("Not the root group");
+    if has_child_group(get_or_create_group(&mut db.root, parent), name) {
+        return Err(anyhow!("/{} already exists.", new_path.join("/")));
+    }
+
+    let new = new_path.join("/");
+    info!(
+        "{}",
+        tr!(
+            "move-affects",
+            old = old,
+            new = new,
+            entries = entries,
+            subgroups = subgroups
+        )
+    );
+    if !Confirm::new(&tr!("continue")).with_default(true).prompt()? {
+        return Ok(false);
+    }
+
+    move_group(db, &path, &new_path);
+    warn_about_references(&old, &new);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::Entry as KEntry;
+
+    use super::*;
+
+    fn path(path: &str) -> Vec<String> {
+        path.split('/').map(String::from).collect()
+    }
+
+    /// `Work` with an entry and `Servers` (with two entries and `Old`),
+    /// and an empty `Home`.
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        let mut servers = Group::new("Servers");
+        servers.add_child(KEntry::new());
+        servers.add_child(KEntry::new());
+        servers.add_child(Group::new("Old"));
+        work.add_child(KEntry::new());
+        work.add_child(servers);
+        db.root.add_child(work);
+        db.root.add_child(Group::new("Home"));
+        db
+    }
+
+    #[test]
+    fn counts_recursively() {
+        let mut db = database();
+        assert_eq!(count(&db.root), (3, 4));
+        assert_eq!(
+            count(get_or_create_group(&mut db.root, &path("Work"))),
+            (3, 2)
+        );
+        assert_eq!(
+            count(get_or_create_group(&mut db.root, &path("Home"))),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn renames_and_moves_groups() {
+        let mut db = database();
+        let moved = chrono::NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let servers = get_or_create_group(&mut db.root, &path("Work/Servers"));
+        servers.times.set_location_changed(moved);
+        move_group(&mut db, &path("Work/Servers"), &path("Work/Hosts"));
+        let work = get_or_create_group(&mut db.root, &path("Work"));
+        assert!(has_child_group(work, "Hosts") && !has_child_group(work, "Servers"));
+        let hosts = get_or_create_group(&mut db.root, &path("Work/Hosts"));
+        assert_eq!(count(hosts), (2, 1));
+        // Renaming isn't moving.
+        assert_eq!(hosts.times.get_location_changed(), Some(&moved));
+
+        move_group(&mut db, &path("Work/Hosts"), &path("Home/Hosts"));
+        let hosts = get_or_create_group(&mut db.root, &path("Home/Hosts"));
+        assert_eq!(count(hosts), (2, 1));
+        assert_eq!(
+            hosts.times.get_location_changed(),
+            hosts.times.get_last_modification()
+        );
+        assert_eq!(
+            count(get_or_create_group(&mut db.root, &path("Work"))),
+            (1, 0)
+        );
+    }
+}
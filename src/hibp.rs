@@ -0,0 +1,381 @@
+//! Looking up passwords in Have I Been Pwned, by SHA-1 hash.
+//!
+//! Online lookups run a few requests at a time (rate-limited and retried),
+//! and save their progress so an interrupted audit resumes where it stopped.
+//! The progress is kept in the config dir, encrypted with the local key
+//! (see `cache::key`), and removed once the audit completes. Which
+//! entries a completed audit found breached is kept the same way, so
+//! the picker can flag them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use cocoon::Cocoon;
+use keepass::db::Entry as KEntry;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{audit, cache, config};
+
+pub const RANGE_API: &str = "https://api.pwnedpasswords.com/range/";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many requests are made at once.
+const WORKERS: usize = 6;
+
+/// Minimum time between starting requests (across workers).
+const REQUEST_INTERVAL: Duration = Duration::from_millis(25);
+
+/// How often a failed request is retried, first after `RETRY_BACKOFF`
+/// and then twice as long each time.
+const MAX_RETRIES: u32 = 4;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Progress is saved every this many prefixes.
+const SAVE_EVERY: usize = 20;
+
+/// Uppercase hex SHA-1 of the password, as HIBP lists them.
+pub fn sha1_hex(password: &str) -> String {
+    hex::encode_upper(Sha1::digest(password.as_bytes()))
+}
+
+/// Look up how often each hash was seen in breaches in a downloaded
+/// corpus of `<SHA-1>:<count>` lines (e.g. from the HIBP downloader).
+pub fn offline(corpus: &Path, hashes: &HashSet<String>) -> Result<HashMap<String, u64>> {
+    let mut seen = HashMap::new();
+    for line in BufReader::new(File::open(corpus)?).lines() {
+        let line = line?;
+        let (hash, count) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+        let hash = hash.to_uppercase();
+        if hashes.contains(&hash) {
+            seen.insert(hash, count.parse().unwrap_or(1));
+        }
+    }
+    Ok(seen)
+}
+
+/// Progress is kept per database, named after a hash of its path.
+fn progress_path(db_path: &Path) -> Result<PathBuf> {
+    let db_path = db_path.canonicalize()?;
+    let hash = Sha256::digest(db_path.as_os_str().as_encoded_bytes());
+    let name: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("hibp")
+        .join(name))
+}
+
+fn read_encrypted<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let key = cache::key()?;
+    let data = Cocoon::new(&key)
+        .parse(&mut File::open(path)?)
+        .map_err(|err| anyhow!("Couldn't read {}: {:?}", path.display(), err))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn write_encrypted(path: &Path, value: &impl Serialize) -> Result<()> {
+    fs::create_dir_all(path.parent().expect("It's in the hibp dir"))?;
+    let data = serde_json::to_vec(value)?;
+    let key = cache::key()?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    Cocoon::new(&key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write {}: {:?}", path.display(), err))?;
+    Ok(())
+}
+
+/// Entries the last completed audit found breached.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Breached {
+    time: NaiveDateTime,
+    /// Their UUIDs.
+    entries: HashSet<String>,
+}
+impl Breached {
+    /// Whether the entry's password was found breached,
+    /// and hasn't been changed since.
+    pub fn contains(&self, entry: &KEntry) -> bool {
+        self.entries.contains(&entry.uuid.to_string())
+            && audit::password_changed(entry).is_none_or(|changed| changed <= self.time)
+    }
+}
+
+/// Entries the last completed audit found breached (none if there wasn't one).
+pub fn breached(db_path: &Path) -> Result<Breached> {
+    read_encrypted(&progress_path(db_path)?.with_extension("breached"))
+}
+
+/// Keep which entries (by UUID) a completed audit found breached.
+pub fn save_breached(db_path: &Path, entries: HashSet<String>) -> Result<()> {
+    let breached = Breached {
+        time: Utc::now().naive_utc(),
+        entries,
+    };
+    write_encrypted(
+        &progress_path(db_path)?.with_extension("breached"),
+        &breached,
+    )
+}
+
+/// Wait for our turn to make a request, spacing requests `REQUEST_INTERVAL` apart.
+fn throttle(next: &Mutex<Instant>) {
+    let wait = {
+        let mut next = next.lock().unwrap();
+        let now = Instant::now();
+        let at = (*next).max(now);
+        *next = at + REQUEST_INTERVAL;
+        at - now
+    };
+    thread::sleep(wait);
+}
+
+/// Fetch the suffixes (and counts) of hashes starting with `prefix`,
+/// retrying with backoff on rate limiting, server errors and network errors.
+fn fetch(agent: &ureq::Agent, next: &Mutex<Instant>, prefix: &str) -> Result<String> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        throttle(next);
+        let result = agent
+            .get(&format!("{}{}", RANGE_API, prefix))
+            .set("Add-Padding", "true")
+            .call();
+        let wait = match result {
+            Ok(response) => return Ok(response.into_string()?),
+            Err(ureq::Error::Status(429, response)) => response
+                .header("Retry-After")
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(backoff),
+            Err(ureq::Error::Status(status, _)) if status < 500 => {
+                return Err(anyhow!("HIBP responded with {}", status));
+            }
+            Err(err) if attempt == MAX_RETRIES => return Err(err.into()),
+            Err(_) => backoff,
+        };
+        if attempt == MAX_RETRIES {
+            return Err(anyhow!(
+                "HIBP kept failing, gave up after {} retries",
+                attempt
+            ));
+        }
+        attempt += 1;
+        thread::sleep(wait);
+        backoff *= 2;
+    }
+}
+
+/// The hashes (and counts) in a range API response for `prefix`,
+/// which lists `<suffix>:<count>` lines.
+fn parse_range(prefix: &str, body: &str) -> HashMap<String, u64> {
+    body.lines()
+        .filter_map(|line| {
+            let (suffix, count) = line.trim().split_once(':')?;
+            Some((format!("{}{}", prefix, suffix), count.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Print a progress bar with an estimate of the time left.
+fn print_progress(done: usize, total: usize, started: Instant) {
+    const WIDTH: usize = 30;
+    let filled = WIDTH * done / total.max(1);
+    let left = if done == 0 {
+        "?".to_string()
+    } else {
+        let secs = started.elapsed().as_secs_f64() / done as f64 * (total - done) as f64;
+        format!("{}:{:02}", secs as u64 / 60, secs as u64 % 60)
+    };
+    print!(
+        "\r> [{}{}] {}/{}, about {} left ",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        done,
+        total,
+        left
+    );
+    let _ = std::io::stdout().flush();
+}
+
+/// Look up how often each hash was seen in breaches with the HIBP range API.
+/// Only the first 5 chars of each hash are sent (k-anonymity),
+/// and responses are padded so their size doesn't reveal anything either.
+pub fn online(db_path: &Path, hashes: &HashSet<String>) -> Result<HashMap<String, u64>> {
+    let path = progress_path(db_path)?;
+    let mut checked: HashMap<String, u64> = read_encrypted(&path)?;
+    checked.retain(|hash, _| hashes.contains(hash));
+    if !checked.is_empty() {
+        info!(
+            "{}",
+            tr!(
+                "hibp-resuming",
+                checked = checked.len(),
+                count = hashes.len()
+            )
+        );
+    }
+
+    let mut todo: HashMap<&str, Vec<&String>> = HashMap::new();
+    for hash in hashes.iter().filter(|hash| !checked.contains_key(*hash)) {
+        todo.entry(&hash[..5]).or_default().push(hash);
+    }
+    let total = todo.len();
+    let queue = Mutex::new(todo.keys().copied().collect::<Vec<_>>());
+    let checked = Mutex::new(checked);
+    let done = Mutex::new(0);
+    let failure = Mutex::new(None);
+    let next = Mutex::new(Instant::now());
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let started = Instant::now();
+
+    print_progress(0, total, started);
+    thread::scope(|scope| {
+        for _ in 0..WORKERS.min(total) {
+            scope.spawn(|| loop {
+                if failure.lock().unwrap().is_some() {
+                    break;
+                }
+                let Some(prefix) = queue.lock().unwrap().pop() else {
+                    break;
+                };
+                let body = match fetch(&agent, &next, prefix) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        *failure.lock().unwrap() = Some(err);
+                        break;
+                    }
+                };
+                let seen = parse_range(prefix, &body);
+
+                let mut checked = checked.lock().unwrap();
+                for hash in &todo[prefix] {
+                    // Padding entries have a count of 0, same as unseen hashes.
+                    checked.insert(hash.to_string(), seen.get(*hash).copied().unwrap_or(0));
+                }
+                let mut done = done.lock().unwrap();
+                *done += 1;
+                print_progress(*done, total, started);
+                if (*done).is_multiple_of(SAVE_EVERY) {
+                    if let Err(err) = write_encrypted(&path, &*checked) {
+                        println!("\n! Couldn't save progress: {}", err);
+                    }
+                }
+            });
+        }
+    });
+    println!();
+
+    let checked = checked.into_inner().unwrap();
+    if let Some(err) = failure.into_inner().unwrap() {
+        write_encrypted(&path, &checked)?;
+        return Err(anyhow!(
+            "{}. Progress was saved; run the audit again to resume.",
+            err
+        ));
+    }
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(checked
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use chrono::TimeDelta;
+    use keepass::db::{History, Times};
+
+    use super::*;
+    use crate::test_util;
+
+    const PASSWORD_SHA1: &str = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8";
+
+    #[test]
+    fn hashes_like_hibp() {
+        assert_eq!(sha1_hex("password"), PASSWORD_SHA1);
+    }
+
+    #[test]
+    fn parses_range_responses() {
+        let body = "1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+                    0018A45C4D1DEF81644B54AB7F969B88D65:0\r\n\
+                    not a line\r\n";
+        let seen = parse_range("5BAA6", body);
+        assert_eq!(seen[PASSWORD_SHA1], 9545824);
+        // Padding.
+        assert_eq!(seen["5BAA60018A45C4D1DEF81644B54AB7F969B88D65"], 0);
+        assert_eq!(seen.len(), 2);
+    }
+
+    #[test]
+    fn looks_up_a_downloaded_corpus() {
+        let corpus = env::temp_dir().join(format!("kpass-test-hibp-{}.txt", std::process::id()));
+        let other = sha1_hex("other");
+        fs::write(
+            &corpus,
+            format!(
+                "{}:3\n{}:12\n{}\n",
+                "0000000000000000000000000000000000000000",
+                PASSWORD_SHA1.to_lowercase(),
+                other
+            ),
+        )
+        .unwrap();
+        let hashes = HashSet::from([PASSWORD_SHA1.to_string(), other.clone(), sha1_hex("unseen")]);
+        let seen = offline(&corpus, &hashes).unwrap();
+        fs::remove_file(&corpus).unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[PASSWORD_SHA1], 12);
+        assert_eq!(seen[&other], 1);
+    }
+
+    fn entry(password: &str, days_ago: i64) -> KEntry {
+        let mut entry = test_util::entry(&[("Password", password)]);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    #[test]
+    fn forgets_breaches_once_passwords_change() {
+        let old = entry("old", 30);
+        let mut changed = entry("new", 1);
+        changed.uuid = old.uuid;
+        let mut history = History::default();
+        history.add_entry(old.clone());
+        changed.history = Some(history);
+
+        let breached = Breached {
+            time: Utc::now().naive_utc() - TimeDelta::try_days(10).unwrap(),
+            entries: HashSet::from([old.uuid.to_string()]),
+        };
+        assert!(breached.contains(&old));
+        assert!(!breached.contains(&changed));
+        assert!(!breached.contains(&entry("old", 30)));
+    }
+}
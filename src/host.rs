@@ -0,0 +1,117 @@
+//! Host metadata for server entries (address, port and location),
+//! e.g. to tell which credential belongs to which machine.
+
+use std::{
+    fmt::Display,
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use keepass::db::Entry as KEntry;
+
+/// Entry fields the metadata is kept in, readable by other clients too.
+pub const HOST_FIELD: &str = "Host";
+pub const PORT_FIELD: &str = "Port";
+pub const LOCATION_FIELD: &str = "Location";
+
+/// Port assumed if the entry doesn't have one (SSH).
+pub const DEFAULT_PORT: u16 = 22;
+
+/// How long to wait for each address of the host to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct Host {
+    pub host: String,
+    pub port: u16,
+    pub location: Option<String>,
+}
+impl Host {
+    /// The entry's host, if it has one.
+    pub fn of(entry: &KEntry) -> Option<Host> {
+        let host = entry.get(HOST_FIELD).filter(|host| !host.is_empty())?;
+        Some(Host {
+            host: host.to_string(),
+            port: entry
+                .get(PORT_FIELD)
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(DEFAULT_PORT),
+            location: entry
+                .get(LOCATION_FIELD)
+                .filter(|location| !location.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Check the host is reachable by connecting to its port,
+    /// returning how long the connection took.
+    pub fn check(&self) -> Result<Duration> {
+        let addrs = (self.host.as_str(), self.port).to_socket_addrs()?;
+        let mut last_err = None;
+        for addr in addrs {
+            let start = Instant::now();
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(_) => return Ok(start.elapsed()),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(match last_err {
+            Some(err) => err.into(),
+            None => anyhow!("{} doesn't resolve to any address", self.host),
+        })
+    }
+}
+impl Display for Host {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)?;
+        if let Some(location) = &self.location {
+            write!(f, " ({})", location)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::test_util::entry;
+
+    #[test]
+    fn reads_the_host() {
+        let host = Host::of(&entry(&[
+            (HOST_FIELD, "db.example.com"),
+            (PORT_FIELD, "5432"),
+            (LOCATION_FIELD, "Rack 4"),
+        ]))
+        .unwrap();
+        assert_eq!(host.to_string(), "db.example.com:5432 (Rack 4)");
+
+        let host = Host::of(&entry(&[
+            (HOST_FIELD, "10.0.0.2"),
+            (PORT_FIELD, "ssh"),
+            (LOCATION_FIELD, ""),
+        ]))
+        .unwrap();
+        assert_eq!(host.to_string(), "10.0.0.2:22");
+
+        assert!(Host::of(&entry(&[(HOST_FIELD, "")])).is_none());
+        assert!(Host::of(&entry(&[(PORT_FIELD, "22")])).is_none());
+    }
+
+    #[test]
+    fn checks_reachability() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let host = Host {
+            host: "127.0.0.1".to_string(),
+            port,
+            location: None,
+        };
+        assert!(host.check().is_ok());
+
+        drop(listener);
+        assert!(host.check().is_err());
+    }
+}
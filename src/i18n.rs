@@ -0,0 +1,250 @@
+//! Translating the interactive UI with Fluent (<https://projectfluent.org>):
+//! messages are looked up by ID in the user's language, falling back to
+//! English (`locales/en.ftl`). The language is `language` in the config,
+//! or else taken from `LC_ALL`, `LC_MESSAGES` or `LANG`.
+//!
+//! Translations are Fluent files using the IDs in `locales/en.ftl`, e.g.
+//! `copied = In die Zwischenablage kopiert!`, with `{ $name }` for the
+//! values filled in. They're built in (see `BUILT_IN`), or read from
+//! `locales/<language>.ftl` in the config dir, whose messages take precedence.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    sync::OnceLock,
+};
+
+pub use fluent::FluentArgs;
+use fluent::{concurrent::FluentBundle, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+use crate::config;
+
+type Bundle = FluentBundle<FluentResource>;
+
+const ENGLISH: &str = include_str!("../locales/en.ftl");
+
+/// Translations compiled in, by language, e.g.
+/// `("de", include_str!("../locales/de.ftl"))`.
+const BUILT_IN: &[(&str, &str)] = &[];
+
+/// The user's language's messages (if not English), then English.
+static BUNDLES: OnceLock<Vec<Bundle>> = OnceLock::new();
+
+/// The user's language, e.g. `de` for `de_DE.UTF-8`,
+/// or `None` for English (or no preference).
+pub fn language() -> Option<String> {
+    let locale = config::get().language.clone().or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+    })?;
+    let language = locale
+        .split(['_', '.', '@', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match language.as_str() {
+        "" | "c" | "posix" | "en" => None,
+        _ => Some(language),
+    }
+}
+
+/// A bundle of the messages in `sources` (where they're from, and the
+/// Fluent source), later ones overriding earlier ones.
+fn bundle(language: &str, sources: &[(&str, &str)]) -> Bundle {
+    let id: LanguageIdentifier = language.parse().unwrap_or_default();
+    let mut bundle = Bundle::new_concurrent(vec![id]);
+    // Unicode isolation marks show up as junk in some terminals.
+    bundle.set_use_isolating(false);
+    for (source, contents) in sources {
+        let resource =
+            FluentResource::try_new(contents.to_string()).unwrap_or_else(|(resource, errors)| {
+                warn!(
+                    "Couldn't read some of the {} translations in {}: {:?}",
+                    language, source, errors
+                );
+                resource
+            });
+        bundle.add_resource_overriding(resource);
+    }
+    bundle
+}
+
+fn bundles() -> &'static [Bundle] {
+    BUNDLES.get_or_init(|| {
+        let mut bundles = vec![];
+        if let Some(language) = language() {
+            let mut sources: Vec<(&str, String)> = BUILT_IN
+                .iter()
+                .filter(|(lang, _)| *lang == language)
+                .map(|(_, contents)| ("kpass", contents.to_string()))
+                .collect();
+            let file =
+                config::config_dir().map(|dir| dir.join(format!("locales/{}.ftl", language)));
+            if let Some(contents) = file.and_then(|file| fs::read_to_string(file).ok()) {
+                sources.push(("the config dir", contents));
+            }
+            let sources: Vec<(&str, &str)> = sources
+                .iter()
+                .map(|(source, contents)| (*source, contents.as_str()))
+                .collect();
+            bundles.push(bundle(&language, &sources));
+        }
+        bundles.push(bundle("en", &[("kpass", ENGLISH)]));
+        bundles
+    })
+}
+
+/// Format the message in the first of the bundles that has it.
+fn format(bundles: &[Bundle], id: &str, args: Option<&FluentArgs>) -> Option<String> {
+    bundles.iter().find_map(|bundle| {
+        let pattern = bundle.get_message(id)?.value()?;
+        let mut errors = vec![];
+        Some(
+            bundle
+                .format_pattern(pattern, args, &mut errors)
+                .into_owned(),
+        )
+    })
+}
+
+/// The message in the user's language, or its ID if there's no such message.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    format(bundles(), id, args).unwrap_or_else(|| id.to_string())
+}
+
+/// A value filled into a message.
+pub fn arg(value: &dyn Display) -> FluentValue<'static> {
+    value.to_string().into()
+}
+
+/// The ID of a name of the given kind, e.g. `action-rename-move-group`
+/// for the action "Rename/move group".
+fn name_id(kind: &str, name: &str) -> String {
+    let mut id = format!("{}-", kind);
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            id.push(c);
+        } else if !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    id.trim_end_matches('-').to_string()
+}
+
+/// A name (e.g. of an action or field) in the user's language,
+/// or as is if it has no message, e.g. custom fields.
+pub fn name(kind: &str, name: &str) -> String {
+    format(bundles(), &name_id(kind, name), None).unwrap_or_else(|| name.to_string())
+}
+
+/// A name of the given kind (see `name`) shown in the user's language,
+/// e.g. as an option to select, that's matched on in English.
+#[derive(Clone, Copy)]
+pub struct Label(pub &'static str, pub &'static str);
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", name(self.0, self.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundles(translation: &str) -> Vec<Bundle> {
+        vec![
+            bundle("de", &[("the config dir", translation)]),
+            bundle("en", &[("kpass", ENGLISH)]),
+        ]
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        let bundles = bundles("copied = In die Zwischenablage kopiert!\n");
+        assert_eq!(
+            format(&bundles, "copied", None).unwrap(),
+            "In die Zwischenablage kopiert!"
+        );
+        assert_eq!(format(&bundles, "saving", None).unwrap(), "Saving...");
+        assert_eq!(format(&bundles, "no-such-message", None), None);
+    }
+
+    #[test]
+    fn fills_in_values() {
+        let bundles = bundles("no-password = { $entry } hat kein Passwort.\n");
+        let mut args = FluentArgs::new();
+        args.set("entry", arg(&"GitHub"));
+        assert_eq!(
+            format(&bundles, "no-password", Some(&args)).unwrap(),
+            "GitHub hat kein Passwort."
+        );
+        args.set("field", arg(&"Username"));
+        assert_eq!(
+            format(&bundles[1..], "copied-field", Some(&args)).unwrap(),
+            "Copied Username to clipboard!"
+        );
+    }
+
+    #[test]
+    fn keeps_valid_messages_of_broken_files() {
+        let bundles = bundles("saved = Gespeichert.\nthis isn't fluent\nok = OK?\n");
+        assert_eq!(format(&bundles, "saved", None).unwrap(), "Gespeichert.");
+        assert_eq!(format(&bundles, "ok", None).unwrap(), "OK?");
+    }
+
+    #[test]
+    fn names_have_ids() {
+        assert_eq!(
+            name_id("action", "Rename/move group"),
+            "action-rename-move-group"
+        );
+        assert_eq!(name_id("field", "OTP code"), "field-otp-code");
+        assert_eq!(name_id("order", "last modified"), "order-last-modified");
+        // Every English message is valid and complete.
+        let english = FluentResource::try_new(ENGLISH.to_string()).unwrap();
+        assert!(english.entries().count() > 50);
+    }
+
+    /// The IDs passed to `tr!` in the files in `dir`.
+    fn ids_used(dir: &std::path::Path, ids: &mut Vec<String>) {
+        for file in fs::read_dir(dir).unwrap() {
+            let path = file.unwrap().path();
+            if path.is_dir() {
+                ids_used(&path, ids);
+                continue;
+            }
+            let code = fs::read_to_string(&path).unwrap();
+            let macro_call = concat!("tr", "!(");
+            for (i, _) in code.match_indices(macro_call) {
+                // Not e.g. `include_str!(`.
+                if code[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    continue;
+                }
+                let call = code[i + macro_call.len()..].trim_start();
+                let id = call.strip_prefix('"').unwrap_or_default();
+                ids.push(id.split('"').next().unwrap_or_default().to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn used_messages_are_defined_once() {
+        // Unlike `bundle`, adding a message that's already there fails.
+        let mut english = Bundle::new_concurrent(vec![]);
+        let resource = FluentResource::try_new(ENGLISH.to_string()).unwrap();
+        english.add_resource(resource).unwrap();
+
+        let mut used = vec![];
+        ids_used(
+            &std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut used,
+        );
+        assert!(used.len() > 100);
+        for id in used {
+            assert!(english.has_message(&id), "{} has no message", id);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Entries' icons: KeePass's standard icons, by index, shown as emoji
+//! or Nerd Font glyphs (see `icons` in the config). Custom icons are
+//! images, so entries with one are shown without an icon.
+
+use anyhow::Result;
+use keepass::db::Entry as KEntry;
+
+use crate::{
+    config::{self, IconStyle},
+    i18n, prompt,
+};
+
+/// KeePass's standard icons, with an emoji and a Nerd Font
+/// (Font Awesome) glyph for each.
+const ICONS: [(&str, &str, char); 69] = [
+    ("Key", "🔑", '\u{f084}'),
+    ("World", "🌐", '\u{f0ac}'),
+    ("Warning", "🚨", '\u{f071}'),
+    ("Network server", "📡", '\u{f233}'),
+    ("Marked directory", "📁", '\u{f07b}'),
+    ("User communication", "💬", '\u{f086}'),
+    ("Parts", "🧩", '\u{f12e}'),
+    ("Notepad", "📝", '\u{f249}'),
+    ("World socket", "🔌", '\u{f1e6}'),
+    ("Identity", "🪪", '\u{f2c2}'),
+    ("Paper ready", "📄", '\u{f15c}'),
+    ("Digicam", "📷", '\u{f030}'),
+    ("IR communication", "📶", '\u{f012}'),
+    ("Multi keys", "🔐", '\u{f21b}'),
+    ("Energy", "⚡", '\u{f0e7}'),
+    ("Scanner", "📠", '\u{f02f}'),
+    ("World star", "🌟", '\u{f005}'),
+    ("CD-ROM", "💿", '\u{f192}'),
+    ("Monitor", "📺", '\u{f108}'),
+    ("Email", "📧", '\u{f0e0}'),
+    ("Configuration", "🔧", '\u{f013}'),
+    ("Clipboard ready", "📋", '\u{f0ea}'),
+    ("Paper new", "📃", '\u{f016}'),
+    ("Screen", "💻", '\u{f26c}'),
+    ("Energy careful", "🔋", '\u{f242}'),
+    ("Email box", "📬", '\u{f01c}'),
+    ("Disk", "💾", '\u{f0c7}'),
+    ("Drive", "📀", '\u{f0a0}'),
+    ("Paper question", "❓", '\u{f059}'),
+    ("Terminal encrypted", "🔒", '\u{f120}'),
+    ("Console", "📟", '\u{f120}'),
+    ("Printer", "🧾", '\u{f02f}'),
+    ("Program icons", "🔣", '\u{f009}'),
+    ("Run", "🚀", '\u{f135}'),
+    ("Settings", "🔩", '\u{f1de}'),
+    ("World computer", "🌍", '\u{f109}'),
+    ("Archive", "📦", '\u{f187}'),
+    ("Homebanking", "🏦", '\u{f19c}'),
+    ("Drive (Windows)", "🪟", '\u{f17a}'),
+    ("Clock", "🕒", '\u{f017}'),
+    ("Email search", "🔎", '\u{f002}'),
+    ("Paper flag", "🚩", '\u{f024}'),
+    ("Memory", "🧠", '\u{f2db}'),
+    ("Trash bin", "🚮", '\u{f1f8}'),
+    ("Note", "📌", '\u{f24a}'),
+    ("Expired", "⌛", '\u{f254}'),
+    ("Info", "💡", '\u{f05a}'),
+    ("Package", "🎁", '\u{f1b2}'),
+    ("Folder", "📁", '\u{f07b}'),
+    ("Folder open", "📂", '\u{f07c}'),
+    ("Folder package", "🧳", '\u{f1c6}'),
+    ("Lock open", "🔓", '\u{f09c}'),
+    ("Paper locked", "🔏", '\u{f023}'),
+    ("Checked", "✅", '\u{f00c}'),
+    ("Pen", "🖊️", '\u{f040}'),
+    ("Thumbnail", "🖼️", '\u{f03e}'),
+    ("Book", "📖", '\u{f02d}'),
+    ("List", "📜", '\u{f03a}'),
+    ("User key", "👤", '\u{f007}'),
+    ("Tool", "🛠️", '\u{f0ad}'),
+    ("Home", "🏠", '\u{f015}'),
+    ("Star", "⭐", '\u{f005}'),
+    ("Tux", "🐧", '\u{f17c}'),
+    ("Feather", "🪶", '\u{f06c}'),
+    ("Apple", "🍎", '\u{f179}'),
+    ("Wiki", "📚", '\u{f266}'),
+    ("Money", "💰", '\u{f0d6}'),
+    ("Certificate", "🎖️", '\u{f0a3}'),
+    ("BlackBerry", "📱", '\u{f10b}'),
+];
+
+/// The glyph for a standard icon, in the configured style.
+fn glyph_of(icon: usize) -> Option<String> {
+    let (_, emoji, nerd) = ICONS.get(icon)?;
+    match config::get().icons {
+        IconStyle::Emoji => Some(emoji.to_string()),
+        IconStyle::Nerd => Some(nerd.to_string()),
+        IconStyle::None => None,
+    }
+}
+
+/// The standard icon the entry shows, if it doesn't have a custom one.
+fn icon(entry: &KEntry) -> Option<usize> {
+    if entry.custom_icon_uuid.is_some() {
+        return None;
+    }
+    Some(entry.icon_id.unwrap_or(0))
+}
+
+/// The glyph for the entry's icon, if icons are shown.
+pub fn glyph(entry: &KEntry) -> Option<String> {
+    glyph_of(icon(entry)?)
+}
+
+/// The entry's icon, as e.g. `🔑 Key`.
+pub fn describe(entry: &KEntry) -> String {
+    match icon(entry).and_then(|icon| ICONS.get(icon).map(|(name, ..)| (icon, name))) {
+        Some((icon, name)) => match glyph_of(icon) {
+            Some(glyph) => format!("{} {}", glyph, name),
+            None => name.to_string(),
+        },
+        None => "(custom)".to_string(),
+    }
+}
+
+/// Pick a standard icon for the entry (replacing a custom one).
+pub fn pick(entry: &mut KEntry) -> Result<()> {
+    let options: Vec<String> = (0..ICONS.len())
+        .map(|icon| match glyph_of(icon) {
+            Some(glyph) => format!("{} {}", glyph, i18n::name("icon", ICONS[icon].0)),
+            None => i18n::name("icon", ICONS[icon].0),
+        })
+        .collect();
+    let picked = prompt::select(&tr!("icon"), options)
+        .with_starting_cursor(icon(entry).filter(|icon| *icon < ICONS.len()).unwrap_or(0))
+        .raw_prompt()?;
+    entry.icon_id = Some(picked.index);
+    entry.custom_icon_uuid = None;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_standard_icons() {
+        let mut entry = KEntry::new();
+        entry.icon_id = None;
+        assert_eq!(describe(&entry), "🔑 Key");
+        entry.icon_id = Some(37);
+        assert_eq!(describe(&entry), "🏦 Homebanking");
+        assert_eq!(glyph(&entry).as_deref(), Some("🏦"));
+        // Out of range, e.g. from a newer client.
+        entry.icon_id = Some(ICONS.len());
+        assert_eq!(describe(&entry), "(custom)");
+        assert_eq!(glyph(&entry), None);
+    }
+
+    #[test]
+    fn leaves_out_custom_icons() {
+        let mut entry = KEntry::new();
+        entry.icon_id = Some(1);
+        entry.custom_icon_uuid = Some(uuid::Uuid::new_v4());
+        assert_eq!(icon(&entry), None);
+        assert_eq!(glyph(&entry), None);
+        assert_eq!(describe(&entry), "(custom)");
+    }
+}
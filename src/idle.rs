@@ -0,0 +1,152 @@
+//! Locking the interactive session after a while without input,
+//! so walking away doesn't leave the database open.
+//!
+//! Input is noticed the way `w` does, by the terminal's access time.
+//! Locking clears the clipboard and restarts kpass in place (`exec`),
+//! which drops the decrypted databases and keys along with the rest
+//! of the process' memory and asks for the (quick) password again.
+//!
+//! Password prompts time out the same way (see `prompt_timeout`),
+//! quitting instead, so one left on screen doesn't wait forever.
+
+use std::{
+    env,
+    fs::{self, File},
+    io::Write,
+    os::unix::{fs::MetadataExt, io::AsRawFd, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use wl_clipboard_rs::copy::{clear, ClipboardType, Seat};
+
+use crate::{config, title};
+
+/// How often the terminal is checked for input.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// When the terminal last received input, in seconds since the epoch.
+/// The kernel only updates this every few seconds, which is plenty here.
+fn last_input(tty: &Path) -> Option<u64> {
+    fs::metadata(tty)
+        .ok()
+        .map(|meta| meta.atime().max(0) as u64)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The controlling terminal, the path of the device it stands for,
+/// and its settings before any prompt changed them.
+struct Terminal {
+    tty: File,
+    path: PathBuf,
+    termios: libc::termios,
+}
+impl Terminal {
+    fn open() -> Option<Self> {
+        let tty = File::open("/dev/tty").ok()?;
+        // `/dev/tty` itself doesn't track input; the terminal it stands for does.
+        let path = fs::read_link(format!("/proc/self/fd/{}", tty.as_raw_fd())).ok()?;
+        // SAFETY: `termios` is plain data, for which zeroes are valid,
+        // and `tty` is open.
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut termios) } != 0 {
+            return None;
+        }
+        Some(Terminal { tty, path, termios })
+    }
+
+    /// Clear the clipboard and the screen. This can happen in the middle of a
+    /// prompt, so the terminal is left as it was before prompting.
+    fn clear(&self) {
+        let _ = clear(ClipboardType::Regular, Seat::All);
+        // SAFETY: `tty` is open, and `termios` was filled in by `tcgetattr`.
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.termios) };
+        let _ = std::io::stdout().write_all(b"\x1b[?25h\x1b[2J\x1b[3J\x1b[H");
+    }
+
+    /// Call `on_idle` once there's been no input for `minutes`, checking
+    /// in the background until `stop` is set.
+    fn watch(self, minutes: u64, stop: Arc<AtomicBool>, on_idle: fn(&Terminal, u64)) {
+        let started = now();
+        thread::spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let Some(input) = last_input(&self.path) else {
+                continue;
+            };
+            if now().saturating_sub(input.max(started)) >= minutes * 60 {
+                on_idle(&self, minutes);
+            }
+        });
+    }
+}
+
+/// Clear the clipboard, the screen and the process' memory.
+fn lock(terminal: &Terminal, minutes: u64) {
+    terminal.clear();
+    info!("{}", tr!("idle-locked", minutes = minutes));
+    let _ = std::io::stdout().flush();
+
+    let mut args = env::args_os();
+    let mut cmd = Command::new("/proc/self/exe");
+    cmd.arg0(args.next().unwrap_or_default()).args(args);
+    title::restarting(&mut cmd);
+    let err = cmd.exec();
+
+    // E.g. the sandbox doesn't allow running the binary again.
+    warn!("{}", tr!("restart-failed", error = err));
+    exit(0);
+}
+
+/// Lock the session once there's been no input for `minutes`,
+/// watching in the background. Does nothing without a terminal.
+pub fn watch(minutes: u64) {
+    if minutes == 0 {
+        return;
+    }
+    if let Some(terminal) = Terminal::open() {
+        terminal.watch(minutes, Arc::default(), lock);
+    }
+}
+
+/// Quits if there's no input while it's held, see `prompt_timeout`.
+pub struct PromptTimeout(Arc<AtomicBool>);
+impl Drop for PromptTimeout {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+fn quit(terminal: &Terminal, minutes: u64) {
+    terminal.clear();
+    warn!("{}", tr!("idle-quitting", minutes = minutes));
+    exit(1);
+}
+
+/// Quit, clearing the clipboard and screen, if there's no input for
+/// `prompt_timeout_minutes` while the returned guard is held, e.g.
+/// around a password prompt.
+pub fn prompt_timeout() -> Option<PromptTimeout> {
+    let minutes = config::get().prompt_timeout_minutes;
+    if minutes == 0 {
+        return None;
+    }
+    let terminal = Terminal::open()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    terminal.watch(minutes, stop.clone(), quit);
+    Some(PromptTimeout(stop))
+}
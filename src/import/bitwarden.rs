@@ -0,0 +1,203 @@
+//! Bitwarden's (unencrypted) JSON export, which unlike its CSV export
+//! includes custom fields and all of a login's URIs.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use super::{group_path, Imported};
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<Folder>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Folder {
+    id: String,
+    name: String,
+}
+
+/// Logins and secure notes; other types (cards, identities) are skipped.
+const LOGIN: u8 = 1;
+const SECURE_NOTE: u8 = 2;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(rename = "type")]
+    kind: u8,
+    name: String,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    login: Option<Login>,
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Vec<Uri>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    name: Option<String>,
+    value: Option<String>,
+}
+
+/// Whether the file looks like a Bitwarden JSON export.
+pub fn is_export(contents: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(contents)
+        .is_ok_and(|json| json["items"].is_array() && json.get("encrypted").is_some())
+}
+
+/// Read entries from a Bitwarden JSON export.
+/// Folders become groups, and extra URIs and custom fields become custom fields.
+pub fn read(path: &Path) -> Result<Vec<Imported>> {
+    let export: Export = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if export.encrypted {
+        return Err(anyhow!(
+            "{} is an encrypted export; export again as unencrypted JSON",
+            path.display()
+        ));
+    }
+    let folders: HashMap<String, String> = export
+        .folders
+        .into_iter()
+        .map(|folder| (folder.id, folder.name))
+        .collect();
+
+    let mut imported = vec![];
+    let mut skipped = 0;
+    for item in export.items {
+        if ![LOGIN, SECURE_NOTE].contains(&item.kind) {
+            skipped += 1;
+            continue;
+        }
+        let folder = item
+            .folder_id
+            .and_then(|id| folders.get(&id))
+            .map(|name| group_path(name))
+            .unwrap_or_default();
+        let mut entry = Imported {
+            group: folder,
+            title: item.name,
+            notes: item.notes.unwrap_or_default(),
+            ..Default::default()
+        };
+        if let Some(login) = item.login {
+            entry.username = login.username.unwrap_or_default();
+            entry.password = login.password.unwrap_or_default();
+            entry.otp = login.totp.unwrap_or_default();
+            let mut uris = login.uris.into_iter().filter_map(|uri| uri.uri);
+            entry.url = uris.next().unwrap_or_default();
+            for (i, uri) in uris.enumerate() {
+                entry.fields.insert(format!("URL {}", i + 2), uri);
+            }
+        }
+        for field in item.fields {
+            if let Some(name) = field.name.filter(|name| !name.is_empty()) {
+                entry.fields.insert(name, field.value.unwrap_or_default());
+            }
+        }
+        imported.push(entry);
+    }
+    if skipped > 0 {
+        info!("{}", tr!("skipping-cards", count = skipped));
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::temp_file;
+
+    const EXPORT: &str = r#"{
+        "encrypted": false,
+        "folders": [{"id": "f1", "name": "Work/Dev"}],
+        "items": [
+            {
+                "type": 1,
+                "name": "GitHub",
+                "notes": null,
+                "folderId": "f1",
+                "login": {
+                    "username": "alice",
+                    "password": "hunter2",
+                    "totp": "JBSWY3DP",
+                    "uris": [
+                        {"uri": "https://github.com"},
+                        {"uri": null},
+                        {"uri": "https://gist.github.com"}
+                    ]
+                },
+                "fields": [
+                    {"name": "Recovery", "value": "1234", "type": 1},
+                    {"name": "", "value": "ignored", "type": 0}
+                ]
+            },
+            {"type": 2, "name": "Wifi", "notes": "pass: x", "folderId": null},
+            {"type": 3, "name": "Visa", "card": {}}
+        ]
+    }"#;
+
+    #[test]
+    fn reads_exports() {
+        let path = temp_file("bitwarden.json", EXPORT);
+        let imported = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.len(), 2);
+
+        let github = &imported[0];
+        assert_eq!(github.group, ["Work", "Dev"]);
+        assert_eq!(
+            (github.title.as_str(), github.username.as_str()),
+            ("GitHub", "alice")
+        );
+        assert_eq!(github.password, "hunter2");
+        assert_eq!(github.otp, "JBSWY3DP");
+        assert_eq!(github.url, "https://github.com");
+        assert_eq!(github.fields["URL 2"], "https://gist.github.com");
+        assert_eq!(github.fields["Recovery"], "1234");
+        assert_eq!(github.fields.len(), 2);
+
+        let note = &imported[1];
+        assert!(note.group.is_empty());
+        assert_eq!(note.notes, "pass: x");
+        assert!(note.password.is_empty());
+    }
+
+    #[test]
+    fn rejects_encrypted_exports() {
+        let contents = r#"{"encrypted": true, "items": []}"#;
+        assert!(is_export(contents));
+        let path = temp_file("bitwarden-encrypted.json", contents);
+        assert!(read(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn detects_exports() {
+        assert!(is_export(EXPORT));
+        assert!(!is_export(r#"{"items": []}"#));
+        assert!(!is_export(r#"{"encrypted": false, "items": {}}"#));
+        assert!(!is_export("name,password"));
+    }
+}
@@ -0,0 +1,257 @@
+//! CSV exports, e.g. from Bitwarden, LastPass, Chrome or Firefox.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use super::{group_path, Imported};
+
+/// Column names used by an exporter.
+pub struct Preset {
+    pub name: &'static str,
+    title: &'static str,
+    username: &'static str,
+    password: &'static str,
+    url: &'static str,
+    notes: &'static str,
+    folder: &'static str,
+    otp: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "bitwarden",
+        title: "name",
+        username: "login_username",
+        password: "login_password",
+        url: "login_uri",
+        notes: "notes",
+        folder: "folder",
+        otp: "login_totp",
+    },
+    Preset {
+        name: "lastpass",
+        title: "name",
+        username: "username",
+        password: "password",
+        url: "url",
+        notes: "extra",
+        folder: "grouping",
+        otp: "totp",
+    },
+    Preset {
+        name: "chrome",
+        title: "name",
+        username: "username",
+        password: "password",
+        url: "url",
+        notes: "note",
+        folder: "",
+        otp: "",
+    },
+    Preset {
+        name: "firefox",
+        title: "",
+        username: "username",
+        password: "password",
+        url: "url",
+        notes: "",
+        folder: "",
+        otp: "",
+    },
+    Preset {
+        name: "generic",
+        title: "title",
+        username: "username",
+        password: "password",
+        url: "url",
+        notes: "notes",
+        folder: "group",
+        otp: "otp",
+    },
+];
+
+/// Find the preset whose columns best match the header.
+fn detect(headers: &[String]) -> &'static Preset {
+    let has = |col: &str| col.is_empty() || headers.iter().any(|h| h == col);
+    PRESETS
+        .iter()
+        .find(|p| {
+            [p.title, p.username, p.password, p.url, p.notes, p.folder]
+                .iter()
+                .all(|col| has(col))
+        })
+        .unwrap_or(&PRESETS[PRESETS.len() - 1])
+}
+
+/// The preset matching the file's header, if it looks like
+/// a password export at all (has a password column).
+pub fn detect_file(path: &Path) -> Option<&'static str> {
+    let mut reader = csv::Reader::from_path(path).ok()?;
+    let headers: Vec<String> = reader
+        .headers()
+        .ok()?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let preset = detect(&headers);
+    headers
+        .contains(&preset.password.to_string())
+        .then_some(preset.name)
+}
+
+/// Read entries from a CSV export, using the named preset
+/// or detecting it from the header.
+pub fn read(path: &Path, preset: Option<&str>) -> Result<Vec<Imported>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+
+    let preset = match preset {
+        Some(name) => PRESETS
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("Unknown CSV format: {}", name))?,
+        None => detect(&headers),
+    };
+    info!("{}", tr!("reading-as", format = preset.name));
+
+    let col = |name: &str| headers.iter().position(|h| !name.is_empty() && h == name);
+    let columns = [
+        col(preset.title),
+        col(preset.username),
+        col(preset.password),
+        col(preset.url),
+        col(preset.notes),
+        col(preset.folder),
+        col(preset.otp),
+    ];
+
+    let mut imported = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let [title, username, password, url, notes, folder, otp] =
+            columns.map(|idx| idx.and_then(|i| record.get(i)).unwrap_or("").to_string());
+
+        // Skip rows with nothing worth importing.
+        if username.is_empty() && password.is_empty() && notes.is_empty() {
+            continue;
+        }
+
+        let title = if title.is_empty() {
+            title_from_url(&url)
+        } else {
+            title
+        };
+        imported.push(Imported {
+            group: group_path(&folder),
+            title,
+            username,
+            password,
+            url,
+            notes,
+            otp,
+            ..Default::default()
+        });
+    }
+    Ok(imported)
+}
+
+/// Use the host as the title for exports without titles (e.g. Firefox).
+fn title_from_url(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    rest.split(['/', '?', '#'])
+        .next()
+        .unwrap_or(rest)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::import::temp_file;
+
+    fn read_csv(name: &str, contents: &str, preset: Option<&str>) -> Vec<Imported> {
+        let path = temp_file(name, contents);
+        let imported = read(&path, preset);
+        fs::remove_file(&path).unwrap();
+        imported.unwrap()
+    }
+
+    #[test]
+    fn detects_presets() {
+        let headers = |cols: &str| -> Vec<String> { cols.split(',').map(String::from).collect() };
+        let name = |cols| detect(&headers(cols)).name;
+        assert_eq!(
+            name("folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp"),
+            "bitwarden"
+        );
+        assert_eq!(
+            name("url,username,password,totp,extra,name,grouping,fav"),
+            "lastpass"
+        );
+        assert_eq!(name("name,url,username,password,note"), "chrome");
+        assert_eq!(
+            name("url,username,password,httprealm,formactionorigin,guid"),
+            "firefox"
+        );
+        assert_eq!(name("something,else"), "generic");
+    }
+
+    #[test]
+    fn reads_bitwarden_exports() {
+        let imported = read_csv(
+            "bitwarden.csv",
+            "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp\n\
+             Work/Dev,,login,GitHub,\"Line 1\nLine 2\",,0,https://github.com,alice,\"pa,ss\",JBSWY3DP\n\
+             ,,note,Empty,,,0,,,,\n",
+            None,
+        );
+        assert_eq!(imported.len(), 1);
+        let github = &imported[0];
+        assert_eq!(github.group, ["Work", "Dev"]);
+        assert_eq!(github.title, "GitHub");
+        assert_eq!(github.username, "alice");
+        assert_eq!(github.password, "pa,ss");
+        assert_eq!(github.url, "https://github.com");
+        assert_eq!(github.notes, "Line 1\nLine 2");
+        assert_eq!(github.otp, "JBSWY3DP");
+    }
+
+    #[test]
+    fn titles_firefox_logins_by_host() {
+        let imported = read_csv(
+            "firefox.csv",
+            "\"url\",\"username\",\"password\",\"httpRealm\"\n\
+             \"https://accounts.example.com/login?next=/\",\"bob\",\"s3cret\",\"\"\n",
+            None,
+        );
+        assert_eq!(imported[0].title, "accounts.example.com");
+        assert!(imported[0].group.is_empty());
+    }
+
+    #[test]
+    fn uses_the_given_preset() {
+        let contents = "Title,Username,Password,URL,Notes,Group,OTP\nBank,bob,pw,,,Money,\n";
+        let imported = read_csv("generic.csv", contents, Some("generic"));
+        assert_eq!(imported[0].title, "Bank");
+        assert_eq!(imported[0].group, ["Money"]);
+        let path = temp_file("unknown.csv", contents);
+        assert!(read(&path, Some("1password")).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn detects_password_exports() {
+        let path = temp_file("detect.csv", "name,url,username,password,note\n");
+        assert_eq!(detect_file(&path), Some("chrome"));
+        fs::write(&path, "date,amount\n1,2\n").unwrap();
+        assert_eq!(detect_file(&path), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+//! KeePass 1.x databases (`.kdb`), as also written by KeePassX 0.4,
+//! unlocked with a password (key files aren't supported).
+
+use std::{collections::HashMap, fs, path::Path};
+
+use aes::{
+    cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncrypt, KeyInit, KeyIvInit},
+    Aes256,
+};
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::Imported;
+use crate::pinentry;
+
+const SIGNATURE: [u32; 2] = [0x9AA2D903, 0xB54BFB65];
+const VERSION: u32 = 0x00030002;
+const HEADER_LEN: usize = 124;
+
+const FLAG_RIJNDAEL: u32 = 2;
+const FLAG_TWOFISH: u32 = 8;
+
+/// Marks the end of a group's or entry's fields.
+const END: u16 = 0xFFFF;
+
+struct Header {
+    flags: u32,
+    final_seed: [u8; 16],
+    iv: [u8; 16],
+    groups: u32,
+    entries: u32,
+    contents_hash: [u8; 32],
+    transform_seed: [u8; 32],
+    transform_rounds: u32,
+}
+
+/// Little-endian integers, or 0 if they don't fit.
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    data.get(at..at + 2)
+        .map_or(0, |bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    data.get(at..at + 4)
+        .map_or(0, |bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn parse_header(data: &[u8]) -> Result<Header> {
+    if data.len() < HEADER_LEN || [u32_at(data, 0), u32_at(data, 4)] != SIGNATURE {
+        return Err(anyhow!("Not a KeePass 1.x database"));
+    }
+    if u32_at(data, 12) & 0xFFFFFF00 != VERSION & 0xFFFFFF00 {
+        return Err(anyhow!("Unsupported KeePass 1.x version"));
+    }
+    Ok(Header {
+        flags: u32_at(data, 8),
+        final_seed: data[16..32].try_into()?,
+        iv: data[32..48].try_into()?,
+        groups: u32_at(data, 48),
+        entries: u32_at(data, 52),
+        contents_hash: data[56..88].try_into()?,
+        transform_seed: data[88..120].try_into()?,
+        transform_rounds: u32_at(data, 120),
+    })
+}
+
+/// The key the contents are encrypted with.
+fn master_key(header: &Header, password: &[u8]) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new(<[u8; 32]>::from(Sha256::digest(password)));
+    let cipher = Aes256::new(&header.transform_seed.into());
+    let (left, right) = key.split_at_mut(16);
+    for _ in 0..header.transform_rounds {
+        cipher.encrypt_block(left.into());
+        cipher.encrypt_block(right.into());
+    }
+    let transformed = Zeroizing::new(<[u8; 32]>::from(Sha256::digest(*key)));
+    let mut hasher = Sha256::new();
+    hasher.update(header.final_seed);
+    hasher.update(*transformed);
+    Zeroizing::new(hasher.finalize().into())
+}
+
+/// Decrypt the contents, or `None` if the password is wrong.
+fn decrypt(header: &Header, body: &[u8], password: &[u8]) -> Result<Option<Zeroizing<Vec<u8>>>> {
+    let key = master_key(header, password);
+    let mut buf = Zeroizing::new(body.to_vec());
+    let plain = if header.flags & FLAG_RIJNDAEL != 0 {
+        cbc::Decryptor::<Aes256>::new(&(*key).into(), &header.iv.into())
+            .decrypt_padded_mut::<Pkcs7>(&mut buf)
+    } else if header.flags & FLAG_TWOFISH != 0 {
+        cbc::Decryptor::<twofish::Twofish>::new(&(*key).into(), &header.iv.into())
+            .decrypt_padded_mut::<Pkcs7>(&mut buf)
+    } else {
+        return Err(anyhow!("Unsupported cipher in KeePass 1.x database"));
+    };
+    // Bad padding also means a wrong key.
+    let Ok(plain) = plain else {
+        return Ok(None);
+    };
+    if Sha256::digest(plain).as_slice() != header.contents_hash {
+        return Ok(None);
+    }
+    Ok(Some(Zeroizing::new(plain.to_vec())))
+}
+
+/// The fields of the next group or entry, by type, and where the next one starts.
+fn read_fields(data: &[u8], mut at: usize) -> Result<(HashMap<u16, &[u8]>, usize)> {
+    let mut fields = HashMap::new();
+    loop {
+        if at + 6 > data.len() {
+            return Err(anyhow!("Truncated KeePass 1.x database"));
+        }
+        let kind = u16_at(data, at);
+        let len = u32_at(data, at + 2) as usize;
+        at += 6;
+        if at + len > data.len() {
+            return Err(anyhow!("Truncated KeePass 1.x database"));
+        }
+        if kind == END {
+            return Ok((fields, at + len));
+        }
+        fields.insert(kind, &data[at..at + len]);
+        at += len;
+    }
+}
+
+fn text(fields: &HashMap<u16, &[u8]>, kind: u16) -> String {
+    let bytes = fields.get(&kind).copied().unwrap_or_default();
+    let bytes = bytes.split(|b| *b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Read the groups and entries. Groups are nested by their level;
+/// KeePass' own meta-stream entries (e.g. UI state) are skipped, as are
+/// attachments.
+fn parse(header: &Header, data: &[u8]) -> Result<Vec<Imported>> {
+    let mut at = 0;
+    let mut paths: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut stack: Vec<String> = vec![];
+    for _ in 0..header.groups {
+        let (fields, next) = read_fields(data, at)?;
+        at = next;
+        let id = fields.get(&1).map(|id| u32_at(id, 0)).unwrap_or_default();
+        let level = fields.get(&8).map(|level| u16_at(level, 0)).unwrap_or(0);
+        stack.truncate(level as usize);
+        stack.push(text(&fields, 2));
+        paths.insert(id, stack.clone());
+    }
+
+    let mut imported = vec![];
+    for _ in 0..header.entries {
+        let (fields, next) = read_fields(data, at)?;
+        at = next;
+        let entry = Imported {
+            group: fields
+                .get(&2)
+                .and_then(|id| paths.get(&u32_at(id, 0)))
+                .cloned()
+                .unwrap_or_default(),
+            title: text(&fields, 4),
+            url: text(&fields, 5),
+            username: text(&fields, 6),
+            password: text(&fields, 7),
+            notes: text(&fields, 8),
+            ..Default::default()
+        };
+        let meta_stream = entry.title == "Meta-Info"
+            && entry.username == "SYSTEM"
+            && entry.url == "$"
+            && text(&fields, 0x0D) == "bin-stream";
+        if !meta_stream {
+            imported.push(entry);
+        }
+    }
+    Ok(imported)
+}
+
+/// Windows-1252's characters for 0x80-0x9F. Its five unassigned bytes
+/// stand for the C1 controls of the same value, as Windows maps them.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// The password as KeePass 1.x hashes it: in Windows-1252, or `None` if
+/// it has characters Windows-1252 lacks. Newer versions of KeePassX
+/// use UTF-8, which is tried if this doesn't work.
+fn legacy_encoding(password: &str) -> Option<Vec<u8>> {
+    password
+        .chars()
+        .map(|c| match c as u32 {
+            code @ (0..=0x7F | 0xA0..=0xFF) => Some(code as u8),
+            _ => CP1252_HIGH
+                .iter()
+                .position(|high| *high == c)
+                .map(|i| 0x80 + i as u8),
+        })
+        .collect()
+}
+
+/// Read entries from a KeePass 1.x database, asking for its password.
+pub fn read(path: &Path) -> Result<Vec<Imported>> {
+    let data = Zeroizing::new(fs::read(path)?);
+    let header = parse_header(&data)?;
+    let body = &data[HEADER_LEN..];
+
+    let prompt = tr!("password-for", database = path.display());
+    let mut error = None;
+    loop {
+        let pass = pinentry::password(&prompt, error.as_deref())?;
+        let utf8 = pass.expose().as_bytes().to_vec();
+        let encodings = legacy_encoding(pass.expose())
+            .into_iter()
+            .chain([utf8])
+            .map(Zeroizing::new);
+        for encoded in encodings {
+            if let Some(plain) = decrypt(&header, body, &encoded)? {
+                return parse(&header, &plain);
+            }
+        }
+        warn!("{}", tr!("open-failed"));
+        error = Some(tr!("wrong-password"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncryptMut;
+
+    use super::*;
+
+    fn field(kind: u16, value: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_le_bytes().to_vec();
+        out.extend((value.len() as u32).to_le_bytes());
+        out.extend(value);
+        out
+    }
+
+    fn text_field(kind: u16, value: &str) -> Vec<u8> {
+        field(kind, &[value.as_bytes(), &[0]].concat())
+    }
+
+    fn group(id: u32, name: &str, level: u16) -> Vec<u8> {
+        [
+            field(1, &id.to_le_bytes()),
+            text_field(2, name),
+            field(8, &level.to_le_bytes()),
+            field(END, &[]),
+        ]
+        .concat()
+    }
+
+    fn entry(group: u32, title: &str, username: &str, password: &str) -> Vec<u8> {
+        [
+            field(2, &group.to_le_bytes()),
+            text_field(4, title),
+            text_field(5, "https://example.com"),
+            text_field(6, username),
+            text_field(7, password),
+            text_field(8, "Some notes"),
+            field(END, &[]),
+        ]
+        .concat()
+    }
+
+    /// A database with nested groups, two entries and a meta-stream,
+    /// encrypted with AES and `password`.
+    fn database(password: &[u8]) -> Vec<u8> {
+        let meta_stream = [
+            field(2, &1u32.to_le_bytes()),
+            text_field(4, "Meta-Info"),
+            text_field(5, "$"),
+            text_field(6, "SYSTEM"),
+            text_field(0x0D, "bin-stream"),
+            field(END, &[]),
+        ]
+        .concat();
+        let plain = [
+            group(1, "Internet", 0),
+            group(2, "Mail", 1),
+            group(3, "Banking", 0),
+            entry(2, "Webmail", "alice", "hunter2"),
+            entry(3, "Bank", "bob", "s3cret"),
+            meta_stream,
+        ]
+        .concat();
+
+        let mut header = vec![0; HEADER_LEN];
+        header[0..4].copy_from_slice(&SIGNATURE[0].to_le_bytes());
+        header[4..8].copy_from_slice(&SIGNATURE[1].to_le_bytes());
+        header[8..12].copy_from_slice(&FLAG_RIJNDAEL.to_le_bytes());
+        header[12..16].copy_from_slice(&VERSION.to_le_bytes());
+        header[16..32].copy_from_slice(&[1; 16]);
+        header[32..48].copy_from_slice(&[2; 16]);
+        header[48..52].copy_from_slice(&3u32.to_le_bytes());
+        header[52..56].copy_from_slice(&3u32.to_le_bytes());
+        header[56..88].copy_from_slice(&Sha256::digest(&plain));
+        header[88..120].copy_from_slice(&[3; 32]);
+        header[120..124].copy_from_slice(&10u32.to_le_bytes());
+
+        let key = master_key(&parse_header(&header).unwrap(), password);
+        let mut buf = plain.clone();
+        buf.resize(plain.len() + 16, 0);
+        let sealed = cbc::Encryptor::<Aes256>::new(&(*key).into(), &[2; 16].into())
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, plain.len())
+            .unwrap();
+        [header, sealed.to_vec()].concat()
+    }
+
+    fn open(data: &[u8], password: &[u8]) -> Option<Vec<Imported>> {
+        let header = parse_header(data).unwrap();
+        let plain = decrypt(&header, &data[HEADER_LEN..], password).unwrap()?;
+        Some(parse(&header, &plain).unwrap())
+    }
+
+    #[test]
+    fn reads_entries() {
+        let imported = open(&database(b"password"), b"password").unwrap();
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0].group, ["Internet", "Mail"]);
+        assert_eq!(imported[0].title, "Webmail");
+        assert_eq!(imported[0].username, "alice");
+        assert_eq!(imported[0].password, "hunter2");
+        assert_eq!(imported[0].url, "https://example.com");
+        assert_eq!(imported[0].notes, "Some notes");
+        assert_eq!(imported[1].group, ["Banking"]);
+        assert_eq!(imported[1].title, "Bank");
+    }
+
+    #[test]
+    fn rejects_wrong_passwords() {
+        assert!(open(&database(b"password"), b"passwort").is_none());
+    }
+
+    #[test]
+    fn opens_windows_1252_passwords() {
+        let password = "€ „quoted“ naïve";
+        let encoded = legacy_encoding(password).unwrap();
+        assert_eq!(encoded, b"\x80 \x84quoted\x93 na\xefve".to_vec(),);
+        let data = database(&encoded);
+        assert!(open(&data, &encoded).is_some());
+        assert!(open(&data, password.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn encodes_windows_1252() {
+        assert_eq!(legacy_encoding("abc").unwrap(), b"abc");
+        assert_eq!(legacy_encoding("é").unwrap(), [0xE9]);
+        assert_eq!(legacy_encoding("Ÿœ™").unwrap(), [0x9F, 0x9C, 0x99]);
+        assert_eq!(legacy_encoding("\u{81}").unwrap(), [0x81]);
+        // C1 controls Windows-1252 has other characters for.
+        assert_eq!(legacy_encoding("\u{80}"), None);
+        assert_eq!(legacy_encoding("日本"), None);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse_header(b"not a database").is_err());
+        let mut data = database(b"password");
+        data[0] ^= 1;
+        assert!(parse_header(&data).is_err());
+        let mut data = database(b"password");
+        data[13] = 0x01;
+        data[14] = 0x01;
+        assert!(parse_header(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_contents() {
+        let header = parse_header(&database(b"password")).unwrap();
+        let data = group(1, "Internet", 0);
+        assert!(parse(&header, &data[..data.len() - 1]).is_err());
+        assert!(read_fields(&field(1, b"abc")[..8], 0).is_err());
+    }
+}
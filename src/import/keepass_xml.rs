@@ -0,0 +1,175 @@
+//! KeePass 2's XML export (also written by KeePassXC), e.g. from
+//! a database in a format or with a key kpass can't open directly.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use anyhow::Result;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::Imported;
+
+/// Whether the start of a file looks like a KeePass XML export.
+pub fn is_export(head: &str) -> bool {
+    head.contains("<KeePassFile")
+}
+
+/// Read entries from a KeePass XML export. Groups below the root
+/// become groups, and fields other than the standard ones custom fields.
+/// Entries' history isn't imported.
+pub fn read(path: &Path) -> Result<Vec<Imported>> {
+    let reader = EventReader::new(BufReader::new(File::open(path)?));
+
+    let mut imported = vec![];
+    // Names of the elements we're in.
+    let mut elements: Vec<String> = vec![];
+    // Names of the groups we're in, starting with the root group.
+    let mut groups: Vec<String> = vec![];
+    let mut entry: Option<Imported> = None;
+    let (mut key, mut value) = (String::new(), String::new());
+    for event in reader {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                let in_history = elements.iter().any(|e| e == "History");
+                match name.local_name.as_str() {
+                    "Group" => groups.push(String::new()),
+                    "Entry" if !in_history => {
+                        entry = Some(Imported {
+                            group: groups.get(1..).unwrap_or_default().to_vec(),
+                            ..Default::default()
+                        })
+                    }
+                    "String" => (key, value) = (String::new(), String::new()),
+                    _ => {}
+                }
+                elements.push(name.local_name);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                let parent = elements.len().checked_sub(2).map(|i| elements[i].as_str());
+                match (parent, elements.last().map(String::as_str)) {
+                    (Some("Group"), Some("Name")) => {
+                        if let Some(group) = groups.last_mut() {
+                            group.push_str(&text);
+                        }
+                    }
+                    (Some("String"), Some("Key")) => key.push_str(&text),
+                    (Some("String"), Some("Value")) => value.push_str(&text),
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                let in_history = elements.iter().any(|e| e == "History");
+                match name.local_name.as_str() {
+                    "Group" => {
+                        groups.pop();
+                    }
+                    "String" if !in_history && elements.last().is_some_and(|e| e == "Entry") => {
+                        if let Some(entry) = &mut entry {
+                            let value = std::mem::take(&mut value);
+                            match key.as_str() {
+                                "Title" => entry.title = value,
+                                "UserName" => entry.username = value,
+                                "Password" => entry.password = value,
+                                "URL" => entry.url = value,
+                                "Notes" => entry.notes = value,
+                                "otp" => entry.otp = value,
+                                _ => {
+                                    entry.fields.insert(key.clone(), value);
+                                }
+                            }
+                        }
+                    }
+                    "Entry" if !in_history => imported.extend(entry.take()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::temp_file;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+  <Meta><Generator>KeePassXC</Generator></Meta>
+  <Root>
+    <Group>
+      <Name>Root</Name>
+      <Entry>
+        <String><Key>Title</Key><Value>Bank</Value></String>
+        <String><Key>Password</Key><Value ProtectInMemory="True">a &lt;b&gt; &amp; c</Value></String>
+      </Entry>
+      <Group>
+        <Name>Work</Name>
+        <Group>
+          <Name>Dev</Name>
+          <Entry>
+            <String><Key>Title</Key><Value>GitHub</Value></String>
+            <String><Key>UserName</Key><Value>alice</Value></String>
+            <String><Key>Password</Key><Value>hunter2</Value></String>
+            <String><Key>URL</Key><Value>https://github.com</Value></String>
+            <String><Key>Notes</Key><Value><![CDATA[Line 1
+Line 2]]></Value></String>
+            <String><Key>otp</Key><Value>otpauth://totp/x?secret=JBSWY3DP</Value></String>
+            <String><Key>Recovery</Key><Value>1234</Value></String>
+            <History>
+              <Entry>
+                <String><Key>Title</Key><Value>GitHub (old)</Value></String>
+                <String><Key>Password</Key><Value>old</Value></String>
+              </Entry>
+            </History>
+          </Entry>
+        </Group>
+        <Entry>
+          <String><Key>Title</Key><Value>Mail</Value></String>
+        </Entry>
+      </Group>
+    </Group>
+  </Root>
+</KeePassFile>
+"#;
+
+    #[test]
+    fn reads_exports() {
+        let path = temp_file("keepass.xml", EXPORT);
+        let imported = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let titles: Vec<_> = imported.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Bank", "GitHub", "Mail"]);
+
+        let bank = &imported[0];
+        assert!(bank.group.is_empty());
+        assert_eq!(bank.password, "a <b> & c");
+
+        let github = &imported[1];
+        assert_eq!(github.group, ["Work", "Dev"]);
+        assert_eq!(github.username, "alice");
+        // Not the password in its history.
+        assert_eq!(github.password, "hunter2");
+        assert_eq!(github.url, "https://github.com");
+        assert_eq!(github.notes, "Line 1\nLine 2");
+        assert_eq!(github.otp, "otpauth://totp/x?secret=JBSWY3DP");
+        assert_eq!(github.fields.len(), 1);
+        assert_eq!(github.fields["Recovery"], "1234");
+
+        assert_eq!(imported[2].group, ["Work"]);
+    }
+
+    #[test]
+    fn detects_exports() {
+        assert!(is_export(&EXPORT[..200]));
+        assert!(!is_export("<?xml version=\"1.0\"?><html>"));
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        let path = temp_file("keepass-bad.xml", "<KeePassFile><Root><Group>");
+        assert!(read(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
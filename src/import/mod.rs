@@ -0,0 +1,215 @@
+//! Importing entries from other password managers.
+
+pub mod bitwarden;
+pub mod csv;
+pub mod kdb;
+pub mod keepass_xml;
+pub mod otp;
+pub mod pass;
+pub mod vault;
+pub mod wizard;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use inquire::Confirm;
+use keepass::{
+    db::{Entry as KEntry, Group, Node, Value},
+    Database,
+};
+
+/// An entry read from another password manager.
+#[derive(Default)]
+pub struct Imported {
+    /// Path of group names, relative to the root group.
+    pub group: Vec<String>,
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    pub url: String,
+    pub notes: String,
+    pub otp: String,
+    /// Custom fields, stored protected.
+    pub fields: BTreeMap<String, String>,
+}
+impl Imported {
+    fn into_entry(self) -> KEntry {
+        let mut entry = KEntry::new();
+        let mut set = |key: &str, value: Value| {
+            entry.fields.insert(key.to_string(), value);
+        };
+        if !self.otp.is_empty() {
+            let otp = if self.otp.starts_with("otpauth://") {
+                self.otp
+            } else {
+                // A bare secret.
+                format!(
+                    "otpauth://totp/{}?secret={}",
+                    self.title.replace(' ', "%20"),
+                    self.otp.replace(' ', "")
+                )
+            };
+            set("otp", Value::Protected(otp.as_bytes().into()));
+        }
+        set("Title", Value::Unprotected(self.title));
+        set("UserName", Value::Unprotected(self.username));
+        set(
+            "Password",
+            Value::Protected(self.password.as_bytes().into()),
+        );
+        set("URL", Value::Unprotected(self.url));
+        if !self.notes.is_empty() {
+            set("Notes", Value::Protected(self.notes.as_bytes().into()));
+        }
+        for (key, value) in self.fields {
+            set(&key, Value::Protected(value.as_bytes().into()));
+        }
+        entry
+    }
+}
+
+/// Split a folder name into a group path.
+/// Exporters separate nested folders with `/` or `\`.
+pub fn group_path(folder: &str) -> Vec<String> {
+    folder
+        .split(['/', '\\'])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Get the group at `path`, creating any missing groups along the way.
+pub fn get_or_create_group<'a>(group: &'a mut Group, path: &[String]) -> &'a mut Group {
+    let Some((name, rest)) = path.split_first() else {
+        return group;
+    };
+    let idx = group
+        .children
+        .iter()
+        .position(|node| matches!(node, Node::Group(g) if &g.name == name));
+    let idx = match idx {
+        Some(idx) => idx,
+        None => {
+            group.add_child(Group::new(name));
+            group.children.len() - 1
+        }
+    };
+    match &mut group.children[idx] {
+        Node::Group(child) => get_or_create_group(child, rest),
+        Node::Entry(_) => unreachable!(),
+    }
+}
+
+/// Show a summary of what will be imported and ask for confirmation.
+pub fn preview(imported: &[Imported]) -> Result<bool> {
+    const PREVIEW_LEN: usize = 10;
+    info!("{}", tr!("entries-to-import", count = imported.len()));
+    for entry in imported.iter().take(PREVIEW_LEN) {
+        let group = if entry.group.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", entry.group.join("/"))
+        };
+        info!("  {}{} ({})", group, entry.title, entry.username);
+    }
+    if imported.len() > PREVIEW_LEN {
+        info!(
+            "  {}",
+            tr!("and-more", count = imported.len() - PREVIEW_LEN)
+        );
+    }
+    Ok(Confirm::new(&tr!("import-confirm"))
+        .with_default(true)
+        .prompt()?)
+}
+
+/// Add the imported entries to the database.
+pub fn add_entries(db: &mut Database, imported: Vec<Imported>) {
+    for mut entry in imported {
+        let path = std::mem::take(&mut entry.group);
+        let group = get_or_create_group(&mut db.root, &path);
+        group.add_child(entry.into_entry());
+    }
+}
+
+/// Write `contents` to a temporary file for a test, returning its path.
+#[cfg(test)]
+pub(crate) fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("kpass-test-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_folders() {
+        assert_eq!(group_path("Work/Mail"), ["Work", "Mail"]);
+        assert_eq!(group_path(r"Work\Mail"), ["Work", "Mail"]);
+        assert_eq!(group_path(" Work / Mail /"), ["Work", "Mail"]);
+        assert!(group_path("").is_empty());
+    }
+
+    #[test]
+    fn makes_entries() {
+        let imported = Imported {
+            title: "My Bank".into(),
+            password: "hunter2".into(),
+            otp: "JBSW Y3DP".into(),
+            fields: BTreeMap::from([("PIN".to_string(), "1234".to_string())]),
+            ..Default::default()
+        };
+        let entry = imported.into_entry();
+        assert_eq!(entry.get_title(), Some("My Bank"));
+        assert_eq!(entry.get_password(), Some("hunter2"));
+        assert_eq!(
+            entry.get_raw_otp_value(),
+            Some("otpauth://totp/My%20Bank?secret=JBSWY3DP")
+        );
+        assert!(matches!(entry.fields["PIN"], Value::Protected(_)));
+        assert!(!entry.fields.contains_key("Notes"));
+
+        let uri = "otpauth://totp/x?secret=JBSWY3DP";
+        let entry = Imported {
+            otp: uri.into(),
+            ..Default::default()
+        }
+        .into_entry();
+        assert_eq!(entry.get_raw_otp_value(), Some(uri));
+    }
+
+    #[test]
+    fn adds_entries_to_their_groups() {
+        let mut db = Database::new(Default::default());
+        let imported = |group: &[&str], title: &str| Imported {
+            group: group.iter().map(|name| name.to_string()).collect(),
+            title: title.into(),
+            ..Default::default()
+        };
+        add_entries(
+            &mut db,
+            vec![
+                imported(&["Work", "Mail"], "Webmail"),
+                imported(&["Work"], "GitHub"),
+                imported(&[], "Bank"),
+            ],
+        );
+        let paths: Vec<_> = crate::entries_with_paths(&db.root)
+            .into_iter()
+            .map(|(path, entry)| (path, entry.get_title().unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (vec!["Work".into(), "Mail".into()], "Webmail".into()),
+                (vec!["Work".into()], "GitHub".into()),
+                (vec![], "Bank".into()),
+            ]
+        );
+        // Groups aren't duplicated.
+        assert_eq!(db.root.children.len(), 2);
+    }
+}
@@ -0,0 +1,581 @@
+//! TOTP seeds from authenticator app backups (Aegis and andOTP), so
+//! codes can move into the database next to the logins they're for,
+//! or from a single `otpauth://` URI (e.g. from a QR code) when
+//! enrolling 2FA. Tokens are matched to entries by issuer and account,
+//! and written to the `otp` field as `otpauth://` URIs.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use inquire::Confirm;
+use keepass::{
+    db::{Entry as KEntry, Group, Node, Times, Value},
+    Database,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::Imported;
+use crate::{domain, get_entry_mut, otp::Totp, prompt};
+
+/// A TOTP token from a backup.
+pub struct Token {
+    pub issuer: String,
+    pub account: String,
+    /// The `otpauth://totp/...` URI.
+    pub uri: String,
+}
+
+#[derive(Deserialize)]
+struct AegisBackup {
+    header: AegisHeader,
+    db: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct AegisHeader {
+    slots: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct AegisDb {
+    entries: Vec<AegisEntry>,
+}
+
+#[derive(Deserialize)]
+struct AegisEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    issuer: String,
+    info: AegisInfo,
+}
+
+#[derive(Deserialize)]
+struct AegisInfo {
+    secret: String,
+    algo: String,
+    digits: u32,
+    period: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct AndOtpEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    /// Often `issuer:account`.
+    label: String,
+    digits: u32,
+    period: Option<u64>,
+    algorithm: String,
+}
+
+/// Percent-encode text for an `otpauth://` URI.
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decode percent-escapes, leaving invalid ones as they are.
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn token(
+    issuer: &str,
+    account: &str,
+    secret: &str,
+    algorithm: &str,
+    digits: u32,
+    period: u64,
+) -> Token {
+    let label = if issuer.is_empty() {
+        encode(account)
+    } else {
+        format!("{}:{}", encode(issuer), encode(account))
+    };
+    let mut uri = format!(
+        "otpauth://totp/{}?secret={}&algorithm={}&digits={}&period={}",
+        label,
+        secret.replace(' ', "").to_uppercase(),
+        algorithm.to_uppercase(),
+        digits,
+        period
+    );
+    if !issuer.is_empty() {
+        uri.push_str(&format!("&issuer={}", encode(issuer)));
+    }
+    Token {
+        issuer: issuer.to_string(),
+        account: account.to_string(),
+        uri,
+    }
+}
+
+/// Read TOTP tokens from an (unencrypted) Aegis or andOTP backup.
+/// HOTP and Steam tokens are skipped, as kpass only does TOTP.
+pub fn read(path: &Path) -> Result<Vec<Token>> {
+    let invalid = || {
+        anyhow!(
+            "{} isn't a JSON backup; export it unencrypted",
+            path.display()
+        )
+    };
+    let contents = fs::read_to_string(path).map_err(|_| invalid())?;
+    let json: serde_json::Value = serde_json::from_str(&contents).map_err(|_| invalid())?;
+
+    let mut tokens = vec![];
+    let mut skipped = 0;
+    if json.is_array() {
+        let entries: Vec<AndOtpEntry> = serde_json::from_value(json)?;
+        for entry in entries {
+            if !entry.kind.eq_ignore_ascii_case("totp") {
+                skipped += 1;
+                continue;
+            }
+            let (issuer, account) = match entry.label.split_once(':') {
+                Some((issuer, account)) if entry.issuer.is_empty() => {
+                    (issuer.trim().to_string(), account.trim().to_string())
+                }
+                _ => (entry.issuer, entry.label),
+            };
+            tokens.push(token(
+                &issuer,
+                &account,
+                &entry.secret,
+                &entry.algorithm,
+                entry.digits,
+                entry.period.unwrap_or(30),
+            ));
+        }
+    } else {
+        let backup: AegisBackup = serde_json::from_value(json)
+            .map_err(|_| anyhow!("{} isn't an Aegis or andOTP backup", path.display()))?;
+        if backup.header.slots.is_some() || !backup.db.is_object() {
+            return Err(anyhow!(
+                "{} is an encrypted backup; export it again without encryption",
+                path.display()
+            ));
+        }
+        let db: AegisDb = serde_json::from_value(backup.db)?;
+        for entry in db.entries {
+            if entry.kind != "totp" {
+                skipped += 1;
+                continue;
+            }
+            tokens.push(token(
+                &entry.issuer,
+                &entry.name,
+                &entry.info.secret,
+                &entry.info.algo,
+                entry.info.digits,
+                entry.info.period.unwrap_or(30),
+            ));
+        }
+    }
+    if skipped > 0 {
+        info!("{}", tr!("skipping-tokens", count = skipped));
+    }
+    Ok(tokens)
+}
+
+/// The token in an `otpauth://totp/` URI, e.g. from a QR code shown when
+/// enrolling 2FA. The issuer is taken from the `issuer` parameter, or
+/// else the label's `issuer:` prefix.
+pub fn from_uri(uri: &str) -> Result<Token> {
+    let uri = uri.trim();
+    if uri.starts_with("otpauth-migration://") {
+        return Err(anyhow!(
+            "This is a Google Authenticator export; scan each account's own QR code instead"
+        ));
+    }
+    if uri.starts_with("otpauth://hotp/") {
+        return Err(anyhow!("HOTP isn't supported, only TOTP"));
+    }
+    let (label, query) = uri
+        .strip_prefix("otpauth://totp/")
+        .and_then(|rest| rest.split_once('?'))
+        .ok_or_else(|| anyhow!("Not an otpauth://totp/ URI"))?;
+    Totp::parse(uri)?
+        .check()
+        .map_err(|err| anyhow!("Invalid OTP settings: {}", err))?;
+
+    let label = decode(label);
+    let (prefix, account) = match label.split_once(':') {
+        Some((issuer, account)) => (issuer.trim(), account.trim()),
+        None => ("", label.trim()),
+    };
+    let issuer = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("issuer="))
+        .map(|issuer| decode(&issuer.replace('+', " ")))
+        .unwrap_or_else(|| prefix.to_string());
+    Ok(Token {
+        issuer,
+        account: account.to_string(),
+        uri: uri.to_string(),
+    })
+}
+
+/// Entries outside the recycle bin, with their paths and UUIDs.
+fn candidates(db: &Database) -> Vec<(String, Uuid, &KEntry)> {
+    fn walk<'a>(
+        group: &'a Group,
+        bin: Option<Uuid>,
+        path: &mut Vec<String>,
+        out: &mut Vec<(String, Uuid, &'a KEntry)>,
+    ) {
+        for node in &group.children {
+            match node {
+                Node::Group(child) if Some(child.uuid) == bin => {}
+                Node::Group(child) => {
+                    path.push(child.name.clone());
+                    walk(child, bin, path, out);
+                    path.pop();
+                }
+                Node::Entry(entry) => {
+                    let mut name = path.clone();
+                    name.push(entry.get_title().unwrap_or("(Untitled)").to_string());
+                    out.push((name.join("/"), entry.uuid, entry));
+                }
+            }
+        }
+    }
+
+    let bin = db
+        .meta
+        .recyclebin_uuid
+        .filter(|_| db.meta.recyclebin_enabled != Some(false));
+    let mut out = vec![];
+    walk(&db.root, bin, &mut vec![], &mut out);
+    out
+}
+
+/// Whether the entry is for the token's issuer and account: the issuer
+/// is in its title or URLs' hosts, and the account is its username
+/// (or the token has none).
+fn matches(entry: &KEntry, token: &Token) -> bool {
+    let issuer = token.issuer.to_lowercase().replace(' ', "");
+    if issuer.is_empty() {
+        return false;
+    }
+    let title = entry
+        .get_title()
+        .unwrap_or("")
+        .to_lowercase()
+        .replace(' ', "");
+    let issuer_matches = title.contains(&issuer)
+        || domain::entry_hosts(entry)
+            .iter()
+            .any(|host| host.contains(&issuer));
+    let account_matches = token.account.is_empty()
+        || entry
+            .get_username()
+            .is_some_and(|username| username.eq_ignore_ascii_case(&token.account));
+    issuer_matches && account_matches
+}
+
+/// What to do with a token.
+enum Plan {
+    /// Set the `otp` field of the entry with the UUID and path,
+    /// and whether that replaces another.
+    Set(Uuid, String, bool),
+    New,
+}
+
+/// Match the tokens to entries (asking when several match), show what
+/// will change and, once confirmed, make the changes.
+/// Returns whether anything changed.
+pub fn add_tokens(db: &mut Database, tokens: Vec<Token>) -> Result<bool> {
+    let mut plans = vec![];
+    let candidates = candidates(db);
+    for token in &tokens {
+        let found: Vec<_> = candidates
+            .iter()
+            .filter(|(_, _, entry)| matches(entry, token))
+            .collect();
+        let found = match found.len() {
+            0 => None,
+            1 => Some(found[0]),
+            _ => {
+                let mut options: Vec<String> =
+                    found.iter().map(|(path, _, _)| path.clone()).collect();
+                options.push(tr!("create-new-entry"));
+                let message = tr!(
+                    "entry-for-token",
+                    issuer = token.issuer,
+                    account = token.account
+                );
+                let choice = prompt::select(&message, options).raw_prompt()?;
+                found.get(choice.index).copied()
+            }
+        };
+        let plan = match found {
+            Some((_, _, entry)) if entry.get_raw_otp_value() == Some(&token.uri) => continue,
+            Some((path, uuid, entry)) => {
+                Plan::Set(*uuid, path.clone(), entry.get_raw_otp_value().is_some())
+            }
+            None => Plan::New,
+        };
+        plans.push((token, plan));
+    }
+    if plans.is_empty() {
+        info!("{}", tr!("tokens-already-imported"));
+        return Ok(false);
+    }
+
+    info!("{}", tr!("tokens-to-import", count = plans.len()));
+    for (token, plan) in &plans {
+        let change = match plan {
+            Plan::Set(_, path, true) => tr!("token-replaces", entry = path),
+            Plan::Set(_, path, false) => tr!("token-adds", entry = path),
+            Plan::New => tr!("token-new-entry"),
+        };
+        info!("  {} ({}) → {}", token.issuer, token.account, change);
+    }
+    if !Confirm::new(&tr!("import-confirm"))
+        .with_default(true)
+        .prompt()?
+    {
+        return Ok(false);
+    }
+
+    let mut new = vec![];
+    for (token, plan) in plans {
+        match plan {
+            Plan::Set(uuid, _, _) => {
+                let entry = get_entry_mut(db, uuid.as_u128()).expect("The entry was just found");
+                let before = entry.clone();
+                entry.fields.insert(
+                    "otp".to_string(),
+                    Value::Protected(token.uri.as_bytes().into()),
+                );
+                entry
+                    .history
+                    .get_or_insert_with(Default::default)
+                    .add_entry(before);
+                entry.times.set_last_modification(Times::now());
+            }
+            Plan::New => new.push(Imported {
+                title: if token.issuer.is_empty() {
+                    token.account.clone()
+                } else {
+                    token.issuer.clone()
+                },
+                username: token.account.clone(),
+                otp: token.uri.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+    super::add_entries(db, new);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::temp_file;
+    use crate::test_util;
+
+    fn read_backup(name: &str, contents: &str) -> Result<Vec<Token>> {
+        let path = temp_file(name, contents);
+        let tokens = read(&path);
+        fs::remove_file(&path).unwrap();
+        tokens
+    }
+
+    #[test]
+    fn percent_encodes() {
+        assert_eq!(encode("Acme Co: a/b@c.com"), "Acme%20Co%3A%20a%2Fb%40c.com");
+        assert_eq!(encode("é"), "%C3%A9");
+        assert_eq!(decode("Acme%20Co%3A%20a%2Fb%40c.com"), "Acme Co: a/b@c.com");
+        assert_eq!(decode("%C3%A9"), "é");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn makes_uris() {
+        let token = token(
+            "Acme Co",
+            "alice@example.com",
+            "jbsw y3dp ehpk 3pxp",
+            "sha256",
+            8,
+            60,
+        );
+        assert_eq!(
+            token.uri,
+            "otpauth://totp/Acme%20Co:alice%40example.com?secret=JBSWY3DPEHPK3PXP\
+             &algorithm=SHA256&digits=8&period=60&issuer=Acme%20Co"
+        );
+        let token = super::token(
+            "",
+            "alice",
+            "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+            "SHA1",
+            6,
+            30,
+        );
+        assert!(token.uri.starts_with("otpauth://totp/alice?"));
+        assert!(!token.uri.contains("issuer="));
+    }
+
+    #[test]
+    fn reads_aegis_backups() {
+        let tokens = read_backup(
+            "aegis.json",
+            r#"{"version": 1, "header": {"slots": null, "params": null}, "db": {
+                "version": 2,
+                "entries": [
+                    {"type": "totp", "name": "alice", "issuer": "GitHub",
+                     "info": {"secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "algo": "SHA1", "digits": 6, "period": 30}},
+                    {"type": "hotp", "name": "bob", "issuer": "Bank",
+                     "info": {"secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "algo": "SHA1", "digits": 6, "counter": 1}}
+                ]}}"#,
+        )
+        .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(
+            (tokens[0].issuer.as_str(), tokens[0].account.as_str()),
+            ("GitHub", "alice")
+        );
+        assert!(Totp::parse(&tokens[0].uri).unwrap().check().is_ok());
+    }
+
+    #[test]
+    fn rejects_encrypted_aegis_backups() {
+        let encrypted = r#"{"version": 1, "header": {"slots": [], "params": {}}, "db": "base64"}"#;
+        assert!(read_backup("aegis-encrypted.json", encrypted).is_err());
+        assert!(read_backup("not-json.json", "PK\u{3}\u{4}").is_err());
+    }
+
+    #[test]
+    fn reads_andotp_backups() {
+        let tokens = read_backup(
+            "andotp.json",
+            r#"[
+                {"secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "issuer": "", "label": "GitHub: alice", "digits": 6,
+                 "type": "TOTP", "algorithm": "SHA1", "period": 30},
+                {"secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "issuer": "Bank", "label": "bob:x", "digits": 8,
+                 "type": "TOTP", "algorithm": "SHA512"},
+                {"secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP", "label": "Steam", "digits": 5,
+                 "type": "STEAM", "algorithm": "SHA1"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(
+            (tokens[0].issuer.as_str(), tokens[0].account.as_str()),
+            ("GitHub", "alice")
+        );
+        assert_eq!(
+            (tokens[1].issuer.as_str(), tokens[1].account.as_str()),
+            ("Bank", "bob:x")
+        );
+        assert!(tokens[1].uri.contains("&digits=8&period=30"));
+    }
+
+    fn entry(title: &str, username: &str, url: &str) -> KEntry {
+        test_util::entry(&[("Title", title), ("UserName", username), ("URL", url)])
+    }
+
+    #[test]
+    fn matches_entries_by_issuer_and_account() {
+        let token = |issuer: &str, account: &str| {
+            super::token(
+                issuer,
+                account,
+                "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+                "SHA1",
+                6,
+                30,
+            )
+        };
+        let github = entry("GitHub (work)", "Alice", "");
+        assert!(matches(&github, &token("GitHub", "alice")));
+        assert!(matches(&github, &token("Git Hub", "")));
+        assert!(!matches(&github, &token("GitHub", "bob")));
+        assert!(!matches(&github, &token("", "alice")));
+        let by_host = entry("Work", "alice", "https://login.acme.com");
+        assert!(matches(&by_host, &token("acme", "alice")));
+    }
+
+    #[test]
+    fn leaves_out_recycled_candidates() {
+        let mut db = Database::new(Default::default());
+        let mut bin = Group::new("Recycle Bin");
+        bin.add_child(entry("Old", "", ""));
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", "", ""));
+        db.root.add_child(work);
+        db.root.add_child(bin);
+        let paths: Vec<_> = candidates(&db).into_iter().map(|(path, ..)| path).collect();
+        assert_eq!(paths, ["Work/GitHub"]);
+    }
+
+    #[test]
+    fn reads_otpauth_uris() {
+        let secret = "secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+        let token = from_uri(&format!(
+            " otpauth://totp/ACME%20Co:alice%40example.com?{}&issuer=Acme+Inc \n",
+            secret
+        ))
+        .unwrap();
+        assert_eq!(token.issuer, "Acme Inc");
+        assert_eq!(token.account, "alice@example.com");
+        assert!(token.uri.starts_with("otpauth://"));
+
+        let token = from_uri(&format!("otpauth://totp/GitHub:%20alice?{}", secret)).unwrap();
+        assert_eq!(
+            (token.issuer.as_str(), token.account.as_str()),
+            ("GitHub", "alice")
+        );
+        let token = from_uri(&format!("otpauth://totp/alice?{}", secret)).unwrap();
+        assert_eq!(
+            (token.issuer.as_str(), token.account.as_str()),
+            ("", "alice")
+        );
+    }
+
+    #[test]
+    fn rejects_other_uris() {
+        let secret = "secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+        assert!(from_uri("otpauth-migration://offline?data=CjEKCkhlbGxv").is_err());
+        assert!(from_uri(&format!("otpauth://hotp/alice?{}&counter=1", secret)).is_err());
+        assert!(from_uri(&format!("https://example.com/?{}", secret)).is_err());
+        assert!(from_uri("otpauth://totp/alice").is_err());
+        assert!(from_uri("otpauth://totp/alice?secret=JBSWY3DP").is_err());
+        assert!(from_uri(&format!("otpauth://totp/alice?{}&digits=4", secret)).is_err());
+    }
+}
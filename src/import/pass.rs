@@ -0,0 +1,200 @@
+//! Stores of `pass`, the standard unix password manager.
+//! Each entry is a GPG-encrypted file; the first line is the password
+//! and following lines are free-form, often `key: value` pairs.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+use super::Imported;
+
+/// The store `pass` uses by default.
+pub fn default_store_dir() -> PathBuf {
+    env::var_os("PASSWORD_STORE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".password-store")))
+        .unwrap_or_else(|| PathBuf::from(".password-store"))
+}
+
+/// Find all `.gpg` files under `dir`, skipping hidden
+/// files and directories (e.g. `.git`).
+fn find_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "gpg") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Decrypt a file with `gpg`, which takes care of
+/// prompting for the key's passphrase.
+fn decrypt(path: &Path) -> Result<Zeroizing<String>> {
+    let output = Command::new("gpg")
+        .args(["--quiet", "--decrypt"])
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|err| anyhow!("Couldn't run gpg: {}", err))?;
+    let contents = Zeroizing::new(String::from_utf8(output.stdout)?);
+    if !output.status.success() {
+        return Err(anyhow!("Couldn't decrypt {}", path.display()));
+    }
+    Ok(contents)
+}
+
+/// Map the contents of a decrypted file to entry fields.
+/// Recognized `key: value` lines fill in the username, URL and OTP;
+/// everything else goes into the notes.
+fn parse(contents: &str, entry: &mut Imported) {
+    let mut lines = contents.lines();
+    entry.password = lines.next().unwrap_or("").to_string();
+
+    let mut notes = vec![];
+    for line in lines {
+        if line.starts_with("otpauth://") {
+            entry.otp = line.trim().to_string();
+            continue;
+        }
+        let field = line.split_once(':').and_then(|(key, value)| {
+            let value = value.trim().to_string();
+            match key.trim().to_lowercase().as_str() {
+                "user" | "username" | "login" | "email" => Some((&mut entry.username, value)),
+                "url" | "website" => Some((&mut entry.url, value)),
+                "otp" | "totp" => Some((&mut entry.otp, value)),
+                _ => None,
+            }
+        });
+        match field {
+            Some((field, value)) if field.is_empty() => *field = value,
+            _ => notes.push(line),
+        }
+    }
+    entry.notes = notes.join("\n").trim().to_string();
+}
+
+/// Read and decrypt all entries in the store.
+/// Directories become groups and file names become titles.
+pub fn read(store: &Path) -> Result<Vec<Imported>> {
+    if !store.is_dir() {
+        return Err(anyhow!("No password store at {}", store.display()));
+    }
+    let mut files = vec![];
+    find_files(store, &mut files)?;
+    files.sort();
+    info!("{}", tr!("decrypting-with-gpg", count = files.len()));
+
+    let mut imported = vec![];
+    for path in files {
+        let relative = path.strip_prefix(store)?;
+        let group = relative
+            .parent()
+            .map(|dir| {
+                dir.components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut entry = Imported {
+            group,
+            title,
+            ..Default::default()
+        };
+        parse(&decrypt(&path)?, &mut entry);
+        imported.push(entry);
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(contents: &str) -> Imported {
+        let mut entry = Imported::default();
+        parse(contents, &mut entry);
+        entry
+    }
+
+    #[test]
+    fn parses_entries() {
+        let entry = parsed(
+            "hunter2\n\
+             login: alice\n\
+             URL: https://example.com:8080/login\n\
+             otpauth://totp/x?secret=JBSWY3DP\n\
+             Security question: first pet\n\
+             \n\
+             Recovery codes below.\n",
+        );
+        assert_eq!(entry.password, "hunter2");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.url, "https://example.com:8080/login");
+        assert_eq!(entry.otp, "otpauth://totp/x?secret=JBSWY3DP");
+        assert_eq!(
+            entry.notes,
+            "Security question: first pet\n\nRecovery codes below."
+        );
+    }
+
+    #[test]
+    fn keeps_repeated_fields_in_the_notes() {
+        let entry = parsed("pw\nuser: alice\nemail: alice@example.com\ntotp: JBSWY3DP\n");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.otp, "JBSWY3DP");
+        assert_eq!(entry.notes, "email: alice@example.com");
+    }
+
+    #[test]
+    fn parses_bare_passwords() {
+        let entry = parsed("only: a password");
+        assert_eq!(entry.password, "only: a password");
+        assert!(entry.username.is_empty() && entry.notes.is_empty());
+        assert!(parsed("").password.is_empty());
+    }
+
+    #[test]
+    fn finds_encrypted_files() {
+        let store = env::temp_dir().join(format!("kpass-test-pass-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&store);
+        for path in [
+            "Work/GitHub.gpg",
+            "Bank.gpg",
+            ".git/x.gpg",
+            "Work/.hidden.gpg",
+            ".gpg-id",
+        ] {
+            let path = store.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let mut files = vec![];
+        find_files(&store, &mut files).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [store.join("Bank.gpg"), store.join("Work/GitHub.gpg")]
+        );
+        fs::remove_dir_all(&store).unwrap();
+    }
+}
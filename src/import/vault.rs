@@ -0,0 +1,342 @@
+//! Secrets in a HashiCorp Vault KV engine (version 1 or 2),
+//! read over Vault's HTTP API. Like the `vault` CLI, the address and
+//! token come from `VAULT_ADDR` and `VAULT_TOKEN` (or `~/.vault-token`).
+
+use std::{env, fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value as Json;
+use zeroize::Zeroizing;
+
+use super::Imported;
+
+const DEFAULT_ADDR: &str = "https://127.0.0.1:8200";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection to Vault.
+struct Client {
+    agent: ureq::Agent,
+    addr: String,
+    token: Zeroizing<String>,
+    namespace: Option<String>,
+}
+impl Client {
+    fn from_env() -> Result<Self> {
+        let addr = env::var("VAULT_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.to_string());
+        let token = match env::var("VAULT_TOKEN") {
+            Ok(token) => token,
+            Err(_) => env::var_os("HOME")
+                .and_then(|home| fs::read_to_string(Path::new(&home).join(".vault-token")).ok())
+                .ok_or_else(|| {
+                    anyhow!("No Vault token; set VAULT_TOKEN or log in with `vault login`")
+                })?,
+        };
+        Ok(Client {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            addr: addr.trim_end_matches('/').to_string(),
+            token: Zeroizing::new(token.trim().to_string()),
+            namespace: env::var("VAULT_NAMESPACE").ok(),
+        })
+    }
+
+    /// Get `path` (under `/v1/`), or `None` if there's nothing there.
+    fn get(&self, path: &str) -> Result<Option<Json>> {
+        let mut request = self
+            .agent
+            .get(&format!("{}/v1/{}", self.addr, path))
+            .set("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.set("X-Vault-Namespace", namespace);
+        }
+        match request.call() {
+            Ok(response) => Ok(Some(serde_json::from_str(&response.into_string()?)?)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(ureq::Error::Status(403, _)) => Err(anyhow!("Vault denied access to {}", path)),
+            Err(err) => Err(anyhow!("Vault request failed: {}", err)),
+        }
+    }
+}
+
+/// The KV engine a path is in: its mount (e.g. `secret`)
+/// and whether it's version 2 (versioned).
+fn find_mount(client: &Client, path: &str) -> Result<(String, bool)> {
+    let mount = client
+        .get(&format!("sys/internal/ui/mounts/{}", path))?
+        .ok_or_else(|| anyhow!("No secrets engine at {}", path))?;
+    let data = &mount["data"];
+    if data["type"].as_str().is_some_and(|t| t != "kv") {
+        return Err(anyhow!("{} isn't in a KV secrets engine", path));
+    }
+    let name = data["path"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Unexpected response from Vault"))?
+        .trim_end_matches('/')
+        .to_string();
+    let v2 = data["options"]["version"].as_str() == Some("2");
+    Ok((name, v2))
+}
+
+/// The keys listed under a directory (ending with `/` for subdirectories).
+fn list(client: &Client, mount: &str, v2: bool, dir: &str) -> Result<Vec<String>> {
+    let path = match v2 {
+        true => format!("{}/metadata/{}?list=true", mount, dir),
+        false => format!("{}/{}?list=true", mount, dir),
+    };
+    Ok(client
+        .get(&path)?
+        .and_then(|list| {
+            list["data"]["keys"].as_array().map(|keys| {
+                keys.iter()
+                    .filter_map(|key| key.as_str().map(String::from))
+                    .collect()
+            })
+        })
+        .unwrap_or_default())
+}
+
+/// The data of the secret at `path`.
+fn read_secret(client: &Client, mount: &str, v2: bool, path: &str) -> Result<Option<Json>> {
+    let secret = match v2 {
+        true => client.get(&format!("{}/data/{}", mount, path))?,
+        false => client.get(&format!("{}/{}", mount, path))?,
+    };
+    Ok(secret.map(|secret| match v2 {
+        true => secret["data"]["data"].clone(),
+        false => secret["data"].clone(),
+    }))
+}
+
+/// Map a secret's keys to entry fields: well-known keys (e.g. `username`,
+/// `password`, `url`) become the standard fields, the rest custom fields.
+fn to_entry(data: &serde_json::Map<String, Json>, entry: &mut Imported) {
+    for (key, value) in data {
+        let value = match value {
+            Json::String(s) => s.clone(),
+            Json::Null => continue,
+            other => other.to_string(),
+        };
+        let field = match key.to_lowercase().as_str() {
+            "username" | "user" | "login" => &mut entry.username,
+            "password" | "pass" => &mut entry.password,
+            "url" | "uri" => &mut entry.url,
+            "notes" => &mut entry.notes,
+            "otp" | "totp" => &mut entry.otp,
+            _ => entry.fields.entry(key.clone()).or_default(),
+        };
+        if field.is_empty() {
+            *field = value;
+        } else {
+            entry.fields.insert(key.clone(), value);
+        }
+    }
+}
+
+/// Read all secrets under `path` (e.g. `secret/infra`), recursively.
+/// Paths within the engine become groups, e.g. `infra/db/prod`
+/// becomes the entry `prod` in the group `infra/db`.
+pub fn read(path: &str) -> Result<Vec<Imported>> {
+    read_from(&Client::from_env()?, path)
+}
+
+fn read_from(client: &Client, path: &str) -> Result<Vec<Imported>> {
+    let path = path.trim_matches('/');
+    let (mount, v2) = find_mount(client, path)?;
+    let start = path
+        .strip_prefix(&mount)
+        .unwrap_or("")
+        .trim_start_matches('/');
+    info!(
+        "{}",
+        tr!(
+            "reading-from-vault",
+            path = path,
+            address = client.addr,
+            version = if v2 { 2 } else { 1 }
+        )
+    );
+
+    // The start is either a directory or a single secret.
+    let mut dirs = vec![format!("{}/", start).trim_start_matches('/').to_string()];
+    let mut secrets = vec![];
+    if !start.is_empty() {
+        secrets.push(start.to_string());
+    }
+    while let Some(dir) = dirs.pop() {
+        for key in list(client, &mount, v2, &dir)? {
+            let key = format!("{}{}", dir, key);
+            if key.ends_with('/') {
+                dirs.push(key);
+            } else {
+                secrets.push(key);
+            }
+        }
+    }
+    secrets.sort();
+
+    let mut imported = vec![];
+    for secret_path in secrets {
+        let Some(Json::Object(data)) = read_secret(client, &mount, v2, &secret_path)? else {
+            // E.g. the start was a directory, or the secret was deleted.
+            continue;
+        };
+        let mut group: Vec<String> = secret_path.split('/').map(String::from).collect();
+        let title = group.pop().unwrap_or_default();
+        let mut entry = Imported {
+            group,
+            title,
+            ..Default::default()
+        };
+        to_entry(&data, &mut entry);
+        imported.push(entry);
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Serve Vault's API for a KV engine at `secret/`, with `infra/db/prod`,
+    /// `infra/web` and `other`, returning its address.
+    fn serve(v2: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let secret = |data: Json| match v2 {
+            true => json!({"data": {"data": data, "metadata": {}}}),
+            false => json!({"data": data}),
+        };
+        let list = |keys: Json| json!({"data": {"keys": keys}});
+        let prefix = if v2 { "secret/metadata/" } else { "secret/" };
+        let data = if v2 { "secret/data/" } else { "secret/" };
+        let responses: Vec<(String, Json)> = vec![
+            (
+                "sys/internal/ui/mounts/secret/infra".into(),
+                json!({"data": {"type": "kv", "path": "secret/",
+                    "options": {"version": if v2 { "2" } else { "1" }}}}),
+            ),
+            (
+                format!("{}infra/?list=true", prefix),
+                list(json!(["db/", "web"])),
+            ),
+            (
+                format!("{}infra/db/?list=true", prefix),
+                list(json!(["prod"])),
+            ),
+            (
+                format!("{}infra/db/prod", data),
+                secret(json!({"username": "admin", "password": "hunter2",
+                    "port": 5432, "unset": null})),
+            ),
+            (
+                format!("{}infra/web", data),
+                secret(json!({"url": "https://example.com", "TOTP": "JBSWY3DP"})),
+            ),
+        ];
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut token = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("X-Vault-Token: ") {
+                        token = value.trim().to_string();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request
+                    .split(' ')
+                    .nth(1)
+                    .unwrap()
+                    .trim_start_matches("/v1/");
+                let body = responses
+                    .iter()
+                    .find(|(p, _)| p == path)
+                    .map(|(_, body)| body.to_string());
+                let status = match (&body, token.as_str()) {
+                    (_, token) if token != "s.token" => "403 Forbidden",
+                    (Some(_), _) => "200 OK",
+                    (None, _) => "404 Not Found",
+                };
+                let body = body.unwrap_or_default();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        addr
+    }
+
+    fn client(addr: String, token: &str) -> Client {
+        Client {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            addr,
+            token: Zeroizing::new(token.to_string()),
+            namespace: None,
+        }
+    }
+
+    #[test]
+    fn reads_secrets_recursively() {
+        for v2 in [false, true] {
+            let client = client(serve(v2), "s.token");
+            let imported = read_from(&client, "/secret/infra/").unwrap();
+            assert_eq!(imported.len(), 2);
+
+            let prod = &imported[0];
+            assert_eq!(prod.group, ["infra", "db"]);
+            assert_eq!(prod.title, "prod");
+            assert_eq!(prod.username, "admin");
+            assert_eq!(prod.password, "hunter2");
+            assert_eq!(prod.fields["port"], "5432");
+            assert!(!prod.fields.contains_key("unset"));
+
+            let web = &imported[1];
+            assert_eq!(
+                (web.group.as_slice(), web.title.as_str()),
+                (&["infra".to_string()][..], "web")
+            );
+            assert_eq!(web.url, "https://example.com");
+            assert_eq!(web.otp, "JBSWY3DP");
+        }
+    }
+
+    #[test]
+    fn reports_denied_access() {
+        let client = client(serve(true), "s.wrong");
+        assert!(read_from(&client, "secret/infra").is_err());
+    }
+
+    #[test]
+    fn maps_keys_to_fields() {
+        let data = json!({"Login": "alice", "pass": "pw", "uri": "https://x.com",
+            "notes": "n", "user": "bob", "pin": "1234", "nested": {"a": 1}});
+        let mut entry = Imported::default();
+        to_entry(data.as_object().unwrap(), &mut entry);
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.password, "pw");
+        assert_eq!(entry.url, "https://x.com");
+        assert_eq!(entry.notes, "n");
+        assert_eq!(entry.fields["pin"], "1234");
+        // Keys for a field that's already set are kept as custom fields.
+        assert_eq!(entry.fields["user"], "bob");
+        assert_eq!(entry.fields["nested"], r#"{"a":1}"#);
+    }
+}
@@ -0,0 +1,252 @@
+//! A guided import for first-time migration: finds exports from other
+//! password managers in the downloads dir, lets you pick one, and
+//! offers to delete it afterwards (it holds all passwords in plain text).
+
+use std::{
+    env,
+    fmt::{Display, Formatter},
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use inquire::{validator::Validation, Confirm, Text};
+
+use super::{bitwarden, csv, keepass_xml, Imported};
+use crate::{config, prompt};
+
+/// How much of a file is read to recognize it.
+const SNIFF_LEN: u64 = 4096;
+
+/// Larger files aren't considered.
+const MAX_LEN: u64 = 50 * 1024 * 1024;
+
+/// The kinds of exports recognized.
+#[derive(Clone, Copy)]
+enum Format {
+    /// A CSV export, by preset (see `csv::PRESETS`).
+    Csv(&'static str),
+    BitwardenJson,
+    KeepassXml,
+}
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Csv(preset) => write!(f, "{} CSV", preset),
+            Format::BitwardenJson => write!(f, "Bitwarden JSON"),
+            Format::KeepassXml => write!(f, "KeePass XML"),
+        }
+    }
+}
+
+/// An export found in the downloads dir.
+struct Found {
+    path: PathBuf,
+    format: Format,
+    modified: SystemTime,
+}
+impl Display for Found {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let modified: DateTime<Local> = self.modified.into();
+        write!(
+            f,
+            "{} ({}, {})",
+            self.path.file_name().unwrap_or_default().to_string_lossy(),
+            self.format,
+            modified.format("%Y-%m-%d %H:%M")
+        )
+    }
+}
+
+/// Where browsers save downloads.
+fn downloads_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DOWNLOAD_DIR")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join("Downloads")))
+}
+
+/// Recognize an export by its extension and contents.
+fn sniff(path: &Path) -> Option<Format> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "csv" => csv::detect_file(path).map(Format::Csv),
+        "json" => {
+            let contents = fs::read_to_string(path).ok()?;
+            bitwarden::is_export(&contents).then_some(Format::BitwardenJson)
+        }
+        "xml" => {
+            let mut head = String::new();
+            File::open(path)
+                .ok()?
+                .take(SNIFF_LEN)
+                .read_to_string(&mut head)
+                .ok()?;
+            keepass_xml::is_export(&head).then_some(Format::KeepassXml)
+        }
+        _ => None,
+    }
+}
+
+/// Exports in `dir`, newest first.
+fn find(dir: &Path) -> Vec<Found> {
+    let mut found: Vec<Found> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|item| {
+            let path = item.path();
+            let meta = item.metadata().ok().filter(|meta| meta.is_file())?;
+            // Password exports are small; don't read through big downloads.
+            if meta.len() > MAX_LEN {
+                return None;
+            }
+            Some(Found {
+                format: sniff(&path)?,
+                modified: meta.modified().ok()?,
+                path,
+            })
+        })
+        .collect();
+    found.sort_by_key(|found| std::cmp::Reverse(found.modified));
+    found
+}
+
+fn read(path: &Path, format: Format) -> Result<Vec<Imported>> {
+    match format {
+        Format::Csv(preset) => csv::read(path, Some(preset)),
+        Format::BitwardenJson => bitwarden::read(path),
+        Format::KeepassXml => keepass_xml::read(path),
+    }
+}
+
+/// Ask which database to import into: one named in the config, or a path.
+fn pick_database() -> Result<PathBuf> {
+    let names: Vec<&str> = config::get().databases.keys().map(String::as_str).collect();
+    if !names.is_empty() {
+        let mut options: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        options.push(tr!("another-database"));
+        let choice = prompt::select(&tr!("import-into"), options).raw_prompt()?;
+        if let Some(name) = names.get(choice.index) {
+            return Ok(config::database_path(name));
+        }
+    }
+    let path = Text::new(&tr!("database-path"))
+        .with_validator(|path: &str| {
+            Ok(if Path::new(path).is_file() {
+                Validation::Valid
+            } else {
+                Validation::Invalid(tr!("no-such-file").into())
+            })
+        })
+        .prompt()?;
+    Ok(PathBuf::from(path))
+}
+
+/// A chosen export, read and confirmed, with the database to import it into.
+pub struct Wizard {
+    pub export: PathBuf,
+    pub imported: Vec<Imported>,
+    pub db_path: PathBuf,
+}
+
+/// Find exports, ask which one to import and where to,
+/// and preview it. Returns `None` if cancelled or nothing was found.
+pub fn run() -> Result<Option<Wizard>> {
+    let Some(dir) = downloads_dir() else {
+        warn!("{}", tr!("no-downloads-dir"));
+        return Ok(None);
+    };
+    info!("{}", tr!("looking-for-exports", dir = dir.display()));
+    let found = find(&dir);
+    if found.is_empty() {
+        info!("{}", tr!("no-exports-found"));
+        info!("  kpass import csv|bitwarden|xml <database> <file>");
+        return Ok(None);
+    }
+    info!("{}", tr!("found-exports", count = found.len()));
+    let Some(Found { path, format, .. }) =
+        prompt::select(&tr!("import"), found).prompt_skippable()?
+    else {
+        return Ok(None);
+    };
+
+    let imported = read(&path, format)?;
+    if !super::preview(&imported)? {
+        return Ok(None);
+    }
+    Ok(Some(Wizard {
+        export: path,
+        imported,
+        db_path: pick_database()?,
+    }))
+}
+
+/// Offer to delete the export after it's been imported.
+pub fn offer_delete(export: &Path) -> Result<()> {
+    warn!("{}", tr!("export-in-plain-text", path = export.display()));
+    let delete = Confirm::new(&tr!("delete-it"))
+        .with_default(true)
+        .prompt()?;
+    if delete {
+        fs::remove_file(export)?;
+        info!("{}", tr!("deleted"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn finds_exports_newest_first() {
+        let dir = env::temp_dir().join(format!("kpass-test-wizard-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("photos.csv")).unwrap();
+        let files = [
+            ("Chrome Passwords.csv", "name,url,username,password,note\n"),
+            (
+                "bitwarden_export.json",
+                r#"{"encrypted": false, "items": []}"#,
+            ),
+            ("vault.XML", "<?xml version=\"1.0\"?>\n<KeePassFile>"),
+            ("contacts.csv", "name,email\nAlice,alice@example.com\n"),
+            ("encrypted.json", r#"{"encrypted": true, "data": "..."}"#),
+            ("notes.txt", "password"),
+        ];
+        let start = SystemTime::now() - Duration::from_secs(3600);
+        for (i, (name, contents)) in files.iter().enumerate() {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(start + Duration::from_secs(i as u64))
+                .unwrap();
+        }
+
+        let found: Vec<String> = find(&dir)
+            .iter()
+            .map(|found| {
+                let name = found.path.file_name().unwrap().to_string_lossy();
+                format!("{}: {}", name, found.format)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "vault.XML: KeePass XML",
+                "bitwarden_export.json: Bitwarden JSON",
+                "Chrome Passwords.csv: chrome CSV",
+            ]
+        );
+        assert!(find(&dir.join("missing")).is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,193 @@
+//! An index of a database's entries, so large databases (thousands of
+//! entries) don't need a walk of the whole tree for every lookup:
+//!
+//! - by UUID, to where the entry is in the tree, e.g. to change it;
+//! - by the trigrams of its lowercase title, username and URL, for searching.
+//!
+//! The index is a snapshot: rebuild it after changing the database.
+//! Lookups check what they find, and fall back to walking the tree
+//! if the index is out of date.
+
+use std::collections::{HashMap, HashSet};
+
+use keepass::{
+    db::{Entry as KEntry, Group, Node},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::get_entry_mut;
+
+#[derive(Default)]
+pub struct Index {
+    /// Where each entry is: the indices of the nodes leading to it from the root.
+    positions: HashMap<Uuid, Vec<usize>>,
+    /// Each entry's lowercase title, username and URL, one per line.
+    texts: HashMap<Uuid, String>,
+    trigrams: HashMap<[char; 3], Vec<Uuid>>,
+}
+
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// The node at `position` under `group`.
+fn entry_at<'a>(group: &'a Group, position: &[usize]) -> Option<&'a KEntry> {
+    let (last, groups) = position.split_last()?;
+    let mut group = group;
+    for &idx in groups {
+        match group.children.get(idx)? {
+            Node::Group(child) => group = child,
+            Node::Entry(_) => return None,
+        }
+    }
+    match group.children.get(*last)? {
+        Node::Entry(entry) => Some(entry),
+        Node::Group(_) => None,
+    }
+}
+
+fn entry_at_mut<'a>(group: &'a mut Group, position: &[usize]) -> Option<&'a mut KEntry> {
+    let (last, groups) = position.split_last()?;
+    let mut group = group;
+    for &idx in groups {
+        match group.children.get_mut(idx)? {
+            Node::Group(child) => group = child,
+            Node::Entry(_) => return None,
+        }
+    }
+    match group.children.get_mut(*last)? {
+        Node::Entry(entry) => Some(entry),
+        Node::Group(_) => None,
+    }
+}
+
+impl Index {
+    pub fn build(db: &Database) -> Self {
+        fn collect(group: &Group, position: &mut Vec<usize>, index: &mut Index) {
+            for (i, node) in group.children.iter().enumerate() {
+                position.push(i);
+                match node {
+                    Node::Group(child) => collect(child, position, index),
+                    Node::Entry(entry) => index.add(entry, position.clone()),
+                }
+                position.pop();
+            }
+        }
+
+        let mut index = Index::default();
+        collect(&db.root, &mut vec![], &mut index);
+        index
+    }
+
+    fn add(&mut self, entry: &KEntry, position: Vec<usize>) {
+        let text = [entry.get_title(), entry.get_username(), entry.get_url()]
+            .map(|field| field.unwrap_or("").to_lowercase())
+            .join("\n");
+        for trigram in trigrams(&text) {
+            self.trigrams.entry(trigram).or_default().push(entry.uuid);
+        }
+        self.texts.insert(entry.uuid, text);
+        self.positions.insert(entry.uuid, position);
+    }
+
+    pub fn contains(&self, uuid: &Uuid) -> bool {
+        self.positions.contains_key(uuid)
+    }
+
+    /// Find an entry to change it.
+    pub fn entry_mut<'a>(&self, db: &'a mut Database, uuid: &Uuid) -> Option<&'a mut KEntry> {
+        let position = self.positions.get(uuid);
+        let current = position
+            .and_then(|position| entry_at(&db.root, position))
+            .is_some_and(|entry| entry.uuid == *uuid);
+        match position {
+            Some(position) if current => entry_at_mut(&mut db.root, position),
+            _ => get_entry_mut(db, uuid.as_u128()),
+        }
+    }
+
+    /// The entries whose title, username or URL contain `query`, ignoring case.
+    pub fn search(&self, query: &str) -> HashSet<Uuid> {
+        let query = query.to_lowercase();
+        let matches = |uuid: &&Uuid| self.texts[*uuid].contains(&query);
+        let query_trigrams = trigrams(&query);
+        if query_trigrams.is_empty() {
+            // Too short to narrow down.
+            return self.texts.keys().filter(matches).copied().collect();
+        }
+        // Only entries with the query's rarest trigram can match.
+        let candidates = query_trigrams
+            .iter()
+            .map(|trigram| self.trigrams.get(trigram).map_or(&[][..], Vec::as_slice))
+            .min_by_key(|uuids| uuids.len())
+            .unwrap_or_default();
+        candidates.iter().filter(matches).copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, username: &str, url: &str) -> KEntry {
+        test_util::entry(&[("Title", title), ("UserName", username), ("URL", url)])
+    }
+
+    fn database(entries: Vec<KEntry>) -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        let mut servers = Group::new("Servers");
+        for (i, entry) in entries.into_iter().enumerate() {
+            if i % 2 == 0 {
+                work.add_child(entry);
+            } else {
+                servers.add_child(entry);
+            }
+        }
+        work.add_child(servers);
+        db.root.add_child(work);
+        db
+    }
+
+    #[test]
+    fn searches_by_trigram() {
+        let github = entry("GitHub", "alice", "https://github.com");
+        let gitlab = entry("GitLab", "bob", "https://gitlab.com");
+        let bank = entry("Bank", "Alice", "");
+        let uuids = [github.uuid, gitlab.uuid, bank.uuid];
+        let index = Index::build(&database(vec![github, gitlab, bank]));
+        assert_eq!(index.search("HUB"), HashSet::from([uuids[0]]));
+        assert_eq!(index.search("alice"), HashSet::from([uuids[0], uuids[2]]));
+        assert_eq!(index.search("lab.c"), HashSet::from([uuids[1]]));
+        assert_eq!(index.search("gi"), HashSet::from([uuids[0], uuids[1]]));
+        assert_eq!(index.search("").len(), 3);
+        assert!(index.search("gitea").is_empty());
+        // Not across fields.
+        assert!(index.search("bankalice").is_empty());
+    }
+
+    #[test]
+    fn finds_entries_to_change() {
+        let (a, b, c) = (entry("A", "", ""), entry("B", "", ""), entry("C", "", ""));
+        let uuids = [a.uuid, b.uuid, c.uuid];
+        let mut db = database(vec![a, b, c]);
+        let index = Index::build(&db);
+        assert!(uuids.iter().all(|uuid| index.contains(uuid)));
+        for uuid in &uuids {
+            assert_eq!(index.entry_mut(&mut db, uuid).unwrap().uuid, *uuid);
+        }
+        assert!(index.entry_mut(&mut db, &Uuid::new_v4()).is_none());
+
+        // Out of date once the tree changes, so it falls back to walking it.
+        let Node::Group(work) = &mut db.root.children[0] else {
+            unreachable!()
+        };
+        work.children.remove(0);
+        assert_eq!(index.entry_mut(&mut db, &uuids[2]).unwrap().uuid, uuids[2]);
+        assert!(index.entry_mut(&mut db, &uuids[0]).is_none());
+    }
+}
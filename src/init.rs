@@ -0,0 +1,224 @@
+//! Creating new databases and changing their master keys.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, Password, PasswordDisplayMode, Text};
+use keepass::{
+    config::{DatabaseConfig, KdfConfig},
+    db::Group,
+    Database, DatabaseKey,
+};
+
+use crate::{config, prompt, recovery, secret::Secret, PW_CACHE};
+
+/// Groups new databases start out with.
+const DEFAULT_GROUPS: &[&str] = &["General", "Email", "Finance", "Internet", "Servers"];
+
+/// Argon2 settings for new databases, which unlock in about a second
+/// on most machines. `kpass kdf` tunes them to the machine at hand.
+pub const ARGON2_MEMORY: u64 = 64 * 1024 * 1024;
+pub const ARGON2_ITERATIONS: u64 = 10;
+pub const ARGON2_PARALLELISM: u32 = 2;
+
+/// Rounds for AES-KDF, KeePass' default.
+const AES_ROUNDS: u64 = 60_000;
+
+/// Pick the key derivation function.
+fn pick_kdf() -> Result<KdfConfig> {
+    let options = vec![tr!("kdf-argon2id"), "Argon2d".to_string(), tr!("kdf-aes")];
+    let choice = prompt::select(&tr!("kdf"), options).raw_prompt()?;
+    let version = argon2::Version::Version13;
+    Ok(match choice.index {
+        0 => KdfConfig::Argon2id {
+            iterations: ARGON2_ITERATIONS,
+            memory: ARGON2_MEMORY,
+            parallelism: ARGON2_PARALLELISM,
+            version,
+        },
+        1 => KdfConfig::Argon2 {
+            iterations: ARGON2_ITERATIONS,
+            memory: ARGON2_MEMORY,
+            parallelism: ARGON2_PARALLELISM,
+            version,
+        },
+        _ => KdfConfig::Aes { rounds: AES_ROUNDS },
+    })
+}
+
+/// Prompt for a new master password, entered twice.
+pub fn new_password() -> Result<Secret> {
+    Ok(Secret::new(
+        Password::new(&tr!("new-master-password"))
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_formatter(&|_| String::from("🔑"))
+            .with_custom_confirmation_message(&tr!("confirm-password"))
+            .prompt()?,
+    ))
+}
+
+/// Optionally pick a key file, generating it if it doesn't exist yet.
+pub fn new_key_file() -> Result<Option<PathBuf>> {
+    let use_key_file = Confirm::new(&tr!("require-key-file"))
+        .with_default(false)
+        .prompt()?;
+    if !use_key_file {
+        return Ok(None);
+    }
+    let path = PathBuf::from(Text::new(&tr!("key-file-path")).prompt()?);
+    if !path.exists() {
+        // A 32 byte key file is used as-is by all KeePass clients.
+        let mut key = zeroize::Zeroizing::new([0; 32]);
+        File::open("/dev/urandom")?.read_exact(&mut *key)?;
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o400)
+            .open(&path)?
+            .write_all(&*key)?;
+        info!("{}", tr!("generated-key-file", path = path.display()));
+        warn!("{}", tr!("keep-key-file-safe"));
+    }
+    Ok(Some(path))
+}
+
+/// The key made of a password and (optionally) a key file.
+pub fn compose_key(pass: &Secret, key_file: Option<&Path>) -> Result<DatabaseKey> {
+    let key = DatabaseKey::new().with_password(pass.expose());
+    match key_file {
+        Some(path) => Ok(key.with_keyfile(&mut File::open(path)?)?),
+        None => Ok(key),
+    }
+}
+
+/// Interactively create a new database.
+pub fn create(db_path: &Path) -> Result<()> {
+    if db_path.exists() {
+        return Err(anyhow!("{} already exists.", db_path.display()));
+    }
+    let pass = new_password()?;
+    let key_file = new_key_file()?;
+    let key = compose_key(&pass, key_file.as_deref())?;
+
+    let config = DatabaseConfig {
+        kdf_config: pick_kdf()?,
+        ..Default::default()
+    };
+    let mut db = Database::new(config);
+    let name = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    db.root.name = name.to_string();
+    db.meta.database_name = Some(name.to_string());
+    for group in DEFAULT_GROUPS {
+        db.root.add_child(Group::new(group));
+    }
+
+    let _lock = crate::lock_db(db_path)?;
+    info!("{}", tr!("creating"));
+    crate::write_verified(&db, &key, db_path)?;
+    info!("{}", tr!("created", path = db_path.display()));
+    if let Some(key_file) = key_file {
+        println!("> {}", tr!("add-key-file"));
+        println!(
+            ">   \"{}\" = \"{}\"",
+            db_path.canonicalize()?.display(),
+            key_file.canonicalize()?.display()
+        );
+    }
+    Ok(())
+}
+
+/// Interactively change the database's password and key file,
+/// returning the new key. The old version is kept as a backup.
+pub fn change_key(db: &mut Database, db_path: &Path) -> Result<DatabaseKey> {
+    let pass = new_password()?;
+    let current = config::key_file(db_path);
+    let key_file = match current {
+        Some(current) => {
+            let options = vec![
+                tr!("keep-key-file"),
+                tr!("change-key-file"),
+                tr!("remove-key-file"),
+            ];
+            match prompt::select(&tr!("key-file"), options)
+                .raw_prompt()?
+                .index
+            {
+                0 => Some(current.to_path_buf()),
+                1 => new_key_file()?,
+                _ => None,
+            }
+        }
+        None => new_key_file()?,
+    };
+    let key = compose_key(&pass, key_file.as_deref())?;
+
+    info!("{}", tr!("saving"));
+    crate::save_db(db, &key, db_path)?;
+    info!("{}", tr!("saved-with-backup"));
+
+    // Both the quick password and recovery codes wrap the old password.
+    let pw_cache = Path::new(PW_CACHE);
+    if pw_cache.exists() {
+        std::fs::remove_file(pw_cache)?;
+    }
+    let recovery_codes = recovery::sidecar_path(db_path);
+    if recovery_codes.exists() {
+        std::fs::remove_file(recovery_codes)?;
+        warn!("{}", tr!("recovery-codes-invalidated"));
+    }
+
+    if key_file.as_deref() != current {
+        match key_file {
+            Some(key_file) => {
+                warn!("{}", tr!("update-key-files"));
+                warn!(
+                    "  \"{}\" = \"{}\"",
+                    db_path.canonicalize()?.display(),
+                    key_file.canonicalize()?.display()
+                );
+            }
+            None => warn!("{}", tr!("remove-from-key-files")),
+        }
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn composes_keys_with_key_files() {
+        let dir = env::temp_dir().join(format!("kpass-test-init-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("db.key");
+        fs::write(&key_file, [7; 32]).unwrap();
+        let pass = Secret::new("correct horse".to_string());
+
+        let mut db = Database::new(DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 1 },
+            ..Default::default()
+        });
+        db.root.add_child(Group::new("Work"));
+        let mut data = vec![];
+        db.save(&mut data, compose_key(&pass, Some(&key_file)).unwrap())
+            .unwrap();
+
+        let open = |key| Database::open(&mut data.as_slice(), key);
+        assert!(open(compose_key(&pass, Some(&key_file)).unwrap()).is_ok());
+        assert!(open(compose_key(&pass, None).unwrap()).is_err());
+        fs::write(&key_file, [8; 32]).unwrap();
+        assert!(open(compose_key(&pass, Some(&key_file)).unwrap()).is_err());
+        assert!(compose_key(&pass, Some(&dir.join("missing.key"))).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
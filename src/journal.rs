@@ -0,0 +1,200 @@
+//! An append-only log of the entries each save added, modified or
+//! removed, for answering what changed when, e.g. last month, without
+//! diffing backups. Only entry paths and times are kept, never values.
+//!
+//! Unlike the changelog it isn't capped, and it stays on this machine:
+//! it's kept in the config dir, one per database, each record encrypted
+//! on its own with the local key (see `cache::key`), so saving only
+//! ever appends to it.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use cocoon::MiniCocoon;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{cache, config};
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    time: NaiveDateTime,
+    /// `added`, `modified` or `removed`.
+    kind: String,
+    /// Path of the entry, e.g. `Work/Servers/db1`.
+    entry: String,
+}
+
+/// The journal lives in the config dir, named after a hash of the database path.
+fn journal_path(db_path: &Path) -> Result<PathBuf> {
+    let db_path = db_path.canonicalize()?;
+    let hash = Sha256::digest(db_path.as_os_str().as_encoded_bytes());
+    let name: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("journal")
+        .join(name))
+}
+
+/// Encrypt records of changes made at `time`, one hex line each.
+fn seal<'a>(
+    key: &[u8],
+    time: NaiveDateTime,
+    changes: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<String> {
+    let mut cocoon = MiniCocoon::from_key(key, &rand::thread_rng().gen::<[u8; 32]>());
+    let mut lines = String::new();
+    for (kind, entry) in changes {
+        let record = Record {
+            time,
+            kind: kind.to_string(),
+            entry: entry.to_string(),
+        };
+        let wrapped = cocoon
+            .wrap(&serde_json::to_vec(&record)?)
+            .map_err(|err| anyhow!("Couldn't encrypt journal record: {:?}", err))?;
+        lines.push_str(&hex::encode(wrapped));
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Decrypt a line written by `seal`.
+fn open(key: &[u8], line: &str) -> Result<Record> {
+    let wrapped = hex::decode(line.trim())?;
+    let data = MiniCocoon::from_key(key, &[0; 32])
+        .unwrap(&wrapped)
+        .map_err(|err| anyhow!("Couldn't read the journal: {:?}", err))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Append records of changes, given as `(kind, entry path)`.
+pub fn append<'a>(
+    db_path: &Path,
+    changes: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Result<()> {
+    let path = journal_path(db_path)?;
+    fs::create_dir_all(path.parent().expect("Journal is in the journal dir"))?;
+    let lines = seal(&cache::key()?, Utc::now().naive_utc(), changes)?;
+    // Written at once, so a save's records aren't split by another's.
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// The start of a `--since` period: a date (`2024-05-01`)
+/// or a number of days back (`30d`).
+fn parse_since(since: &str) -> Result<NaiveDateTime> {
+    if let Some(days) = since.strip_suffix('d') {
+        let days = days
+            .parse()
+            .ok()
+            .and_then(TimeDelta::try_days)
+            .ok_or_else(|| anyhow!("Invalid number of days: {}", since))?;
+        return Ok(Utc::now().naive_utc() - days);
+    }
+    let date = NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Expected a date (YYYY-MM-DD) or days (e.g. 30d): {}", since))?;
+    let start = date.and_hms_opt(0, 0, 0).expect("Midnight exists");
+    Ok(start
+        .and_local_timezone(Local)
+        .earliest()
+        .map_or(start, |start| start.naive_utc()))
+}
+
+/// Print the recorded changes, newest first, optionally only those since
+/// a date or a number of days ago (see `parse_since`).
+pub fn print(db_path: &Path, since: Option<&str>) -> Result<()> {
+    let since = since.map(parse_since).transpose()?;
+    let path = journal_path(db_path)?;
+    if !path.exists() {
+        info!("{}", tr!("no-journal"));
+        return Ok(());
+    }
+    let key = cache::key()?;
+    let mut records = vec![];
+    for line in BufReader::new(File::open(&path)?).lines() {
+        let record = open(&key, &line?)?;
+        if since.is_none_or(|since| record.time >= since) {
+            records.push(record);
+        }
+    }
+    if records.is_empty() {
+        info!("{}", tr!("no-changes-in-period"));
+    }
+    for record in records.iter().rev() {
+        let time = record.time.and_utc().with_timezone(&Local);
+        println!(
+            "{} {:8} {}",
+            time.format("%Y-%m-%d %H:%M"),
+            record.kind,
+            record.entry
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_records() {
+        let key = [7; 32];
+        let time = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let lines = seal(&key, time, [("added", "Work/GitHub"), ("removed", "Old")]).unwrap();
+        let records: Vec<Record> = lines
+            .lines()
+            .map(|line| open(&key, line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            (records[0].kind.as_str(), records[0].entry.as_str()),
+            ("added", "Work/GitHub")
+        );
+        assert_eq!(
+            (records[1].kind.as_str(), records[1].entry.as_str()),
+            ("removed", "Old")
+        );
+        assert_eq!(records[1].time, time);
+        // Nothing about the entries is left in the clear.
+        let first = lines.lines().next().unwrap();
+        let sealed = hex::decode(first).unwrap();
+        assert!(!sealed.windows(6).any(|window| window == b"GitHub"));
+        assert!(open(&[8; 32], first).is_err());
+    }
+
+    #[test]
+    fn parses_since() {
+        let days = Utc::now().naive_utc() - parse_since("30d").unwrap();
+        assert!((days.num_days() - 30).abs() <= 1);
+        let date = parse_since("2024-05-01").unwrap();
+        assert!(
+            (date
+                - NaiveDate::from_ymd_opt(2024, 5, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap())
+            .num_hours()
+            .abs()
+                <= 14
+        );
+        for invalid in ["d", "-d", "thirty", "2024-13-01", "01/05/2024"] {
+            assert!(parse_since(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Tuning the key derivation function to the machine,
+//! so unlocking takes about as long as the user is willing to wait.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use inquire::Confirm;
+use keepass::config::KdfConfig;
+
+use crate::{init, lock_db, prompt, save_db, unlock};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Fewest Argon2 iterations to suggest, however slow the machine.
+const MIN_ITERATIONS: u64 = 2;
+
+/// A human-readable description of the KDF settings.
+pub(crate) fn describe(kdf: &KdfConfig) -> String {
+    match kdf {
+        KdfConfig::Aes { rounds } => format!("AES-KDF, {} rounds", rounds),
+        KdfConfig::Argon2 {
+            iterations,
+            memory,
+            parallelism,
+            ..
+        } => format!(
+            "Argon2d, {} MiB, {} iterations, {} lanes",
+            memory / MIB,
+            iterations,
+            parallelism
+        ),
+        KdfConfig::Argon2id {
+            iterations,
+            memory,
+            parallelism,
+            ..
+        } => format!(
+            "Argon2id, {} MiB, {} iterations, {} lanes",
+            memory / MIB,
+            iterations,
+            parallelism
+        ),
+    }
+}
+
+/// Time a single Argon2 iteration with the given memory and lanes.
+/// Two iterations are run and averaged, to amortize setup costs.
+fn time_per_iteration(variant: argon2::Variant, memory: u64, parallelism: u32) -> Result<Duration> {
+    let config = argon2::Config {
+        variant,
+        mem_cost: (memory / 1024) as u32,
+        time_cost: 2,
+        lanes: parallelism,
+        ..argon2::Config::default()
+    };
+    let start = Instant::now();
+    argon2::hash_raw(b"kpass benchmark", &[0; 32], &config)
+        .map_err(|err| anyhow!("Benchmark failed: {}", err))?;
+    Ok(start.elapsed() / 2)
+}
+
+/// The Argon2 variant (whether Argon2d), memory and lanes to tune from.
+/// Keeps those of Argon2, but moves off AES-KDF,
+/// which is much cheaper to brute-force on GPUs.
+fn base(current: &KdfConfig) -> (bool, u64, u32) {
+    match *current {
+        KdfConfig::Argon2 {
+            memory,
+            parallelism,
+            ..
+        } => (true, memory, parallelism),
+        KdfConfig::Argon2id {
+            memory,
+            parallelism,
+            ..
+        } => (false, memory, parallelism),
+        KdfConfig::Aes { .. } => (false, init::ARGON2_MEMORY, init::ARGON2_PARALLELISM),
+    }
+}
+
+/// Iterations that fit in `target` milliseconds.
+fn iterations(target: u64, per_iteration: Duration) -> u64 {
+    (target as u128 / per_iteration.as_millis().max(1)) as u64
+}
+
+fn settings(argon2d: bool, iterations: u64, memory: u64, parallelism: u32) -> KdfConfig {
+    let version = argon2::Version::Version13;
+    if argon2d {
+        KdfConfig::Argon2 {
+            iterations,
+            memory,
+            parallelism,
+            version,
+        }
+    } else {
+        KdfConfig::Argon2id {
+            iterations,
+            memory,
+            parallelism,
+            version,
+        }
+    }
+}
+
+/// Show the database's KDF settings, benchmark Argon2 and
+/// rewrite the database with settings matching a target unlock time.
+pub fn tune(db_path: &Path) -> Result<()> {
+    let _lock = lock_db(db_path)?;
+    let (mut db, key) = unlock(db_path)?;
+    let current = db.config.kdf_config.clone();
+    info!("{}", tr!("kdf-current", settings = describe(&current)));
+
+    let (argon2d, memory, parallelism) = base(&current);
+    let target: u64 = prompt::number_in_range(&tr!("target-unlock-time"), 1000, 100..=10_000)?;
+    let memory = prompt::number_in_range(&tr!("kdf-memory"), memory / MIB, 8..=4096)? * MIB;
+
+    info!("{}", tr!("benchmarking"));
+    let variant = if argon2d {
+        argon2::Variant::Argon2d
+    } else {
+        argon2::Variant::Argon2id
+    };
+    let per_iteration = time_per_iteration(variant, memory, parallelism)?;
+    let iterations = iterations(target, per_iteration);
+    if iterations < MIN_ITERATIONS {
+        warn!("{}", tr!("kdf-over-target", iterations = MIN_ITERATIONS));
+    }
+    let iterations = iterations.max(MIN_ITERATIONS);
+    let suggested = settings(argon2d, iterations, memory, parallelism);
+    info!(
+        "{}",
+        tr!(
+            "kdf-suggested",
+            settings = describe(&suggested),
+            ms = per_iteration.as_millis() * iterations as u128
+        )
+    );
+
+    if suggested == current {
+        info!("{}", tr!("kdf-unchanged"));
+        return Ok(());
+    }
+    let confirm = Confirm::new(&tr!("kdf-confirm"))
+        .with_default(true)
+        .prompt()?;
+    if !confirm {
+        return Ok(());
+    }
+    db.config.kdf_config = suggested;
+    info!("{}", tr!("saving"));
+    save_db(&mut db, &key, db_path)?;
+    info!("{}", tr!("saved"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_settings() {
+        assert_eq!(
+            describe(&KdfConfig::Aes { rounds: 60000 }),
+            "AES-KDF, 60000 rounds"
+        );
+        assert_eq!(
+            describe(&settings(true, 10, 64 * MIB, 2)),
+            "Argon2d, 64 MiB, 10 iterations, 2 lanes"
+        );
+        assert_eq!(
+            describe(&settings(false, 3, 1024 * MIB, 4)),
+            "Argon2id, 1024 MiB, 3 iterations, 4 lanes"
+        );
+    }
+
+    #[test]
+    fn moves_off_aes() {
+        assert_eq!(
+            base(&KdfConfig::Aes { rounds: 60000 }),
+            (false, init::ARGON2_MEMORY, init::ARGON2_PARALLELISM)
+        );
+        assert_eq!(base(&settings(true, 10, 64 * MIB, 2)), (true, 64 * MIB, 2));
+        assert_eq!(base(&settings(false, 3, 32 * MIB, 4)), (false, 32 * MIB, 4));
+    }
+
+    #[test]
+    fn fits_iterations_in_the_target() {
+        assert_eq!(iterations(1000, Duration::from_millis(100)), 10);
+        assert_eq!(iterations(1000, Duration::from_millis(300)), 3);
+        assert_eq!(iterations(100, Duration::from_millis(300)), 0);
+        // Iterations too quick to measure count as a millisecond.
+        assert_eq!(iterations(1000, Duration::from_micros(10)), 1000);
+    }
+
+    #[test]
+    fn benchmarks() {
+        let time = time_per_iteration(argon2::Variant::Argon2id, MIB, 1).unwrap();
+        assert!(time > Duration::ZERO);
+        assert!(time_per_iteration(argon2::Variant::Argon2id, MIB, 0).is_err());
+    }
+}
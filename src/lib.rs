@@ -0,0 +1,1612 @@
+//! kpass: a KeePass password manager for the terminal.
+//!
+//! The library holds what the `kpass` binary is made of, for embedding
+//! the same database handling in other front ends (e.g. a GUI or a
+//! status-bar widget):
+//!
+//! - opening databases: [`unlock`] (prompting for the password, or using the
+//!   cached quick password, see [`pw_cache`]) and [`vault::Vault`], a database
+//!   unlocked for a session, with saving, undo and reloading;
+//! - saving: [`save_db`], which merges changes made on disk in the meantime,
+//!   and keeps backups and the change log;
+//! - finding entries: [`entries`], [`entries_with_paths`], [`address`] and
+//!   [`index::Index`];
+//! - editing: [`edit_entry`] and [`new_entry`], which prompt in the terminal,
+//!   or [`apply`] to change entries from a spec without prompting;
+//! - the entry metadata cache, readable without unlocking: [`cache`].
+//!
+//! Errors are [`anyhow::Error`]s. Messages for the user are printed to
+//! stdout, prefixed with `>` (status, with [`info!`], left out with
+//! `--quiet`; results the user needs, e.g. a generated password, are
+//! printed with `println!` and kept) or `!` (warnings).
+
+/// Print information, as `> message`, unless `QUIET`.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+            println!("> {}", format_args!($($arg)*))
+        }
+    };
+}
+
+/// Print a warning, as `! message` (in the theme's color).
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        println!("{}", $crate::theme::warning(&format!("! {}", format_args!($($arg)*))))
+    };
+}
+
+/// Format a message in the user's language (see `i18n`) by its ID,
+/// with values for its variables, e.g. `tr!("no-password", entry = title)`.
+#[macro_export]
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = $crate::i18n::FluentArgs::new();
+        $(args.set(stringify!($name), $crate::i18n::arg(&$value));)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+
+pub mod activity;
+pub mod address;
+pub mod agent;
+pub mod api;
+pub mod apply;
+pub mod audit;
+pub mod autotype;
+pub mod backup;
+pub mod browser;
+pub mod bulk;
+pub mod cache;
+pub mod changelog;
+pub mod check;
+pub mod cleanup;
+pub mod clipboard;
+pub mod colors;
+pub mod compact;
+pub mod config;
+pub mod dedupe;
+pub mod domain;
+pub mod error;
+pub mod exec;
+pub mod export;
+pub mod favorites;
+pub mod files;
+pub mod filter;
+pub mod generate;
+pub mod git;
+pub mod groups;
+pub mod hibp;
+pub mod host;
+pub mod i18n;
+pub mod icons;
+pub mod idle;
+pub mod import;
+pub mod index;
+pub mod init;
+pub mod journal;
+pub mod kdf;
+pub mod lock;
+pub mod lockout;
+pub mod logging;
+pub mod menu;
+pub mod merge;
+pub mod notes;
+pub mod notify;
+pub mod otp;
+pub mod pairing;
+pub mod passkey;
+pub mod pinentry;
+pub mod prompt;
+pub mod protect;
+pub mod pw_cache;
+pub mod qr;
+pub mod quick;
+pub mod recovery;
+pub mod reference;
+pub mod remote;
+pub mod reveal;
+pub mod rotate;
+pub mod sandbox;
+pub mod secret;
+pub mod secret_service;
+pub mod shamir;
+pub mod share;
+pub mod show;
+pub mod ssh;
+pub mod stats;
+pub mod strength;
+pub mod templates;
+pub mod theme;
+pub mod title;
+pub mod tty;
+pub mod tui;
+pub mod upgrade;
+pub mod usage;
+pub mod vault;
+pub mod watch;
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{Display, Formatter},
+    fs::{DirBuilder, File, OpenOptions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use cocoon::Cocoon;
+use error::Error;
+use inquire::{
+    required, validator::Validation, Confirm, CustomUserError, Editor, Password,
+    PasswordDisplayMode, Text,
+};
+use keepass::{
+    db::{AutoType, CustomDataItem, Entry as KEntry, Group, Node, NodeRef, Times, Value},
+    error::DatabaseOpenError,
+    Database, DatabaseKey,
+};
+use secret::Secret;
+use sha2::{Digest, Sha256};
+use strength::Strength;
+use tracing::debug;
+use uuid::Uuid;
+use wl_clipboard_rs::copy::{MimeSource, MimeType, Options, ServeRequests, Source};
+use zeroize::Zeroizing;
+
+pub const PW_CACHE: &str = "/tmp/.kpw";
+
+/// A KeePass entry.
+pub struct Entry<'a>(pub &'a KEntry);
+impl Display for Entry<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let title = self.0.get_title().unwrap_or("(no title)");
+        write!(f, "{}", title)
+    }
+}
+impl Entry<'_> {
+    pub fn password(&self) -> Option<&str> {
+        self.0.get_password()
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.0.get_username()
+    }
+
+    pub fn url(&self) -> Option<&str> {
+        self.0.get_url()
+    }
+
+    pub fn notes(&self) -> Option<&str> {
+        self.0.fields.get("Notes").and_then(|val| match val {
+            Value::Unprotected(notes) => Some(notes.as_str()),
+            Value::Protected(data) => std::str::from_utf8(data.unsecure()).ok(),
+            _ => None,
+        })
+    }
+
+    /// How long what's copied from the entry stays in the clipboard, in
+    /// seconds, if it overrides the config (see `CLIP_TIMEOUT_FIELD`).
+    pub fn clip_timeout(&self) -> Option<u64> {
+        let value = self.0.get(CLIP_TIMEOUT_FIELD)?;
+        let seconds = value.trim().parse().ok();
+        if seconds.is_none() {
+            warn!(
+                "{}",
+                tr!(
+                    "invalid-field",
+                    field = CLIP_TIMEOUT_FIELD,
+                    entry = self,
+                    value = value
+                )
+            );
+        }
+        seconds
+    }
+
+    /// The (local) date the entry expires on, if it expires.
+    pub fn expiry(&self) -> Option<chrono::NaiveDate> {
+        let expiry = self.0.times.get_expiry().filter(|_| self.0.times.expires)?;
+        Some(expiry.and_utc().with_timezone(&chrono::Local).date_naive())
+    }
+
+    /// Whether the entry has expired.
+    pub fn is_expired(&self) -> bool {
+        self.0.times.expires
+            && self
+                .0
+                .times
+                .get_expiry()
+                .is_some_and(|t| *t < chrono::Utc::now().naive_utc())
+    }
+
+    /// A warning if the entry has expired or expires
+    /// within `expiry_warning_days` (see config).
+    pub fn expiry_warning(&self) -> Option<String> {
+        let expiry = self.expiry()?;
+        let days_left = (expiry - chrono::Local::now().date_naive()).num_days();
+        if self.is_expired() {
+            Some(format!("expired {}", expiry.format("%Y-%m-%d")))
+        } else if days_left <= config::get().expiry_warning_days {
+            Some(format!("expires {}", expiry.format("%Y-%m-%d")))
+        } else {
+            None
+        }
+    }
+
+    /// Problems to flag where the entry is picked: its expiry, a password
+    /// the last HIBP audit found breached, or one not changed in
+    /// `old_password_days` (see config).
+    pub fn warnings(&self, breached: &hibp::Breached) -> Vec<String> {
+        let mut warnings: Vec<String> = self.expiry_warning().into_iter().collect();
+        if breached.contains(self.0) {
+            warnings.push("breached".to_string());
+        }
+        let max_days = config::get().old_password_days;
+        let has_password = self.password().is_some_and(|pw| !pw.is_empty());
+        if let Some(changed) = audit::password_changed(self.0).filter(|_| has_password) {
+            let days = (chrono::Utc::now().naive_utc() - changed).num_days();
+            if max_days > 0 && days >= max_days {
+                warnings.push(format!("password {} days old", days));
+            }
+        }
+        warnings
+    }
+}
+
+/// For conveniently editing an entry.
+struct EditEntry<'a>(&'a mut KEntry);
+impl EditEntry<'_> {
+    fn set_title(&mut self) -> Result<()> {
+        let current = self.0.get_title().unwrap_or("");
+        let value = Text::new(&tr!("edit-title"))
+            .with_initial_value(current)
+            .with_validator(required!())
+            .prompt()?;
+        self.0
+            .fields
+            .insert("Title".to_string(), Value::Unprotected(value));
+        Ok(())
+    }
+
+    /// Set the username, suggesting the given ones (see `known_usernames`).
+    fn set_username(&mut self, suggestions: &[String]) -> Result<()> {
+        let current = self.0.get_username().unwrap_or("");
+        let value = Text::new(&tr!("edit-username"))
+            .with_initial_value(current)
+            .with_autocomplete(prompt::Suggestions(suggestions.to_vec()))
+            .with_validator(required!())
+            .prompt()?;
+        self.0
+            .fields
+            .insert("UserName".to_string(), Value::Unprotected(value));
+        Ok(())
+    }
+
+    fn set_url(&mut self) -> Result<()> {
+        let current = self.0.get_url().unwrap_or("");
+        let value = Text::new(&tr!("edit-url"))
+            .with_initial_value(current)
+            .with_help_message(&tr!("edit-url-help"))
+            .with_validator(validate_url)
+            .prompt()?;
+        let url = normalize_url(&value);
+        if url.is_empty() {
+            self.0.fields.remove("URL");
+        } else {
+            self.0
+                .fields
+                .insert("URL".to_string(), Value::Unprotected(url));
+        }
+        Ok(())
+    }
+
+    /// Add or remove URLs besides the main one, e.g. for services
+    /// spanning several domains. They're stored as KeePassXC does.
+    fn set_extra_urls(&mut self) -> Result<()> {
+        loop {
+            let urls = domain::extra_urls(self.0);
+            let mut options: Vec<String> = urls
+                .iter()
+                .map(|(_, url)| tr!("remove-url", url = url))
+                .collect();
+            options.push(tr!("add-url"));
+            options.push(tr!("done"));
+            let choice = prompt::select(&tr!("other-urls"), options).raw_prompt()?;
+            if let Some((name, _)) = urls.get(choice.index) {
+                let name = name.to_string();
+                self.0.fields.remove(&name);
+            } else if choice.index == urls.len() {
+                let value = Text::new(&tr!("edit-url"))
+                    .with_validator(validate_url)
+                    .prompt()?;
+                let url = normalize_url(&value);
+                if url.is_empty() {
+                    continue;
+                }
+                let name = std::iter::once("KP2A_URL".to_string())
+                    .chain((1..).map(|n| format!("KP2A_URL_{}", n)))
+                    .find(|name| !self.0.fields.contains_key(name))
+                    .expect("There are fewer fields than names");
+                self.0.fields.insert(name, Value::Unprotected(url));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Choose which custom fields are stored protected (encrypted in
+    /// memory, and masked by KeePass clients), as KeePassXC lets you.
+    fn set_protected_fields(&mut self) -> Result<()> {
+        let fields = custom_fields(self.0);
+        if fields.is_empty() {
+            info!("{}", tr!("no-custom-fields"));
+            return Ok(());
+        }
+        let defaults: Vec<usize> = (0..fields.len())
+            .filter(|i| matches!(fields[*i].1, Value::Protected(_)))
+            .collect();
+        let names: Vec<String> = fields.iter().map(|(name, _)| name.to_string()).collect();
+        let selected = prompt::multi_select(&tr!("protected-fields"), names.clone())
+            .with_default(&defaults)
+            .prompt()?;
+        for name in names {
+            let value = match self.0.fields.remove(&name) {
+                Some(Value::Protected(secret)) if !selected.contains(&name) => {
+                    match String::from_utf8(secret.unsecure().to_vec()) {
+                        Ok(text) => Value::Unprotected(text),
+                        Err(_) => {
+                            warn!("{}", tr!("not-text-kept-protected", field = name));
+                            Value::Protected(secret)
+                        }
+                    }
+                }
+                Some(Value::Unprotected(text)) if selected.contains(&name) => {
+                    Value::Protected(text.as_bytes().into())
+                }
+                Some(value) => value,
+                None => continue,
+            };
+            self.0.fields.insert(name, value);
+        }
+        Ok(())
+    }
+
+    fn set_notes(&mut self) -> Result<()> {
+        let entry = Entry(self.0);
+        let mut current = entry.notes().unwrap_or("").to_string();
+
+        let snippets = &config::get().snippets;
+        if !snippets.is_empty() {
+            let mut options = vec![tr!("no-snippet")];
+            options.extend(snippets.keys().cloned());
+            let choice = prompt::select(&tr!("insert-snippet"), options).raw_prompt()?;
+            // The first option is "(none)".
+            let snippet = choice.index.checked_sub(1);
+            if let Some(snippet) = snippet.and_then(|i| snippets.values().nth(i)) {
+                if !current.is_empty() && !current.ends_with('\n') {
+                    current.push('\n');
+                }
+                current.push_str(snippet);
+            }
+        }
+
+        let notes = Editor::new(&tr!("edit-notes-field"))
+            .with_predefined_text(&current)
+            .prompt()?;
+        self.0.fields.insert(
+            "Notes".to_string(),
+            Value::Protected(notes.as_bytes().into()),
+        );
+        Ok(())
+    }
+
+    fn set_autotype_sequence(&mut self) -> Result<()> {
+        let current = autotype::sequence(self.0).to_string();
+        let value = Text::new(&tr!("autotype-sequence"))
+            .with_initial_value(&current)
+            .with_help_message(&tr!("autotype-sequence-help"))
+            .with_validator(|seq: &str| {
+                Ok(match autotype::validate(seq) {
+                    Ok(_) => Validation::Valid,
+                    Err(err) => Validation::Invalid(err.to_string().into()),
+                })
+            })
+            .prompt()?;
+        let autotype = self.0.autotype.get_or_insert_with(|| AutoType {
+            enabled: true,
+            ..Default::default()
+        });
+        autotype.sequence = Some(value).filter(|seq| !seq.is_empty());
+        Ok(())
+    }
+
+    fn set_autotype_delays(&mut self) -> Result<()> {
+        let items = &mut self.0.custom_data.items;
+        for (key, message) in [
+            (autotype::START_DELAY_KEY, tr!("autotype-start-delay")),
+            (autotype::KEY_DELAY_KEY, tr!("autotype-key-delay")),
+        ] {
+            let current = items
+                .get(key)
+                .and_then(|item| match &item.value {
+                    Some(Value::Unprotected(ms)) => ms.parse().ok(),
+                    _ => None,
+                })
+                .unwrap_or(0);
+            let ms: u64 = prompt::number_in_range(&message, current, 0..=10_000)?;
+            if ms == 0 {
+                items.remove(key);
+            } else {
+                items.insert(
+                    key.to_string(),
+                    CustomDataItem {
+                        value: Some(Value::Unprotected(ms.to_string())),
+                        last_modification_time: Some(Times::now()),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn set_host(&mut self) -> Result<()> {
+        let current = host::Host::of(self.0);
+        let value = Text::new(&tr!("edit-host"))
+            .with_initial_value(current.as_ref().map_or("", |h| h.host.as_str()))
+            .with_help_message(&tr!("edit-host-help"))
+            .prompt()?;
+        if value.is_empty() {
+            for field in [host::HOST_FIELD, host::PORT_FIELD, host::LOCATION_FIELD] {
+                self.0.fields.remove(field);
+            }
+            return Ok(());
+        }
+        let port = prompt::number_in_range(
+            &tr!("edit-port"),
+            current.as_ref().map_or(host::DEFAULT_PORT, |h| h.port),
+            1..=u16::MAX,
+        )?;
+        let location = Text::new(&tr!("edit-location"))
+            .with_initial_value(
+                current
+                    .as_ref()
+                    .and_then(|h| h.location.as_deref())
+                    .unwrap_or(""),
+            )
+            .with_help_message(&tr!("edit-location-help"))
+            .prompt()?;
+        for (field, value) in [
+            (host::HOST_FIELD, value),
+            (host::PORT_FIELD, port.to_string()),
+            (host::LOCATION_FIELD, location),
+        ] {
+            if value.is_empty() {
+                self.0.fields.remove(field);
+            } else {
+                self.0
+                    .fields
+                    .insert(field.to_string(), Value::Unprotected(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_require_master_password(&mut self) -> Result<()> {
+        let required = Confirm::new(&tr!("require-master-password"))
+            .with_default(protect::is_required(self.0))
+            .with_help_message(&tr!("require-master-password-help"))
+            .prompt()?;
+        protect::set_required(self.0, required);
+        Ok(())
+    }
+
+    fn set_colors(&mut self) -> Result<()> {
+        for (name, color) in [
+            (tr!("foreground-color"), &mut self.0.foreground_color),
+            (tr!("background-color"), &mut self.0.background_color),
+        ] {
+            let value = Text::new(&name)
+                .with_initial_value(&color.as_ref().map(colors::hex).unwrap_or_default())
+                .with_help_message(&tr!("color-help"))
+                .with_validator(|value: &str| {
+                    Ok(match colors::parse(value) {
+                        Err(err) if !value.trim().is_empty() => {
+                            Validation::Invalid(err.to_string().into())
+                        }
+                        _ => Validation::Valid,
+                    })
+                })
+                .prompt()?;
+            *color = if value.trim().is_empty() {
+                None
+            } else {
+                Some(colors::parse(&value)?)
+            };
+        }
+        Ok(())
+    }
+
+    fn set_favorite(&mut self) -> Result<()> {
+        let favorite = Confirm::new(&tr!("favorite"))
+            .with_default(favorites::is_favorite(self.0))
+            .with_help_message(&tr!("favorite-help"))
+            .prompt()?;
+        favorites::set(self.0, favorite);
+        Ok(())
+    }
+
+    fn set_icon(&mut self) -> Result<()> {
+        icons::pick(self.0)
+    }
+
+    fn set_expiry(&mut self) -> Result<()> {
+        let current = self
+            .0
+            .times
+            .get_expiry()
+            .filter(|_| self.0.times.expires)
+            .map(|t| t.and_utc().with_timezone(&chrono::Local).date_naive());
+        match prompt::expiry(current)? {
+            Some(date) => {
+                self.0.times.expires = true;
+                self.0.times.set_expiry(prompt::end_of_day_utc(date));
+            }
+            None => self.0.times.expires = false,
+        }
+        Ok(())
+    }
+
+    fn set_password(&mut self) -> Result<()> {
+        let how = prompt::select(
+            &tr!("label-password"),
+            vec![
+                tr!("generate"),
+                tr!("generate-pronounceable"),
+                tr!("enter-manually"),
+            ],
+        )
+        .raw_prompt()?;
+        match how.index {
+            0 => self.set_generated_password(),
+            1 => self.set_pronounceable_password(),
+            _ => self.set_manual_password(),
+        }
+    }
+
+    /// Generate a password of random syllables,
+    /// for typing on devices without a proper keyboard.
+    fn set_pronounceable_password(&mut self) -> Result<()> {
+        let mut customize = Confirm::new(&tr!("customize-generation"))
+            .with_default(false)
+            .prompt()?;
+        let password = loop {
+            let settings = if customize {
+                generate::prompt_pronounceable()?
+            } else {
+                generate::Pronounceable::default()
+            };
+            match generate::generate_pronounceable(&settings)? {
+                Some(password) => break password,
+                None => customize = true,
+            }
+        };
+        self.store_generated_password(password);
+        Ok(())
+    }
+
+    /// Generate a password, previewing it until the user accepts one.
+    fn set_generated_password(&mut self) -> Result<()> {
+        let password = generate::preview(generate::default_generator())?;
+        self.insert_password(password);
+        Ok(())
+    }
+
+    fn store_generated_password(&mut self, password: Secret) {
+        info!("{}", tr!("password-generated"));
+        info!(
+            "{}",
+            tr!("strength", strength = Strength::of(password.expose()))
+        );
+        self.insert_password(password);
+    }
+
+    fn insert_password(&mut self, password: Secret) {
+        self.0.fields.insert(
+            "Password".to_string(),
+            Value::Protected(password.expose().as_bytes().into()),
+        );
+    }
+
+    fn set_manual_password(&mut self) -> Result<()> {
+        let password = loop {
+            let password = Secret::new(
+                Password::new(&tr!("label-password"))
+                    .with_display_toggle_enabled()
+                    .with_display_mode(PasswordDisplayMode::Masked)
+                    .with_custom_confirmation_message(&tr!("confirm-password"))
+                    .prompt()?,
+            );
+            let strength = Strength::of(password.expose());
+            info!("{}", tr!("strength", strength = strength));
+            if !strength.is_very_weak()
+                || Confirm::new(&tr!("use-anyway"))
+                    .with_default(false)
+                    .prompt()?
+            {
+                break password;
+            }
+        };
+        self.0.fields.insert(
+            "Password".to_string(),
+            Value::Protected(password.expose().as_bytes().into()),
+        );
+        Ok(())
+    }
+}
+
+/// Get the last `n` chars of a string, or all of it if it's shorter.
+fn last_n_chars(s: &str, n: usize) -> &str {
+    let idx = match n.checked_sub(1) {
+        Some(back) => s.char_indices().nth_back(back).map_or(0, |(idx, _)| idx),
+        None => s.len(),
+    };
+    &s[idx..]
+}
+
+/// Try loading the full password from the quick password.
+/// There is only one chance to input the correct quick password,
+/// otherwise the cached password is destroyed.
+fn try_load_pass() -> Result<Option<Secret>> {
+    let pw_path = Path::new(PW_CACHE);
+    if !pw_path.exists() {
+        return Ok(None);
+    }
+    let data = std::fs::read(pw_path)?;
+
+    let method = pw_cache::Method::configured()?;
+    if !matches!(method, pw_cache::Method::Quick) {
+        return match pw_cache::decrypt(&method, &data) {
+            Ok(pass) => match String::from_utf8(pass.to_vec()) {
+                Ok(pass) => Ok(Some(Secret::new(pass))),
+                Err(_) => discard_corrupt_cache(),
+            },
+            Err(err) => {
+                // E.g. the PIN prompt was cancelled; the cache is
+                // replaced once the full password is entered.
+                warn!("{}", tr!("cache-decrypt-failed", error = err));
+                Ok(None)
+            }
+        };
+    }
+
+    // A truncated or mangled cache fails to parse regardless of the password,
+    // so it can be told apart before asking for the quick password.
+    let malformed = matches!(
+        Cocoon::parse_only(b"").unwrap(&data),
+        Err(cocoon::Error::UnrecognizedFormat | cocoon::Error::TooShort | cocoon::Error::TooLarge)
+    );
+    if malformed {
+        return discard_corrupt_cache();
+    }
+
+    let qpw = pinentry::password(&tr!("quick-pass"), None)?;
+    match Cocoon::parse_only(qpw.expose().as_bytes()).unwrap(&data) {
+        Ok(pass) => {
+            let pass = Zeroizing::new(pass);
+            match std::str::from_utf8(&pass) {
+                Ok(pass) => Ok(Some(Secret::new(pass.to_string()))),
+                Err(_) => discard_corrupt_cache(),
+            }
+        }
+        Err(cocoon::Error::Cryptography) => {
+            warn!("{}", tr!("quick-pass-incorrect"));
+            std::fs::remove_file(pw_path)?;
+            lockout::record_failure()?;
+            Ok(None)
+        }
+        Err(_) => discard_corrupt_cache(),
+    }
+}
+
+/// Remove an unreadable password cache, falling back to the full password.
+fn discard_corrupt_cache() -> Result<Option<Secret>> {
+    warn!("{}", tr!("cache-corrupt"));
+    std::fs::remove_file(PW_CACHE)?;
+    Ok(None)
+}
+
+/// Cache the full password, locked by the quick password
+/// (or encrypted as set by `password_cache`, see `pw_cache`).
+/// Written to a temporary file first and renamed into place,
+/// so an interrupted write can't leave a partial cache behind.
+pub fn cache_pass(password: &Secret) -> Result<()> {
+    let password = password.expose();
+    let method = pw_cache::Method::configured()?;
+    let external = match method {
+        pw_cache::Method::Quick => None,
+        _ => Some(pw_cache::encrypt(&method, password)?),
+    };
+
+    let temp_path = format!("{}.tmp", PW_CACHE);
+    let _ = std::fs::remove_file(&temp_path);
+    cleanup::track(Path::new(&temp_path));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    let written = match external {
+        Some(data) => file.write_all(&data).map_err(Into::into),
+        None => Cocoon::new(last_n_chars(password, 3).as_bytes())
+            .dump(password.as_bytes().to_vec(), &mut file)
+            .map_err(|err| anyhow!("Couldn't encrypt the password cache: {:?}", err)),
+    };
+    let result = written
+        .and_then(|_| Ok(file.sync_all()?))
+        .and_then(|_| Ok(std::fs::rename(&temp_path, PW_CACHE)?));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    cleanup::untrack(Path::new(&temp_path));
+    result
+}
+
+/// Open the database, using the cached password if possible,
+/// otherwise prompting for the full password.
+pub fn unlock(db_path: &Path) -> Result<(Database, DatabaseKey)> {
+    unlock_with(db_path, true)
+}
+
+/// Open the database, using (and updating) the cached password
+/// only if `use_cache`, e.g. not for additional databases in a session.
+pub fn unlock_with(db_path: &Path, use_cache: bool) -> Result<(Database, DatabaseKey)> {
+    let (db, key, _) = unlock_password(db_path, use_cache)?;
+    Ok((db, key))
+}
+
+/// The password given with `--password-stdin`.
+pub static STDIN_PASSWORD: OnceLock<Secret> = OnceLock::new();
+
+/// A password given non-interactively, for scripts: through stdin
+/// (`--password-stdin`) or printed by the `KPASS_PASSWORD_CMD` command.
+pub fn supplied_password() -> Result<Option<Secret>> {
+    if let Some(pass) = STDIN_PASSWORD.get() {
+        return Ok(Some(Secret::new(pass.expose().to_string())));
+    }
+    let Some(cmd) = env::var_os("KPASS_PASSWORD_CMD") else {
+        return Ok(None);
+    };
+    let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+    let stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+        return Err(anyhow!("KPASS_PASSWORD_CMD exited with {}", output.status));
+    }
+    let pass = std::str::from_utf8(&stdout)
+        .map_err(|_| anyhow!("KPASS_PASSWORD_CMD printed invalid UTF-8"))?;
+    Ok(Some(Secret::new(
+        pass.trim_end_matches(['\n', '\r']).to_string(),
+    )))
+}
+
+/// Open the database (see `unlock_with`), also returning the password.
+/// A password given non-interactively (see `supplied_password`) is used
+/// instead of prompting, and otherwise one granted by a paired device
+/// (see `pairing`) is tried first.
+pub fn unlock_password(db_path: &Path, use_cache: bool) -> Result<(Database, DatabaseKey, Secret)> {
+    lockout::check()?;
+    let supplied = supplied_password()?;
+    let granted = if supplied.is_some() || sandbox::is_enabled() {
+        None
+    } else {
+        pairing::request(db_path).and_then(|pass| {
+            let key = database_key(db_path, &pass).ok()?;
+            let mut file = File::open(db_path).ok()?;
+            match Database::open(&mut file, key.clone()) {
+                Ok(db) => Some((db, key, pass)),
+                Err(_) => {
+                    warn!(
+                        "{}",
+                        tr!("granted-password-wrong", database = db_path.display())
+                    );
+                    None
+                }
+            }
+        })
+    };
+    let cached = if use_cache && supplied.is_none() && granted.is_none() {
+        match try_load_pass()? {
+            Some(pass) => open_cached(db_path, pass)?,
+            None => None,
+        }
+    } else {
+        None
+    };
+    let message = if use_cache {
+        tr!("label-password")
+    } else {
+        tr!("password-for", database = db_path.display())
+    };
+    let unlocked = if let Some(pass) = supplied {
+        // Scripts get an error rather than a prompt.
+        debug!(path = %db_path.display(), "Opening with the supplied password");
+        let key = database_key(db_path, &pass)?;
+        let mut file = File::open(db_path)?;
+        match Database::open(&mut file, key.clone()) {
+            Ok(db) => {
+                record_opened(db_path)?;
+                (db, key, pass)
+            }
+            Err(err) => {
+                lockout::record_failure()?;
+                return Err(Error::Open(err).into());
+            }
+        }
+    } else if let Some(granted) = granted {
+        debug!(path = %db_path.display(), "Opened with a granted password");
+        record_opened(db_path)?;
+        granted
+    } else if let Some(cached) = cached {
+        debug!(path = %db_path.display(), "Opened with the cached password");
+        record_opened(db_path)?;
+        cached
+    } else {
+        let mut error = None;
+        loop {
+            lockout::check()?;
+            debug!(path = %db_path.display(), "Prompting for the password");
+            let pass = pinentry::password(&message, error.as_deref())?;
+
+            let key = database_key(db_path, &pass)?;
+            let mut file = File::open(db_path)?;
+            match Database::open(&mut file, key.clone()) {
+                Ok(db) => {
+                    if use_cache {
+                        cache_pass(&pass)?;
+                    }
+                    record_opened(db_path)?;
+                    break (db, key, pass);
+                }
+                Err(err) => {
+                    warn!("{}", tr!("open-failed"));
+                    info!("  {:?}", err);
+                    error = Some(tr!("wrong-password"));
+                    lockout::record_failure()?;
+                }
+            }
+        }
+    };
+    lockout::reset()?;
+    activity::summarize(db_path)?;
+    changelog::snapshot(db_path, &unlocked.0);
+    Ok(unlocked)
+}
+
+/// Open the database with the cached password. If the password doesn't
+/// open it anymore (e.g. the master password was changed in another
+/// client), the cache is removed, to ask for the full password instead.
+fn open_cached(db_path: &Path, pass: Secret) -> Result<Option<(Database, DatabaseKey, Secret)>> {
+    let key = database_key(db_path, &pass)?;
+    let mut file = File::open(db_path)?;
+    match Database::open(&mut file, key.clone()) {
+        Ok(db) => Ok(Some((db, key, pass))),
+        Err(DatabaseOpenError::Key(_)) => {
+            warn!("{}", tr!("cache-stale", error = Error::StaleCache));
+            std::fs::remove_file(PW_CACHE)?;
+            Ok(None)
+        }
+        Err(err) => Err(Error::Open(err).into()),
+    }
+}
+
+/// The database's key: the password, plus the key file configured for it.
+pub fn database_key(db_path: &Path, pass: &Secret) -> Result<DatabaseKey> {
+    init::compose_key(pass, config::key_file(db_path))
+}
+
+/// Lock the database before changing it.
+pub fn lock_db(db_path: &Path) -> Result<lock::Lock> {
+    lock::acquire(db_path)?.map_err(|holder| Error::Locked(holder.to_string()).into())
+}
+
+pub fn get_entry_mut(db: &mut Database, uuid: u128) -> Option<&mut KEntry> {
+    _find_entry_mut(&mut db.root, uuid)
+}
+fn _find_entry_mut(group: &mut Group, uuid: u128) -> Option<&mut KEntry> {
+    for ch in &mut group.children {
+        match ch {
+            Node::Group(group) => {
+                if let Some(entry) = _find_entry_mut(group, uuid) {
+                    return Some(entry);
+                }
+            }
+            Node::Entry(entry) => {
+                if entry.get_uuid().as_u128() == uuid {
+                    return Some(entry);
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail.
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir`, accessible only by the user, or check that it
+/// already is: in `/tmp`, someone else could have created it first.
+pub fn private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            let meta = std::fs::symlink_metadata(dir)?;
+            if !meta.is_dir() || meta.uid() != uid() || meta.mode() & 0o077 != 0 {
+                return Err(anyhow!(
+                    "{} isn't a directory only you can access; remove it and try again",
+                    dir.display()
+                ));
+            }
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Hashes of database files as they were when unlocked, to tell
+/// if something else (e.g. a sync tool) changed them before saving.
+static OPENED: Mutex<BTreeMap<PathBuf, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+pub fn file_hash(path: &Path) -> Result<Vec<u8>> {
+    Ok(Sha256::digest(std::fs::read(path)?).to_vec())
+}
+
+pub fn record_opened(path: &Path) -> Result<()> {
+    let hash = file_hash(path)?;
+    OPENED.lock().unwrap().insert(path.to_path_buf(), hash);
+    Ok(())
+}
+
+/// Whether the database changed on disk since it was unlocked (or last saved).
+pub fn changed_on_disk(path: &Path) -> Result<bool> {
+    let opened = OPENED.lock().unwrap().get(path).cloned();
+    match opened {
+        Some(opened) => Ok(file_hash(path)? != opened),
+        None => Ok(false),
+    }
+}
+
+/// If the database changed on disk since it was unlocked,
+/// ask whether to merge our changes into it or overwrite it.
+pub fn resolve_external_changes(db: &mut Database, key: &DatabaseKey, path: &Path) -> Result<()> {
+    if !changed_on_disk(path)? {
+        return Ok(());
+    }
+
+    warn!("{}", tr!("changed-on-disk"));
+    debug!(path = %path.display(), "Changed on disk since it was opened");
+    let options = vec![tr!("merge-into-it"), tr!("overwrite-it"), tr!("cancel")];
+    match prompt::select(&tr!("save"), options).raw_prompt()?.index {
+        0 => {
+            debug!("Merging into the version on disk");
+            let mut on_disk = merge::open_other(path, key)?;
+            merge::merge(&mut on_disk, db, merge::Strategy::Newest).print();
+            *db = on_disk;
+            Ok(())
+        }
+        1 => {
+            debug!("Overwriting the version on disk");
+            Ok(())
+        }
+        _ => Err(Error::Cancelled.into()),
+    }
+}
+
+/// Write the database to `path`, flushed to disk,
+/// and check that it opens again with the key.
+pub fn write_verified(db: &Database, key: &DatabaseKey, path: &Path) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    db.save(&mut file, key.clone())?;
+    file.sync_all()?;
+
+    Database::open(&mut File::open(path)?, key.clone())
+        .map_err(|err| anyhow!("Saved database doesn't open again: {:?}", err))?;
+    Ok(())
+}
+
+/// A failure to write the database, as an `Error::Save`.
+fn save_failed(err: anyhow::Error) -> anyhow::Error {
+    Error::Save(err.to_string()).into()
+}
+
+pub fn save_db(db: &mut Database, key: &DatabaseKey, path: &Path) -> Result<()> {
+    save(db, key, path, true)
+}
+
+/// Like `save_db`, for front ends that can't ask (e.g. the API): if the
+/// database changed on disk since it was opened, fails with
+/// `Error::Changed` instead of asking whether to merge or overwrite.
+pub fn save_db_unattended(db: &mut Database, key: &DatabaseKey, path: &Path) -> Result<()> {
+    save(db, key, path, false)
+}
+
+fn save(db: &mut Database, key: &DatabaseKey, path: &Path, interactive: bool) -> Result<()> {
+    if !lock::is_held(path) {
+        debug!(path = %path.display(), "Not saving, as the lock isn't held");
+        return Err(Error::ReadOnly.into());
+    }
+    debug!(path = %path.display(), "Saving");
+    remote::refresh(path)?;
+    if !interactive && changed_on_disk(path)? {
+        debug!(path = %path.display(), "Not saving, as it changed on disk");
+        return Err(Error::Changed.into());
+    }
+    let summary = changelog::summary(path, db);
+    if let Some(summary) = &summary {
+        info!("{}.", summary);
+        if config::get().notify_changes {
+            notify::notify("kpass", summary);
+        }
+    }
+    let recorded = changelog::record(path, db)?;
+    resolve_external_changes(db, key, path)?;
+    colors::make_writable(db);
+
+    backup::create(path).map_err(save_failed)?;
+
+    // Write to a temporary file on the same filesystem first,
+    // so it can be renamed into place atomically.
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", name));
+    debug!(temp = %temp_path.display(), "Writing to a temporary file");
+    cleanup::track(&temp_path);
+    let result = write_verified(db, key, &temp_path).and_then(|_| {
+        std::fs::set_permissions(&temp_path, std::fs::metadata(path)?.permissions())?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    });
+    if let Err(err) = &result {
+        debug!(temp = %temp_path.display(), error = %err, "Writing failed");
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    cleanup::untrack(&temp_path);
+    result.map_err(save_failed)?;
+    debug!(path = %path.display(), "Renamed into place");
+
+    // Make the rename itself durable.
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|err| save_failed(err.into()))?;
+    }
+    record_opened(path)?;
+    changelog::snapshot(path, db);
+    changelog::add_to_journal(path, &recorded)?;
+    remote::upload(path).map_err(save_failed)?;
+
+    if config::get().cache_metadata {
+        cache::write(db, path)?;
+    }
+    git::commit(path, summary.as_deref());
+    debug!(path = %path.display(), "Saved");
+    Ok(())
+}
+
+/// Iterate over all entries in the database.
+pub fn entries(db: &Database) -> impl Iterator<Item = &KEntry> {
+    db.root.into_iter().filter_map(|node| match node {
+        NodeRef::Group(_) => None,
+        NodeRef::Entry(e) => Some(e),
+    })
+}
+
+/// Usernames and email addresses in the database's entries,
+/// most used first, to suggest for new entries.
+pub fn known_usernames(db: &Database) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in entries(db) {
+        let emails = entry.fields.iter().filter_map(|(name, value)| match value {
+            Value::Unprotected(value) if name != "UserName" && prompt::is_email(value) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        });
+        for value in entry.get_username().into_iter().chain(emails) {
+            let value = value.trim();
+            if !value.is_empty() {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+    }
+    let mut usernames: Vec<(&str, usize)> = counts.into_iter().collect();
+    usernames.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    usernames
+        .into_iter()
+        .map(|(username, _)| username.to_string())
+        .collect()
+}
+
+/// All entries under `group` (including subgroups),
+/// each with the path of the group it's in, relative to `group`.
+pub fn entries_with_paths(group: &Group) -> Vec<(Vec<String>, &KEntry)> {
+    fn collect<'a>(
+        group: &'a Group,
+        path: &mut Vec<String>,
+        out: &mut Vec<(Vec<String>, &'a KEntry)>,
+    ) {
+        for node in &group.children {
+            match node {
+                Node::Entry(entry) => out.push((path.clone(), entry)),
+                Node::Group(child) => {
+                    path.push(child.name.clone());
+                    collect(child, path, out);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    let mut out = vec![];
+    collect(group, &mut vec![], &mut out);
+    out
+}
+
+/// Pick a group, returning its path.
+pub fn pick_group(db: &Database) -> Result<Vec<String>> {
+    fn collect(group: &Group, path: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+        for node in &group.children {
+            if let Node::Group(child) = node {
+                path.push(child.name.clone());
+                paths.push(path.clone());
+                collect(child, path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    let mut paths = vec![vec![]];
+    collect(&db.root, &mut vec![], &mut paths);
+    let options: Vec<String> = paths
+        .iter()
+        .map(|path| format!("/{}", path.join("/")))
+        .collect();
+    let choice = prompt::select(&tr!("group"), options).raw_prompt()?;
+    Ok(paths.swap_remove(choice.index))
+}
+
+/// Validate a URL typed in, which may be empty.
+fn validate_url(url: &str) -> Result<Validation, CustomUserError> {
+    let url = url.trim();
+    Ok(if url.contains(char::is_whitespace) {
+        Validation::Invalid("URLs can't contain spaces".into())
+    } else if !url.is_empty() && domain::host_of(url).is_none() {
+        Validation::Invalid("Not a URL".into())
+    } else {
+        Validation::Valid
+    })
+}
+
+/// Trim a URL and lowercase its scheme, assuming `https://` if it has none,
+/// e.g. `https://example.com/Login` for ` HTTPS://example.com/Login`.
+fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    match url.split_once("://") {
+        Some((scheme, rest)) => format!("{}://{}", scheme.to_lowercase(), rest),
+        None if url.is_empty() => String::new(),
+        None => format!("https://{}", url),
+    }
+}
+
+/// Fields that have their own option when picking what to copy.
+pub const STANDARD_FIELDS: &[&str] = &["Title", "UserName", "Password", "URL", "Notes", "otp"];
+
+/// Custom field overriding `clipboard_clear_seconds` (see config) for
+/// what's copied from an entry, e.g. `5` for recovery codes.
+pub const CLIP_TIMEOUT_FIELD: &str = "kpass:clip-timeout";
+
+/// Custom text fields, by name, leaving out those kpass shows or
+/// edits on their own (extra URLs, the host, passkeys).
+pub fn custom_fields(entry: &KEntry) -> Vec<(&str, &Value)> {
+    let own = [
+        CLIP_TIMEOUT_FIELD,
+        host::HOST_FIELD,
+        host::PORT_FIELD,
+        host::LOCATION_FIELD,
+    ];
+    let mut fields: Vec<(&str, &Value)> = entry
+        .fields
+        .iter()
+        .map(|(name, value)| (name.as_str(), value))
+        .filter(|(name, value)| {
+            !STANDARD_FIELDS.contains(name)
+                && !own.contains(name)
+                && !domain::is_extra_url(name)
+                && !name.starts_with(passkey::FIELD_PREFIX)
+                && !name.starts_with(files::FIELD_PREFIX)
+                && !matches!(value, Value::Bytes(_))
+        })
+        .collect();
+    fields.sort_by_key(|(name, _)| *name);
+    fields
+}
+
+/// The MIME type marking clipboard contents as a password manager's.
+pub(crate) const PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+/// Set by `--quiet`: leave out information (see `info!`),
+/// printing only results and warnings.
+pub static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--paste-once`: serve copied text for a single paste only.
+pub static PASTE_ONCE: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--selection`, overriding `selection` in the config.
+pub static SELECTION: OnceLock<config::Selection> = OnceLock::new();
+
+pub fn copy_to_clipboard(text: &str) -> Result<()> {
+    copy_clearing_after(text, config::get().clipboard_clear_seconds)
+}
+
+/// Copy a field of the entry, cleared after the entry's own timeout
+/// if it has one (see `CLIP_TIMEOUT_FIELD`).
+pub fn copy_from_entry(entry: &Entry, text: &str) -> Result<()> {
+    let seconds = entry
+        .clip_timeout()
+        .unwrap_or(config::get().clipboard_clear_seconds);
+    copy_clearing_after(text, seconds)
+}
+
+/// Copy, clearing the clipboard after `seconds` (0 to leave it).
+fn copy_clearing_after(text: &str, seconds: u64) -> Result<()> {
+    let selection = clipboard::selection();
+    let mut opts = Options::new();
+    opts.clipboard(clipboard::copy_type(selection));
+    let paste_once = PASTE_ONCE.load(Ordering::Relaxed) || config::get().paste_once;
+    if paste_once {
+        opts.serve_requests(ServeRequests::Only(1));
+    }
+    debug!(
+        ?selection,
+        paste_once,
+        clear_after = seconds,
+        "Copying with wl-clipboard"
+    );
+    cleanup::copied(selection);
+    opts.copy_multi(vec![
+        MimeSource {
+            source: Source::Bytes(text.as_bytes().into()),
+            mime_type: MimeType::Text,
+        },
+        // Tells clipboard managers (e.g. Klipper) to keep it out of their history.
+        MimeSource {
+            source: Source::Bytes(b"secret".as_slice().into()),
+            mime_type: MimeType::Specific(PASSWORD_MANAGER_HINT.to_string()),
+        },
+    ])?;
+    clipboard::copied(text, selection, paste_once, seconds);
+    Ok(())
+}
+
+/// Create an entry, suggesting the given usernames.
+pub fn new_entry(usernames: &[String]) -> Result<KEntry> {
+    let mut entry = KEntry::new();
+    let mut edit = EditEntry(&mut entry);
+
+    edit.set_title()?;
+    edit.set_username(usernames)?;
+    edit.set_url()?;
+    edit.set_notes()?;
+    edit.set_password()?;
+    edit.set_icon()?;
+
+    Ok(entry)
+}
+
+/// Take the entry out of the tree.
+pub fn take_entry(group: &mut Group, uuid: &Uuid) -> Option<KEntry> {
+    let idx = group
+        .children
+        .iter()
+        .position(|node| matches!(node, Node::Entry(e) if e.uuid == *uuid));
+    if let Some(idx) = idx {
+        return match group.children.remove(idx) {
+            Node::Entry(entry) => Some(entry),
+            Node::Group(_) => unreachable!(),
+        };
+    }
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Group(child) => take_entry(child, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
+/// Delete an entry as KeePass does: move it to the recycle bin (creating it
+/// if needed), or if it's in there already, or the bin is turned off, remove
+/// it for good, remembering the deletion so merges delete it elsewhere too.
+/// Returns whether the entry was moved to the recycle bin.
+pub fn delete_entry(db: &mut Database, uuid: &Uuid) -> Result<bool> {
+    let bin = db
+        .meta
+        .recyclebin_uuid
+        .filter(|_| db.meta.recyclebin_enabled != Some(false));
+    let in_bin = bin.is_some_and(|bin| {
+        db.root.iter().any(|node| match node {
+            NodeRef::Group(group) => {
+                group.uuid == bin
+                    && group
+                        .children
+                        .iter()
+                        .any(|node| matches!(node, Node::Entry(e) if e.uuid == *uuid))
+            }
+            NodeRef::Entry(_) => false,
+        })
+    });
+    let mut entry = take_entry(&mut db.root, uuid).ok_or_else(|| anyhow!("Entry not found"))?;
+    if in_bin || db.meta.recyclebin_enabled == Some(false) {
+        db.deleted_objects.objects.push(keepass::db::DeletedObject {
+            uuid: *uuid,
+            deletion_time: Times::now(),
+        });
+        return Ok(false);
+    }
+    entry.times.set_location_changed(Times::now());
+    let bin_group = bin.and_then(|bin| {
+        fn find(group: &mut Group, uuid: Uuid) -> Option<&mut Group> {
+            if group.uuid == uuid {
+                return Some(group);
+            }
+            group.children.iter_mut().find_map(|node| match node {
+                Node::Group(child) => find(child, uuid),
+                Node::Entry(_) => None,
+            })
+        }
+        find(&mut db.root, bin)
+    });
+    match bin_group {
+        Some(bin) => bin.add_child(entry),
+        None => {
+            let mut bin = Group::new("Recycle Bin");
+            db.meta.recyclebin_enabled = Some(true);
+            db.meta.recyclebin_uuid = Some(bin.uuid);
+            db.meta.recyclebin_changed = Some(Times::now());
+            bin.add_child(entry);
+            db.root.add_child(bin);
+        }
+    }
+    Ok(true)
+}
+
+/// A copy of the entry under a new UUID, titled "`<title>` - Copy",
+/// e.g. for creating similar accounts per environment.
+pub fn duplicate_entry(entry: &KEntry) -> Result<KEntry> {
+    let mut copy = entry.clone();
+    copy.uuid = Uuid::new_v4();
+    copy.times = Times::new();
+    let title = tr!("copy-of", title = entry.get_title().unwrap_or("(no title)"));
+    copy.fields
+        .insert("Title".to_string(), Value::Unprotected(title));
+
+    let keep_history = Confirm::new(&tr!("keep-history"))
+        .with_default(false)
+        .prompt()?;
+    if !keep_history {
+        copy.history = None;
+    }
+    let new_password = Confirm::new(&tr!("generate-new-password"))
+        .with_default(true)
+        .prompt()?;
+    let mut edit = EditEntry(&mut copy);
+    edit.set_title()?;
+    if new_password {
+        edit.set_generated_password()?;
+    }
+    Ok(copy)
+}
+
+pub fn edit_entry(entry: &mut KEntry) -> Result<()> {
+    let before = entry.clone();
+    let mut edit = EditEntry(entry);
+
+    loop {
+        let parts = [
+            "Title",
+            "UserName",
+            "URL",
+            "Other URLs",
+            "Notes",
+            "Protected fields",
+            "Password",
+            "Auto-type sequence",
+            "Auto-type delays",
+            "Expiry",
+            "Host",
+            "Favorite",
+            "Icon",
+            "Colors",
+            "Require master password",
+            "Done",
+        ];
+        let options = parts.iter().map(|part| i18n::name("part", part)).collect();
+        let choice = prompt::select(">", options).raw_prompt()?;
+        match parts[choice.index] {
+            "Title" => {
+                edit.set_title()?;
+            }
+            "UserName" => {
+                edit.set_username(&[])?;
+            }
+            "URL" => {
+                edit.set_url()?;
+            }
+            "Other URLs" => {
+                edit.set_extra_urls()?;
+            }
+            "Notes" => {
+                edit.set_notes()?;
+            }
+            "Protected fields" => {
+                edit.set_protected_fields()?;
+            }
+            "Password" => {
+                edit.set_password()?;
+            }
+            "Auto-type sequence" => {
+                edit.set_autotype_sequence()?;
+            }
+            "Auto-type delays" => {
+                edit.set_autotype_delays()?;
+            }
+            "Expiry" => {
+                edit.set_expiry()?;
+            }
+            "Host" => {
+                edit.set_host()?;
+            }
+            "Favorite" => {
+                edit.set_favorite()?;
+            }
+            "Icon" => {
+                edit.set_icon()?;
+            }
+            "Colors" => {
+                edit.set_colors()?;
+            }
+            "Require master password" => {
+                edit.set_require_master_password()?;
+            }
+            "Done" => {
+                break;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Keep the previous version, as KeePass does,
+    // and bump the modification time so merges pick up the change.
+    if *entry != before {
+        entry
+            .history
+            .get_or_insert_with(Default::default)
+            .add_entry(before);
+        entry.times.set_last_modification(Times::now());
+    }
+    Ok(())
+}
+
+/// Fixtures shared by the modules' tests.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use keepass::{
+        db::{Entry as KEntry, Group, Value},
+        Database,
+    };
+
+    /// An entry with `fields`, those named in `protected` protected
+    /// (as KeePass does the password).
+    pub fn entry_protecting(fields: &[(&str, &str)], protected: &[&str]) -> KEntry {
+        let mut entry = KEntry::new();
+        for (name, value) in fields {
+            let value = if protected.contains(name) {
+                Value::Protected(value.as_bytes().into())
+            } else {
+                Value::Unprotected(value.to_string())
+            };
+            entry.fields.insert(name.to_string(), value);
+        }
+        entry
+    }
+
+    /// An entry with `fields`, none of them protected.
+    pub fn entry(fields: &[(&str, &str)]) -> KEntry {
+        entry_protecting(fields, &[])
+    }
+
+    /// A database with `entries` in a `Work` group.
+    pub fn db_with(entries: Vec<KEntry>) -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        for entry in entries {
+            work.add_child(entry);
+        }
+        db.root.add_child(work);
+        db
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kpass-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn private_dir_is_created_private() {
+        let dir = temp_dir("agent-new");
+        private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        // And is accepted once it exists.
+        private_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn private_dir_refuses_shared_dirs() {
+        let dir = temp_dir("agent-shared");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn custom_fields_leave_out_what_kpass_handles() {
+        let entry = test_util::entry(&[
+            ("Title", "GitHub"),
+            ("Password", "hunter2"),
+            ("API token", "x"),
+            ("KP2A_URL_1", "https://github.com"),
+            ("KPEX_PASSKEY_PRIVATE_KEY_PEM", "x"),
+            ("KPASS_FILE_id_rsa", "x"),
+            (host::HOST_FIELD, "github.com"),
+            (CLIP_TIMEOUT_FIELD, "5"),
+            ("Account", "x"),
+        ]);
+        let names: Vec<_> = custom_fields(&entry)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["API token", "Account"]);
+    }
+
+    #[test]
+    fn private_dir_refuses_files() {
+        let dir = temp_dir("agent-file");
+        std::fs::write(&dir, "").unwrap();
+        assert!(private_dir(&dir).is_err());
+        std::fs::remove_file(&dir).unwrap();
+    }
+}
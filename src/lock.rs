@@ -0,0 +1,108 @@
+//! Advisory locking, so two kpass instances don't overwrite each other's changes.
+//!
+//! Like KeePassXC, the lock is a file next to the database (`.<name>.lock`).
+//! It's held with `flock`, so a crashed instance never leaves a stale lock behind.
+
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, Write},
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+
+/// Databases this process holds locks on.
+static HELD: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// A held lock, released when dropped.
+pub struct Lock {
+    db_path: PathBuf,
+    path: PathBuf,
+    _file: File,
+}
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        HELD.lock().unwrap().remove(&self.db_path);
+    }
+}
+
+fn lock_path(db_path: &Path) -> PathBuf {
+    let name = db_path.file_name().unwrap_or_default().to_string_lossy();
+    db_path.with_file_name(format!(".{}.lock", name))
+}
+
+/// Try to lock the database. If another instance holds
+/// the lock, returns a description of that instance instead.
+pub fn acquire(db_path: &Path) -> Result<std::result::Result<Lock, String>> {
+    let path = lock_path(db_path);
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        // SAFETY: `file` is open for the whole call.
+        let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+        if !locked {
+            let mut holder = String::new();
+            file.read_to_string(&mut holder)?;
+            return Ok(Err(holder.trim().to_string()));
+        }
+
+        // The previous holder may have removed the file after we opened it,
+        // in which case we locked a file nobody else will see.
+        let current = fs::metadata(&path).map(|meta| meta.ino()).ok();
+        if current != Some(file.metadata()?.ino()) {
+            continue;
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "pid {}", std::process::id())?;
+        HELD.lock().unwrap().insert(db_path.to_path_buf());
+        return Ok(Ok(Lock {
+            db_path: db_path.to_path_buf(),
+            path,
+            _file: file,
+        }));
+    }
+}
+
+/// Whether this process holds the lock on the database.
+pub fn is_held(db_path: &Path) -> bool {
+    HELD.lock().unwrap().contains(db_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn locks_out_other_instances() {
+        let db_path = env::temp_dir().join(format!("kpass-test-lock-{}.kdbx", std::process::id()));
+        assert!(!is_held(&db_path));
+        let lock = acquire(&db_path).unwrap().unwrap();
+        assert!(is_held(&db_path));
+        assert_eq!(
+            lock_path(&db_path).file_name().unwrap(),
+            &*format!(".kpass-test-lock-{}.kdbx.lock", std::process::id())
+        );
+
+        // Another open file, as another instance would have, can't lock it.
+        let holder = acquire(&db_path).unwrap().err().unwrap();
+        assert_eq!(holder, format!("pid {}", std::process::id()));
+
+        drop(lock);
+        assert!(!is_held(&db_path));
+        assert!(!lock_path(&db_path).exists());
+        let lock = acquire(&db_path).unwrap();
+        assert!(lock.is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! Soft-lock after repeated failed unlock attempts.
+//!
+//! Failures are recorded on disk next to the password cache,
+//! so they're counted across separate invocations. Each failure is followed
+//! by a short, doubling delay, so guesses can't be made rapidly, and after
+//! a few each further attempt has to wait out a growing cool-down.
+//! With `max_unlock_attempts`, reaching it removes the cached password
+//! (so the quick password no longer works) and locks for the longest
+//! cool-down.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{config, PW_CACHE};
+
+/// Failures allowed before cool-downs kick in.
+const FREE_ATTEMPTS: u32 = 3;
+
+/// Cool-down after the first failure past the free attempts;
+/// it doubles with each further failure, up to `MAX_COOLDOWN`.
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+fn path() -> String {
+    format!("{}.fails", PW_CACHE)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The number of failures and the time of the last one.
+fn failures() -> (u32, u64) {
+    parse(&fs::read_to_string(path()).unwrap_or_default())
+}
+
+/// Failures as recorded by `record_failure`, e.g. `3 1717171717`.
+fn parse(contents: &str) -> (u32, u64) {
+    let mut parts = contents.split_whitespace().map(|p| p.parse().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(count), Some(last)) => (count as u32, last),
+        _ => (0, 0),
+    }
+}
+
+/// Whether the failures reached `max_unlock_attempts`.
+fn locked_out(count: u32) -> bool {
+    reached(count, config::get().max_unlock_attempts)
+}
+
+/// Whether `count` failures reach a limit of `max` (none if 0).
+fn reached(count: u32, max: u32) -> bool {
+    max > 0 && count >= max
+}
+
+fn cooldown(count: u32) -> Duration {
+    cooldown_with(count, config::get().max_unlock_attempts)
+}
+
+fn cooldown_with(count: u32, max: u32) -> Duration {
+    if reached(count, max) {
+        return MAX_COOLDOWN;
+    }
+    if count < FREE_ATTEMPTS {
+        return Duration::ZERO;
+    }
+    let doublings = (count - FREE_ATTEMPTS).min(16);
+    (BASE_COOLDOWN * 2u32.pow(doublings)).min(MAX_COOLDOWN)
+}
+
+/// Fail if still cooling down from previous failures.
+pub fn check() -> Result<()> {
+    let (count, last) = failures();
+    let ready_at = last + cooldown(count).as_secs();
+    let now = now();
+    if now < ready_at {
+        return Err(anyhow!(
+            "Too many failed attempts; try again in {}s.",
+            ready_at - now
+        ));
+    }
+    Ok(())
+}
+
+/// Record a failed attempt.
+pub fn record_failure() -> Result<()> {
+    let (count, _) = failures();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path())?;
+    write!(file, "{} {}", count + 1, now())?;
+
+    if locked_out(count + 1) {
+        match fs::remove_file(PW_CACHE) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        warn!(
+            "{}",
+            tr!(
+                "locked-out-cache-removed",
+                attempts = count + 1,
+                seconds = MAX_COOLDOWN.as_secs()
+            )
+        );
+        return Ok(());
+    }
+    let cooldown = cooldown(count + 1);
+    if cooldown.is_zero() {
+        // 1s, 2s, 4s, ... before the next attempt.
+        thread::sleep(Duration::from_secs(1 << count.min(FREE_ATTEMPTS)));
+    } else {
+        warn!("{}", tr!("locked-out", seconds = cooldown.as_secs()));
+    }
+    Ok(())
+}
+
+/// Clear failures after a successful unlock.
+pub fn reset() -> Result<()> {
+    match fs::remove_file(path()) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_failures() {
+        assert_eq!(parse("3 1717171717"), (3, 1717171717));
+        assert_eq!(parse(""), (0, 0));
+        assert_eq!(parse("3"), (0, 0));
+        assert_eq!(parse("x 1717171717"), (0, 0));
+    }
+
+    #[test]
+    fn cooldowns_double_up_to_the_max() {
+        let secs = |count| cooldown_with(count, 0).as_secs();
+        assert_eq!([secs(0), secs(1), secs(2)], [0, 0, 0]);
+        assert_eq!([secs(3), secs(4), secs(5)], [30, 60, 120]);
+        assert_eq!(secs(10), MAX_COOLDOWN.as_secs());
+        assert_eq!(secs(u32::MAX), MAX_COOLDOWN.as_secs());
+    }
+
+    #[test]
+    fn locks_out_at_the_limit() {
+        assert!(!reached(9, 0));
+        assert!(!reached(4, 5));
+        assert!(reached(5, 5));
+        assert_eq!(cooldown_with(1, 5), Duration::ZERO);
+        assert_eq!(cooldown_with(5, 5), MAX_COOLDOWN);
+        assert_eq!(cooldown_with(2, 2), MAX_COOLDOWN);
+    }
+}
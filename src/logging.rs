@@ -0,0 +1,158 @@
+//! Debug logging of what kpass does, to diagnose e.g. failed saves on
+//! unusual filesystems: which files are opened and written, how the
+//! password was supplied (not the password), which clipboard is copied
+//! to, and what merging decided, by entry UUID. Never secrets, field
+//! values or entry titles.
+//!
+//! Off by default. `--verbose` logs to stderr, and `KPASS_LOG=<path>`
+//! appends to a file. Events are recorded with `tracing`'s macros,
+//! written out by the small subscriber here.
+
+use std::{
+    env,
+    fmt::{self, Write as _},
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+/// Writes kpass' events, one per line.
+struct Logger {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Formats an event's fields: its message, then the rest as `name=value`.
+#[derive(Default)]
+struct Line {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Line {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // Not the events of dependencies (e.g. zbus), which are many.
+        metadata.target().starts_with("kpass") && *metadata.level() <= Level::DEBUG
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = Line::default();
+        event.record(&mut line);
+        let metadata = event.metadata();
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(
+                out,
+                "{} {:5} {}: {}{}",
+                Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+                metadata.level(),
+                metadata.target(),
+                line.message,
+                line.fields
+            );
+        }
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// Start logging, to the `KPASS_LOG` file if set, or else to stderr
+/// if `verbose`.
+pub fn init(verbose: bool) -> Result<()> {
+    let out: Box<dyn Write + Send> = match env::var_os("KPASS_LOG") {
+        Some(path) if !path.is_empty() => Box::new(
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .mode(0o600)
+                .open(&path)
+                .map_err(|err| anyhow!("Couldn't open the KPASS_LOG file: {}", err))?,
+        ),
+        _ if verbose => Box::new(std::io::stderr()),
+        _ => return Ok(()),
+    };
+    let logger = Logger {
+        out: Mutex::new(out),
+    };
+    tracing::subscriber::set_global_default(logger)
+        .map_err(|_| anyhow!("Logging was already started"))?;
+    tracing::debug!(version = env!("CARGO_PKG_VERSION"), "kpass started");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Output shared with the test, to read back what was logged.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_events_as_lines() {
+        let buffer = Buffer::default();
+        let logger = Logger {
+            out: Mutex::new(Box::new(buffer.clone())),
+        };
+        tracing::subscriber::with_default(logger, || {
+            tracing::debug!(path = %"/tmp/db.kdbx", attempts = 2, "Saving");
+            tracing::info!(target: "zbus", "Not ours");
+            tracing::trace!("Too detailed");
+        });
+
+        let logged = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = logged.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", logged);
+        let (time, line) = lines[0].split_once(' ').unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.3f").is_ok());
+        assert_eq!(
+            line,
+            "DEBUG kpass::logging::tests: Saving path=/tmp/db.kdbx attempts=2"
+        );
+    }
+}
@@ -1,354 +1,2073 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     env,
     fmt::{Display, Formatter},
     fs::File,
-    path::Path,
+    io::Write,
+    path::{Path, PathBuf},
     process::exit,
+    sync::atomic::Ordering,
+    time::Duration,
 };
 
-use anyhow::Result;
-use cocoon::Cocoon;
-use inquire::{required, Confirm, Editor, Password, PasswordDisplayMode, Select, Text};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use inquire::{Confirm, Select};
 use keepass::{
-    db::{Entry as KEntry, Group, Node, NodeRef, Value},
-    Database, DatabaseKey,
+    db::{Entry as KEntry, Times, Value},
+    Database,
 };
-use passwords::PasswordGenerator;
-use wl_clipboard_rs::copy::{MimeType, Options, Source};
+use kpass::{
+    activity, address, agent, api, apply, audit, autotype, backup, browser, bulk, cache, changelog,
+    check, cleanup, colors, compact, config,
+    config::EntrySort,
+    copy_from_entry, copy_to_clipboard, custom_fields, dedupe, domain, duplicate_entry, edit_entry,
+    entries, entries_with_paths, error,
+    error::{is_cancelled, is_interrupted},
+    exec, export, favorites, files, filter, generate, get_entry_mut, groups, hibp, host, i18n,
+    icons, idle, import, info, init, journal, kdf, known_usernames, lock_db, logging, menu, merge,
+    new_entry, notes, otp, pairing, passkey, pick_group, prompt, protect, qr, quick, recovery,
+    reference, remote, reveal, rotate, sandbox, save_db, secret,
+    secret::Secret,
+    secret_service, shamir, share, show, ssh, stats,
+    strength::Strength,
+    templates, theme, title, tr, tty, tui, unlock, unlock_password, upgrade, usage,
+    vault::Vault,
+    warn, Entry, PASTE_ONCE, QUIET, SELECTION, STANDARD_FIELDS, STDIN_PASSWORD,
+};
+use uuid::Uuid;
+use zeroize::Zeroizing;
 
-const PW_CACHE: &str = "/tmp/.kpw";
+/// Seconds to wait before auto-typing from the interactive session.
+const AUTOTYPE_DELAY: u64 = 3;
 
-/// A KeePass entry.
-struct Entry<'a>(&'a KEntry);
-impl Display for Entry<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let title = self.0.get_title().unwrap_or("(no title)");
-        write!(f, "{}", title)
+/// How long `kpass grant` lets paired devices unlock a database by default.
+const GRANT_MINUTES: u64 = 10;
+
+/// How long the agent stays unlocked without receiving requests.
+const AGENT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {:?}", err);
+        exit(error::exit_code(&err));
     }
 }
-impl Entry<'_> {
-    fn password(&self) -> Option<&str> {
-        self.0.get_password()
-    }
 
-    fn username(&self) -> Option<&str> {
-        self.0.get_username()
+fn run() -> Result<()> {
+    if ssh::is_askpass() {
+        return ssh::askpass();
     }
-
-    fn url(&self) -> Option<&str> {
-        self.0.get_url()
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // What follows `--` is a command for `kpass exec`, not kpass' own arguments.
+    let command: Vec<String> = match args.iter().position(|arg| arg == "--") {
+        Some(idx) => args.split_off(idx).into_iter().skip(1).collect(),
+        None => vec![],
+    };
+    logging::init(take_flag(&mut args, "--verbose"))?;
+    if take_flag(&mut args, "--sandbox") {
+        sandbox::enable();
+    }
+    if take_flag(&mut args, "--password-stdin") {
+        let pass = tty::piped_secret()?
+            .ok_or_else(|| anyhow!("--password-stdin needs the password piped into stdin"))?;
+        let _ = STDIN_PASSWORD.set(pass);
+    }
+    if take_flag(&mut args, "--from-shares") {
+        let _ = STDIN_PASSWORD.set(shamir::prompt_password()?);
+    }
+    let output = take_opt(&mut args, "--output");
+    // `gen` has a `--copy` flag of its own.
+    let copy_query = match args.first().map(String::as_str) {
+        Some("gen") => None,
+        _ => take_opt(&mut args, "--copy"),
+    };
+    if take_flag(&mut args, "--quiet") {
+        QUIET.store(true, Ordering::Relaxed);
+    }
+    if take_flag(&mut args, "--plain") {
+        theme::PLAIN.store(true, Ordering::Relaxed);
+    }
+    // `export` and `rotate` have `--group` (and `--tag`) options of their own.
+    if !matches!(args.first().map(String::as_str), Some("export" | "rotate")) {
+        filter::set(filter::Filter {
+            group: take_opt(&mut args, "--group")
+                .map(|group| address::split(&group))
+                .unwrap_or_default(),
+            tag: take_opt(&mut args, "--tag"),
+            include_recycled: take_flag(&mut args, "--include-recycled"),
+            expired_only: take_flag(&mut args, "--expired-only"),
+        });
+    }
+    if take_flag(&mut args, "--paste-once") {
+        PASTE_ONCE.store(true, Ordering::Relaxed);
+    }
+    if let Some(selection) = take_opt(&mut args, "--selection") {
+        let _ = SELECTION.set(config::Selection::parse(&selection)?);
     }
 
-    fn notes(&self) -> Option<&str> {
-        self.0.fields.get("Notes").and_then(|val| match val {
-            Value::Unprotected(notes) => Some(notes.as_str()),
-            Value::Protected(data) => std::str::from_utf8(data.unsecure()).ok(),
-            _ => None,
+    secret::disable_core_dumps();
+    cleanup::install()?;
+    config::load()?;
+    if args.is_empty() {
+        // Open all the databases named in the config.
+        args = config::get().databases.keys().cloned().collect();
+    }
+    if args.is_empty() {
+        println!("Please provide an database path.");
+        exit(1);
+    }
+    // Remote databases (by URL or by name) are worked on through a local mirror.
+    let remotes: Vec<(String, String)> = path_args(&args)
+        .into_iter()
+        .map(|arg| {
+            (
+                arg,
+                config::database_path(arg).to_string_lossy().into_owned(),
+            )
         })
+        .filter(|(_, target)| remote::is_remote(target))
+        .map(|(arg, url)| (arg.to_string(), url))
+        .collect();
+    for (arg, url) in remotes {
+        sandbox::needs_network("A remote database")?;
+        let mirror = remote::open(&url)?.to_string_lossy().into_owned();
+        for a in args.iter_mut().filter(|a| **a == arg) {
+            *a = mirror.clone();
+        }
     }
-}
-
-/// For conveniently editing an entry.
-struct EditEntry<'a>(&'a mut KEntry);
-impl EditEntry<'_> {
-    fn set_title(&mut self) -> Result<()> {
-        let current = self.0.get_title().unwrap_or("");
-        let value = Text::new("Title: ")
-            .with_initial_value(current)
-            .with_validator(required!())
-            .prompt()?;
-        self.0
-            .fields
-            .insert("Title".to_string(), Value::Unprotected(value));
-        Ok(())
+    if sandbox::is_enabled() {
+        let files: Vec<PathBuf> = path_args(&args)
+            .into_iter()
+            .map(config::database_path)
+            .chain(output.as_deref().map(PathBuf::from))
+            .collect();
+        let files: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+        sandbox::apply(&files)?;
     }
 
-    fn set_username(&mut self) -> Result<()> {
-        let current = self.0.get_username().unwrap_or("");
-        let value = Text::new("UserName: ")
-            .with_initial_value(current)
-            .with_validator(required!())
-            .prompt()?;
-        self.0
-            .fields
-            .insert("UserName".to_string(), Value::Unprotected(value));
-        Ok(())
-    }
+    match args[0].as_str() {
+        "gen" => {
+            let count: usize = match take_opt(&mut args, "--count") {
+                Some(n) => n.parse()?,
+                None => 1,
+            };
+            let copy = take_flag(&mut args, "--copy");
+            let passwords = if take_flag(&mut args, "--pronounceable") {
+                let mut settings = generate::Pronounceable::default();
+                if let Some(n) = take_opt(&mut args, "--syllables") {
+                    settings.syllables = n.parse()?;
+                }
+                settings.capital_and_digit = take_flag(&mut args, "--capital-and-digit");
+                generate::many_pronounceable(&settings, count)
+            } else {
+                let mut pg = generate::default_generator();
+                if let Some(length) = take_opt(&mut args, "--length") {
+                    pg.length = length.parse()?;
+                }
+                pg.numbers = !take_flag(&mut args, "--no-numbers");
+                pg.lowercase_letters = !take_flag(&mut args, "--no-lowercase");
+                pg.uppercase_letters = !take_flag(&mut args, "--no-uppercase");
+                pg.symbols = !take_flag(&mut args, "--no-symbols");
+                pg.spaces = !take_flag(&mut args, "--no-spaces");
+                pg.exclude_similar_characters = take_flag(&mut args, "--exclude-similar");
+                generate::many(&pg, count)?
+            };
+            if !copy {
+                for password in &passwords {
+                    println!("{}", password.expose());
+                }
+                return Ok(());
+            }
+            let password = match passwords.len() {
+                0 => return Ok(()),
+                1 => &passwords[0],
+                _ => {
+                    let options: Vec<&str> = passwords.iter().map(Secret::expose).collect();
+                    let choice = prompt::select(&tr!("copy-field"), options).raw_prompt()?;
+                    &passwords[choice.index]
+                }
+            };
+            copy_to_clipboard(password.expose())?;
+            info!("{}", tr!("copied"));
+            Ok(())
+        }
+        "agent" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let (db, _, password) = unlock_password(db_path, true)?;
+            agent::serve(db, password, db_path, AGENT_IDLE_TIMEOUT)
+        }
+        "lock" => {
+            if agent::status()?.is_none() {
+                info!("{}", tr!("no-agent"));
+                return Ok(());
+            }
+            agent::lock()?;
+            info!("{}", tr!("agent-locked"));
+            Ok(())
+        }
+        "unlock" => match agent::status()? {
+            None => Err(anyhow!(
+                "No agent running. Start one with `kpass agent <database>`."
+            )),
+            Some((true, _)) => {
+                info!("{}", tr!("agent-already-unlocked"));
+                Ok(())
+            }
+            Some((false, db_path)) => {
+                let (_, _, pass) = unlock_password(&db_path, true)?;
+                agent::unlock(&pass)?;
+                info!("{}", tr!("agent-unlocked"));
+                Ok(())
+            }
+        },
+        "status" => match agent::status()? {
+            None => {
+                info!("{}", tr!("no-agent"));
+                exit(error::NO_AGENT);
+            }
+            Some((unlocked, db_path)) => {
+                let status = if unlocked { "unlocked" } else { "locked" };
+                println!("> Agent {}: {}", status, db_path.display());
+                if !unlocked {
+                    exit(error::AGENT_LOCKED);
+                }
+                Ok(())
+            }
+        },
+        "serve" => {
+            sandbox::needs_network("The API")?;
+            let listen =
+                take_opt(&mut args, "--listen").unwrap_or_else(|| api::DEFAULT_LISTEN.to_string());
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            // Held while serving, as entries can be created.
+            let _lock = lock_db(db_path)?;
+            let (db, key) = unlock(db_path)?;
+            api::serve(db, key, db_path, &listen, AGENT_IDLE_TIMEOUT)
+        }
+        "secret-service" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let (db, _) = unlock(db_path)?;
+            secret_service::serve(db)
+        }
+        "export" => {
+            let include_secrets = take_flag(&mut args, "--include-secrets");
+            let encrypt = take_flag(&mut args, "--encrypt");
+            let include_denied = take_flag(&mut args, "--include-denied");
+            let group = take_opt(&mut args, "--group");
+            let format = require_arg(&args, 1, "an export format");
+            if !["html", "csv", "json", "vault-kv", "sops"].contains(&format) {
+                println!("Unsupported export format: {}", format);
+                exit(1);
+            }
+            let db_path = Path::new(require_arg(&args, 2, "a database path"));
+            let Some(output) = output else {
+                println!("Please provide an output path with --output.");
+                exit(1);
+            };
 
-    fn set_notes(&mut self) -> Result<()> {
-        let entry = Entry(self.0);
-        let current = entry.notes().unwrap_or("");
-        let notes = Editor::new("Notes: ")
-            .with_predefined_text(current)
-            .prompt()?;
-        self.0.fields.insert(
-            "Notes".to_string(),
-            Value::Protected(notes.as_bytes().into()),
-        );
-        Ok(())
-    }
-
-    fn set_password(&mut self) -> Result<()> {
-        let pg = PasswordGenerator {
-            length: 12,
-            numbers: true,
-            lowercase_letters: true,
-            uppercase_letters: true,
-            symbols: true,
-            spaces: true,
-            exclude_similar_characters: false,
-            strict: true,
-        };
-        let password = pg.generate_one().unwrap();
-        println!("> Password generated.");
-        self.0.fields.insert(
-            "Password".to_string(),
-            Value::Protected(password.as_bytes().into()),
-        );
-        Ok(())
+            // Plain text formats always include secrets.
+            let include_secrets = include_secrets || format != "html";
+            // sops output is encrypted already.
+            let encrypted = encrypt || format == "sops";
+            let passphrase = if encrypt {
+                Some(share::prompt_passphrase(&tr!("export-passphrase"), true)?)
+            } else {
+                None
+            };
+            if include_secrets && !encrypted && !export::confirm_secrets_export()? {
+                info!("{}", tr!("export-cancelled"));
+                return Ok(());
+            }
+            let (db, _) = unlock(db_path)?;
+            let (group, group_path) = address::find_group(&db, group.as_deref())?;
+            let pruned;
+            let group = if include_denied {
+                group
+            } else {
+                if let Some(denied) = export::denied_at(&db, &group_path) {
+                    return Err(anyhow!(
+                        "{} denies export; pass --include-denied to export it anyway.",
+                        denied
+                    ));
+                }
+                let denied;
+                (pruned, denied) = export::without_denied(group, &group_path);
+                for path in denied {
+                    warn!("{}", tr!("leaving-out-denied", group = path));
+                }
+                &pruned
+            };
+            let protected = entries_with_paths(group)
+                .iter()
+                .any(|(_, entry)| protect::is_required(entry));
+            if include_secrets && protected {
+                info!("{}", tr!("some-require-master-password"));
+                if !protect::verify(db_path)? {
+                    info!("{}", tr!("export-cancelled"));
+                    return Ok(());
+                }
+            }
+            let output_path = Path::new(&output);
+            let passphrase = passphrase.as_ref();
+            match format {
+                "csv" => export::write_csv(group, &group_path, output_path, passphrase)?,
+                "json" => export::write_json(group, &group_path, output_path, passphrase)?,
+                "vault-kv" => export::write_vault_kv(group, &group_path, output_path, passphrase)?,
+                "sops" => export::write_sops(group, &group_path, output_path, passphrase)?,
+                _ => export::write_html(
+                    &db,
+                    group,
+                    &group_path,
+                    include_secrets,
+                    output_path,
+                    passphrase,
+                )?,
+            }
+            info!("{}", tr!("exported-to", path = output));
+            Ok(())
+        }
+        "export-permission" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let group = require_arg(&args, 2, "a group");
+            let denied = match require_arg(&args, 3, "allow or deny") {
+                "allow" => false,
+                "deny" => true,
+                other => {
+                    println!("Expected allow or deny, not {}.", other);
+                    exit(1);
+                }
+            };
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            let (_, path) = address::find_group(&db, Some(group))?;
+            if path.is_empty() {
+                return Err(anyhow!("Please name a group, not the root."));
+            }
+            export::set_export_denied(import::get_or_create_group(&mut db.root, &path), denied);
+            info!("{}", tr!("saving"));
+            save_db(&mut db, &key, db_path)?;
+            info!("{}", tr!("saved"));
+            Ok(())
+        }
+        "decrypt-export" => {
+            let file = Path::new(require_arg(&args, 1, "an encrypted export"));
+            let data = export::decrypt(file)?;
+            std::io::stdout().write_all(&data)?;
+            Ok(())
+        }
+        "import" if args.len() == 1 => {
+            let Some(wizard) = import::wizard::run()? else {
+                return Ok(());
+            };
+            import_into(&wizard.db_path, wizard.imported)?;
+            import::wizard::offer_delete(&wizard.export)
+        }
+        "add" => {
+            let uri = take_opt(&mut args, "--otpauth");
+            let image = take_opt(&mut args, "--qr");
+            let screen = take_flag(&mut args, "--qr-screen");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let uri = match (uri, image) {
+                (Some(uri), _) => Zeroizing::new(uri),
+                (None, Some(image)) => qr::scan(Path::new(&image))?,
+                (None, None) if screen => qr::scan_screen()?,
+                (None, None) => {
+                    println!("Please provide --otpauth <uri>, --qr <image> or --qr-screen.");
+                    exit(1);
+                }
+            };
+            let token = import::otp::from_uri(&uri)?;
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            if import::otp::add_tokens(&mut db, vec![token])? {
+                info!("{}", tr!("saving"));
+                save_db(&mut db, &key, db_path)?;
+                info!("{}", tr!("saved"));
+            }
+            Ok(())
+        }
+        "import" => {
+            let format = take_opt(&mut args, "--format");
+            let source = require_arg(&args, 1, "an import source");
+            let db_path = Path::new(require_arg(&args, 2, "a database path"));
+            let imported = match source {
+                "csv" => {
+                    let file = Path::new(require_arg(&args, 3, "a file to import"));
+                    import::csv::read(file, format.as_deref())?
+                }
+                "bitwarden" => {
+                    let file = Path::new(require_arg(&args, 3, "a file to import"));
+                    import::bitwarden::read(file)?
+                }
+                "xml" => {
+                    let file = Path::new(require_arg(&args, 3, "a file to import"));
+                    import::keepass_xml::read(file)?
+                }
+                "kdb" => {
+                    let file = Path::new(require_arg(&args, 3, "a file to import"));
+                    import::kdb::read(file)?
+                }
+                "pass" => {
+                    let store = args
+                        .get(3)
+                        .map(PathBuf::from)
+                        .unwrap_or_else(import::pass::default_store_dir);
+                    import::pass::read(&store)?
+                }
+                "vault" => {
+                    sandbox::needs_network("Importing from Vault")?;
+                    import::vault::read(require_arg(&args, 3, "a Vault path"))?
+                }
+                "otp" => {
+                    let file = Path::new(require_arg(&args, 3, "a file to import"));
+                    let tokens = import::otp::read(file)?;
+                    let _lock = lock_db(db_path)?;
+                    let (mut db, key) = unlock(db_path)?;
+                    if import::otp::add_tokens(&mut db, tokens)? {
+                        info!("{}", tr!("saving"));
+                        save_db(&mut db, &key, db_path)?;
+                        info!("{}", tr!("saved"));
+                    }
+                    return Ok(());
+                }
+                _ => {
+                    println!("Unsupported import source: {}", source);
+                    exit(1);
+                }
+            };
+            if !import::preview(&imported)? {
+                return Ok(());
+            }
+            import_into(db_path, imported)
+        }
+        "audit" => {
+            let corpus = take_opt(&mut args, "--corpus");
+            let check = require_arg(&args, 1, "a check to run");
+            let db_path = Path::new(require_arg(&args, 2, "a database path"));
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            let modified = match check {
+                "urls" => audit::check_urls(&mut db)?,
+                "passwords" => audit::check_passwords(&mut db)?,
+                "rules" => {
+                    let findings = audit::run_rules(&db, &audit::rules());
+                    audit::print_report(&findings);
+                    false
+                }
+                "hibp" => {
+                    let corpus = corpus.as_deref().map(Path::new);
+                    if let Some(findings) = audit::check_breaches(&db, db_path, corpus)? {
+                        audit::print_report(&findings);
+                    }
+                    false
+                }
+                "otp" => {
+                    let with_otp = entries(&db)
+                        .filter(|e| e.get_raw_otp_value().is_some())
+                        .count();
+                    info!("{}", tr!("checking-otp", count = with_otp));
+                    let findings = audit::run_rules(&db, &[Box::new(audit::OtpSettings)]);
+                    audit::print_report(&findings);
+                    false
+                }
+                _ => {
+                    println!("Unknown check: {}", check);
+                    exit(1);
+                }
+            };
+            if modified {
+                info!("{}", tr!("saving"));
+                save_db(&mut db, &key, db_path)?;
+                info!("{}", tr!("saved"));
+            }
+            Ok(())
+        }
+        "list" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let cached = if config::get().cache_metadata {
+                cache::read(db_path)?
+            } else {
+                None
+            };
+            let entries = match cached {
+                Some(entries) => entries,
+                None => {
+                    let (db, _) = unlock(db_path)?;
+                    if config::get().cache_metadata {
+                        cache::write(&db, db_path)?;
+                    }
+                    cache::entries(&db)
+                }
+            };
+            let filter = filter::get();
+            let entries = entries.into_iter().filter(|entry| {
+                filter.matches(
+                    &entry.group,
+                    &entry.tags,
+                    entry.recycled,
+                    entry.is_expired(),
+                )
+            });
+            for entry in entries {
+                println!("{}", entry.path());
+            }
+            Ok(())
+        }
+        "merge" => {
+            let non_interactive = take_flag(&mut args, "--non-interactive");
+            let strategy = match take_opt(&mut args, "--strategy") {
+                Some(strategy) => strategy.parse()?,
+                None => merge::Strategy::Newest,
+            };
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let other_path = Path::new(require_arg(&args, 2, "a database to merge"));
+            let _lock = lock_db(db_path)?;
+
+            // Without prompts, the password must be supplied (see
+            // `supplied_password`) and open both databases.
+            // The summary is printed as JSON.
+            if non_interactive {
+                if STDIN_PASSWORD.get().is_none() && env::var_os("KPASS_PASSWORD_CMD").is_none() {
+                    return Err(anyhow!(
+                        "--non-interactive needs the password from --password-stdin or KPASS_PASSWORD_CMD"
+                    ));
+                }
+                let (mut db, key, _) = unlock_password(db_path, false)?;
+                changelog::snapshot(db_path, &db);
+                let other = Database::open(&mut File::open(other_path)?, key.clone())
+                    .map_err(|_| anyhow!("Couldn't open {}", other_path.display()))?;
+                let summary = merge::merge(&mut db, &other, strategy);
+                if !summary.is_empty() {
+                    save_db(&mut db, &key, db_path)?;
+                }
+                println!("{}", serde_json::to_string(&summary)?);
+                return Ok(());
+            }
+
+            let (mut db, key) = unlock(db_path)?;
+            let other = merge::open_other(other_path, &key)?;
+            let summary = merge::merge(&mut db, &other, strategy);
+            summary.print();
+            if summary.is_empty() {
+                return Ok(());
+            }
+            if !Confirm::new(&tr!("save-confirm"))
+                .with_default(true)
+                .prompt()?
+            {
+                return Ok(());
+            }
+            info!("{}", tr!("saving"));
+            save_db(&mut db, &key, db_path)?;
+            info!("{}", tr!("saved"));
+            Ok(())
+        }
+        "compact" => {
+            let yes = take_flag(&mut args, "--yes");
+            let keep = take_opt(&mut args, "--keep");
+            let max_days = take_opt(&mut args, "--max-days");
+            let deleted_days = take_opt(&mut args, "--deleted-days");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            let mut limits = compact::Limits::of(&db);
+            if let Some(keep) = keep {
+                limits.keep = keep.parse()?;
+            }
+            if let Some(days) = max_days {
+                limits.max_days = days.parse()?;
+            }
+            if let Some(days) = deleted_days {
+                limits.deleted_days = days.parse()?;
+            }
+
+            let mut compacted = db.clone();
+            let summary = compact::compact(&mut compacted, &limits);
+            if summary.is_empty() {
+                info!("{}", tr!("nothing-to-compact"));
+                return Ok(());
+            }
+            info!(
+                "{}",
+                tr!(
+                    "compact-summary",
+                    revisions = summary.revisions,
+                    keep = limits.keep,
+                    max_days = limits.max_days,
+                    deleted = summary.deleted_objects,
+                    deleted_days = limits.deleted_days
+                )
+            );
+            let confirmed = yes
+                || Confirm::new(&tr!("compact-confirm"))
+                    .with_default(true)
+                    .prompt()?;
+            if !confirmed {
+                return Ok(());
+            }
+            db = compacted;
+            let before = std::fs::metadata(db_path)?.len();
+            save_db(&mut db, &key, db_path)?;
+            let after = std::fs::metadata(db_path)?.len();
+            info!(
+                "{}",
+                tr!(
+                    "compacted",
+                    before = format!("{:.1}", before as f64 / 1024.),
+                    after = format!("{:.1}", after as f64 / 1024.)
+                )
+            );
+            Ok(())
+        }
+        "rotate" => {
+            let filter = rotate::Filter {
+                tag: take_opt(&mut args, "--tag"),
+                group: take_opt(&mut args, "--group"),
+                older_than_days: take_opt(&mut args, "--older-than")
+                    .map(|age| rotate::parse_age(&age))
+                    .transpose()?,
+            };
+            let yes = take_flag(&mut args, "--yes");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            let due = rotate::matching(&db, &filter);
+            if due.is_empty() {
+                info!("{}", tr!("nothing-to-rotate"));
+                return Ok(());
+            }
+            for due in &due {
+                match due.age_days {
+                    Some(age) => println!("> {} ({} days old)", due.path, age),
+                    None => println!("> {}", due.path),
+                }
+            }
+            let confirmed = yes
+                || Confirm::new(&tr!("rotate-confirm", count = due.len()))
+                    .with_default(false)
+                    .prompt()?;
+            if !confirmed {
+                return Ok(());
+            }
+            rotate::rotate(&mut db, &due)?;
+            save_db(&mut db, &key, db_path)?;
+            info!("{}", tr!("rotated"));
+            for due in &due {
+                println!(
+                    "[ ] {}  {}",
+                    due.path,
+                    due.url.as_deref().unwrap_or("(no URL)")
+                );
+            }
+            Ok(())
+        }
+        "expiring" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let days = match args.get(2) {
+                Some(days) => days.parse()?,
+                None => config::get().expiry_warning_days,
+            };
+            let (db, _) = unlock(db_path)?;
+            let today = chrono::Local::now().date_naive();
+            let mut due: Vec<_> = entries_with_paths(&db.root)
+                .into_iter()
+                .filter_map(|(mut path, entry)| {
+                    let expiry = Entry(entry).expiry()?;
+                    path.push(Entry(entry).to_string());
+                    ((expiry - today).num_days() <= days).then(|| (expiry, path.join("/")))
+                })
+                .collect();
+            if due.is_empty() {
+                info!("{}", tr!("nothing-expires", days = days));
+            }
+            due.sort();
+            for (expiry, path) in due {
+                let state = if expiry < today { "expired" } else { "expires" };
+                println!("{} {} {}", state, expiry.format("%Y-%m-%d"), path);
+            }
+            Ok(())
+        }
+        "changelog" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let n = match args.get(2) {
+                Some(n) => n.parse()?,
+                None => 20,
+            };
+            let (db, _) = unlock(db_path)?;
+            changelog::print(&db, n)
+        }
+        "log" => {
+            let since = take_opt(&mut args, "--since");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            journal::print(db_path, since.as_deref())
+        }
+        "check" => {
+            let repair = take_flag(&mut args, "--repair");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let _lock = if repair {
+                Some(lock_db(db_path)?)
+            } else {
+                None
+            };
+            let (mut db, key) = unlock(db_path)?;
+            info!("{}", tr!("database-parses"));
+            let report = check::check(&mut db, repair);
+            check::print(&report, repair);
+            if repair && report.repairable > 0 {
+                save_db(&mut db, &key, db_path)?;
+            }
+            Ok(())
+        }
+        "kdf" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            kdf::tune(db_path)
+        }
+        "dedupe" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            if dedupe::run(&mut db)? {
+                save_db(&mut db, &key, db_path)?;
+            }
+            Ok(())
+        }
+        "diff" => {
+            let a = Path::new(require_arg(&args, 1, "a database path"));
+            let b = Path::new(require_arg(&args, 2, "another database path"));
+            let (a, _) = unlock(a)?;
+            let (b, _) = unlock(b)?;
+            changelog::diff(&a, &b);
+            Ok(())
+        }
+        "stats" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let (db, _) = unlock(db_path)?;
+            println!("{}", stats::Stats::of(&db, db_path)?);
+            Ok(())
+        }
+        "upgrade" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            upgrade::upgrade(db_path)
+        }
+        "restore-backup" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let _lock = lock_db(db_path)?;
+            backup::restore(db_path)?;
+            info!("{}", tr!("restored"));
+            Ok(())
+        }
+        "share" => {
+            let include_denied = take_flag(&mut args, "--include-denied");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let query = args[2..].join(" ");
+            let Some(output) = output else {
+                println!("Please provide an output path with --output.");
+                exit(1);
+            };
+            let (db, _) = unlock(db_path)?;
+            let entry = address::find_entry(&db, &query)?;
+            let group_path = entries_with_paths(&db.root)
+                .into_iter()
+                .find(|(_, e)| e.uuid == entry.uuid)
+                .map(|(path, _)| path)
+                .unwrap_or_default();
+            if let Some(denied) = export::denied_at(&db, &group_path).filter(|_| !include_denied) {
+                return Err(anyhow!(
+                    "{} denies export; pass --include-denied to share it anyway.",
+                    denied
+                ));
+            }
+            if protect::is_required(entry) && !protect::verify(db_path)? {
+                return Ok(());
+            }
+            share::share(entry, Path::new(&output))?;
+            info!("{}", tr!("shared", entry = Entry(entry), path = output));
+            Ok(())
+        }
+        "receive" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let file = Path::new(require_arg(&args, 2, "a file to receive"));
+            let entry = share::receive(file)?;
+            info!("{}", tr!("received", entry = Entry(&entry)));
+
+            let _lock = lock_db(db_path)?;
+            let (mut db, key) = unlock(db_path)?;
+            let path = pick_group(&db)?;
+            import::get_or_create_group(&mut db.root, &path).add_child(entry);
+            info!("{}", tr!("saving"));
+            save_db(&mut db, &key, db_path)?;
+            info!("{}", tr!("saved"));
+            Ok(())
+        }
+        "file" => {
+            let to_stdout = take_flag(&mut args, "--stdout");
+            let out = take_opt(&mut args, "-o").or(output);
+            let action = require_arg(&args, 1, "put or get");
+            let db_path = Path::new(require_arg(&args, 2, "a database path"));
+            let query = require_arg(&args, 3, "an entry query");
+            match action {
+                "put" => {
+                    let file = Path::new(require_arg(&args, 4, "a file to store"));
+                    let _lock = lock_db(db_path)?;
+                    let (mut db, key) = unlock(db_path)?;
+                    let uuid = address::find_entry(&db, query)?.uuid;
+                    let entry =
+                        get_entry_mut(&mut db, uuid.as_u128()).expect("The entry was just found");
+                    let name = files::put(entry, file)?;
+                    info!("{}", tr!("stored-file", file = name, entry = Entry(entry)));
+                    info!("{}", tr!("saving"));
+                    save_db(&mut db, &key, db_path)?;
+                    info!("{}", tr!("saved"));
+                    Ok(())
+                }
+                "get" => {
+                    let name = require_arg(&args, 4, "a file name");
+                    let mut stdout = if to_stdout {
+                        Some(tty::divert_stdout()?)
+                    } else {
+                        None
+                    };
+                    let (db, _) = unlock(db_path)?;
+                    let entry = address::find_entry(&db, query)?;
+                    if protect::is_required(entry) && !protect::verify(db_path)? {
+                        return Ok(());
+                    }
+                    let contents = files::get(entry, name)?;
+                    if let Some(stdout) = &mut stdout {
+                        stdout.write_all(&contents)?;
+                    } else {
+                        let out = out.unwrap_or_else(|| name.to_string());
+                        files::write(Path::new(&out), &contents)?;
+                        info!("{}", tr!("wrote-file", file = name, path = out));
+                    }
+                    Ok(())
+                }
+                _ => Err(anyhow!("Unknown file command '{}'; use put or get", action)),
+            }
+        }
+        "passkeys" => {
+            let import_from = take_opt(&mut args, "--import");
+            let delete = take_opt(&mut args, "--delete");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            if let Some(file) = import_from {
+                let _lock = lock_db(db_path)?;
+                let (mut db, key) = unlock(db_path)?;
+                let entry = passkey::import(Path::new(&file), &db.root)?;
+                info!("{}", tr!("imported-passkey", entry = Entry(&entry)));
+                let path = pick_group(&db)?;
+                import::get_or_create_group(&mut db.root, &path).add_child(entry);
+                info!("{}", tr!("saving"));
+                save_db(&mut db, &key, db_path)?;
+                info!("{}", tr!("saved"));
+            } else if let Some(query) = delete {
+                let _lock = lock_db(db_path)?;
+                let (mut db, key) = unlock(db_path)?;
+                let entry = address::find_entry(&db, &query)?;
+                let Some(passkey) = passkey::Passkey::of(entry) else {
+                    return Err(anyhow!("{} has no passkey", Entry(entry)));
+                };
+                let prompt = tr!(
+                    "delete-passkey",
+                    site = passkey.relying_party,
+                    username = passkey.username,
+                    entry = Entry(entry)
+                );
+                let uuid = entry.uuid;
+                if !Confirm::new(&prompt).with_default(false).prompt()? {
+                    return Ok(());
+                }
+                let entry = get_entry_mut(&mut db, uuid.as_u128())
+                    .ok_or_else(|| anyhow!("Entry not found"))?;
+                passkey::remove(entry);
+                info!("{}", tr!("saving"));
+                save_db(&mut db, &key, db_path)?;
+                info!("{}", tr!("saved-passkey-in-history"));
+            } else {
+                let (db, _) = unlock(db_path)?;
+                let mut found = false;
+                for (mut path, entry) in entries_with_paths(&db.root) {
+                    let Some(passkey) = passkey::Passkey::of(entry) else {
+                        continue;
+                    };
+                    found = true;
+                    path.push(Entry(entry).to_string());
+                    println!(
+                        "{}  {}  {}",
+                        passkey.relying_party,
+                        passkey.username,
+                        address::format(&path)
+                    );
+                }
+                if !found {
+                    info!("{}", tr!("no-passkeys"));
+                }
+            }
+            Ok(())
+        }
+        "init" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            init::create(db_path)
+        }
+        "tui" => {
+            let db_path = config::database_path(require_arg(&args, 1, "a database path"));
+            let Some(mut vault) = Vault::open(&db_path, true)? else {
+                return Ok(());
+            };
+            tui::run(&mut vault)
+        }
+        "recovery" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            recovery::enable(db_path)
+        }
+        "shard" => {
+            let threshold = take_opt(&mut args, "--threshold");
+            let count = take_opt(&mut args, "--shares");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let threshold = threshold.map_or(Ok(2), |n| n.parse())?;
+            let count = count.map_or(Ok(3), |n| n.parse())?;
+            shamir::shard(db_path, threshold, count)
+        }
+        "recover" => {
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            recovery::recover(db_path)
+        }
+        "apply" => {
+            let yes = take_flag(&mut args, "--yes");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let spec = Path::new(require_arg(&args, 2, "a spec file"));
+            let specs = apply::read(spec)?;
+            let _lock = lock_db(db_path)?;
+            let (db, key) = unlock(db_path)?;
+            let mut updated = db.clone();
+            let changes = apply::apply(&mut updated, &specs)?;
+            if changes.is_empty() {
+                info!("{}", tr!("nothing-to-change"));
+                return Ok(());
+            }
+            for change in &changes {
+                println!("> {} {}", if change.added { "+" } else { "~" }, change.path);
+                for field in &change.fields {
+                    println!(">     {}", field);
+                }
+            }
+            let confirmed = yes
+                || Confirm::new(&tr!("apply-confirm", count = changes.len()))
+                    .with_default(true)
+                    .prompt()?;
+            if !confirmed {
+                return Ok(());
+            }
+            info!("{}", tr!("saving"));
+            save_db(&mut updated, &key, db_path)?;
+            info!("{}", tr!("saved"));
+            Ok(())
+        }
+        "browser-host" => {
+            let install = take_flag(&mut args, "--install");
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            if install {
+                browser::install(db_path)
+            } else {
+                // Browsers add their own arguments, which are ignored.
+                browser::serve(db_path)
+            }
+        }
+        "pair" => {
+            sandbox::needs_network("Pairing")?;
+            let db_path = Path::new(require_arg(&args, 1, "a database path"));
+            let (_, _, password) = unlock_password(db_path, true)?;
+            pairing::pair(db_path, &password, args.get(2).map(String::as_str))
+        }
+        "unpair" => pairing::unpair(require_arg(&args, 1, "a device name")),
+        "grant" => {
+            let minutes = match args.get(1) {
+                Some(minutes) => minutes.parse()?,
+                None => GRANT_MINUTES,
+            };
+            agent::grant(minutes)?;
+            info!("{}", tr!("granted", minutes = minutes));
+            Ok(())
+        }
+        "get" | "otp" => {
+            let query = query_arg(&args)?;
+            let value = agent::request(&args[0], query)?;
+            println!("{}", value.expose());
+            Ok(())
+        }
+        "open" => {
+            let query = query_arg(&args)?;
+            let url = agent::request("url", query)?;
+            let password = agent::request("get", query)?;
+            copy_to_clipboard(password.expose())?;
+            info!(
+                "{}",
+                tr!("copied-field", field = i18n::name("field", "Password"))
+            );
+            open_url(url.expose())
+        }
+        "show" => {
+            let to_stdout = take_flag(&mut args, "--stdout");
+            let query = query_arg(&args)?;
+            let password = agent::request("get", query)?;
+            if to_stdout {
+                println!("{}", password.expose());
+                Ok(())
+            } else {
+                show::on_screen(&tr!("the-password"), password.expose())
+            }
+        }
+        "field" => {
+            let to_stdout = take_flag(&mut args, "--stdout");
+            let query = require_arg(&args, 1, "an entry query");
+            let field = require_arg(&args, 2, "a field name");
+            let value = agent::request_field(query, field)?;
+            if to_stdout {
+                println!("{}", value.expose());
+            } else {
+                copy_to_clipboard(value.expose())?;
+                info!("{}", tr!("copied-field", field = field));
+            }
+            Ok(())
+        }
+        "url" => {
+            let type_it = take_flag(&mut args, "--autotype");
+            let url = require_arg(&args, 1, "a URL");
+            let query = format!("url:{}", url);
+            println!("> {}", agent::request("path", &query)?.expose());
+            if type_it {
+                let seq = agent::request("autotype", &query)?;
+                autotype::run(&autotype::parse(seq.expose())?)
+            } else {
+                let password = agent::request("get", &query)?;
+                copy_to_clipboard(password.expose())?;
+                info!(
+                    "{}",
+                    tr!("copied-field", field = i18n::name("field", "Password"))
+                );
+                Ok(())
+            }
+        }
+        "menu" => menu::run(false),
+        "fav" => menu::run(true),
+        "ssh-add" => {
+            let lifetime = take_opt(&mut args, "--lifetime");
+            let query = query_arg(&args)?;
+            let (key, passphrase) = ssh::decode(agent::request("ssh-key", query)?.expose())?;
+            ssh::add(key.expose(), passphrase.expose(), lifetime.as_deref())?;
+            info!("{}", tr!("ssh-key-added"));
+            Ok(())
+        }
+        "exec" => {
+            let mut vars = vec![];
+            while let Some(spec) = take_opt(&mut args, "--env") {
+                vars.push(exec::parse_var(&spec)?);
+            }
+            if vars.is_empty() {
+                vars = exec::DEFAULT_VARS
+                    .iter()
+                    .map(|(name, field)| (name.to_string(), field.to_string()))
+                    .collect();
+            }
+            let query = require_arg(&args, 1, "an entry query");
+            let status = exec::run(query, &vars, &command)?;
+            exit(status.code().unwrap_or(1));
+        }
+        "autotype" => {
+            let pick = take_flag(&mut args, "--pick");
+            let query = query_arg(&args)?;
+            let window = if pick { Some(pick_window()?) } else { None };
+            let seq = agent::request("autotype", query)?;
+            let actions = autotype::parse(seq.expose())?;
+            if let Some(window) = window {
+                autotype::focus(&window)?;
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            autotype::run(&actions)
+        }
+        _ => match copy_query {
+            Some(query) => {
+                let db_paths: Vec<PathBuf> =
+                    args.iter().map(|arg| config::database_path(arg)).collect();
+                quick::copy(&db_paths, &query)
+            }
+            None => interactive(&args),
+        },
     }
 }
 
-/// Get the last `n` chars of a string.
-fn last_n_chars(s: &str, n: usize) -> &str {
-    let idx = s.char_indices().nth_back(n - 1).unwrap().0;
-    &s[idx..]
+/// Remove `flag` from the arguments, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
 }
 
-/// Try loading the full password from the quick password.
-/// There is only one chance to input the correct quick password,
-/// otherwise the cached password is destroyed.
-fn try_load_pass() -> Result<Option<String>> {
-    let pw_path = Path::new(PW_CACHE);
-    let pass = if pw_path.exists() {
-        let mut file = File::open(pw_path)?;
-        let qpw = Password::new("Quick Pass:")
-            .with_display_toggle_enabled()
-            .with_display_mode(PasswordDisplayMode::Masked)
-            .with_formatter(&|_| String::from("🔑"))
-            .without_confirmation()
-            .prompt()?;
+/// Remove `opt` and its value from the arguments, returning the value.
+fn take_opt(args: &mut Vec<String>, opt: &str) -> Option<String> {
+    let idx = args.iter().position(|arg| arg == opt)?;
+    args.remove(idx);
+    (idx < args.len()).then(|| args.remove(idx))
+}
 
-        let cocoon = Cocoon::new(qpw.as_bytes());
-        if let Ok(pass) = cocoon.parse(&mut file) {
-            let pass = std::str::from_utf8(&pass)?;
-            Some(pass.to_string())
-        } else {
-            println!("! Quick Pass was incorrect.");
-            std::fs::remove_file(pw_path)?;
-            None
-        }
-    } else {
-        None
+/// The file paths (e.g. the database) given in the arguments.
+fn path_args(args: &[String]) -> Vec<&str> {
+    let paths = match args[0].as_str() {
+        "agent" | "secret-service" | "list" | "merge" | "receive" | "recovery" | "recover"
+        | "restore-backup" | "init" | "kdf" | "upgrade" | "stats" | "dedupe" | "diff" => &args[1..],
+        "changelog" | "log" | "check" | "shard" | "expiring" | "pair" | "browser-host"
+        | "serve" | "rotate" | "compact" | "passkeys" | "add" => &args[1..args.len().min(2)],
+        "apply" => &args[1..args.len().min(3)],
+        "share" => &args[1..args.len().min(2)],
+        "file" => &args[2.min(args.len())..args.len().min(3)],
+        "export" | "audit" => &args[2..],
+        "export-permission" => &args[1..args.len().min(2)],
+        "import" => &args[args.len().min(2)..],
+        "get" | "otp" | "open" | "show" | "autotype" | "menu" | "ssh-add" | "grant" | "unpair"
+        | "gen" | "url" | "exec" | "fav" | "field" | "lock" | "unlock" | "status" => &[],
+        _ => args,
     };
-    Ok(pass)
+    paths.iter().map(String::as_str).collect()
 }
 
-/// Cache the full password, locked by the quick password;
-fn cache_pass(password: &str) -> Result<()> {
-    let quick_pw = last_n_chars(password, 3);
-    let mut cocoon = Cocoon::new(quick_pw.as_bytes());
-    let mut pw_cache = File::create(PW_CACHE)?;
-    cocoon
-        .dump(password.as_bytes().to_vec(), &mut pw_cache)
-        .unwrap();
+/// Add imported entries to the database and save it.
+fn import_into(db_path: &Path, imported: Vec<import::Imported>) -> Result<()> {
+    let _lock = lock_db(db_path)?;
+    let (mut db, key) = unlock(db_path)?;
+    import::add_entries(&mut db, imported);
+    info!("{}", tr!("saving"));
+    save_db(&mut db, &key, db_path)?;
+    info!("{}", tr!("saved"));
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.is_empty() {
-        println!("Please provide an database path.");
-        exit(1);
+/// Get the positional argument at `idx`, or exit if it's missing.
+fn require_arg<'a>(args: &'a [String], idx: usize, what: &str) -> &'a str {
+    match args.get(idx) {
+        Some(arg) => arg,
+        None => {
+            println!("Please provide {}.", what);
+            exit(1);
+        }
     }
+}
 
-    let db_path = Path::new(&args[0]);
+/// The entry query: the argument after the command,
+/// or else the first line piped into stdin.
+fn query_arg(args: &[String]) -> Result<&str> {
+    match args.get(1) {
+        Some(query) => Ok(query),
+        None => match tty::piped_line()? {
+            Some(query) => Ok(query),
+            None => Ok(require_arg(args, 1, "an entry query")),
+        },
+    }
+}
 
-    let (mut db, key) = if let Some(pass) = try_load_pass()? {
-        let key = DatabaseKey::new().with_password(&pass);
-        let mut file = File::open(db_path)?;
-        let db = Database::open(&mut file, key.clone()).expect("Cache password is correct");
-        (db, key)
-    } else {
-        loop {
-            let pass = Password::new("Password:")
-                .with_display_toggle_enabled()
-                .with_display_mode(PasswordDisplayMode::Masked)
-                .with_formatter(&|_| String::from("🔑"))
-                .without_confirmation()
-                .prompt()?;
+/// An entry in the combined picker, labeled
+/// by its vault when there are several.
+struct VaultEntry<'a> {
+    /// Index of the vault it's in.
+    idx: usize,
+    /// Path of the group it's in.
+    group: Vec<String>,
+    /// How often and recently it was used, see `usage`.
+    frecency: f64,
+    /// The notes in lowercase, if they're searched too (see `search_notes`).
+    notes: Option<Zeroizing<String>>,
+    /// The label without colors, for searching. Made once
+    /// rather than on every keystroke, for large databases.
+    label: String,
+    entry: Entry<'a>,
+}
+impl VaultEntry<'_> {
+    /// E.g. `Work/Servers/db01 — root`, so entries with the same
+    /// title in different groups can be told apart.
+    fn label(
+        vault: Option<&str>,
+        group: &[String],
+        entry: &Entry,
+        breached: &hibp::Breached,
+    ) -> String {
+        let mut path = group.to_vec();
+        path.push(entry.to_string());
+        let mut title = path.join("/");
+        if let Some(username) = entry.username().filter(|u| !u.is_empty()) {
+            title.push_str(&format!(" — {}", username));
+        }
+        let title = match icons::glyph(entry.0) {
+            Some(glyph) => format!("{} {}", glyph, title),
+            None => title,
+        };
+        let title = if favorites::is_favorite(entry.0) {
+            format!("★ {}", title)
+        } else {
+            title
+        };
+        let mut label = match vault {
+            Some(vault) => format!("[{}] {}", vault, title),
+            None => title,
+        };
+        let warnings = entry.warnings(breached);
+        if !warnings.is_empty() {
+            label.push_str(&format!("  ⚠ {}", warnings.join(", ")));
+        }
+        label
+    }
+}
+impl Display for VaultEntry<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", colors::paint(self.entry.0, &self.label))
+    }
+}
 
-            let key = DatabaseKey::new().with_password(&pass);
-            let mut file = File::open(db_path)?;
-            match Database::open(&mut file, key.clone()) {
-                Ok(db) => {
-                    cache_pass(&pass)?;
-                    break (db, key);
-                }
-                Err(err) => {
-                    println!("! Failed to open database. Wrong password?");
-                    println!(">   {:?}", err);
-                }
-            }
+/// Sort picker entries in the given order.
+fn sort_entries(entries: &mut [&VaultEntry], sort: EntrySort) {
+    let title = |e: &&VaultEntry| e.entry.to_string().to_lowercase();
+    match sort {
+        // Entries are collected in database order.
+        EntrySort::Database => {}
+        EntrySort::Title => entries.sort_by_cached_key(title),
+        EntrySort::Modified => entries
+            .sort_by_cached_key(|e| Reverse(e.entry.0.times.get_last_modification().copied())),
+        EntrySort::Group => entries.sort_by_cached_key(|e| (e.group.join("/"), title(e))),
+        EntrySort::Frecency => entries.sort_by(|a, b| b.frecency.total_cmp(&a.frecency)),
+    }
+    // Favorites first, keeping the order among them.
+    entries.sort_by_key(|e| !favorites::is_favorite(e.entry.0));
+}
+
+/// A row of the entry picker.
+enum PickerRow<'a, 'b> {
+    /// Selecting it changes the order.
+    Sort(EntrySort),
+    Entry(&'b VaultEntry<'a>),
+}
+impl Display for PickerRow<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PickerRow::Sort(sort) => write!(
+                f,
+                "⇅ {}",
+                tr!("sorted-by", order = i18n::name("order", &sort.to_string()))
+            ),
+            PickerRow::Entry(entry) => write!(f, "{}", entry),
         }
-    };
+    }
+}
+
+/// Pick an entry to use (rather than change),
+/// with its references resolved (see `reference`).
+fn pick_entry_to_use<'a>(
+    vaults: &'a [Vault],
+    scope: Option<usize>,
+    filter: &str,
+    sort: &mut EntrySort,
+) -> Result<(usize, Cow<'a, KEntry>)> {
+    let (idx, entry) = pick_vault_entry(vaults, scope, filter, sort)?;
+    Ok((idx, reference::resolved(&vaults[idx].db, entry.0)))
+}
 
+/// Pick an entry from the vault in `scope`, or any of them if `None`,
+/// returning the vault's index too.
+/// The search starts out as `filter` (e.g. piped in).
+/// The entries are listed in the `sort` order, which can be changed
+/// from the picker (and is kept for the next pick).
+fn pick_vault_entry<'a>(
+    vaults: &'a [Vault],
+    scope: Option<usize>,
+    filter: &str,
+    sort: &mut EntrySort,
+) -> Result<(usize, Entry<'a>)> {
+    let labeled = vaults.len() > 1 && scope.is_none();
+    let mut options = vec![];
+    let in_scope = vaults
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| scope.is_none_or(|scope| scope == *i));
+    for (i, vault) in in_scope {
+        let scores = usage::scores(&vault.path).unwrap_or_else(|err| {
+            warn!("{}", tr!("usage-read-failed", error = err));
+            HashMap::new()
+        });
+        let breached = hibp::breached(&vault.path).unwrap_or_else(|err| {
+            warn!("{}", tr!("breach-audit-read-failed", error = err));
+            Default::default()
+        });
+        let name = labeled.then(|| vault.name());
+        for (group, entry) in filter::get().entries(&vault.db) {
+            options.push(VaultEntry {
+                label: VaultEntry::label(name.as_deref(), &group, &Entry(entry), &breached),
+                idx: i,
+                group,
+                frecency: scores.get(entry.get_uuid()).copied().unwrap_or(0.),
+                notes: config::get()
+                    .search_notes
+                    .then(|| {
+                        Entry(entry)
+                            .notes()
+                            .map(|n| Zeroizing::new(n.to_lowercase()))
+                    })
+                    .flatten(),
+                entry: Entry(entry),
+            });
+        }
+    }
     loop {
-        let action = Select::new(">", vec!["Search", "Edit", "New", "Quit"]).prompt()?;
-        match action {
-            "Quit" => {
-                break;
+        let mut sorted: Vec<&VaultEntry> = options.iter().collect();
+        sort_entries(&mut sorted, *sort);
+        let mut rows = vec![PickerRow::Sort(*sort)];
+        rows.extend(sorted.into_iter().map(PickerRow::Entry));
+        // The entries whose username or URL contain the search, per vault,
+        // found once per search rather than for each entry.
+        let found: RefCell<(String, Vec<HashSet<Uuid>>)> = RefCell::default();
+        let found_by_index = |input: &str, option: &VaultEntry| {
+            let mut found = found.borrow_mut();
+            if found.0 != input || found.1.is_empty() {
+                *found = (
+                    input.to_string(),
+                    vaults.iter().map(|v| v.index.search(input)).collect(),
+                );
             }
-            "Search" => {
-                let entry = pick_entry(&db)?;
-                view_entry(&entry)?
+            found.1[option.idx].contains(option.entry.0.get_uuid())
+        };
+        let choice = prompt::select(&tr!("select-entry"), rows)
+            .with_scorer(&|input, row, _, idx| match row {
+                // Only offered while not searching, at the top.
+                PickerRow::Sort(_) => input.is_empty().then_some(i64::MAX),
+                PickerRow::Entry(option) => {
+                    Select::<PickerRow>::DEFAULT_SCORER(input, row, &option.label, idx)
+                        // Then entries with it in their username or URL,
+                        .or_else(|| found_by_index(input, option).then_some(i64::MIN + 1))
+                        .or_else(|| {
+                            // and then those mentioning it in their notes.
+                            let notes = option.notes.as_ref()?;
+                            notes.contains(&input.to_lowercase()).then_some(i64::MIN)
+                        })
+                }
+            })
+            .with_starting_filter_input(filter)
+            .prompt()?;
+        let option = match choice {
+            PickerRow::Sort(current) => {
+                *sort = current.next();
+                continue;
             }
-            "New" => {
-                let entry = new_entry()?;
+            PickerRow::Entry(option) => option,
+        };
+        let entry = &option.entry;
+        let path = &vaults[option.idx].path;
+        if !protect::is_required(entry.0) || protect::verify(path)? {
+            if let Err(err) = usage::record(path, entry.0.get_uuid()) {
+                warn!("{}", tr!("usage-record-failed", error = err));
+            }
+            vaults[option.idx].accessed(entry.0.get_uuid());
+            return Ok((option.idx, Entry(entry.0)));
+        }
+    }
+}
 
-                view_entry(&Entry(&entry))?;
-                let confirm = Confirm::new("Ok?").with_default(true).prompt()?;
+/// Pick one of the vaults open for changes, only asking
+/// if there are several and the vault in `scope` isn't one.
+fn pick_writable_vault(vaults: &[Vault], scope: Option<usize>, message: &str) -> Result<usize> {
+    if let Some(scope) = scope.filter(|i| vaults[*i].lock.is_some()) {
+        return Ok(scope);
+    }
+    let writable: Vec<usize> = (0..vaults.len())
+        .filter(|i| vaults[*i].lock.is_some())
+        .collect();
+    if writable.len() > 1 {
+        let names: Vec<String> = writable.iter().map(|i| vaults[*i].name()).collect();
+        Ok(writable[prompt::select(message, names).raw_prompt()?.index])
+    } else {
+        Ok(writable[0])
+    }
+}
 
-                if confirm {
-                    println!("> Saving...");
-                    db.root.add_child(entry);
-                    save_db(&db, key.clone(), db_path)?;
-                    println!("> Saved.");
-                }
-            }
-            "Edit" => {
-                let entry = pick_entry(&db)?;
-                view_entry(&entry)?;
+fn interactive(db_paths: &[String]) -> Result<()> {
+    let filter = tty::piped_line()?.unwrap_or("");
+    let names = db_paths
+        .iter()
+        .map(|path| {
+            let path = config::database_path(path);
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    let title = title::Title::new(names);
+    let mut vaults = vec![];
+    for (i, path) in db_paths.iter().enumerate() {
+        // Only the first database uses the quick password.
+        if let Some(vault) = Vault::open(&config::database_path(path), i == 0)? {
+            vaults.push(vault);
+        }
+    }
+    if vaults.is_empty() {
+        return Ok(());
+    }
+    title.unlocked();
+    idle::watch(config::get().idle_lock_minutes);
 
-                let uuid = entry.0.get_uuid().as_u128();
-                let entry =
-                    get_entry_mut(&mut db, uuid).expect("We just checked that the entry exists");
+    let writable = vaults.iter().any(|v| v.lock.is_some());
+    let actions = session_actions(|action| {
+        if WRITE_ACTIONS.contains(&action) {
+            writable
+        } else if action == "Switch database" {
+            vaults.len() > 1
+        } else {
+            true
+        }
+    });
 
-                edit_entry(entry)?;
-                save_db(&db, key.clone(), db_path)?;
-            }
-            _ => {
-                unreachable!();
+    // The vault entries are picked from, or `None` for all of them.
+    let mut scope: Option<usize> = None;
+    let mut sort = config::get().entry_sort;
+    loop {
+        // Save before waiting for input, so changes made by an action
+        // are written once and nothing is pending while idle.
+        for vault in &mut vaults {
+            flush_or_retry(vault)?;
+        }
+        let message = match scope {
+            Some(idx) => format!("[{}] >", vaults[idx].name()),
+            None => ">".to_string(),
+        };
+        let labels = actions
+            .iter()
+            .map(|action| i18n::Label("action", action))
+            .collect();
+        let action = prompt::select(&message, labels).prompt()?.1;
+        // The prompt may have been open a while.
+        for vault in &mut vaults {
+            if let Err(err) = vault.reload_if_changed() {
+                warn!(
+                    "{}",
+                    tr!("reload-failed", database = vault.name(), error = err)
+                );
             }
         }
+        if action == "Quit" {
+            break;
+        }
+        match run_action(action, &mut vaults, &mut scope, &mut sort, filter) {
+            Ok(()) => {}
+            // Ctrl-C ends the session, as from the menu.
+            Err(err) if is_interrupted(&err) => break,
+            // E.g. Esc, back to the menu.
+            Err(err) if is_cancelled(&err) => {}
+            Err(err) => warn!("{}", err),
+        }
     }
 
     Ok(())
 }
 
-fn get_entry_mut(db: &mut Database, uuid: u128) -> Option<&mut KEntry> {
-    _find_entry_mut(&mut db.root, uuid)
-}
-fn _find_entry_mut(group: &mut Group, uuid: u128) -> Option<&mut KEntry> {
-    for ch in &mut group.children {
-        match ch {
-            Node::Group(group) => {
-                if let Some(entry) = _find_entry_mut(group, uuid) {
-                    return Some(entry);
-                }
-            }
-            Node::Entry(entry) => {
-                if entry.get_uuid().as_u128() == uuid {
-                    return Some(entry);
+/// The interactive session's actions, in their default order.
+const ACTIONS: &[&str] = &[
+    "Search",
+    "Show password",
+    "Show QR",
+    "View notes in editor",
+    "Open URL",
+    "Auto-type",
+    "Auto-type into window",
+    "Check reachable",
+    "Edit",
+    "Bulk edit",
+    "New",
+    "Duplicate",
+    "Rename/move group",
+    "Change master key",
+    "Undo",
+    "Switch database",
+    "Reveal session",
+    "Quit",
+];
+
+/// Actions only offered when a database is open for changes.
+const WRITE_ACTIONS: &[&str] = &[
+    "Edit",
+    "Bulk edit",
+    "New",
+    "Duplicate",
+    "Rename/move group",
+    "Change master key",
+    "Undo",
+];
+
+/// The actions to offer, in the configured order (see `actions` in the
+/// config), leaving out those not `available`. "Quit" is always offered.
+fn session_actions(available: impl Fn(&str) -> bool) -> Vec<&'static str> {
+    let configured = &config::get().actions;
+    let mut actions: Vec<&'static str> = if configured.is_empty() {
+        ACTIONS.to_vec()
+    } else {
+        configured
+            .iter()
+            .filter_map(|name| {
+                let action = ACTIONS
+                    .iter()
+                    .find(|action| action.eq_ignore_ascii_case(name));
+                if action.is_none() {
+                    warn!("{}", tr!("unknown-action", action = name));
                 }
+                action.copied()
+            })
+            .collect()
+    };
+    actions.retain(|action| available(action));
+    if !actions.contains(&"Quit") {
+        actions.push("Quit");
+    }
+    actions
+}
+
+/// Save the vault's changes, offering to try again if that fails (e.g. the
+/// disk is full). Otherwise the changes stay pending, and saving is tried
+/// again after the next action.
+fn flush_or_retry(vault: &mut Vault) -> Result<()> {
+    while let Err(err) = vault.flush() {
+        if !is_cancelled(&err) {
+            warn!(
+                "{}",
+                tr!("save-failed", database = vault.name(), error = err)
+            );
+            if Confirm::new(&tr!("try-again"))
+                .with_default(true)
+                .prompt()?
+            {
+                continue;
             }
         }
+        warn!("{}", tr!("changes-pending"));
+        break;
     }
-    None
+    Ok(())
 }
 
-fn save_db(db: &Database, key: DatabaseKey, path: &Path) -> Result<()> {
-    // Backup file.
-    std::fs::copy(path, path.with_file_name(".backup.kdbx"))?;
+/// Run an action from the interactive session's menu. Errors end the
+/// action, not the session (see `interactive`).
+fn run_action(
+    action: &str,
+    vaults: &mut [Vault],
+    scope: &mut Option<usize>,
+    sort: &mut EntrySort,
+    filter: &str,
+) -> Result<()> {
+    match action {
+        "Search" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            view_entry(&entry)?;
+            activity::record(&vaults[idx].path, "viewed", &entry.to_string())?;
+            copy_fields(&entry)?;
+        }
+        "Show password" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            match entry.password() {
+                Some(pw) => {
+                    show::on_screen(&tr!("the-password"), pw)?;
+                    activity::record(&vaults[idx].path, "shown", &entry.to_string())?;
+                }
+                None => warn!("{}", tr!("no-password", entry = entry)),
+            }
+        }
+        "Show QR" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            qr::show_entry(&entry)?;
+            activity::record(&vaults[idx].path, "shown", &entry.to_string())?;
+        }
+        "View notes in editor" => {
+            let (idx, entry) = pick_vault_entry(vaults, *scope, filter, sort)?;
+            let Some(notes) = entry.notes().filter(|notes| !notes.is_empty()) else {
+                warn!("{}", tr!("no-notes", entry = entry));
+                return Ok(());
+            };
+            let notes = Zeroizing::new(notes.to_string());
+            let uuid = entry.0.uuid;
+            let title = entry.to_string();
+            let vault = &mut vaults[idx];
+            let writable = vault.lock.is_some()
+                && Confirm::new(&tr!("edit-notes"))
+                    .with_default(false)
+                    .with_help_message(&tr!("edit-notes-help"))
+                    .prompt()?;
+            let edited = notes::open_in_editor(&notes, writable)?;
+            activity::record(&vault.path, "viewed", &title)?;
+            if writable && *edited != *notes {
+                let entry = vault
+                    .index
+                    .entry_mut(&mut vault.db, &uuid)
+                    .expect("We just checked that the entry exists");
+                let before = entry.clone();
+                entry.fields.insert(
+                    "Notes".to_string(),
+                    Value::Protected(edited.as_bytes().into()),
+                );
+                entry
+                    .history
+                    .get_or_insert_with(Default::default)
+                    .add_entry(before);
+                entry.times.set_last_modification(Times::now());
+                vault.changed();
+            }
+        }
+        "Switch database" => {
+            let mut names = vec![tr!("all-databases")];
+            names.extend(vaults.iter().map(Vault::name));
+            let choice = prompt::select(&tr!("search-in"), names).raw_prompt()?;
+            *scope = choice.index.checked_sub(1);
+        }
+        "Reveal session" => {
+            if reveal::remaining().is_some() {
+                reveal::end();
+            } else {
+                reveal::start(&vaults[0].path)?;
+            }
+        }
+        "Open URL" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            open_entry(&entry)?;
+            activity::record(&vaults[idx].path, "opened", &entry.to_string())?;
+        }
+        "Auto-type" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            autotype_entry(&entry)?;
+            activity::record(&vaults[idx].path, "typed", &entry.to_string())?;
+        }
+        "Auto-type into window" => {
+            let (idx, entry) = pick_entry_to_use(vaults, *scope, filter, sort)?;
+            let entry = Entry(&entry);
+            autotype_entry_into_window(&entry)?;
+            activity::record(&vaults[idx].path, "typed", &entry.to_string())?;
+        }
+        "New" => {
+            let idx = pick_writable_vault(vaults, *scope, &tr!("add-to"))?;
+            const BLANK: &str = "Login";
+            let kinds: Vec<&str> = [BLANK]
+                .into_iter()
+                .chain(templates::TEMPLATES.iter().map(|t| t.name))
+                .collect();
+            let kind = prompt::select(&tr!("new-kind"), kinds).raw_prompt()?;
+            let usernames = known_usernames(&vaults[idx].db);
+            let entry = match kind.index {
+                0 => new_entry(&usernames)?,
+                i => templates::new_entry(&templates::TEMPLATES[i - 1], &usernames)?,
+            };
 
-    // Write to temporary file first.
-    const TEMP_PATH: &str = "/tmp/.pass.kdbx";
-    let mut file = File::create(TEMP_PATH)?;
-    db.save(&mut file, key.clone())?;
+            view_entry(&Entry(&entry))?;
+            let confirm = Confirm::new(&tr!("ok")).with_default(true).prompt()?;
 
-    // Then copy to the target file.
-    std::fs::copy(TEMP_PATH, path)?;
-    Ok(())
-}
+            if confirm {
+                let vault = &mut vaults[idx];
+                vault.db.root.add_child(entry);
+                vault.changed();
+            }
+        }
+        "Duplicate" => {
+            let (idx, entry) = pick_vault_entry(vaults, *scope, filter, sort)?;
+            if vaults[idx].lock.is_none() {
+                warn!("{}", tr!("read-only", database = vaults[idx].name()));
+                return Ok(());
+            }
+            let uuid = entry.0.uuid;
+            let copy = duplicate_entry(entry.0)?;
+            let group_path = entries_with_paths(&vaults[idx].db.root)
+                .into_iter()
+                .find(|(_, e)| e.uuid == uuid)
+                .map(|(path, _)| path)
+                .unwrap_or_default();
 
-fn pick_entry(db: &Database) -> Result<Entry> {
-    let entries: Vec<_> = db
-        .root
-        .into_iter()
-        .filter_map(|node| match node {
-            NodeRef::Group(_) => None,
-            NodeRef::Entry(e) => Some(Entry(e)),
-        })
-        .collect();
+            view_entry(&Entry(&copy))?;
+            let confirm = Confirm::new(&tr!("ok")).with_default(true).prompt()?;
+            if confirm {
+                let vault = &mut vaults[idx];
+                import::get_or_create_group(&mut vault.db.root, &group_path).add_child(copy);
+                vault.changed();
+            }
+        }
+        "Check reachable" => {
+            let (_, entry) = pick_vault_entry(vaults, *scope, filter, sort)?;
+            check_reachable(&entry);
+        }
+        "Bulk edit" => {
+            let idx = pick_writable_vault(vaults, *scope, &tr!("edit-in"))?;
+            let vault = &mut vaults[idx];
+            if bulk::run(&mut vault.db)? {
+                vault.changed();
+            }
+        }
+        "Rename/move group" => {
+            let idx = pick_writable_vault(vaults, *scope, &tr!("group-in"))?;
+            let vault = &mut vaults[idx];
+            if groups::rename_or_move(&mut vault.db)? {
+                vault.changed();
+            }
+        }
+        "Change master key" => {
+            let idx = pick_writable_vault(vaults, *scope, &tr!("change-key-of"))?;
+            let vault = &mut vaults[idx];
+            vault.key = init::change_key(&mut vault.db, &vault.path)?;
+        }
+        "Undo" => {
+            // The vault saved most recently.
+            let last = (0..vaults.len())
+                .filter_map(|i| Some((vaults[i].undo.last()?.0, i)))
+                .max();
+            let Some((time, idx)) = last else {
+                info!("{}", tr!("nothing-to-undo"));
+                return Ok(());
+            };
+            let vault = &mut vaults[idx];
+            let confirm = Confirm::new(&tr!(
+                "undo-confirm",
+                database = vault.name(),
+                time = time.format("%H:%M:%S")
+            ))
+            .with_default(true)
+            .prompt()?;
+            if confirm {
+                vault.undo()?;
+            }
+        }
+        "Edit" => {
+            let (idx, entry) = pick_vault_entry(vaults, *scope, filter, sort)?;
+            view_entry(&entry)?;
+            activity::record(&vaults[idx].path, "viewed", &entry.to_string())?;
+            let uuid = *entry.0.get_uuid();
 
-    let entry = Select::new("Select entry", entries)
-        .with_page_size(15)
-        .prompt()?;
+            let vault = &mut vaults[idx];
+            if vault.lock.is_none() {
+                warn!("{}", tr!("read-only", database = vault.name()));
+                return Ok(());
+            }
+            let entry = vault
+                .index
+                .entry_mut(&mut vault.db, &uuid)
+                .expect("We just checked that the entry exists");
 
-    Ok(entry)
+            edit_entry(entry)?;
+            vault.changed();
+        }
+        _ => {
+            unreachable!();
+        }
+    }
+    Ok(())
 }
 
 fn view_entry(entry: &Entry) -> Result<()> {
+    println!(
+        "> {}",
+        colors::paint(
+            entry.0,
+            &format!("{}  ({})", entry, icons::describe(entry.0))
+        )
+    );
+    if entry.0.foreground_color.is_some() || entry.0.background_color.is_some() {
+        let color = |color: &Option<_>| color.as_ref().map_or("none".to_string(), colors::hex);
+        println!(
+            "> {} {} on {}",
+            theme::label(&tr!("label-colors")),
+            color(&entry.0.foreground_color),
+            color(&entry.0.background_color)
+        );
+    }
     if let Some(username) = entry.username() {
-        println!("> Username: {}", username);
+        println!("> {} {}", theme::label(&tr!("label-username")), username);
     }
     if let Some(url) = entry.url() {
-        println!("> Url: {}", url);
+        println!("> {} {}", theme::label(&tr!("label-url")), url);
+    }
+    for (_, url) in domain::extra_urls(entry.0) {
+        println!("> {} {}", theme::label(&tr!("label-other-url")), url);
+    }
+    if let Some(host) = host::Host::of(entry.0) {
+        println!("> {} {}", theme::label(&tr!("label-host")), host);
+    }
+    if let Some(passkey) = passkey::Passkey::of(entry.0) {
+        println!(
+            "> {} {} ({})",
+            theme::label(&tr!("label-passkey")),
+            passkey.relying_party,
+            passkey.username
+        );
+    }
+    let stored = files::names(entry.0);
+    if !stored.is_empty() {
+        println!(
+            "> {} {}",
+            theme::label(&tr!("label-files")),
+            stored.join(", ")
+        );
+    }
+    for (name, value) in custom_fields(entry.0) {
+        let shown = match value {
+            Value::Unprotected(text) => text.lines().next().unwrap_or("").to_string(),
+            _ => theme::secret("••••••").to_string(),
+        };
+        println!("> {} {}", theme::label(&format!("{}:", name)), shown);
+    }
+    if protect::is_required(entry.0) {
+        println!("> {}", tr!("requires-master-password"));
+    }
+    if let Some(warning) = entry.expiry_warning() {
+        warn!("{}", tr!("rotate-password", warning = warning));
+    } else if let Some(expiry) = entry.expiry() {
+        println!(
+            "> {} {}",
+            theme::label(&tr!("label-expires")),
+            expiry.format("%Y-%m-%d")
+        );
+    }
+    let local = |time: Option<&NaiveDateTime>| {
+        time.map(|t| {
+            t.and_utc()
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+    };
+    if let Some(created) = local(entry.0.times.get_creation()) {
+        println!("> {} {}", theme::label(&tr!("label-created")), created);
+    }
+    if let Some(modified) = local(entry.0.times.get_last_modification()) {
+        println!("> {} {}", theme::label(&tr!("label-modified")), modified);
+    }
+    if let Some(accessed) = local(entry.0.times.get_last_access()) {
+        println!(
+            "> {} {}",
+            theme::label(&tr!("label-last-accessed")),
+            accessed
+        );
     }
     if let Some(notes) = entry.notes() {
         println!("-- Notes ----------------");
-        println!("{}", notes);
+        println!("{}", notes::expand(notes, entry.0));
         println!("-------------------------");
     }
 
     if let Some(pw) = entry.password() {
-        let opts = Options::new();
-        opts.copy(
-            Source::Bytes(pw.to_string().into_bytes().into()),
-            MimeType::Autodetect,
-        )?;
-        println!("> Copied to clipboard!");
+        let strength = Strength::of(pw);
+        if strength.is_very_weak() {
+            warn!("{}", tr!("weak-password", strength = strength));
+        }
+        if let Some(left) = reveal::remaining() {
+            println!(
+                "> {} {}  (reveal session, {} min left)",
+                theme::label(&tr!("label-password")),
+                pw,
+                left.as_secs().div_ceil(60)
+            );
+        }
+        copy_from_entry(entry, pw)?;
+        info!("{}", tr!("copied"));
     }
 
     Ok(())
 }
 
-fn new_entry() -> Result<KEntry> {
-    let mut entry = KEntry::new();
-    let mut edit = EditEntry(&mut entry);
-
-    edit.set_title()?;
-    edit.set_username()?;
-    edit.set_notes()?;
-    edit.set_password()?;
-
-    Ok(entry)
+/// A field to pick for copying, with a preview of its value
+/// (unless it's protected) to help tell similar fields apart.
+struct FieldOption<'a> {
+    name: &'a str,
+    preview: Option<String>,
+}
+impl<'a> FieldOption<'a> {
+    fn new(name: &'a str, value: Option<&Value>) -> Self {
+        let preview = match value {
+            Some(Value::Unprotected(value)) => {
+                let line = value.lines().next().unwrap_or_default();
+                Some(match line.char_indices().nth(40) {
+                    Some((idx, _)) => format!("{}…", &line[..idx]),
+                    None => line.to_string(),
+                })
+            }
+            Some(Value::Protected(_)) => Some("••••••".to_string()),
+            _ => None,
+        };
+        FieldOption { name, preview }
+    }
+}
+impl Display for FieldOption<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.preview.as_deref().filter(|p| !p.is_empty()) {
+            Some(preview) => write!(f, "{}  {}", i18n::name("field", self.name), preview),
+            None => write!(f, "{}", i18n::name("field", self.name)),
+        }
+    }
 }
 
-fn edit_entry(entry: &mut KEntry) -> Result<()> {
-    let mut edit = EditEntry(entry);
+/// After viewing an entry, keep offering its fields to copy,
+/// e.g. the username, then the password, then a custom field.
+/// Typing filters the fields (fuzzily), for entries with many of them.
+fn copy_fields(entry: &Entry) -> Result<()> {
+    let fields = &entry.0.fields;
+    let mut options = vec![];
+    if entry.username().is_some() {
+        options.push(FieldOption::new("Username", fields.get("UserName")));
+    }
+    if entry.password().is_some() {
+        options.push(FieldOption::new("Password", fields.get("Password")));
+    }
+    if entry.url().is_some() {
+        options.push(FieldOption::new("URL", fields.get("URL")));
+    }
+    if entry.0.get_raw_otp_value().is_some() {
+        options.push(FieldOption::new("OTP code", None));
+    }
+    if entry.notes().is_some() {
+        options.push(FieldOption::new("Notes", fields.get("Notes")));
+    }
+    options.extend(
+        custom_fields(entry.0)
+            .into_iter()
+            .map(|(name, value)| FieldOption::new(name, Some(value))),
+    );
+    if options.is_empty() {
+        return Ok(());
+    }
+    options.push(FieldOption::new("Done", None));
 
     loop {
-        let action =
-            Select::new(">", vec!["Title", "UserName", "Notes", "Password", "Done"]).prompt()?;
-        match action {
-            "Title" => {
-                edit.set_title()?;
-            }
-            "UserName" => {
-                edit.set_username()?;
-            }
-            "Notes" => {
-                edit.set_notes()?;
-            }
-            "Password" => {
-                edit.set_password()?;
-            }
-            "Done" => {
-                break;
+        let choice = prompt::select(&tr!("copy-field"), options.iter().collect())
+            .with_help_message(&tr!("copy-field-help"))
+            .prompt_skippable()?;
+        let Some(field) = choice.map(|option| option.name) else {
+            break;
+        };
+        let value = match field {
+            "Done" => break,
+            "Username" => entry.username().map(String::from),
+            "Password" => entry.password().map(String::from),
+            "URL" => entry.url().map(String::from),
+            "OTP code" => match entry.0.get_raw_otp_value().map(otp::Totp::parse) {
+                Some(Ok(totp)) => Some(totp.code_now()?),
+                Some(Err(err)) => {
+                    warn!("{}", tr!("invalid-otp", error = err));
+                    continue;
+                }
+                None => None,
+            },
+            "Notes" => entry.notes().map(|notes| notes::expand(notes, entry.0)),
+            name => entry.0.get(name).map(String::from),
+        };
+        if let Some(value) = value.map(Zeroizing::new) {
+            // Protected custom fields are masked when viewing the entry,
+            // so they can be revealed here instead.
+            if matches!(fields.get(field), Some(Value::Protected(_)))
+                && !STANDARD_FIELDS.contains(&field)
+            {
+                let reveal = tr!("reveal");
+                let options = vec![tr!("copy"), reveal.clone()];
+                let choice = prompt::select(&format!("{}:", field), options).prompt_skippable()?;
+                match choice {
+                    Some(choice) if choice == reveal => {
+                        show::on_screen(&tr!("the-field", field = field), &value)?;
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => continue,
+                }
             }
-            _ => unreachable!(),
+            copy_from_entry(entry, &value)?;
+            info!(
+                "{}",
+                tr!("copied-field", field = i18n::name("field", field))
+            );
         }
     }
     Ok(())
 }
+
+/// Copy the entry's password and open its URL in the browser.
+fn open_entry(entry: &Entry) -> Result<()> {
+    let Some(url) = entry.url().filter(|url| !url.is_empty()) else {
+        warn!("{}", tr!("no-url", entry = entry));
+        return Ok(());
+    };
+    if let Some(pw) = entry.password() {
+        copy_from_entry(entry, pw)?;
+        info!(
+            "{}",
+            tr!("copied-field", field = i18n::name("field", "Password"))
+        );
+    }
+    open_url(url)
+}
+
+/// Open a URL with `xdg-open`.
+/// URLs without a scheme (e.g. `example.com`) are assumed to be https.
+fn open_url(url: &str) -> Result<()> {
+    let url = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    };
+    std::process::Command::new("xdg-open")
+        .arg(&url)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|err| anyhow!("Couldn't run xdg-open: {}", err))?;
+    info!("{}", tr!("opened", url = url));
+    Ok(())
+}
+
+/// Auto-type an entry, giving the user
+/// a moment to focus the target window first.
+fn autotype_entry(entry: &Entry) -> Result<()> {
+    for i in (1..=AUTOTYPE_DELAY).rev() {
+        info!("{}", tr!("typing-in", seconds = i));
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    autotype::type_entry(entry.0)?;
+    info!("{}", tr!("typed"));
+    Ok(())
+}
+
+/// Auto-type an entry into a window picked by the user,
+/// rather than whatever happens to be focused.
+fn autotype_entry_into_window(entry: &Entry) -> Result<()> {
+    let window = pick_window()?;
+    autotype::focus(&window)?;
+
+    // Give the compositor a moment to move focus.
+    std::thread::sleep(Duration::from_millis(200));
+    autotype::type_entry(entry.0)?;
+    info!("{}", tr!("typed"));
+    Ok(())
+}
+
+/// Check whether the entry's host accepts connections.
+fn check_reachable(entry: &Entry) {
+    let Some(host) = host::Host::of(entry.0) else {
+        warn!("{}", tr!("no-host", entry = entry));
+        return;
+    };
+    info!("{}", tr!("connecting", host = host));
+    if let Err(err) = sandbox::needs_network("Checking a host") {
+        warn!("{}", err);
+        return;
+    }
+    match host.check() {
+        Ok(elapsed) => info!("{}", tr!("reachable", ms = elapsed.as_millis())),
+        Err(err) => warn!("{}", tr!("unreachable", error = err)),
+    }
+}
+
+fn pick_window() -> Result<autotype::Window> {
+    let windows = autotype::list_windows()?;
+    let window = prompt::select(&tr!("type-into"), windows).prompt()?;
+    Ok(window)
+}
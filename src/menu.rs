@@ -0,0 +1,128 @@
+//! Pick entries with an external launcher (fuzzel, rofi, wofi, dmenu)
+//! so kpass can be used from a global hotkey, without a terminal.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{agent, autotype, config, copy_to_clipboard, i18n};
+
+/// Launchers to try (in order) when none is configured.
+const LAUNCHERS: &[&[&str]] = &[
+    &["fuzzel", "--dmenu", "--prompt", "kpass> "],
+    &["rofi", "-dmenu", "-i", "-p", "kpass"],
+    &["wofi", "--dmenu", "--prompt", "kpass"],
+    &["dmenu", "-i", "-p", "kpass"],
+];
+
+const COPY_PASSWORD: &str = "Copy password";
+const COPY_USERNAME: &str = "Copy username";
+const AUTOTYPE: &str = "Auto-type";
+const COPY_OTP: &str = "Copy OTP";
+const ACTIONS: [&str; 4] = [COPY_PASSWORD, AUTOTYPE, COPY_USERNAME, COPY_OTP];
+
+/// Show the options in the launcher, returning the chosen one
+/// (or `None` if the launcher was dismissed).
+fn choose(options: &[String]) -> Result<Option<String>> {
+    let configured = config::get()
+        .launcher
+        .as_ref()
+        .map(|cmd| cmd.split_whitespace().map(String::from).collect::<Vec<_>>());
+    let candidates: Vec<Vec<String>> = match configured {
+        Some(cmd) => vec![cmd],
+        None => LAUNCHERS
+            .iter()
+            .map(|cmd| cmd.iter().map(|s| s.to_string()).collect())
+            .collect(),
+    };
+
+    for cmd in candidates {
+        let child = Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        child
+            .stdin
+            .take()
+            .expect("Stdin is piped")
+            .write_all(options.join("\n").as_bytes())?;
+
+        // Close it if left open, as a password prompt would time out.
+        let minutes = config::get().prompt_timeout_minutes;
+        let deadline = Instant::now() + Duration::from_secs(minutes * 60);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if minutes > 0 && Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+        let mut choice = String::new();
+        child
+            .stdout
+            .take()
+            .expect("Stdout is piped")
+            .read_to_string(&mut choice)?;
+        let choice = choice.trim_end().to_string();
+        return Ok(Some(choice).filter(|c| status.success() && !c.is_empty()));
+    }
+    Err(anyhow!(
+        "No launcher found; install fuzzel, rofi, wofi or dmenu, or set `launcher` in the config."
+    ))
+}
+
+/// Pick an entry (or only a favorite, see `favorites`)
+/// from the agent's database and act on it.
+pub fn run(only_favorites: bool) -> Result<()> {
+    // Entries are listed (and requested) by their full path,
+    // so entries with the same title in different groups can be told apart.
+    let command = if only_favorites { "favorites" } else { "list" };
+    let paths: Vec<String> = agent::request(command, "")?
+        .expose()
+        .split('\t')
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect();
+    if paths.is_empty() && only_favorites {
+        return Err(anyhow!(
+            "No favorites yet; mark entries as favorites when editing them."
+        ));
+    }
+    let Some(path) = choose(&paths)? else {
+        return Ok(());
+    };
+
+    let labels = ACTIONS.map(|action| i18n::name("menu", action));
+    let Some(action) = choose(&labels)? else {
+        return Ok(());
+    };
+    let action = labels.iter().position(|label| *label == action);
+    match action.map(|i| ACTIONS[i]) {
+        Some(COPY_PASSWORD) => {
+            let password = agent::request("get", &path)?;
+            copy_to_clipboard(password.expose())
+        }
+        Some(COPY_USERNAME) => copy_to_clipboard(agent::request("user", &path)?.expose()),
+        Some(COPY_OTP) => copy_to_clipboard(agent::request("otp", &path)?.expose()),
+        Some(AUTOTYPE) => {
+            let seq = agent::request("autotype", &path)?;
+            autotype::run(&autotype::parse(seq.expose())?)
+        }
+        _ => Ok(()),
+    }
+}
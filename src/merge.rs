@@ -0,0 +1,351 @@
+//! Synchronizing two databases (e.g. copies that diverged on different devices).
+//!
+//! Entries are matched by UUID. If one version is already in the other's
+//! history, the newer one is taken. Otherwise both were changed separately,
+//! a conflict, which is resolved according to the `Strategy`. Either way
+//! the version not taken is kept in the entry's history. Entries that only
+//! exist in the other database are added to the group with the same path.
+
+use std::{fs::File, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use keepass::{
+    db::{Entry as KEntry, History},
+    Database, DatabaseKey,
+};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{entries_with_paths, import::get_or_create_group, index::Index, pinentry, Entry};
+
+/// How to resolve entries changed in both databases.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Take the most recently modified version.
+    Newest,
+    /// Take the other database's version.
+    Theirs,
+    /// Keep our version.
+    Ours,
+}
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "newest" => Ok(Strategy::Newest),
+            "theirs" => Ok(Strategy::Theirs),
+            "ours" => Ok(Strategy::Ours),
+            _ => Err(anyhow!("Unknown merge strategy: {}", s)),
+        }
+    }
+}
+
+/// An entry changed in both databases.
+#[derive(Serialize)]
+pub struct Conflict {
+    pub uuid: String,
+    pub title: String,
+    pub ours_modified: Option<NaiveDateTime>,
+    pub theirs_modified: Option<NaiveDateTime>,
+    /// Which version was taken: `ours` or `theirs`.
+    pub resolution: &'static str,
+}
+
+/// What a merge changed.
+#[derive(Default, Serialize)]
+pub struct Summary {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+}
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.conflicts.is_empty()
+    }
+
+    pub fn print(&self) {
+        info!(
+            "{}",
+            tr!(
+                "merge-summary",
+                added = self.added.len(),
+                updated = self.updated.len()
+            )
+        );
+        for title in &self.added {
+            info!("  + {}", title);
+        }
+        for title in &self.updated {
+            info!("  ~ {}", title);
+        }
+        for conflict in &self.conflicts {
+            let kept = match conflict.resolution {
+                "ours" => tr!("kept-ours"),
+                _ => tr!("kept-theirs"),
+            };
+            warn!(
+                "{}",
+                tr!("merge-conflict", kept = kept, entry = conflict.title)
+            );
+        }
+    }
+}
+
+/// Whether `version` (identified by its modification time)
+/// is the entry itself or in its history.
+fn has_version(entry: &KEntry, version: Option<NaiveDateTime>) -> bool {
+    let modified = |e: &KEntry| e.times.get_last_modification().copied();
+    modified(entry) == version
+        || entry
+            .history
+            .as_ref()
+            .is_some_and(|h| h.get_entries().iter().any(|e| modified(e) == version))
+}
+
+/// Open the other database, trying our key first
+/// and otherwise prompting for its password.
+pub fn open_other(path: &Path, key: &DatabaseKey) -> Result<Database> {
+    if let Ok(db) = Database::open(&mut File::open(path)?, key.clone()) {
+        return Ok(db);
+    }
+    let prompt = tr!("password-for", database = path.display());
+    let mut error = None;
+    loop {
+        let pass = pinentry::password(&prompt, error.as_deref())?;
+        let key = DatabaseKey::new().with_password(pass.expose());
+        match Database::open(&mut File::open(path)?, key) {
+            Ok(db) => return Ok(db),
+            Err(_) => {
+                warn!("{}", tr!("open-failed"));
+                error = Some(tr!("wrong-password"));
+            }
+        }
+    }
+}
+
+/// Combine the histories of both versions of an entry (plus the version
+/// being superseded), dropping duplicates. Newest versions come first.
+fn merge_history(ours: &KEntry, theirs: &KEntry, superseded: Option<KEntry>) -> History {
+    let mut versions: Vec<KEntry> = [ours, theirs]
+        .iter()
+        .filter_map(|e| e.history.as_ref())
+        .flat_map(|h| h.get_entries().iter().cloned())
+        .chain(superseded)
+        .collect();
+    versions.sort_by_key(|e| e.times.get_last_modification().copied());
+    versions.dedup_by_key(|e| e.times.get_last_modification().copied());
+
+    // `add_entry` inserts at the front, so add oldest first.
+    let mut history = History::default();
+    for version in versions {
+        history.add_entry(version);
+    }
+    history
+}
+
+/// Merge `other` into `db`.
+pub fn merge(db: &mut Database, other: &Database, strategy: Strategy) -> Summary {
+    let mut summary = Summary::default();
+    let index = Index::build(db);
+    for (path, their_entry) in entries_with_paths(&other.root) {
+        let their_entry = their_entry.clone();
+        let title = Entry(&their_entry).to_string();
+        if !index.contains(&their_entry.uuid) {
+            debug!(uuid = %their_entry.uuid, "Merge: adding their entry");
+            get_or_create_group(&mut db.root, &path).add_child(their_entry);
+            summary.added.push(title);
+            continue;
+        }
+
+        let ours = index
+            .entry_mut(db, &their_entry.uuid)
+            .expect("Entry exists");
+        let our_modified = ours.times.get_last_modification().copied();
+        let their_modified = their_entry.times.get_last_modification().copied();
+        if our_modified == their_modified {
+            continue;
+        }
+
+        let conflict =
+            !has_version(ours, their_modified) && !has_version(&their_entry, our_modified);
+        let take_theirs = match strategy {
+            _ if !conflict => their_modified > our_modified,
+            Strategy::Newest => their_modified > our_modified,
+            Strategy::Theirs => true,
+            Strategy::Ours => false,
+        };
+        debug!(
+            uuid = %their_entry.uuid,
+            conflict,
+            taken = if take_theirs { "theirs" } else { "ours" },
+            "Merge: entry changed"
+        );
+        if conflict {
+            summary.conflicts.push(Conflict {
+                uuid: their_entry.uuid.to_string(),
+                title: title.clone(),
+                ours_modified: our_modified,
+                theirs_modified: their_modified,
+                resolution: if take_theirs { "theirs" } else { "ours" },
+            });
+        }
+
+        if take_theirs {
+            let mut previous = ours.clone();
+            previous.history = None;
+            let history = merge_history(ours, &their_entry, Some(previous));
+            *ours = their_entry;
+            ours.history = Some(history);
+            summary.updated.push(title);
+        } else {
+            let mut older = their_entry.clone();
+            older.history = None;
+            ours.history = Some(merge_history(ours, &their_entry, Some(older)));
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::{Group, Times, Value};
+
+    use super::*;
+    use crate::test_util::{self, db_with};
+
+    fn entry(title: &str, password: &str, days_ago: i64) -> KEntry {
+        let mut entry = test_util::entry(&[("Title", title), ("Password", password)]);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    /// A later version of `entry`, with `entry` in its history.
+    fn edit(entry: &KEntry, password: &str, days_ago: i64) -> KEntry {
+        let mut edited = entry.clone();
+        edited.fields.insert(
+            "Password".to_string(),
+            Value::Unprotected(password.to_string()),
+        );
+        edited
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        let mut history = History::default();
+        history.add_entry(entry.clone());
+        edited.history = Some(history);
+        edited
+    }
+
+    fn find<'a>(db: &'a Database, uuid: &uuid::Uuid) -> (Vec<String>, &'a KEntry) {
+        entries_with_paths(&db.root)
+            .into_iter()
+            .find(|(_, entry)| entry.uuid == *uuid)
+            .expect("Entry exists")
+    }
+
+    /// The entry's password, and those in its history, newest first.
+    fn passwords(entry: &KEntry) -> (String, Vec<String>) {
+        let history = entry
+            .history
+            .iter()
+            .flat_map(|h| h.get_entries())
+            .map(|old| old.get_password().unwrap().to_string())
+            .collect();
+        (entry.get_password().unwrap().to_string(), history)
+    }
+
+    #[test]
+    fn parses_strategies() {
+        assert!(matches!("newest".parse(), Ok(Strategy::Newest)));
+        assert!(matches!("theirs".parse(), Ok(Strategy::Theirs)));
+        assert!(matches!("ours".parse(), Ok(Strategy::Ours)));
+        assert!("mine".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn adds_their_entries_to_the_same_group() {
+        let mut db = db_with(vec![entry("GitHub", "a", 1)]);
+        let mut other = Database::new(Default::default());
+        let mut mail = Group::new("Mail");
+        let theirs = entry("Webmail", "b", 1);
+        let uuid = theirs.uuid;
+        mail.add_child(theirs);
+        let mut personal = Group::new("Personal");
+        personal.add_child(mail);
+        other.root.add_child(personal);
+
+        let summary = merge(&mut db, &other, Strategy::Newest);
+        assert_eq!(summary.added, ["Webmail"]);
+        assert!(summary.updated.is_empty() && summary.conflicts.is_empty());
+        assert_eq!(find(&db, &uuid).0, ["Personal", "Mail"]);
+    }
+
+    #[test]
+    fn takes_their_newer_version() {
+        let base = entry("GitHub", "old", 10);
+        let mut db = db_with(vec![base.clone()]);
+        let other = db_with(vec![edit(&base, "new", 5)]);
+
+        let summary = merge(&mut db, &other, Strategy::Ours);
+        assert_eq!(summary.updated, ["GitHub"]);
+        assert!(summary.conflicts.is_empty());
+        let (_, merged) = find(&db, &base.uuid);
+        assert_eq!(passwords(merged), ("new".into(), vec!["old".into()]));
+
+        // Merging again changes nothing.
+        assert!(merge(&mut db, &other, Strategy::Ours).is_empty());
+    }
+
+    #[test]
+    fn keeps_our_newer_version() {
+        let base = entry("GitHub", "old", 10);
+        let mut db = db_with(vec![edit(&base, "new", 5)]);
+        let other = db_with(vec![base.clone()]);
+
+        let summary = merge(&mut db, &other, Strategy::Theirs);
+        assert!(summary.is_empty());
+        let (_, merged) = find(&db, &base.uuid);
+        assert_eq!(passwords(merged), ("new".into(), vec!["old".into()]));
+    }
+
+    fn conflict(strategy: Strategy) -> (Summary, (String, Vec<String>)) {
+        let base = entry("GitHub", "base", 10);
+        let mut db = db_with(vec![edit(&base, "ours", 3)]);
+        let other = db_with(vec![edit(&base, "theirs", 5)]);
+        let summary = merge(&mut db, &other, strategy);
+        let merged = passwords(find(&db, &base.uuid).1);
+        (summary, merged)
+    }
+
+    #[test]
+    fn resolves_conflicts_by_strategy() {
+        let (summary, merged) = conflict(Strategy::Newest);
+        assert_eq!(summary.conflicts.len(), 1);
+        assert_eq!(summary.conflicts[0].resolution, "ours");
+        assert_eq!(summary.conflicts[0].title, "GitHub");
+        assert!(summary.updated.is_empty());
+        assert_eq!(
+            merged,
+            ("ours".into(), vec!["theirs".into(), "base".into()])
+        );
+
+        let (summary, merged) = conflict(Strategy::Theirs);
+        assert_eq!(summary.conflicts[0].resolution, "theirs");
+        assert_eq!(summary.updated, ["GitHub"]);
+        assert_eq!(
+            merged,
+            ("theirs".into(), vec!["ours".into(), "base".into()])
+        );
+
+        let (summary, merged) = conflict(Strategy::Ours);
+        assert_eq!(summary.conflicts[0].resolution, "ours");
+        assert_eq!(
+            merged,
+            ("ours".into(), vec!["theirs".into(), "base".into()])
+        );
+    }
+}
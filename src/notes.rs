@@ -0,0 +1,166 @@
+use std::{
+    env,
+    fs::{self, DirBuilder, OpenOptions, Permissions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use keepass::db::Entry as KEntry;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::cleanup;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M";
+
+fn format_date(time: Option<&NaiveDateTime>) -> String {
+    time.map(|t| t.format(DATE_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+/// Expand template variables in notes:
+/// `{TITLE}`, `{USERNAME}`, `{URL}`, `{DATE}` and `{TIME}` (now),
+/// and `{CREATED}` and `{MODIFIED}` (the entry's dates).
+/// Unknown variables are left as-is.
+pub fn expand(notes: &str, entry: &KEntry) -> String {
+    if !notes.contains('{') {
+        return notes.to_string();
+    }
+
+    let now = Local::now();
+    let vars = [
+        ("{TITLE}", entry.get_title().unwrap_or("").to_string()),
+        ("{USERNAME}", entry.get_username().unwrap_or("").to_string()),
+        ("{URL}", entry.get_url().unwrap_or("").to_string()),
+        ("{DATE}", now.format(DATE_FORMAT).to_string()),
+        ("{TIME}", now.format(TIME_FORMAT).to_string()),
+        ("{CREATED}", format_date(entry.times.get_creation())),
+        (
+            "{MODIFIED}",
+            format_date(entry.times.get_last_modification()),
+        ),
+    ];
+    vars.iter().fold(notes.to_string(), |notes, (var, val)| {
+        notes.replace(var, val)
+    })
+}
+
+/// A private directory on a tmpfs (the runtime dir, or `/dev/shm`),
+/// so notes opened in an editor never reach the disk. Its files are
+/// overwritten before it's removed, including any the editor made
+/// (swap files, backups).
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn create() -> Result<Self> {
+        let base = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/dev/shm"));
+        let dir = base.join(format!("kpass-notes-{}", Uuid::new_v4().simple()));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        cleanup::track(&dir);
+        Ok(ScratchDir(dir))
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        shred_dir(&self.0);
+        let _ = fs::remove_dir_all(&self.0);
+        cleanup::untrack(&self.0);
+    }
+}
+
+fn shred_dir(dir: &Path) {
+    let Ok(files) = fs::read_dir(dir) else {
+        return;
+    };
+    for file in files.flatten() {
+        let path = file.path();
+        if path.is_dir() {
+            shred_dir(&path);
+            continue;
+        }
+        let _ = fs::set_permissions(&path, Permissions::from_mode(0o600));
+        let len = file.metadata().map_or(0, |meta| meta.len()) as usize;
+        if let Ok(mut f) = OpenOptions::new().write(true).open(&path) {
+            let _ = f.write_all(&vec![0; len]).and_then(|_| f.sync_all());
+        }
+    }
+}
+
+/// Open the notes in `$VISUAL` or `$EDITOR` (or `vi`), from a file only
+/// the user can read, that's read-only unless `writable`. Returns the
+/// notes as they were saved.
+pub fn open_in_editor(notes: &str, writable: bool) -> Result<Zeroizing<String>> {
+    let dir = ScratchDir::create()?;
+    let path = dir.0.join("notes.txt");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(notes.as_bytes())?;
+    drop(file);
+    if !writable {
+        fs::set_permissions(&path, Permissions::from_mode(0o400))?;
+    }
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("$EDITOR is empty"))?;
+    let status = Command::new(program).args(words).arg(&path).status()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(Zeroizing::new(fs::read_to_string(&path)?))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn expands_variables() {
+        let entry = test_util::entry(&[("Title", "GitHub"), ("UserName", "alice")]);
+        let created = format_date(entry.times.get_creation());
+        assert_eq!(created.len(), 10);
+        assert_eq!(
+            expand("{TITLE} ({USERNAME}) at {URL}, since {CREATED}", &entry),
+            format!("GitHub (alice) at , since {}", created)
+        );
+        let today = Local::now().format(DATE_FORMAT).to_string();
+        assert!(expand("{DATE} {TIME}", &entry).starts_with(&today));
+        assert_eq!(expand("{UNKNOWN} {title}", &entry), "{UNKNOWN} {title}");
+        assert_eq!(format_date(None), "");
+    }
+
+    #[test]
+    fn shreds_scratch_files() {
+        let dir = env::temp_dir().join(format!("kpass-test-notes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("backups")).unwrap();
+        let files = [dir.join("notes.txt"), dir.join("backups/notes.txt~")];
+        for file in &files {
+            fs::write(file, "secret notes").unwrap();
+        }
+        // As opened read-only.
+        fs::set_permissions(&files[0], Permissions::from_mode(0o400)).unwrap();
+        shred_dir(&dir);
+        for file in &files {
+            assert_eq!(fs::read(file).unwrap(), [0; 12]);
+        }
+
+        drop(ScratchDir(dir.clone()));
+        assert!(!dir.exists());
+    }
+}
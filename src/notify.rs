@@ -0,0 +1,74 @@
+//! Notifying about events that happen out of sight,
+//! e.g. the agent locking after being idle.
+//!
+//! Which backend is used is set by `notify` in the config.
+//! Notifications are best-effort: failing to notify never fails the operation.
+
+use std::{fs::OpenOptions, io::Write, process::Command};
+
+use anyhow::{anyhow, Result};
+
+use crate::config;
+
+enum Backend {
+    /// A desktop notification, through `notify-send` (libnotify).
+    Desktop,
+    /// Ring the terminal bell.
+    Bell,
+    None,
+    /// Run a command with the title and message appended as arguments.
+    Command(Vec<&'static str>),
+}
+impl Backend {
+    fn configured() -> Self {
+        match config::get().notify.as_str() {
+            "desktop" => Backend::Desktop,
+            "bell" => Backend::Bell,
+            "none" | "" => Backend::None,
+            cmd => Backend::Command(cmd.split_whitespace().collect()),
+        }
+    }
+}
+
+fn ring_bell() -> Result<()> {
+    match OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(mut tty) => tty.write_all(b"\x07")?,
+        Err(_) => std::io::stderr().write_all(b"\x07")?,
+    }
+    Ok(())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program).args(args).status()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
+
+fn send(title: &str, message: &str) -> Result<()> {
+    match Backend::configured() {
+        Backend::Desktop => {
+            // Without a notification daemon (e.g. over SSH),
+            // the bell still gets attention.
+            let has_display = ["DISPLAY", "WAYLAND_DISPLAY"]
+                .iter()
+                .any(|var| std::env::var_os(var).is_some());
+            if has_display && run("notify-send", &["--app-name=kpass", title, message]).is_ok() {
+                Ok(())
+            } else {
+                ring_bell()
+            }
+        }
+        Backend::Bell => ring_bell(),
+        Backend::None => Ok(()),
+        Backend::Command(cmd) => run(cmd[0], &[&cmd[1..], &[title, message]].concat()),
+    }
+}
+
+/// Notify the user, warning (but carrying on) if that fails.
+pub fn notify(title: &str, message: &str) {
+    if let Err(err) = send(title, message) {
+        warn!("{}", tr!("notify-failed", error = err));
+    }
+}
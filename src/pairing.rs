@@ -0,0 +1,724 @@
+//! Pairing devices, so one with a database unlocked can let another
+//! (e.g. with a synced copy of it) unlock it for a while over the LAN,
+//! without typing the password there.
+//!
+//! Pairing (`kpass pair`) exchanges X25519 keys, and both devices show a code
+//! to compare. The first key is committed to before the second is sent,
+//! so someone in between can't pick keys that make the codes match.
+//! Each device gets a key to request the password with and a key to check
+//! the other's requests with. Pairings are kept in the config dir, encrypted
+//! with the local key (see `cache::key`), and the keys for checking requests
+//! also with the database's master password, so what's kept on a device
+//! can't be used to get the password from that device.
+//!
+//! While a grant lasts (`kpass grant`), the agent answers paired devices'
+//! requests for the database's password from its unlocked state: the
+//! requester proves it holds its request key, and the password is sent
+//! encrypted with a key derived from it.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use cocoon::Cocoon;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use inquire::Confirm;
+use ring::{aead, agreement, rand::SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{cache, config, secret::Secret};
+
+/// The port granting (and pairing) devices listen on.
+pub const PORT: u16 = 7843;
+
+/// How long to wait for a device to answer a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the other device's user to compare the codes.
+const PAIR_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Messages are small; anything larger is refused.
+const MAX_MESSAGE: usize = 4096;
+
+/// A device paired for a database.
+#[derive(Serialize, Deserialize)]
+struct Device {
+    /// The host name to reach it by.
+    name: String,
+    /// The database's file name (see `db_name`).
+    db: String,
+    /// The key to request the password from it with, hex-encoded.
+    request_key: String,
+    /// The key it requests the password from this device with,
+    /// encrypted with the master password, hex-encoded.
+    grant_key: String,
+}
+impl Device {
+    fn request_key(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(hex::decode(&self.request_key)?))
+    }
+
+    fn grant_key(&self, password: &Secret) -> Result<Zeroizing<Vec<u8>>> {
+        let wrapped = hex::decode(&self.grant_key)?;
+        Cocoon::new(password.expose().as_bytes())
+            .unwrap(&wrapped)
+            .map(Zeroizing::new)
+            .map_err(|_| anyhow!("Couldn't decrypt the key for {}", self.name))
+    }
+}
+
+fn devices_path() -> Result<PathBuf> {
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("pairings"))
+}
+
+fn read_devices() -> Result<Vec<Device>> {
+    let path = devices_path()?;
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let key = cache::key()?;
+    let data = Zeroizing::new(
+        Cocoon::new(&key)
+            .parse(&mut File::open(path)?)
+            .map_err(|err| anyhow!("Couldn't read pairings: {:?}", err))?,
+    );
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn write_devices(devices: &[Device]) -> Result<()> {
+    let path = devices_path()?;
+    fs::create_dir_all(path.parent().expect("Pairings are in the config dir"))?;
+    let data = serde_json::to_vec(devices)?;
+    let key = cache::key()?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    Cocoon::new(&key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write pairings: {:?}", err))?;
+    Ok(())
+}
+
+/// Send a length-prefixed message.
+fn send(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(data)?;
+    Ok(())
+}
+
+/// Receive a length-prefixed message.
+fn recv(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(anyhow!("Message too large"));
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Receive a message of a fixed length, e.g. a key or nonce.
+fn recv_exact<const N: usize>(stream: &mut TcpStream) -> Result<[u8; N]> {
+    recv(stream)?
+        .try_into()
+        .map_err(|_| anyhow!("Malformed message"))
+}
+
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer's length is passed along.
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return "unknown".to_string();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+/// The address of a device, on the default port unless one is given.
+fn address(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:{}", name, PORT)
+    }
+}
+
+fn hmac(key: &[u8], label: &str, parts: &[&[u8]]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("Any key length works");
+    mac.update(label.as_bytes());
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+/// The code to compare and the pairing key, from the X25519 shared secret
+/// and the transcript (the initiator's public key, then the other's).
+fn derive(shared: &[u8], transcript: &[u8]) -> (u32, Zeroizing<Vec<u8>>) {
+    let digest = |label: &str| {
+        let mut hash = Sha256::new();
+        hash.update(label);
+        hash.update(shared);
+        hash.update(transcript);
+        hash.finalize()
+    };
+    let code = u32::from_be_bytes(digest("kpass pair code")[..4].try_into().unwrap());
+    (
+        code % 1_000_000,
+        Zeroizing::new(digest("kpass pairing key").to_vec()),
+    )
+}
+
+/// The keys each side requests the password with, from the pairing key:
+/// first this device's, then the other's.
+fn request_keys(key: &[u8], initiator: bool) -> (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>) {
+    let role = |role: &str| {
+        let key = hmac(key, "kpass request key", &[role.as_bytes()]).finalize();
+        Zeroizing::new(key.into_bytes().to_vec())
+    };
+    let (initiator_key, responder_key) = (role("initiator"), role("responder"));
+    if initiator {
+        (initiator_key, responder_key)
+    } else {
+        (responder_key, initiator_key)
+    }
+}
+
+/// Pair the database with the device named `host`,
+/// or wait for one to pair with this one.
+pub fn pair(db_path: &Path, password: &Secret, host: Option<&str>) -> Result<()> {
+    let db_name = db_name(db_path);
+    let (mut stream, initiator) = match host {
+        Some(host) => (TcpStream::connect(address(host))?, true),
+        None => {
+            let listener = TcpListener::bind(("0.0.0.0", PORT))?;
+            info!("{}", tr!("waiting-to-pair", host = hostname()));
+            (listener.accept()?.0, false)
+        }
+    };
+    stream.set_read_timeout(Some(PAIR_TIMEOUT))?;
+
+    send(&mut stream, hostname().as_bytes())?;
+    let peer_name = String::from_utf8(recv(&mut stream)?)?;
+    // Reach the device by the name it was paired with if given,
+    // otherwise by the name it reports.
+    let name = host.map_or(peer_name, str::to_string);
+    send(&mut stream, db_name.as_bytes())?;
+    let peer_db = String::from_utf8(recv(&mut stream)?)?;
+    if peer_db != db_name {
+        return Err(anyhow!("{} is pairing {}, not {}", name, peer_db, db_name));
+    }
+
+    let rng = SystemRandom::new();
+    let private = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+        .map_err(|_| anyhow!("Couldn't generate a key"))?;
+    let public = private
+        .compute_public_key()
+        .map_err(|_| anyhow!("Couldn't generate a key"))?;
+    let public = public.as_ref();
+    let peer_public: [u8; 32] = if initiator {
+        send(&mut stream, &Sha256::digest(public))?;
+        let peer_public = recv_exact(&mut stream)?;
+        send(&mut stream, public)?;
+        peer_public
+    } else {
+        let commitment: [u8; 32] = recv_exact(&mut stream)?;
+        send(&mut stream, public)?;
+        let peer_public = recv_exact(&mut stream)?;
+        if Sha256::digest(peer_public)[..] != commitment {
+            return Err(anyhow!("{} didn't send the key it committed to", name));
+        }
+        peer_public
+    };
+    let transcript = if initiator {
+        [public, &peer_public].concat()
+    } else {
+        [&peer_public, public].concat()
+    };
+    let (code, key) = agreement::agree_ephemeral(
+        private,
+        &agreement::UnparsedPublicKey::new(&agreement::X25519, peer_public),
+        |shared| derive(shared, &transcript),
+    )
+    .map_err(|_| anyhow!("Invalid key from {}", name))?;
+
+    println!(
+        "> {} {:03} {:03}",
+        tr!("pairing-code"),
+        code / 1000,
+        code % 1000
+    );
+    let confirmed = Confirm::new(&tr!("same-code", device = name))
+        .with_default(false)
+        .prompt()?;
+    send(&mut stream, &[confirmed as u8])?;
+    if !confirmed {
+        return Err(anyhow!("Pairing cancelled"));
+    }
+    info!("{}", tr!("waiting-to-confirm", device = name));
+    if recv(&mut stream)? != [1] {
+        return Err(anyhow!("Pairing cancelled on {}", name));
+    }
+
+    let (request_key, grant_key) = request_keys(&key, initiator);
+    let grant_key = Cocoon::new(password.expose().as_bytes())
+        .wrap(&grant_key)
+        .map_err(|err| anyhow!("Couldn't encrypt the pairing key: {:?}", err))?;
+    let mut devices = read_devices()?;
+    devices.retain(|device| device.name != name || device.db != db_name);
+    devices.push(Device {
+        name: name.clone(),
+        db: db_name.clone(),
+        request_key: hex::encode(&*request_key),
+        grant_key: hex::encode(grant_key),
+    });
+    write_devices(&devices)?;
+    info!("{}", tr!("paired", database = db_name, device = name));
+    if !config::get().pairing_unlock {
+        info!("{}", tr!("enable-pairing-unlock"));
+    }
+    Ok(())
+}
+
+/// Forget a paired device, for all databases.
+pub fn unpair(name: &str) -> Result<()> {
+    let mut devices = read_devices()?;
+    let len = devices.len();
+    devices.retain(|device| device.name != name);
+    if devices.len() == len {
+        return Err(anyhow!("No device named {} is paired", name));
+    }
+    write_devices(&devices)?;
+    info!("{}", tr!("unpaired", device = name));
+    Ok(())
+}
+
+/// The name databases are requested by, so synced copies match.
+fn db_name(db_path: &Path) -> String {
+    db_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The key the password is encrypted with for one request.
+fn session_key(key: &[u8], nonces: &[u8]) -> Result<aead::LessSafeKey> {
+    let key = hmac(key, "kpass session", &[nonces])
+        .finalize()
+        .into_bytes();
+    let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow!("Couldn't derive the session key"))?;
+    Ok(aead::LessSafeKey::new(key))
+}
+
+/// The password, encrypted for the device with its request key for one request.
+/// Each request has new nonces, so the key is never reused.
+fn seal_password(key: &[u8], nonces: &[u8], db_name: &str, password: &Secret) -> Result<Vec<u8>> {
+    let mut sealed = Zeroizing::new(password.expose().as_bytes().to_vec());
+    session_key(key, nonces)?
+        .seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key([0; 12]),
+            aead::Aad::from(db_name.as_bytes()),
+            &mut *sealed,
+        )
+        .map_err(|_| anyhow!("Couldn't encrypt the password"))?;
+    Ok(sealed.to_vec())
+}
+
+fn open_password(key: &[u8], nonces: &[u8], db_name: &str, sealed: &mut [u8]) -> Result<Secret> {
+    let password = session_key(key, nonces)?
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key([0; 12]),
+            aead::Aad::from(db_name.as_bytes()),
+            sealed,
+        )
+        .map_err(|_| anyhow!("Couldn't decrypt the password"))?;
+    Ok(Secret::new(String::from_utf8(password.to_vec())?))
+}
+
+/// A grant: for a while, paired devices' requests for the password of the
+/// agent's database are answered (see `agent`).
+pub struct Grant {
+    listener: TcpListener,
+    db_name: String,
+    until: Instant,
+    /// The keys paired devices request the password with, by their names.
+    keys: Vec<(String, Zeroizing<Vec<u8>>)>,
+}
+
+/// Let devices paired for the database unlock it for `duration`.
+/// The grant is answered with `Grant::poll` until it expires.
+pub fn grant(db_path: &Path, password: &Secret, duration: Duration) -> Result<Grant> {
+    let db_name = db_name(db_path);
+    let mut keys = vec![];
+    for device in read_devices()? {
+        if device.db != db_name {
+            continue;
+        }
+        match device.grant_key(password) {
+            Ok(key) => keys.push((device.name, key)),
+            Err(err) => warn!("{}", tr!("pair-again", error = err)),
+        }
+    }
+    if keys.is_empty() {
+        return Err(anyhow!(
+            "No devices paired for {}; pair one with `kpass pair` first",
+            db_name
+        ));
+    }
+    let listener = TcpListener::bind(("0.0.0.0", PORT))?;
+    listener.set_nonblocking(true)?;
+    info!(
+        "{}",
+        tr!(
+            "granting",
+            database = db_name,
+            minutes = duration.as_secs() / 60
+        )
+    );
+    Ok(Grant {
+        listener,
+        db_name,
+        until: Instant::now() + duration,
+        keys,
+    })
+}
+
+impl Grant {
+    pub fn is_active(&self) -> bool {
+        Instant::now() < self.until
+    }
+
+    /// Answer a request, if one is waiting.
+    pub fn poll(&self, password: &Secret) -> Result<()> {
+        let (mut stream, addr) = match self.listener.accept() {
+            Ok(accepted) => accepted,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        match answer(&mut stream, &self.db_name, &self.keys, password) {
+            Ok(device) => info!(
+                "{}",
+                tr!("unlocked-on", database = self.db_name, device = device)
+            ),
+            Err(err) => warn!(
+                "{}",
+                tr!("refused-request", address = addr.ip(), error = err)
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Answer a request for the password: the requester sends the database name
+/// and its nonce, we send ours, and it proves it holds a request key by
+/// MACing them. Returns the requesting device's name.
+fn answer(
+    stream: &mut TcpStream,
+    db_name: &str,
+    keys: &[(String, Zeroizing<Vec<u8>>)],
+    password: &Secret,
+) -> Result<String> {
+    let requested = String::from_utf8(recv(stream)?)?;
+    let nonce_r: [u8; 32] = recv_exact(stream)?;
+    let nonce_g: [u8; 32] = rand::random();
+    send(stream, &nonce_g)?;
+    let proof = recv(stream)?;
+
+    let nonces = [nonce_r, nonce_g].concat();
+    let (name, key) = keys
+        .iter()
+        .find(|(_, key)| {
+            hmac(key, "kpass request", &[&nonces, requested.as_bytes()])
+                .verify_slice(&proof)
+                .is_ok()
+        })
+        .ok_or_else(|| anyhow!("Request from an unpaired device"))?;
+    if requested != db_name {
+        send(stream, &[0])?;
+        return Err(anyhow!("{} requested {}, not {}", name, requested, db_name));
+    }
+
+    let sealed = seal_password(key, &nonces, db_name, password)?;
+    send(stream, &[&[1], &sealed[..]].concat())?;
+    Ok(name.clone())
+}
+
+/// Request the database's password from the device.
+fn request_from(device: &Device, db_name: &str) -> Result<Secret> {
+    let addr = address(&device.name)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Couldn't resolve {}", device.name))?;
+    let mut stream = TcpStream::connect_timeout(&addr, REQUEST_TIMEOUT)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let key = device.request_key()?;
+    let nonce_r: [u8; 32] = rand::random();
+    send(&mut stream, db_name.as_bytes())?;
+    send(&mut stream, &nonce_r)?;
+    let nonce_g: [u8; 32] = recv_exact(&mut stream)?;
+    let nonces = [nonce_r, nonce_g].concat();
+    let proof = hmac(&key, "kpass request", &[&nonces, db_name.as_bytes()]).finalize();
+    send(&mut stream, &proof.into_bytes())?;
+
+    let mut response = Zeroizing::new(recv(&mut stream)?);
+    match response.split_first_mut() {
+        Some((1, sealed)) => open_password(&key, &nonces, db_name, sealed),
+        _ => Err(anyhow!("Not granting {}", db_name)),
+    }
+}
+
+/// Ask paired devices for the database's password (with `pairing_unlock`),
+/// returning the first one granted. Devices that aren't granting are skipped.
+pub fn request(db_path: &Path) -> Option<Secret> {
+    if !config::get().pairing_unlock {
+        return None;
+    }
+    let devices = match read_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            warn!("{}", tr!("pairings-read-failed", error = err));
+            return None;
+        }
+    };
+    let db_name = db_name(db_path);
+    for device in devices.into_iter().filter(|device| device.db == db_name) {
+        match request_from(&device, &db_name) {
+            Ok(password) => {
+                info!("{}", tr!("unlocked-by", device = device.name));
+                return Some(password);
+            }
+            Err(err) => {
+                let unreachable = err.downcast_ref::<std::io::Error>().is_some();
+                if !unreachable {
+                    warn!(
+                        "{}",
+                        tr!("unlock-with-failed", device = device.name, error = err)
+                    );
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// A key pair, as each device makes when pairing.
+    fn key_pair() -> (agreement::EphemeralPrivateKey, [u8; 32]) {
+        let private =
+            agreement::EphemeralPrivateKey::generate(&agreement::X25519, &SystemRandom::new())
+                .unwrap();
+        let public = private.compute_public_key().unwrap();
+        let public = public.as_ref().try_into().unwrap();
+        (private, public)
+    }
+
+    /// The code and key a device derives, given its own key pair and
+    /// the public keys in the transcript's order.
+    fn side(
+        private: agreement::EphemeralPrivateKey,
+        peer_public: &[u8; 32],
+        transcript: &[u8],
+    ) -> (u32, Zeroizing<Vec<u8>>) {
+        agreement::agree_ephemeral(
+            private,
+            &agreement::UnparsedPublicKey::new(&agreement::X25519, peer_public),
+            |shared| derive(shared, transcript),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn both_sides_derive_the_same_code_and_key() {
+        let (initiator, initiator_public) = key_pair();
+        let (responder, responder_public) = key_pair();
+        let transcript = [initiator_public, responder_public].concat();
+        let (code_i, key_i) = side(initiator, &responder_public, &transcript);
+        let (code_r, key_r) = side(responder, &initiator_public, &transcript);
+        assert_eq!(code_i, code_r);
+        assert_eq!(key_i, key_r);
+        assert!(code_i < 1_000_000);
+        assert_eq!(key_i.len(), 32);
+    }
+
+    #[test]
+    fn the_transcript_is_bound() {
+        let (initiator, initiator_public) = key_pair();
+        let (responder, responder_public) = key_pair();
+        let (code_i, key_i) = side(
+            initiator,
+            &responder_public,
+            &[initiator_public, responder_public].concat(),
+        );
+        // Swapped roles give different results for the same shared secret.
+        let (code_r, key_r) = side(
+            responder,
+            &initiator_public,
+            &[responder_public, initiator_public].concat(),
+        );
+        assert_ne!(key_i, key_r);
+        assert_ne!(code_i, code_r);
+    }
+
+    #[test]
+    fn someone_in_between_gets_other_codes() {
+        // The attacker pairs with each device on its own key.
+        let (initiator, initiator_public) = key_pair();
+        let (responder, responder_public) = key_pair();
+        let (attacker_1, attacker_1_public) = key_pair();
+        let (attacker_2, attacker_2_public) = key_pair();
+        let (code_i, key_i) = side(
+            initiator,
+            &attacker_1_public,
+            &[initiator_public, attacker_1_public].concat(),
+        );
+        let (code_r, key_r) = side(
+            responder,
+            &attacker_2_public,
+            &[attacker_2_public, responder_public].concat(),
+        );
+        assert_ne!(key_i, key_r);
+        assert_ne!(code_i, code_r);
+        // And the attacker's own views match each device's.
+        let (code_a1, _) = side(
+            attacker_1,
+            &initiator_public,
+            &[initiator_public, attacker_1_public].concat(),
+        );
+        let (code_a2, _) = side(
+            attacker_2,
+            &responder_public,
+            &[attacker_2_public, responder_public].concat(),
+        );
+        assert_eq!((code_a1, code_a2), (code_i, code_r));
+    }
+
+    #[test]
+    fn derivation_is_fixed() {
+        // So devices paired by different versions agree.
+        let (code, key) = derive(&[1; 32], &[2; 64]);
+        assert_eq!(code, 86630);
+        assert_eq!(
+            hex::encode(&*key),
+            "2b1f97c78ec13bc5fd92c55fcacf4e5db357d0e6d46bce6d17adda2bd82b9810"
+        );
+    }
+
+    #[test]
+    fn requests_prove_the_pairing_key() {
+        let key = [5; 32];
+        let nonces = [6; 64];
+        let proof = hmac(&key, "kpass request", &[&nonces, b"db.kdbx"]).finalize();
+        let proof = proof.into_bytes();
+        let verify = |key: &[u8], nonces: &[u8], name: &[u8]| {
+            hmac(key, "kpass request", &[nonces, name])
+                .verify_slice(&proof)
+                .is_ok()
+        };
+        assert!(verify(&key, &nonces, b"db.kdbx"));
+        assert!(!verify(&[7; 32], &nonces, b"db.kdbx"));
+        assert!(!verify(&key, &[8; 64], b"db.kdbx"));
+        assert!(!verify(&key, &nonces, b"other.kdbx"));
+    }
+
+    #[test]
+    fn passwords_open_only_for_the_request() {
+        let key = [5; 32];
+        let nonces = [6; 64];
+        let password = Secret::new("hunter2".to_string());
+        let sealed = seal_password(&key, &nonces, "db.kdbx", &password).unwrap();
+        assert!(!sealed.windows(7).any(|window| window == b"hunter2"));
+
+        let opened = open_password(&key, &nonces, "db.kdbx", &mut sealed.clone()).unwrap();
+        assert_eq!(opened.expose(), "hunter2");
+        assert!(open_password(&[7; 32], &nonces, "db.kdbx", &mut sealed.clone()).is_err());
+        assert!(open_password(&key, &[8; 64], "db.kdbx", &mut sealed.clone()).is_err());
+        assert!(open_password(&key, &nonces, "other.kdbx", &mut sealed.clone()).is_err());
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open_password(&key, &nonces, "db.kdbx", &mut tampered).is_err());
+    }
+
+    #[test]
+    fn each_side_requests_with_its_own_key() {
+        let key = [5; 32];
+        let (initiator_request, initiator_grant) = request_keys(&key, true);
+        let (responder_request, responder_grant) = request_keys(&key, false);
+        assert_eq!(initiator_request, responder_grant);
+        assert_eq!(responder_request, initiator_grant);
+        assert_ne!(initiator_request, responder_request);
+    }
+
+    #[test]
+    fn grant_keys_need_the_master_password() {
+        let password = Secret::new("correct horse".to_string());
+        let wrapped = Cocoon::new(b"correct horse").wrap(&[9; 32]).unwrap();
+        let device = Device {
+            name: "desktop".to_string(),
+            db: "db.kdbx".to_string(),
+            request_key: hex::encode([8; 32]),
+            grant_key: hex::encode(wrapped),
+        };
+        assert_eq!(*device.grant_key(&password).unwrap(), [9; 32]);
+        let wrong = Secret::new("battery staple".to_string());
+        assert!(device.grant_key(&wrong).is_err());
+    }
+
+    #[test]
+    fn answers_paired_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let device = Device {
+            name: listener.local_addr().unwrap().to_string(),
+            db: "db.kdbx".to_string(),
+            request_key: hex::encode([8; 32]),
+            grant_key: String::new(),
+        };
+        let keys = vec![("laptop".to_string(), Zeroizing::new(vec![8; 32]))];
+        let password = Secret::new("hunter2".to_string());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..2 {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let _ = answer(&mut stream, "db.kdbx", &keys, &password);
+                }
+            });
+            let granted = request_from(&device, "db.kdbx").unwrap();
+            assert_eq!(granted.expose(), "hunter2");
+            let unpaired = Device {
+                request_key: hex::encode([7; 32]),
+                ..device
+            };
+            assert!(request_from(&unpaired, "db.kdbx").is_err());
+        });
+    }
+
+    #[test]
+    fn addresses_default_to_the_port() {
+        assert_eq!(address("laptop"), format!("laptop:{}", PORT));
+        assert_eq!(address("laptop:9000"), "laptop:9000");
+    }
+}
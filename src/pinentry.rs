@@ -0,0 +1,237 @@
+//! Asking for the master password (and quick password) through the system's
+//! pinentry (e.g. `pinentry-gnome3`), as set by `pinentry` in the config,
+//! so the prompt is a secure dialog even without a terminal,
+//! e.g. when kpass is started from a keybinding.
+//!
+//! Pinentry programs speak the Assuan protocol over stdin/stdout.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, Password, PasswordDisplayMode};
+use zeroize::Zeroizing;
+
+use crate::{config, idle, secret::Secret};
+
+/// Assuan's error codes for a cancelled dialog, and a declined confirmation.
+const CANCELLED: &str = "83886179";
+const NOT_CONFIRMED: &str = "83886194";
+
+/// Percent-encode what Assuan can't carry in a line.
+fn escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+/// Decode percent-escapes in data lines.
+fn unescape(data: &str) -> Zeroizing<Vec<u8>> {
+    let bytes = data.as_bytes();
+    let mut out = Zeroizing::new(Vec::with_capacity(bytes.len()));
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = (bytes[i] == b'%')
+            .then(|| data.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+struct Pinentry {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+impl Pinentry {
+    fn spawn(program: &str) -> Result<Self> {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| anyhow!("Couldn't run {}: {}", program, err))?;
+        let mut pinentry = Pinentry {
+            stdin: child.stdin.take().expect("stdin is piped"),
+            stdout: BufReader::new(child.stdout.take().expect("stdout is piped")),
+            child,
+        };
+        pinentry.response()?;
+        Ok(pinentry)
+    }
+
+    /// Read the response to a command, returning its data lines (decoded).
+    fn response(&mut self) -> Result<Zeroizing<Vec<u8>>> {
+        let mut data = Zeroizing::new(vec![]);
+        loop {
+            let mut line = Zeroizing::new(String::new());
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(anyhow!("pinentry exited unexpectedly"));
+            }
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line == "OK" || line.starts_with("OK ") {
+                return Ok(data);
+            } else if let Some(chunk) = line.strip_prefix("D ") {
+                data.extend_from_slice(&unescape(chunk));
+            } else if let Some(err) = line.strip_prefix("ERR ") {
+                if err.starts_with(CANCELLED) || err.starts_with(NOT_CONFIRMED) {
+                    return Err(anyhow!("Cancelled"));
+                }
+                return Err(anyhow!("pinentry failed: {}", err));
+            }
+            // Ignore status ("S") and comment ("#") lines.
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Result<Zeroizing<Vec<u8>>> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        self.response()
+    }
+}
+impl Drop for Pinentry {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "BYE");
+        let _ = self.child.wait();
+    }
+}
+
+/// Ask for a password through pinentry, showing `error` from a previous attempt.
+fn get_pin(program: &str, prompt: &str, error: Option<&str>) -> Result<Secret> {
+    let mut pinentry = Pinentry::spawn(program)?;
+    // For pinentry-curses and -tty; graphical ones ignore these.
+    if std::io::stdin().is_terminal() {
+        if let Ok(tty) = std::fs::read_link("/proc/self/fd/0") {
+            let _ = pinentry.command(&format!("OPTION ttyname={}", tty.display()));
+        }
+        if let Ok(term) = env::var("TERM") {
+            let _ = pinentry.command(&format!("OPTION ttytype={}", term));
+        }
+    }
+    pinentry.command("SETTITLE kpass")?;
+    pinentry.command(&format!("SETPROMPT {}", escape(prompt)))?;
+    if let Some(error) = error {
+        pinentry.command(&format!("SETERROR {}", escape(error)))?;
+    }
+    let minutes = config::get().prompt_timeout_minutes;
+    if minutes > 0 {
+        pinentry.command(&format!("SETTIMEOUT {}", minutes * 60))?;
+    }
+    let pin = pinentry.command("GETPIN")?;
+    let pin = std::str::from_utf8(&pin).map_err(|_| anyhow!("Invalid password from pinentry"))?;
+    Ok(Secret::new(pin.to_string()))
+}
+
+/// Ask for a password (e.g. the master password), through pinentry if
+/// configured and otherwise in the terminal. `error` is why the previous
+/// attempt failed, which pinentry shows in the dialog.
+pub fn password(prompt: &str, error: Option<&str>) -> Result<Secret> {
+    if let Some(program) = &config::get().pinentry {
+        return get_pin(program, prompt, error);
+    }
+    let _timeout = idle::prompt_timeout();
+    Ok(Secret::new(
+        Password::new(prompt)
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_formatter(&|_| String::from("🔑"))
+            .without_confirmation()
+            .prompt()?,
+    ))
+}
+
+/// Ask to confirm something, through pinentry if configured
+/// and otherwise in the terminal.
+pub fn confirm(description: &str) -> Result<bool> {
+    let Some(program) = &config::get().pinentry else {
+        return Ok(Confirm::new(description).with_default(false).prompt()?);
+    };
+    let mut pinentry = Pinentry::spawn(program)?;
+    pinentry.command("SETTITLE kpass")?;
+    pinentry.command(&format!("SETDESC {}", escape(description)))?;
+    match pinentry.command("CONFIRM") {
+        Ok(_) => Ok(true),
+        Err(err) if err.to_string() == "Cancelled" => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    /// A pinentry that greets, answers `GETPIN` with `pin`,
+    /// cancels `CONFIRM` and accepts everything else.
+    fn fake_pinentry(name: &str, pin: &str) -> String {
+        let path = env::temp_dir().join(format!(
+            "kpass-test-pinentry-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let script = format!(
+            "#!/bin/sh\n\
+             echo 'OK Pleased to meet you'\n\
+             while read -r command rest; do\n\
+             case $command in\n\
+             GETPIN) echo '# Typing'; echo 'S PASSWORD_FROM_CACHE'; echo 'D {}'; echo OK ;;\n\
+             CONFIRM) echo 'ERR {} Operation cancelled <Pinentry>' ;;\n\
+             BYE) echo 'OK closing connection'; exit 0 ;;\n\
+             *) echo OK ;;\n\
+             esac\n\
+             done\n",
+            pin, CANCELLED
+        );
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn escapes_lines() {
+        assert_eq!(escape("50%\nof it\r"), "50%25%0Aof it%0D");
+        assert_eq!(&*unescape("50%25%0Aof it%0D"), b"50%\nof it\r");
+        // Not escapes.
+        assert_eq!(&*unescape("100%"), b"100%");
+        assert_eq!(&*unescape("%zz%4"), b"%zz%4");
+        assert_eq!(&*unescape("caf%C3%A9"), "café".as_bytes());
+    }
+
+    #[test]
+    fn reads_the_pin() {
+        let program = fake_pinentry("pin", "hunter%25 2");
+        let pin = get_pin(&program, "Password:", Some("Wrong password")).unwrap();
+        assert_eq!(pin.expose(), "hunter% 2");
+        let _ = fs::remove_file(program);
+    }
+
+    #[test]
+    fn reports_cancelling() {
+        let program = fake_pinentry("cancel", "");
+        let mut pinentry = Pinentry::spawn(&program).unwrap();
+        pinentry.command("SETDESC Allow?").unwrap();
+        let err = pinentry.command("CONFIRM").unwrap_err();
+        assert_eq!(err.to_string(), "Cancelled");
+        drop(pinentry);
+        let _ = fs::remove_file(program);
+    }
+
+    #[test]
+    fn fails_when_pinentry_is_missing() {
+        assert!(Pinentry::spawn("/nonexistent/pinentry").is_err());
+    }
+}
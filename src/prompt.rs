@@ -0,0 +1,177 @@
+//! Structured prompts, so values like dates and lengths
+//! are picked rather than typed and parsed ad hoc.
+
+use std::{fmt::Display, ops::RangeInclusive, str::FromStr};
+
+use anyhow::Result;
+use chrono::{Days, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use inquire::{
+    autocompletion::Replacement, validator::Validation, Autocomplete, CustomType, CustomUserError,
+    DateSelect, MultiSelect, Select,
+};
+
+use crate::config;
+
+/// Prompt for a number within `range`.
+pub fn number_in_range<T>(message: &str, default: T, range: RangeInclusive<T>) -> Result<T>
+where
+    T: Copy + Display + FromStr + PartialOrd + 'static,
+{
+    let help = tr!("between", min = range.start(), max = range.end());
+    let value = CustomType::<T>::new(message)
+        .with_default(default)
+        .with_help_message(&help)
+        .with_error_message(&tr!("enter-a-number"))
+        .with_validator(move |value: &T| {
+            Ok(if range.contains(value) {
+                Validation::Valid
+            } else {
+                Validation::Invalid(
+                    tr!("must-be-between", min = range.start(), max = range.end()).into(),
+                )
+            })
+        })
+        .prompt()?;
+    Ok(value)
+}
+
+/// Prompt for when something expires: never, after a number of days,
+/// or on a date picked from a calendar. Expiry is at the end of the day.
+pub fn expiry(current: Option<NaiveDate>) -> Result<Option<NaiveDate>> {
+    let today = Local::now().date_naive();
+    let options = vec![
+        tr!("expires-never"),
+        tr!("expires-in-days"),
+        tr!("expires-on-date"),
+    ];
+    let choice = select(&tr!("expires"), options).raw_prompt()?;
+    let date = match choice.index {
+        1 => {
+            let days = number_in_range(&tr!("expires-in"), 90, 1..=3650)?;
+            Some(today + Days::new(days))
+        }
+        2 => Some(
+            DateSelect::new(&tr!("expires-on"))
+                .with_starting_date(current.filter(|d| *d > today).unwrap_or(today))
+                .with_min_date(today)
+                .with_week_start(chrono::Weekday::Mon)
+                .prompt()?,
+        ),
+        _ => None,
+    };
+    Ok(date)
+}
+
+/// The last moment of a local date, in UTC (as KeePass stores times).
+pub fn end_of_day_utc(date: NaiveDate) -> NaiveDateTime {
+    let end = date.and_time(NaiveTime::from_hms_opt(23, 59, 59).expect("Valid time"));
+    end.and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.naive_utc())
+        .unwrap_or(end)
+}
+
+/// Whether the text looks like a single email address.
+pub fn is_email(text: &str) -> bool {
+    let text = text.trim();
+    text.split_once('@').is_some_and(|(user, domain)| {
+        !user.is_empty() && domain.contains('.') && !text.contains(char::is_whitespace)
+    })
+}
+
+/// Suggestions for a text prompt from known values (e.g. usernames
+/// already in the database), matching what's typed anywhere, ignoring case.
+/// Tab completes the highlighted one, or else the first.
+#[derive(Clone)]
+pub struct Suggestions(pub Vec<String>);
+impl Autocomplete for Suggestions {
+    fn get_suggestions(&mut self, input: &str) -> Result<Vec<String>, CustomUserError> {
+        let input = input.to_lowercase();
+        Ok(self
+            .0
+            .iter()
+            .filter(|value| value.to_lowercase().contains(&input))
+            .cloned()
+            .collect())
+    }
+
+    fn get_completion(
+        &mut self,
+        input: &str,
+        highlighted: Option<String>,
+    ) -> Result<Replacement, CustomUserError> {
+        Ok(highlighted.or_else(|| self.get_suggestions(input).ok()?.into_iter().next()))
+    }
+}
+
+/// A `Select` with the configured page size and keys.
+pub fn select<'a, T: Display>(message: &'a str, options: Vec<T>) -> Select<'a, T> {
+    let config = config::get();
+    Select::new(message, options)
+        .with_page_size(config.page_size.max(1))
+        .with_vim_mode(config.vim_keys)
+}
+
+/// A `MultiSelect` with the configured page size and keys.
+pub fn multi_select<'a, T: Display>(message: &'a str, options: Vec<T>) -> MultiSelect<'a, T> {
+    let config = config::get();
+    MultiSelect::new(message, options)
+        .with_page_size(config.page_size.max(1))
+        .with_vim_mode(config.vim_keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_emails() {
+        assert!(is_email("alice@example.com"));
+        assert!(is_email(" alice+kpass@mail.example.co.uk "));
+        for text in [
+            "alice",
+            "@example.com",
+            "alice@localhost",
+            "alice @example.com",
+        ] {
+            assert!(!is_email(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn ends_days_in_utc() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let end = end_of_day_utc(date);
+        let local = end.and_utc().with_timezone(&Local);
+        assert_eq!(local.date_naive(), date);
+        assert_eq!(local.time(), NaiveTime::from_hms_opt(23, 59, 59).unwrap());
+    }
+
+    #[test]
+    fn suggests_known_values() {
+        let mut suggestions = Suggestions(vec![
+            "alice".to_string(),
+            "Alice@example.com".to_string(),
+            "bob".to_string(),
+        ]);
+        assert_eq!(
+            suggestions.get_suggestions("ALI").unwrap(),
+            ["alice", "Alice@example.com"]
+        );
+        assert_eq!(
+            suggestions.get_suggestions("example").unwrap(),
+            ["Alice@example.com"]
+        );
+        assert_eq!(
+            suggestions.get_completion("b", None).unwrap(),
+            Some("bob".to_string())
+        );
+        assert_eq!(
+            suggestions
+                .get_completion("ali", Some("Alice@example.com".to_string()))
+                .unwrap(),
+            Some("Alice@example.com".to_string())
+        );
+        assert_eq!(suggestions.get_completion("carol", None).unwrap(), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! Entries flagged to always require the full master password:
+//! the quick password isn't enough to release their fields,
+//! and the agent and secret service don't serve them at all.
+
+use std::{fs::File, path::Path};
+
+use anyhow::Result;
+use keepass::{
+    db::{CustomDataItem, Entry as KEntry, Times, Value},
+    Database,
+};
+
+use crate::{database_key, lockout, pinentry};
+
+/// Custom data key the flag is kept under.
+const FLAG_KEY: &str = "kpass.require_master_password";
+
+/// Whether the entry requires the full master password.
+pub fn is_required(entry: &KEntry) -> bool {
+    entry.custom_data.items.contains_key(FLAG_KEY)
+}
+
+pub fn set_required(entry: &mut KEntry, required: bool) {
+    if required {
+        entry.custom_data.items.insert(
+            FLAG_KEY.to_string(),
+            CustomDataItem {
+                value: Some(Value::Unprotected("true".to_string())),
+                last_modification_time: Some(Times::now()),
+            },
+        );
+    } else {
+        entry.custom_data.items.remove(FLAG_KEY);
+    }
+}
+
+/// Prompt for the full master password and check that it opens the database,
+/// counting failures towards the lockout.
+pub fn verify(db_path: &Path) -> Result<bool> {
+    if let Err(err) = lockout::check() {
+        warn!("{}", err);
+        return Ok(false);
+    }
+    let pass = pinentry::password(&tr!("full-password"), None)?;
+    let key = database_key(db_path, &pass)?;
+    if Database::open(&mut File::open(db_path)?, key).is_err() {
+        warn!("{}", tr!("incorrect-password"));
+        lockout::record_failure()?;
+        return Ok(false);
+    }
+    lockout::reset()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_entries() {
+        let mut entry = KEntry::new();
+        assert!(!is_required(&entry));
+        set_required(&mut entry, true);
+        assert!(is_required(&entry));
+        set_required(&mut entry, true);
+        assert_eq!(entry.custom_data.items.len(), 1);
+        set_required(&mut entry, false);
+        assert!(!is_required(&entry));
+        assert!(entry.custom_data.items.is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! Encrypting the password cache to a GPG key or an age identity instead of
+//! the quick password, so unlocking goes through gpg-agent (or an age plugin),
+//! e.g. with a smartcard, and there's no short secret to guess.
+//!
+//! Which is used is set by `password_cache` in the config.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+use crate::config;
+
+pub enum Method {
+    /// Locked by the last 3 chars of the password, see `cache_pass`.
+    Quick,
+    /// Encrypted to a GPG recipient (key ID, fingerprint or email).
+    Gpg(&'static str),
+    /// Encrypted to the recipients of an age identity file.
+    Age(&'static str),
+}
+impl Method {
+    pub fn configured() -> Result<Self> {
+        Self::parse(config::get().password_cache.as_str())
+    }
+
+    fn parse(setting: &'static str) -> Result<Self> {
+        match setting.split_once(':') {
+            None if setting == "quick" || setting.is_empty() => Ok(Method::Quick),
+            Some(("gpg", recipient)) if !recipient.is_empty() => Ok(Method::Gpg(recipient)),
+            Some(("age", identity)) if !identity.is_empty() => Ok(Method::Age(identity)),
+            _ => Err(anyhow!(
+                "Invalid password_cache: {:?} (expected \"quick\", \"gpg:<recipient>\" or \"age:<identity file>\")",
+                setting
+            )),
+        }
+    }
+}
+
+/// Run `program`, passing `input` through its stdin, and return its output.
+fn pipe(program: &str, args: &[&str], input: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow!("Couldn't run {}: {}", program, err))?;
+    // Small enough to fit in the pipe's buffer, so this doesn't block.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(input)?;
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    let stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+        return Err(anyhow!("{} exited with {}", program, output.status));
+    }
+    Ok(stdout)
+}
+
+/// Encrypt the password with an external method.
+pub fn encrypt(method: &Method, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    match method {
+        Method::Quick => unreachable!("The quick password is handled by cocoon"),
+        Method::Gpg(recipient) => pipe(
+            "gpg",
+            &["--quiet", "--batch", "--encrypt", "--recipient", recipient],
+            password.as_bytes(),
+        ),
+        Method::Age(identity) => pipe(
+            "age",
+            &["--encrypt", "--identity", identity],
+            password.as_bytes(),
+        ),
+    }
+}
+
+/// Decrypt the password with an external method,
+/// which may ask for a PIN or passphrase itself.
+pub fn decrypt(method: &Method, data: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    match method {
+        Method::Quick => unreachable!("The quick password is handled by cocoon"),
+        Method::Gpg(_) => pipe("gpg", &["--quiet", "--decrypt"], data),
+        Method::Age(identity) => pipe("age", &["--decrypt", "--identity", identity], data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_methods() {
+        assert!(matches!(Method::parse(""), Ok(Method::Quick)));
+        assert!(matches!(Method::parse("quick"), Ok(Method::Quick)));
+        assert!(matches!(
+            Method::parse("gpg:me@example.com"),
+            Ok(Method::Gpg("me@example.com"))
+        ));
+        assert!(matches!(
+            Method::parse("age:/home/me/key.txt"),
+            Ok(Method::Age("/home/me/key.txt"))
+        ));
+        for setting in ["gpg:", "age:", "gpg", "pass:x", "Quick"] {
+            assert!(Method::parse(setting).is_err(), "{}", setting);
+        }
+    }
+
+    #[test]
+    fn pipes_through_programs() {
+        assert_eq!(*pipe("cat", &[], b"secret").unwrap(), b"secret");
+        assert!(pipe("false", &[], b"secret").is_err());
+        assert!(pipe("kpass-no-such-program", &[], b"secret").is_err());
+    }
+}
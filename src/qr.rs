@@ -0,0 +1,176 @@
+//! Showing secrets as QR codes in the terminal,
+//! to scan them onto a phone without any network transfer,
+//! and reading QR codes (e.g. for 2FA enrollment) from images
+//! or a region of the screen, with `zbarimg` (and `grim` and `slurp`).
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Result};
+use inquire::Text;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::{cleanup, i18n, prompt, show, Entry};
+
+/// Field holding a WiFi network's name, for WiFi entries.
+const SSID_FIELD: &str = "SSID";
+
+/// Render `data` as a QR code of unicode half blocks.
+/// Drawn black on white regardless of the terminal's colors,
+/// as not all scanners handle inverted codes.
+fn render(data: &str) -> Result<String> {
+    let code = QrCode::new(data.as_bytes())?;
+    let image = code.render::<Dense1x2>().quiet_zone(true).build();
+    Ok(image
+        .lines()
+        .map(|line| format!("\x1b[30;47m{}\x1b[0m", line))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Escape a value for a `WIFI:` string.
+fn escape_wifi(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The `WIFI:` string phones use to join a network.
+fn wifi(ssid: &str, password: &str) -> String {
+    format!(
+        "WIFI:T:WPA;S:{};P:{};;",
+        escape_wifi(ssid),
+        escape_wifi(password)
+    )
+}
+
+/// Ask which of the entry's secrets to show, and show it as a QR code.
+pub fn show_entry(entry: &Entry) -> Result<()> {
+    let mut options = vec![];
+    if entry.password().is_some() {
+        options.extend(["Password", "WiFi network"]);
+    }
+    if entry.0.get_raw_otp_value().is_some() {
+        options.push("OTP (otpauth:// URI)");
+    }
+    if options.is_empty() {
+        warn!("{}", tr!("no-password-or-otp", entry = entry));
+        return Ok(());
+    }
+    let labels = options
+        .iter()
+        .map(|option| i18n::Label("qr", option))
+        .collect();
+    let Some(choice) = prompt::select(&tr!("show-as-qr"), labels).prompt_skippable()? else {
+        return Ok(());
+    };
+
+    let password = entry.password().unwrap_or_default();
+    let data = Zeroizing::new(match choice.1 {
+        "Password" => password.to_string(),
+        "WiFi network" => {
+            let ssid = Text::new(&tr!("wifi-ssid"))
+                .with_initial_value(entry.0.get(SSID_FIELD).unwrap_or(&entry.to_string()))
+                .prompt()?;
+            wifi(&ssid, password)
+        }
+        _ => entry.0.get_raw_otp_value().unwrap_or_default().to_string(),
+    });
+    show::on_screen(&tr!("the-qr-code"), &Zeroizing::new(render(&data)?))
+}
+
+/// The text of the first QR code in the image.
+pub fn scan(image: &Path) -> Result<Zeroizing<String>> {
+    let output = Command::new("zbarimg")
+        .args(["--raw", "--quiet", "-Sdisable", "-Sqrcode.enable"])
+        .arg(image)
+        .output()
+        .map_err(|_| anyhow!("Reading QR codes requires zbarimg (from zbar)."))?;
+    let text = Zeroizing::new(String::from_utf8_lossy(&output.stdout).into_owned());
+    match text.lines().next() {
+        Some(line) if output.status.success() => Ok(Zeroizing::new(line.to_string())),
+        _ => Err(anyhow!("No QR code found in {}", image.display())),
+    }
+}
+
+/// Select a region of the screen and read the QR code in it.
+/// The screenshot is kept in a file only the user can read,
+/// on a tmpfs, and removed afterwards.
+pub fn scan_screen() -> Result<Zeroizing<String>> {
+    let region = Command::new("slurp")
+        .output()
+        .map_err(|_| anyhow!("Selecting a region of the screen requires slurp."))?;
+    if !region.status.success() {
+        return Err(anyhow!("No region selected"));
+    }
+    let region = String::from_utf8_lossy(&region.stdout).trim().to_string();
+
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/dev/shm"));
+    let path = dir.join(format!("kpass-qr-{}.png", Uuid::new_v4().simple()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    cleanup::track(&path);
+    let shot = Command::new("grim")
+        .args(["-g", &region])
+        .arg(&path)
+        .status();
+    let scanned = match shot {
+        Ok(status) if status.success() => {
+            scan(&path).map_err(|_| anyhow!("No QR code found in the selected region"))
+        }
+        Ok(status) => Err(anyhow!("Taking a screenshot failed ({})", status)),
+        Err(_) => Err(anyhow!("Taking a screenshot requires grim.")),
+    };
+    let _ = fs::remove_file(&path);
+    cleanup::untrack(&path);
+    scanned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_wifi_networks() {
+        assert_eq!(wifi("Home", "hunter2"), "WIFI:T:WPA;S:Home;P:hunter2;;");
+        assert_eq!(
+            wifi(r#"Café;"5G""#, r"a:b,c\d"),
+            r#"WIFI:T:WPA;S:Café\;\"5G\";P:a\:b\,c\\d;;"#
+        );
+    }
+
+    #[test]
+    fn renders_black_on_white() {
+        let rendered = render("hunter2").unwrap();
+        let lines: Vec<&str> = rendered.lines().collect();
+        // A version 1 code is 21 modules, plus a quiet zone of 4 on each side,
+        // two modules to a line.
+        assert_eq!(lines.len(), 15);
+        for line in lines {
+            let blocks = line
+                .strip_prefix("\x1b[30;47m")
+                .and_then(|line| line.strip_suffix("\x1b[0m"))
+                .unwrap();
+            assert_eq!(blocks.chars().count(), 29);
+            assert!(blocks.chars().all(|c| " ▀▄█".contains(c)));
+        }
+        assert!(!rendered.contains("hunter2"));
+    }
+}
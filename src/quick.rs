@@ -0,0 +1,69 @@
+//! `kpass <db> --copy <query>`: copy the password of the entry best
+//! matching a query and exit, without any menus, e.g. from a hotkey
+//! bound to a fixed query.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    activity, address, copy_from_entry, error::Error, protect, reference, unlock_with, usage, Entry,
+};
+
+/// Copy the password of the entry in the databases that matches the
+/// query (see `address`). Of several matches, the most used one is
+/// taken; if none stands out, the matches are listed instead.
+pub fn copy(db_paths: &[PathBuf], query: &str) -> Result<()> {
+    let mut dbs = vec![];
+    for (i, path) in db_paths.iter().enumerate() {
+        // Only the first database uses the quick password.
+        let (db, _) = unlock_with(path, i == 0)?;
+        dbs.push(db);
+    }
+
+    let mut matches = vec![];
+    for (path, db) in db_paths.iter().zip(&dbs) {
+        let scores = usage::scores(path).unwrap_or_default();
+        for (entry_path, entry) in address::find_entries(db, query)? {
+            let score = scores.get(&entry.uuid).copied().unwrap_or(0.);
+            matches.push((path, db, entry_path, entry, score));
+        }
+    }
+    matches.sort_by(|a, b| b.4.total_cmp(&a.4));
+    let (path, db, entry_path, entry, _) = match matches.as_slice() {
+        [] => return Err(Error::NotFound(format!("No entry matches '{}'", query)).into()),
+        [only] => only,
+        [best, next, ..] if best.4 > next.4 => best,
+        _ => {
+            let paths: Vec<String> = matches
+                .iter()
+                .map(|(.., entry_path, _, _)| address::format(entry_path))
+                .collect();
+            return Err(Error::Ambiguous(format!(
+                "Multiple entries match '{}' and none is used most: {}",
+                query,
+                paths.join(", ")
+            ))
+            .into());
+        }
+    };
+
+    if protect::is_required(entry) && !protect::verify(path)? {
+        return Ok(());
+    }
+    let resolved = reference::resolved(db, entry);
+    let resolved = Entry(&resolved);
+    let password = resolved
+        .password()
+        .ok_or_else(|| anyhow!("{} has no password", address::format(entry_path)))?;
+    copy_from_entry(&resolved, password)?;
+    info!(
+        "{}",
+        tr!("copied-password-of", entry = address::format(entry_path))
+    );
+    if let Err(err) = usage::record(path, &entry.uuid) {
+        warn!("{}", tr!("usage-record-failed", error = err));
+    }
+    activity::record(path, "copied", &Entry(entry).to_string())?;
+    Ok(())
+}
@@ -0,0 +1,263 @@
+//! Master key recovery codes.
+//!
+//! Each recovery code wraps (encrypts) the full master password.
+//! The wrapped secrets can't live inside the database itself,
+//! as everything there is encrypted by the master key we're trying
+//! to recover, so they're stored in a sidecar file next to the database.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use cocoon::Cocoon;
+use inquire::{Confirm, Password, PasswordDisplayMode};
+use keepass::Database;
+use passwords::PasswordGenerator;
+
+use crate::{error::Error, lockout, secret::Secret, PW_CACHE};
+
+/// How many recovery codes to generate.
+const N_CODES: usize = 8;
+
+/// Path of the sidecar file holding the wrapped secrets.
+pub fn sidecar_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".recovery");
+    db_path.with_file_name(name)
+}
+
+/// Write the wrapped secrets to a temporary file only the user can read,
+/// then rename it into place, replacing any previous codes.
+fn write_sidecar(path: &Path, lines: &str) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", name));
+    let _ = fs::remove_file(&temp_path);
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(lines.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+/// Normalize a code as entered by the user.
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Generate a code, formatted as dash-separated groups for readability.
+fn generate_code() -> Result<String> {
+    let pg = PasswordGenerator {
+        length: 20,
+        numbers: true,
+        lowercase_letters: false,
+        uppercase_letters: true,
+        symbols: false,
+        spaces: false,
+        exclude_similar_characters: true,
+        strict: true,
+    };
+    let code: Vec<char> = pg
+        .generate_one()
+        .map_err(Error::Generator)?
+        .chars()
+        .collect();
+    Ok(code
+        .chunks(5)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// Wrap the password with a (normalized) code.
+fn wrap(code: &str, pass: &str) -> Result<Vec<u8>> {
+    Cocoon::new(code.as_bytes())
+        .wrap(pass.as_bytes())
+        .map_err(|err| anyhow!("Failed to wrap secret: {:?}", err))
+}
+
+/// The password wrapped by any of `wrapped` with the (normalized) code.
+fn unwrap(code: &str, wrapped: &[Vec<u8>]) -> Option<Vec<u8>> {
+    let cocoon = Cocoon::parse_only(code.as_bytes());
+    wrapped.iter().find_map(|w| cocoon.unwrap(w).ok())
+}
+
+/// Interactively enable recovery codes for the database.
+pub fn enable(db_path: &Path) -> Result<()> {
+    warn!("{}", tr!("recovery-codes-intro"));
+    warn!("{}", tr!("recovery-codes-sensitive"));
+    warn!("{}", tr!("recovery-codes-offline"));
+    warn!("{}", tr!("recovery-codes-replaced"));
+    let confirm = Confirm::new(&tr!("generate-recovery-codes"))
+        .with_default(false)
+        .prompt()?;
+    if !confirm {
+        return Ok(());
+    }
+
+    let pass = Secret::new(
+        Password::new(&tr!("confirm-full-password"))
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_formatter(&|_| String::from("🔑"))
+            .without_confirmation()
+            .prompt()?,
+    );
+    lockout::check()?;
+    let key = crate::database_key(db_path, &pass)?;
+    if Database::open(&mut File::open(db_path)?, key).is_err() {
+        lockout::record_failure()?;
+        return Err(anyhow!("Incorrect password"));
+    }
+    lockout::reset()?;
+
+    info!("{}", tr!("generating-codes"));
+    let mut codes = vec![];
+    let mut lines = String::new();
+    for _ in 0..N_CODES {
+        let code = generate_code()?;
+        let wrapped = wrap(&normalize(&code), pass.expose())?;
+        lines.push_str(&hex::encode(wrapped));
+        lines.push('\n');
+        codes.push(code);
+    }
+    write_sidecar(&sidecar_path(db_path), &lines)?;
+
+    println!("-- Recovery Codes -------");
+    for code in codes {
+        println!("{}", code);
+    }
+    println!("-------------------------");
+    info!("{}", tr!("recovery-codes-use"));
+    Ok(())
+}
+
+/// Recover the database with a recovery code and set a new master password.
+pub fn recover(db_path: &Path) -> Result<()> {
+    let path = sidecar_path(db_path);
+    if !path.exists() {
+        return Err(anyhow!("No recovery codes exist for this database."));
+    }
+
+    let wrapped: Vec<Vec<u8>> = BufReader::new(File::open(&path)?)
+        .lines()
+        .map(|line| Ok(hex::decode(line?.trim())?))
+        .collect::<Result<_>>()?;
+
+    let _lock = crate::lock_db(db_path)?;
+    lockout::check()?;
+    let code = Secret::new(
+        Password::new(&tr!("recovery-code"))
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .without_confirmation()
+            .prompt()?,
+    );
+    let Some(pass) = unwrap(&normalize(code.expose()), &wrapped) else {
+        lockout::record_failure()?;
+        return Err(anyhow!("Recovery code is incorrect"));
+    };
+    lockout::reset()?;
+    let pass = Secret::new(String::from_utf8(pass)?);
+
+    let key = crate::database_key(db_path, &pass)?;
+    let mut db = Database::open(&mut File::open(db_path)?, key)
+        .map_err(|_| anyhow!("Recovered password no longer opens the database"))?;
+
+    info!("{}", tr!("recovered"));
+    let new_pass = Secret::new(
+        Password::new(&tr!("new-password"))
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_formatter(&|_| String::from("🔑"))
+            .prompt()?,
+    );
+    let new_key = crate::database_key(db_path, &new_pass)?;
+    crate::save_db(&mut db, &new_key, db_path)?;
+
+    // The remaining codes wrap the old password, so they're useless now.
+    std::fs::remove_file(&path)?;
+    let pw_cache = Path::new(PW_CACHE);
+    if pw_cache.exists() {
+        std::fs::remove_file(pw_cache)?;
+    }
+    info!("{}", tr!("saved-recovery-codes-used"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_readable_codes() {
+        let code = generate_code().unwrap();
+        let groups: Vec<&str> = code.split('-').collect();
+        assert_eq!(groups.len(), 4);
+        assert!(groups.iter().all(|group| group.len() == 5));
+        assert!(code
+            .chars()
+            .all(|c| c == '-' || c.is_ascii_uppercase() || c.is_ascii_digit()));
+        assert_eq!(normalize(&code).len(), 20);
+    }
+
+    #[test]
+    fn replaces_the_sidecar_privately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "kpass-test-recovery-{}.recovery",
+            std::process::id()
+        ));
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_sidecar(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn normalizes_codes_as_entered() {
+        assert_eq!(normalize(" abcde-12345 FGHIJ "), "ABCDE12345FGHIJ");
+        assert_eq!(normalize("ab—cd"), "ABCD");
+    }
+
+    #[test]
+    fn codes_unwrap_the_password() {
+        let codes = ["AAAAA-BBBBB-CCCCC-DDDDD", "EEEEE-FFFFF-GGGGG-HHHHH"];
+        let wrapped: Vec<Vec<u8>> = codes
+            .iter()
+            .map(|code| wrap(&normalize(code), "correct horse").unwrap())
+            .collect();
+        for code in codes {
+            let entered = normalize(&code.to_lowercase());
+            assert_eq!(unwrap(&entered, &wrapped).unwrap(), b"correct horse");
+        }
+        assert!(unwrap(&normalize("EEEEE-FFFFF-GGGGG-HHHHI"), &wrapped).is_none());
+    }
+
+    #[test]
+    fn sidecar_is_next_to_the_database() {
+        assert_eq!(
+            sidecar_path(Path::new("/home/me/vault.kdbx")),
+            Path::new("/home/me/vault.kdbx.recovery")
+        );
+    }
+}
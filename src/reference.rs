@@ -0,0 +1,316 @@
+//! Placeholders in field values, as KeePass supports them, resolved when
+//! using entries (viewing, copying, auto-typing, serving them). They're
+//! stored as they are.
+//!
+//! - The entry's own fields: `{TITLE}`, `{USERNAME}`, `{PASSWORD}`, `{URL}`,
+//!   `{NOTES}` and `{S:<field>}`, e.g. a URL `https://example.com/?user={USERNAME}`.
+//! - Field references, e.g. `{REF:P@I:46C9B1FFBD4ABC4BBB260C6190BAD20C}` for the
+//!   password of the entry with that UUID, so entries can share credentials.
+//!
+//! The reference syntax is `{REF:<wanted>@<search in>:<text>}`, where both fields are
+//! one of `T` (title), `U` (username), `P` (password), `A` (URL), `N` (notes)
+//! or `I` (UUID), and `<search in>` can also be `O` (any other field).
+//! The first entry whose field contains the text (ignoring case) is used,
+//! or whose UUID is the text. Placeholders that can't be resolved (or that
+//! aren't for fields, like auto-type's `{TAB}`) are left as they are.
+
+use std::borrow::Cow;
+
+use keepass::{
+    db::{Entry as KEntry, Value},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{entries, STANDARD_FIELDS};
+
+/// How deep placeholders in resolved values are resolved,
+/// so placeholders in a cycle don't loop forever.
+const MAX_DEPTH: usize = 10;
+
+fn field_name(code: char) -> Option<&'static str> {
+    match code.to_ascii_uppercase() {
+        'T' => Some("Title"),
+        'U' => Some("UserName"),
+        'P' => Some("Password"),
+        'A' => Some("URL"),
+        'N' => Some("Notes"),
+        _ => None,
+    }
+}
+
+/// The entry a reference points to, and the field wanted from it.
+fn target<'a>(db: &'a Database, reference: &str) -> Option<(&'a KEntry, char)> {
+    let (wanted, rest) = reference.split_once('@')?;
+    let (search_in, text) = rest.split_once(':')?;
+    let [wanted] = wanted.chars().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let [search_in] = search_in.chars().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let text = text.to_lowercase();
+    let contains = |value: Option<&str>| value.is_some_and(|v| v.to_lowercase().contains(&text));
+    let entry = match search_in.to_ascii_uppercase() {
+        'I' => {
+            let uuid = Uuid::parse_str(&text).ok()?;
+            entries(db).find(|entry| entry.uuid == uuid)
+        }
+        'O' => entries(db).find(|entry| {
+            entry
+                .fields
+                .keys()
+                .filter(|name| !STANDARD_FIELDS.contains(&name.as_str()))
+                .any(|name| contains(entry.get(name)))
+        }),
+        code => {
+            let name = field_name(code)?;
+            entries(db).find(|entry| contains(entry.get(name)))
+        }
+    }?;
+    Some((entry, wanted))
+}
+
+/// The value of a placeholder (without braces) in a value of `entry`.
+fn expand(db: &Database, entry: &KEntry, placeholder: &str, depth: usize) -> Option<String> {
+    let upper = placeholder.to_ascii_uppercase();
+    if upper.starts_with("REF:") {
+        let (target, wanted) = target(db, &placeholder[4..])?;
+        return match wanted.to_ascii_uppercase() {
+            'I' => Some(target.uuid.simple().to_string().to_uppercase()),
+            code => Some(resolve_at(
+                db,
+                target,
+                target.get(field_name(code)?)?,
+                depth + 1,
+            )),
+        };
+    }
+    let value = match upper.as_str() {
+        "TITLE" => entry.get_title(),
+        "USERNAME" => entry.get_username(),
+        "PASSWORD" => entry.get_password(),
+        "URL" => entry.get_url(),
+        "NOTES" => entry.get("Notes"),
+        _ if upper.starts_with("S:") => {
+            return Some(resolve_at(
+                db,
+                entry,
+                entry.get(&placeholder[2..])?,
+                depth + 1,
+            ))
+        }
+        _ => return None,
+    };
+    Some(resolve_at(db, entry, value.unwrap_or(""), depth + 1))
+}
+
+fn resolve_at(db: &Database, entry: &KEntry, value: &str, depth: usize) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let placeholder = &rest[1..end];
+        if placeholder.contains('{') {
+            // Not a placeholder, but one may follow.
+            out.push('{');
+            rest = &rest[1..];
+            continue;
+        }
+        let expanded = (depth < MAX_DEPTH)
+            .then(|| expand(db, entry, placeholder, depth))
+            .flatten();
+        out.push_str(expanded.as_deref().unwrap_or(&rest[..=end]));
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The field references in the entry's values (without braces) that
+/// don't point to any entry, e.g. to one deleted since.
+pub fn broken(db: &Database, entry: &KEntry) -> Vec<String> {
+    let mut broken = vec![];
+    for name in entry.fields.keys() {
+        let Some(mut rest) = entry.get(name) else {
+            continue;
+        };
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start + 1..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let placeholder = &rest[..end];
+            let is_reference = placeholder
+                .get(..4)
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case("REF:"));
+            if is_reference && target(db, &placeholder[4..]).is_none() {
+                broken.push(placeholder.to_string());
+            }
+        }
+    }
+    broken.sort();
+    broken
+}
+
+/// A value of `entry` with its references and placeholders resolved.
+pub fn resolve(db: &Database, entry: &KEntry, value: &str) -> String {
+    resolve_at(db, entry, value, 0)
+}
+
+/// The entry with references and placeholders in its fields resolved,
+/// to use it. Don't save it, or they're lost.
+pub fn resolved<'a>(db: &Database, entry: &'a KEntry) -> Cow<'a, KEntry> {
+    let has_placeholders = entry
+        .fields
+        .keys()
+        .any(|name| entry.get(name).is_some_and(|value| value.contains('{')));
+    if !has_placeholders {
+        return Cow::Borrowed(entry);
+    }
+    let mut resolved = entry.clone();
+    for value in resolved.fields.values_mut() {
+        *value = match value {
+            Value::Unprotected(text) => Value::Unprotected(resolve(db, entry, text)),
+            Value::Protected(data) => match std::str::from_utf8(data.unsecure()) {
+                Ok(text) => Value::Protected(resolve(db, entry, text).as_bytes().into()),
+                Err(_) => continue,
+            },
+            Value::Bytes(_) => continue,
+        };
+    }
+    Cow::Owned(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn entry(fields: &[(&str, &str)]) -> KEntry {
+        test_util::entry_protecting(fields, &["Password"])
+    }
+
+    /// A database with `GitHub` (which `Deploy` refers to) and `entries`.
+    fn db_with(entries: Vec<KEntry>) -> (Database, Uuid) {
+        let mut db = Database::new(Default::default());
+        let github = entry(&[
+            ("Title", "GitHub"),
+            ("UserName", "alice"),
+            ("Password", "hunter2"),
+            ("URL", "https://github.com"),
+            ("Team", "Platform"),
+        ]);
+        let uuid = github.uuid;
+        db.root.add_child(github);
+        for entry in entries {
+            db.root.add_child(entry);
+        }
+        (db, uuid)
+    }
+
+    fn resolved_in(value: &str) -> String {
+        let (db, _) = db_with(vec![]);
+        let entry = entry(&[("Title", "Deploy"), ("UserName", "deployer")]);
+        resolve(&db, &entry, value)
+    }
+
+    #[test]
+    fn resolves_references() {
+        let (db, uuid) = db_with(vec![]);
+        let by_uuid = format!("{{REF:P@I:{}}}", uuid.simple());
+        let deploy = entry(&[("Title", "Deploy")]);
+        assert_eq!(resolve(&db, &deploy, &by_uuid), "hunter2");
+        assert_eq!(resolved_in("{REF:U@T:github}"), "alice");
+        assert_eq!(resolved_in("{ref:a@u:ALI}"), "https://github.com");
+        assert_eq!(resolved_in("{REF:T@O:platform}"), "GitHub");
+        assert_eq!(
+            resolve(&db, &deploy, "{REF:I@T:GitHub}"),
+            uuid.simple().to_string().to_uppercase()
+        );
+        assert_eq!(
+            resolved_in("{REF:U@T:GitHub}:{REF:P@T:GitHub}"),
+            "alice:hunter2"
+        );
+    }
+
+    #[test]
+    fn leaves_unresolvable_references() {
+        for value in [
+            "{REF:P@T:nothing}",
+            "{REF:X@T:GitHub}",
+            "{REF:P@X:GitHub}",
+            "{REF:PP@T:GitHub}",
+            "{REF:P@I:not-a-uuid}",
+            "{REF:P}",
+        ] {
+            assert_eq!(resolved_in(value), value);
+        }
+    }
+
+    #[test]
+    fn stops_at_cycles() {
+        let mut looping = entry(&[("Title", "Loop")]);
+        looping.fields.insert(
+            "Password".to_string(),
+            Value::Unprotected("{REF:P@T:Loop}".to_string()),
+        );
+        let (db, _) = db_with(vec![looping.clone()]);
+        assert_eq!(resolve(&db, &looping, "{REF:P@T:Loop}"), "{REF:P@T:Loop}");
+    }
+
+    #[test]
+    fn resolves_entries_for_use() {
+        let deploy = entry(&[("Title", "Deploy"), ("Password", "{REF:P@T:GitHub}")]);
+        let (db, _) = db_with(vec![]);
+        let resolved = resolved(&db, &deploy);
+        assert!(matches!(resolved, Cow::Owned(_)));
+        assert_eq!(resolved.get_password(), Some("hunter2"));
+        assert!(matches!(resolved.fields["Password"], Value::Protected(_)));
+        let plain = entry(&[("Title", "Plain")]);
+        assert!(matches!(super::resolved(&db, &plain), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn resolves_own_fields() {
+        assert_eq!(resolved_in("{TITLE} as {username}"), "Deploy as deployer");
+        assert_eq!(
+            resolved_in("https://x.com/?u={USERNAME}"),
+            "https://x.com/?u=deployer"
+        );
+        assert_eq!(resolved_in("{URL}{NOTES}"), "");
+        assert_eq!(resolved_in("{S:Title}"), "Deploy");
+        assert_eq!(resolved_in("{S:Missing}"), "{S:Missing}");
+    }
+
+    #[test]
+    fn leaves_other_braces() {
+        assert_eq!(resolved_in("{TAB}{ENTER}"), "{TAB}{ENTER}");
+        assert_eq!(resolved_in("{"), "{");
+        assert_eq!(resolved_in("a}b"), "a}b");
+        assert_eq!(resolved_in("{ {TITLE}"), "{ Deploy");
+        assert_eq!(resolved_in("{{TITLE}}"), "{Deploy}");
+    }
+
+    #[test]
+    fn finds_broken_references() {
+        let deploy = entry(&[
+            ("Title", "Deploy"),
+            ("UserName", "{REF:U@T:GitHub}"),
+            ("Password", "{REF:P@T:Deleted}"),
+            ("Notes", "{ref:n@i:00000000000000000000000000000000} {TAB}"),
+        ]);
+        let (db, _) = db_with(vec![]);
+        assert_eq!(
+            broken(&db, &deploy),
+            [
+                "REF:P@T:Deleted",
+                "ref:n@i:00000000000000000000000000000000"
+            ]
+        );
+    }
+}
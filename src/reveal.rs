@@ -0,0 +1,68 @@
+//! Reveal sessions: for a few minutes after re-entering the full
+//! master password, secrets are shown on screen instead of only copied,
+//! e.g. to check a batch of them against another system.
+
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::{config, prompt, protect};
+
+/// When the current reveal session ends, if there is one.
+static UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Start a reveal session, after checking the full master password
+/// (the quick password isn't enough).
+pub fn start(db_path: &Path) -> Result<()> {
+    if !protect::verify(db_path)? {
+        return Ok(());
+    }
+
+    let minutes: u64 = prompt::number_in_range(
+        "Reveal secrets for (minutes):",
+        config::get().reveal_minutes,
+        1..=60,
+    )?;
+    *UNTIL.lock().unwrap() = Some(Instant::now() + Duration::from_secs(minutes * 60));
+    info!("{}", tr!("reveal-started", minutes = minutes));
+    Ok(())
+}
+
+/// End the reveal session early.
+pub fn end() {
+    *UNTIL.lock().unwrap() = None;
+    info!("{}", tr!("reveal-ended"));
+}
+
+/// Time left in the reveal session, if one is active.
+pub fn remaining() -> Option<Duration> {
+    let mut until = UNTIL.lock().unwrap();
+    let left = until.as_ref()?.checked_duration_since(Instant::now());
+    if left.is_none() {
+        *until = None;
+        info!("{}", tr!("reveal-expired"));
+    }
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_end() {
+        *UNTIL.lock().unwrap() = Some(Instant::now() + Duration::from_secs(60));
+        assert!(remaining().is_some_and(|left| left > Duration::from_secs(50)));
+        end();
+        assert_eq!(remaining(), None);
+
+        // Over, so it's ended.
+        *UNTIL.lock().unwrap() = Instant::now().checked_sub(Duration::from_secs(1));
+        assert_eq!(remaining(), None);
+        assert_eq!(*UNTIL.lock().unwrap(), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Rotating the passwords of many entries at once (`kpass rotate`),
+//! e.g. of all work accounts whose passwords are over a year old.
+//!
+//! New passwords are generated with the default settings, made as long
+//! as the audit rules for the entry require (see `audit::RuleConfig`).
+//! The old passwords are kept in the entries' history.
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use keepass::{
+    db::{Times, Value},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{address, audit, config, entries_with_paths, generate, index::Index, Entry};
+
+/// Which entries to rotate.
+#[derive(Default)]
+pub struct Filter {
+    pub tag: Option<String>,
+    /// A group path, including subgroups.
+    pub group: Option<String>,
+    /// Only passwords last changed more than this many days ago.
+    pub older_than_days: Option<i64>,
+}
+
+/// An entry whose password is due, for the checklist.
+pub struct Due {
+    uuid: Uuid,
+    group: Vec<String>,
+    pub path: String,
+    pub url: Option<String>,
+    /// Days since the password was last changed, if known.
+    pub age_days: Option<i64>,
+}
+
+/// Parse an age like `90d`, `2w`, `6m` or `1y` into days.
+pub fn parse_age(age: &str) -> Result<i64> {
+    let invalid = || anyhow!("Invalid age {:?}, expected e.g. 90d, 2w, 6m or 1y", age);
+    let unit = age.chars().last().ok_or_else(invalid)?;
+    let n: i64 = age[..age.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let days = match unit {
+        'd' => 1,
+        'w' => 7,
+        'm' => 30,
+        'y' => 365,
+        _ => return Err(invalid()),
+    };
+    Ok(n * days)
+}
+
+/// The entries with passwords the filter matches.
+pub fn matching(db: &Database, filter: &Filter) -> Vec<Due> {
+    let rule = audit::RuleConfig {
+        group: filter.group.clone(),
+        tag: filter.tag.clone(),
+        ..Default::default()
+    };
+    let now = Utc::now().naive_utc();
+    let mut due = vec![];
+    for (group, entry) in entries_with_paths(&db.root) {
+        if entry.get_password().unwrap_or("").is_empty() || !rule.applies(entry, &group) {
+            continue;
+        }
+        let age_days = audit::password_changed(entry).map(|changed| (now - changed).num_days());
+        if let Some(min) = filter.older_than_days {
+            // Without a date, it can't be told to be recent.
+            if age_days.is_some_and(|age| age <= min) {
+                continue;
+            }
+        }
+        let mut path = group.clone();
+        path.push(Entry(entry).to_string());
+        due.push(Due {
+            uuid: entry.uuid,
+            group,
+            path: address::format(&path),
+            url: entry
+                .get_url()
+                .filter(|url| !url.is_empty())
+                .map(str::to_string),
+            age_days,
+        });
+    }
+    due
+}
+
+/// Give each entry a new password, keeping the old one in its history.
+pub fn rotate(db: &mut Database, due: &[Due]) -> Result<()> {
+    let index = Index::build(db);
+    for due in due {
+        let entry = index
+            .entry_mut(db, &due.uuid)
+            .ok_or_else(|| anyhow!("{} no longer exists", due.path))?;
+
+        let mut pg = generate::default_generator();
+        // As long as the entry's rules require.
+        for rule in &config::get().audit_rules {
+            if let Some(min) = rule.min_length.filter(|_| rule.applies(entry, &due.group)) {
+                pg.length = pg.length.max(min);
+            }
+        }
+        let password = generate::many(&pg, 1)?
+            .pop()
+            .ok_or_else(|| anyhow!("No password generated"))?;
+
+        let before = entry.clone();
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected(password.expose().as_bytes().into()),
+        );
+        entry
+            .history
+            .get_or_insert_with(Default::default)
+            .add_entry(before);
+        entry.times.set_last_modification(Times::now());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::{Entry as KEntry, Group};
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, password: &str, tags: &[&str], days_ago: i64) -> KEntry {
+        let mut entry =
+            test_util::entry_protecting(&[("Title", title), ("Password", password)], &["Password"]);
+        entry.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    /// GitHub (tagged `dev`) and an old VPN in `Work`,
+    /// a bank in `Home` and an entry without a password.
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", "a", &["dev"], 10));
+        work.add_child(entry("VPN", "b", &[], 400));
+        let mut home = Group::new("Home");
+        home.add_child(entry("Bank", "c", &[], 100));
+        home.add_child(entry("Note", "", &[], 1000));
+        db.root.add_child(work);
+        db.root.add_child(home);
+        db
+    }
+
+    fn paths(db: &Database, filter: &Filter) -> Vec<String> {
+        matching(db, filter)
+            .into_iter()
+            .map(|due| due.path)
+            .collect()
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("90d").unwrap(), 90);
+        assert_eq!(parse_age("2w").unwrap(), 14);
+        assert_eq!(parse_age("6m").unwrap(), 180);
+        assert_eq!(parse_age("1y").unwrap(), 365);
+        for invalid in ["", "d", "90", "1.5y", "-x", "3é"] {
+            assert!(parse_age(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn matches_entries_with_passwords() {
+        let db = database();
+        assert_eq!(
+            paths(&db, &Filter::default()),
+            ["Work/GitHub", "Work/VPN", "Home/Bank"]
+        );
+        let work = Filter {
+            group: Some("work".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(paths(&db, &work), ["Work/GitHub", "Work/VPN"]);
+        let dev = Filter {
+            tag: Some("dev".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(paths(&db, &dev), ["Work/GitHub"]);
+        let old = Filter {
+            older_than_days: Some(90),
+            ..Default::default()
+        };
+        let due = matching(&db, &old);
+        assert_eq!(due.len(), 2);
+        assert_eq!(
+            (due[0].path.as_str(), due[0].age_days),
+            ("Work/VPN", Some(400))
+        );
+    }
+
+    #[test]
+    fn keeps_old_passwords_in_history() {
+        let mut db = database();
+        let due = matching(&db, &Filter::default());
+        rotate(&mut db, &due[..2]).unwrap();
+        let entries = entries_with_paths(&db.root);
+        for (old, (_, entry)) in ["a", "b"].iter().zip(&entries) {
+            let password = entry.get_password().unwrap();
+            assert_ne!(password, *old);
+            assert!(password.len() >= generate::default_generator().length);
+            let history = entry.history.as_ref().unwrap().get_entries();
+            assert_eq!(history[0].get_password(), Some(*old));
+        }
+        assert_eq!(entries[2].1.get_password(), Some("c"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Sandboxing with Landlock (`--sandbox`), limiting what kpass (and the
+//! programs it runs) can reach if it's compromised, e.g. through a crafted
+//! database or import.
+//!
+//! No seccomp filter is applied. Landlock already takes away what matters
+//! here, the network and files outside kpass' own, while a syscall filter
+//! would have to allow whatever the programs kpass runs need (`$EDITOR`,
+//! `gpg`, pinentry, clipboard and typing tools), so it would either break
+//! them or block little.
+
+use std::{
+    env, fs,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use landlock::{
+    path_beneath_rules, Access, AccessFs, AccessNet, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetStatus, ABI,
+};
+
+use crate::{agent, config, config::config_dir, pw_cache};
+
+/// Directories needed to run kpass and the programs it spawns
+/// (e.g. `$EDITOR`), which only need to be read.
+const SYSTEM_DIRS: &[&str] = &["/usr", "/bin", "/lib", "/lib64", "/etc", "/proc"];
+
+/// Whether `--sandbox` was given.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sandbox kpass (see `apply`), refusing what needs the network from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Fail if sandboxed, as `what` needs the network, rather than
+/// letting its connections fail later on (e.g. after unlocking).
+pub fn needs_network(what: &str) -> Result<()> {
+    if is_enabled() {
+        return Err(anyhow!(
+            "{} needs the network, which --sandbox blocks",
+            what
+        ));
+    }
+    Ok(())
+}
+
+/// `~/.gnupg` (or `$GNUPGHOME`), for gpg, e.g. to decrypt the password cache.
+fn gnupg_dir() -> Option<PathBuf> {
+    match env::var_os("GNUPGHOME") {
+        Some(dir) => Some(dir.into()),
+        None => Some(PathBuf::from(env::var_os("HOME")?).join(".gnupg")),
+    }
+}
+
+/// Rules only apply to paths that exist, so create the directories
+/// kpass writes to, or warn that they won't be accessible.
+fn create_dirs(dirs: &[PathBuf]) {
+    for dir in dirs {
+        if let Err(err) = fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+        {
+            warn!(
+                "{}",
+                tr!("sandbox-create-failed", path = dir.display(), error = err)
+            );
+        }
+    }
+}
+
+/// Restrict the process (and its children) with Landlock:
+/// no TCP connections at all, and filesystem access limited to
+/// the directories of `files` (e.g. the database), the runtime dir,
+/// `/tmp`, config and `~/.gnupg`. Key files and the age identity
+/// can only be read.
+pub fn apply(files: &[&Path]) -> Result<()> {
+    let abi = ABI::V4;
+    if config::get().git_push {
+        warn!("{}", tr!("sandbox-blocks-push"));
+    }
+
+    let mut writable: Vec<PathBuf> = vec!["/tmp".into(), "/dev".into()];
+    for file in files {
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+        if let Some(parent) = file.parent().and_then(|p| p.canonicalize().ok()) {
+            writable.push(parent);
+        }
+    }
+    let mut created = vec![];
+    if let Some(dir) = agent::socket_path().parent() {
+        created.push(dir.to_path_buf());
+    }
+    if let Some(dir) = config_dir() {
+        created.push(dir);
+    }
+    if let Some(dir) = &config::get().backup_dir {
+        created.push(dir.clone());
+    }
+    create_dirs(&created);
+    writable.extend(created);
+    writable.extend(gnupg_dir().filter(|dir| dir.exists()));
+
+    let mut readable: Vec<PathBuf> = config::get().key_files.values().cloned().collect();
+    if let Ok(pw_cache::Method::Age(identity)) = pw_cache::Method::configured() {
+        readable.push(identity.into());
+    }
+    for path in readable.iter().filter(|path| !path.exists()) {
+        warn!("{}", tr!("sandbox-missing", path = path.display()));
+    }
+
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .handle_access(AccessNet::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(SYSTEM_DIRS, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(&readable, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(&writable, AccessFs::from_all(abi)))?
+        .restrict_self()?;
+
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => {}
+        RulesetStatus::PartiallyEnforced => {
+            warn!("{}", tr!("sandbox-partial"))
+        }
+        RulesetStatus::NotEnforced => {
+            warn!("{}", tr!("sandbox-unsupported"))
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use zeroize::Zeroize;
+
+/// A secret string (e.g. a password).
+/// The backing buffer is locked into memory (where possible)
+/// so it isn't swapped to disk, and is wiped on drop.
+pub struct Secret(String);
+impl Secret {
+    pub fn new(value: String) -> Self {
+        lock(value.as_bytes());
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret::new(value)
+    }
+}
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+impl Drop for Secret {
+    fn drop(&mut self) {
+        unlock(self.0.as_bytes());
+        self.0.zeroize();
+    }
+}
+
+/// How many secrets are on each locked page, by address.
+/// Pages are locked and unlocked as a whole, and secrets can share them,
+/// so a page is only unlocked once the last secret on it is dropped.
+static LOCKED: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Whether `lock_all` locked everything, so nothing should be unlocked.
+static LOCKED_ALL: AtomicBool = AtomicBool::new(false);
+
+fn page_size() -> usize {
+    // SAFETY: `sysconf` only reads a system setting.
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+/// The addresses of the pages `buf` spans.
+fn pages(buf: &[u8], page_size: usize) -> impl Iterator<Item = usize> {
+    span(buf.as_ptr() as usize, buf.len(), page_size)
+}
+
+fn span(addr: usize, len: usize, page_size: usize) -> impl Iterator<Item = usize> {
+    (addr / page_size * page_size..addr + len).step_by(page_size)
+}
+
+/// Try to lock the memory backing `buf`.
+/// Failures are ignored as `mlock` is subject to `RLIMIT_MEMLOCK`.
+fn lock(buf: &[u8]) {
+    if buf.is_empty() || LOCKED_ALL.load(Ordering::Relaxed) {
+        return;
+    }
+    let page_size = page_size();
+    let mut locked = LOCKED.lock().unwrap_or_else(|err| err.into_inner());
+    for page in pages(buf, page_size) {
+        let count = locked.entry(page).or_default();
+        if *count == 0 {
+            // SAFETY: the page is mapped, as it backs part of `buf`,
+            // and `mlock` doesn't change its contents.
+            unsafe {
+                libc::mlock(page as *const libc::c_void, page_size);
+            }
+        }
+        *count += 1;
+    }
+}
+
+fn unlock(buf: &[u8]) {
+    if buf.is_empty() || LOCKED_ALL.load(Ordering::Relaxed) {
+        return;
+    }
+    let page_size = page_size();
+    let mut locked = LOCKED.lock().unwrap_or_else(|err| err.into_inner());
+    for page in pages(buf, page_size) {
+        let Some(count) = locked.get_mut(&page) else {
+            continue;
+        };
+        *count -= 1;
+        if *count == 0 {
+            locked.remove(&page);
+            // SAFETY: as in `lock`; no other secret is on the page.
+            unsafe {
+                libc::munlock(page as *const libc::c_void, page_size);
+            }
+        }
+    }
+}
+
+/// Prevent secrets from leaking into core dumps.
+pub fn disable_core_dumps() {
+    // SAFETY: both only change process settings, and `limit`
+    // outlives the call.
+    unsafe {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        libc::setrlimit(libc::RLIMIT_CORE, &limit);
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
+
+/// Lock all current and future memory of the process,
+/// for long-lived processes holding the decrypted database.
+pub fn lock_all() {
+    // SAFETY: `mlockall` only changes how memory is paged.
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+        LOCKED_ALL.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(page: usize) -> usize {
+        LOCKED.lock().unwrap().get(&page).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn spans_pages() {
+        let spanned = |addr, len| span(addr, len, 4096).collect::<Vec<_>>();
+        assert_eq!(spanned(4096, 1), [4096]);
+        assert_eq!(spanned(8191, 1), [4096]);
+        assert_eq!(spanned(8191, 2), [4096, 8192]);
+        assert_eq!(spanned(4100, 8192), [4096, 8192, 12288]);
+        assert_eq!(spanned(4096, 4096), [4096]);
+    }
+
+    #[test]
+    fn shared_pages_stay_locked() {
+        // A page of its own, so other tests' secrets aren't on it.
+        let page_size = page_size();
+        let layout = std::alloc::Layout::from_size_align(page_size, page_size).unwrap();
+        // SAFETY: the layout isn't empty, and the page is freed below.
+        let page = unsafe { std::alloc::alloc_zeroed(layout) };
+        assert!(!page.is_null());
+        // SAFETY: the page was allocated above, zeroed.
+        let buf = unsafe { std::slice::from_raw_parts(page, page_size) };
+        let (first, second) = (&buf[..8], &buf[8..16]);
+        lock(first);
+        lock(second);
+        assert_eq!(count(page as usize), 2);
+        unlock(first);
+        assert_eq!(count(page as usize), 1);
+        unlock(second);
+        assert_eq!(count(page as usize), 0);
+        // Unlocking what wasn't locked is ignored.
+        unlock(first);
+        assert_eq!(count(page as usize), 0);
+        // SAFETY: allocated above with the same layout, and unused since.
+        unsafe { std::alloc::dealloc(page, layout) };
+    }
+
+    #[test]
+    fn hidden_from_debug() {
+        let secret = Secret::new("hunter2".into());
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+    }
+
+    #[test]
+    fn database_keys_are_wiped() {
+        // Copies of the key (e.g. those given to `Database::open`)
+        // are wiped when dropped.
+        fn wiped_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+        wiped_on_drop::<keepass::DatabaseKey>();
+    }
+}
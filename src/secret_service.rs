@@ -0,0 +1,386 @@
+//! A read-only freedesktop Secret Service provider
+//! (`org.freedesktop.secrets`) backed by the unlocked database.
+//!
+//! Only the "plain" session algorithm is supported; the secrets
+//! never leave the user's session bus.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Result;
+use keepass::{
+    db::{Entry as KEntry, Value},
+    Database,
+};
+use zbus::{
+    blocking::connection,
+    fdo, interface,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+    ObjectServer,
+};
+
+use crate::{entries, protect, secret};
+
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const COLLECTION_PATH: &str = "/org/freedesktop/secrets/collection/kpass";
+
+/// A secret as transferred over D-Bus: (session, parameters, value, content type).
+type DbusSecret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+fn path(s: String) -> OwnedObjectPath {
+    ObjectPath::try_from(s)
+        .expect("Paths are well-formed")
+        .into()
+}
+
+fn no_prompt() -> OwnedObjectPath {
+    path("/".to_string())
+}
+
+fn not_supported() -> fdo::Error {
+    fdo::Error::NotSupported("kpass' secret service is read-only".to_string())
+}
+
+/// The entries served: all but those requiring the full master password.
+fn served(db: &Database) -> impl Iterator<Item = &KEntry> {
+    entries(db).filter(|entry| !protect::is_required(entry))
+}
+
+fn item_path(entry: &KEntry) -> OwnedObjectPath {
+    path(format!("{}/{}", COLLECTION_PATH, entry.uuid.simple()))
+}
+
+/// An entry's attributes are all of its unprotected fields,
+/// which includes any attributes other Secret Service
+/// clients (e.g. libsecret) would have stored.
+fn attributes(entry: &KEntry) -> HashMap<String, String> {
+    entry
+        .fields
+        .iter()
+        .filter_map(|(key, val)| match val {
+            Value::Unprotected(val) if key != "Notes" => Some((key.clone(), val.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn search(db: &Database, query: &HashMap<String, String>) -> Vec<OwnedObjectPath> {
+    served(db)
+        .filter(|entry| {
+            let attrs = attributes(entry);
+            query.iter().all(|(k, v)| attrs.get(k) == Some(v))
+        })
+        .map(item_path)
+        .collect()
+}
+
+fn timestamp(time: Option<&chrono::NaiveDateTime>) -> u64 {
+    time.map(|t| t.and_utc().timestamp() as u64).unwrap_or(0)
+}
+
+struct Service {
+    db: Arc<Database>,
+    sessions: AtomicUsize,
+}
+
+#[interface(name = "org.freedesktop.Secret.Service")]
+impl Service {
+    async fn open_session(
+        &self,
+        algorithm: &str,
+        _input: OwnedValue,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+        if algorithm != "plain" {
+            return Err(fdo::Error::NotSupported(format!(
+                "Unsupported algorithm: {}",
+                algorithm
+            )));
+        }
+        let n = self.sessions.fetch_add(1, Ordering::SeqCst);
+        let session = path(format!("{}/session/{}", SERVICE_PATH, n));
+        server.at(&session, Session).await?;
+        let output = OwnedValue::try_from(zbus::zvariant::Value::from(""))
+            .expect("Strings convert to owned values");
+        Ok((output, session))
+    }
+
+    fn create_collection(
+        &self,
+        _properties: HashMap<String, OwnedValue>,
+        _alias: &str,
+    ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        Err(not_supported())
+    }
+
+    fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+    ) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+        (search(&self.db, &attributes), vec![])
+    }
+
+    /// The collection is always unlocked.
+    fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        (objects, no_prompt())
+    }
+
+    fn lock(&self, _objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, OwnedObjectPath) {
+        (vec![], no_prompt())
+    }
+
+    fn get_secrets(
+        &self,
+        items: Vec<OwnedObjectPath>,
+        session: OwnedObjectPath,
+    ) -> HashMap<OwnedObjectPath, DbusSecret> {
+        served(&self.db)
+            .filter_map(|entry| {
+                let item = item_path(entry);
+                items
+                    .contains(&item)
+                    .then(|| (item, to_secret(entry, &session)))
+            })
+            .collect()
+    }
+
+    fn read_alias(&self, name: &str) -> OwnedObjectPath {
+        if name == "default" {
+            path(COLLECTION_PATH.to_string())
+        } else {
+            no_prompt()
+        }
+    }
+
+    fn set_alias(&self, _name: &str, _collection: OwnedObjectPath) -> fdo::Result<()> {
+        Err(not_supported())
+    }
+
+    #[zbus(property)]
+    fn collections(&self) -> Vec<OwnedObjectPath> {
+        vec![path(COLLECTION_PATH.to_string())]
+    }
+}
+
+fn to_secret(entry: &KEntry, session: &OwnedObjectPath) -> DbusSecret {
+    let value = entry.get_password().unwrap_or("").as_bytes().to_vec();
+    (
+        session.clone(),
+        vec![],
+        value,
+        "text/plain; charset=utf8".to_string(),
+    )
+}
+
+struct Collection {
+    db: Arc<Database>,
+}
+
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl Collection {
+    fn delete(&self) -> fdo::Result<OwnedObjectPath> {
+        Err(not_supported())
+    }
+
+    fn search_items(&self, attributes: HashMap<String, String>) -> Vec<OwnedObjectPath> {
+        search(&self.db, &attributes)
+    }
+
+    fn create_item(
+        &self,
+        _properties: HashMap<String, OwnedValue>,
+        _secret: DbusSecret,
+        _replace: bool,
+    ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        Err(not_supported())
+    }
+
+    #[zbus(property)]
+    fn items(&self) -> Vec<OwnedObjectPath> {
+        served(&self.db).map(item_path).collect()
+    }
+
+    #[zbus(property)]
+    fn label(&self) -> String {
+        self.db
+            .meta
+            .database_name
+            .clone()
+            .unwrap_or_else(|| "kpass".to_string())
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn created(&self) -> u64 {
+        timestamp(self.db.root.times.get_creation())
+    }
+
+    #[zbus(property)]
+    fn modified(&self) -> u64 {
+        timestamp(self.db.root.times.get_last_modification())
+    }
+}
+
+struct Item {
+    db: Arc<Database>,
+    uuid: uuid::Uuid,
+}
+impl Item {
+    fn entry(&self) -> fdo::Result<&KEntry> {
+        served(&self.db)
+            .find(|e| e.uuid == self.uuid)
+            .ok_or_else(|| fdo::Error::UnknownObject("No such item".to_string()))
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Item")]
+impl Item {
+    fn delete(&self) -> fdo::Result<OwnedObjectPath> {
+        Err(not_supported())
+    }
+
+    fn get_secret(&self, session: OwnedObjectPath) -> fdo::Result<DbusSecret> {
+        Ok(to_secret(self.entry()?, &session))
+    }
+
+    fn set_secret(&self, _secret: DbusSecret) -> fdo::Result<()> {
+        Err(not_supported())
+    }
+
+    #[zbus(property)]
+    fn locked(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn attributes(&self) -> fdo::Result<HashMap<String, String>> {
+        Ok(attributes(self.entry()?))
+    }
+
+    #[zbus(property)]
+    fn label(&self) -> fdo::Result<String> {
+        Ok(self.entry()?.get_title().unwrap_or("").to_string())
+    }
+
+    #[zbus(property)]
+    fn created(&self) -> fdo::Result<u64> {
+        Ok(timestamp(self.entry()?.times.get_creation()))
+    }
+
+    #[zbus(property)]
+    fn modified(&self) -> fdo::Result<u64> {
+        Ok(timestamp(self.entry()?.times.get_last_modification()))
+    }
+}
+
+struct Session;
+
+#[interface(name = "org.freedesktop.Secret.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(header)] header: zbus::message::Header<'_>,
+    ) -> fdo::Result<()> {
+        if let Some(path) = header.path() {
+            server.remove::<Session, _>(path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Serve the database over the session bus until killed.
+pub fn serve(db: Database) -> Result<()> {
+    secret::lock_all();
+
+    let db = Arc::new(db);
+    let mut builder = connection::Builder::session()?
+        .name("org.freedesktop.secrets")?
+        .serve_at(
+            SERVICE_PATH,
+            Service {
+                db: db.clone(),
+                sessions: AtomicUsize::new(0),
+            },
+        )?
+        .serve_at(COLLECTION_PATH, Collection { db: db.clone() })?
+        .serve_at(
+            "/org/freedesktop/secrets/aliases/default",
+            Collection { db: db.clone() },
+        )?;
+    for entry in served(&db) {
+        let item = Item {
+            db: db.clone(),
+            uuid: entry.uuid,
+        };
+        builder = builder.serve_at(item_path(entry), item)?;
+    }
+    let _conn = builder.build()?;
+
+    info!("{}", tr!("secret-service-serving"));
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::Group;
+
+    use super::*;
+    use crate::test_util::entry;
+
+    #[test]
+    fn finds_items_by_attributes() {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        let mut github = entry(&[
+            ("Title", "GitHub"),
+            ("UserName", "alice"),
+            ("Notes", "recovery codes"),
+            ("service", "github"),
+        ]);
+        github
+            .fields
+            .insert("Password".to_string(), Value::Protected("hunter2".into()));
+        let github_path = item_path(&github);
+        work.add_child(github);
+        let mut protected = entry(&[("Title", "Bank"), ("service", "github")]);
+        protect::set_required(&mut protected, true);
+        work.add_child(protected);
+        work.add_child(entry(&[("Title", "GitLab"), ("UserName", "alice")]));
+        db.root.add_child(work);
+
+        let github = entries(&db).next().unwrap();
+        let mut attrs: Vec<_> = attributes(github).into_keys().collect();
+        attrs.sort();
+        // Not the notes or protected fields.
+        assert_eq!(attrs, ["Title", "UserName", "service"]);
+
+        let query = |pairs: &[(&str, &str)]| {
+            let query = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            search(&db, &query)
+        };
+        // Not the entry requiring the master password.
+        assert_eq!(query(&[("service", "github")]), vec![github_path.clone()]);
+        assert_eq!(
+            query(&[("UserName", "alice"), ("Title", "GitHub")]),
+            [github_path]
+        );
+        assert_eq!(query(&[("UserName", "alice")]).len(), 2);
+        assert!(query(&[("Notes", "recovery codes")]).is_empty());
+        assert!(query(&[("service", "gitlab")]).is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+//! Splitting the master password into shares (`kpass shard`), any
+//! `threshold` of which put it back together (`--from-shares`), e.g. for
+//! a team vault no one should open alone, or to leave with people for
+//! disaster recovery. Fewer shares reveal nothing about the password.
+//!
+//! This is Shamir's secret sharing, byte by byte over GF(256). Shares are
+//! text like `kpass-2-1-<hex>` (the threshold, the share's number, and its
+//! bytes). A checksum of the password is shared along with it, so shares
+//! of different passwords (or a mistyped one) aren't taken for it.
+//! Nothing is stored: changing the master password makes shares useless.
+
+use std::{fs::File, path::Path};
+
+use anyhow::{anyhow, Result};
+use inquire::{Confirm, Password, PasswordDisplayMode};
+use keepass::Database;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::{database_key, secret::Secret};
+
+/// Bytes of the password's hash shared along with it.
+const CHECKSUM_LEN: usize = 4;
+
+const PREFIX: &str = "kpass";
+
+/// Multiplication in GF(256), with the AES polynomial.
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// The multiplicative inverse in GF(256) (of a non-zero element).
+fn inv(a: u8) -> u8 {
+    // a^254 = a^-1, as a^255 = 1.
+    let mut result = 1;
+    for _ in 0..254 {
+        result = mul(result, a);
+    }
+    result
+}
+
+/// A share: its number (the x coordinate, from 1) and bytes.
+struct Share {
+    threshold: u8,
+    x: u8,
+    ys: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    fn encode(&self) -> String {
+        format!(
+            "{}-{}-{}-{}",
+            PREFIX,
+            self.threshold,
+            self.x,
+            hex::encode(&*self.ys)
+        )
+    }
+
+    fn decode(text: &str) -> Result<Self> {
+        let invalid = || anyhow!("Not a kpass share");
+        let mut parts = text.trim().splitn(4, '-');
+        if parts.next() != Some(PREFIX) {
+            return Err(invalid());
+        }
+        let threshold = parts.next().ok_or_else(invalid)?.parse()?;
+        let x = parts.next().ok_or_else(invalid)?.parse()?;
+        let ys = Zeroizing::new(hex::decode(parts.next().ok_or_else(invalid)?)?);
+        if x == 0 || threshold < 2 || ys.len() <= CHECKSUM_LEN {
+            return Err(invalid());
+        }
+        Ok(Share { threshold, x, ys })
+    }
+}
+
+/// Split the secret into `count` shares, `threshold` of which recover it.
+fn split(secret: &[u8], threshold: u8, count: u8) -> Vec<Share> {
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|x| Share {
+            threshold,
+            x,
+            ys: Zeroizing::new(Vec::with_capacity(secret.len())),
+        })
+        .collect();
+    // The coefficients of each byte's polynomial, the byte being the constant.
+    let mut coefficients = Zeroizing::new(vec![0; threshold as usize]);
+    for byte in secret {
+        coefficients[0] = *byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for share in &mut shares {
+            // Horner's method.
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, c| mul(acc, share.x) ^ c);
+            share.ys.push(y);
+        }
+    }
+    shares
+}
+
+/// Recover the secret from shares (at least the threshold), interpolating
+/// each byte's polynomial at 0.
+fn combine(shares: &[Share]) -> Zeroizing<Vec<u8>> {
+    let len = shares[0].ys.len();
+    let mut secret = Zeroizing::new(vec![0; len]);
+    for (i, share) in shares.iter().enumerate() {
+        // The Lagrange basis polynomial at 0 (subtraction is xor).
+        let mut basis = 1;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                basis = mul(basis, mul(other.x, inv(other.x ^ share.x)));
+            }
+        }
+        for (byte, y) in secret.iter_mut().zip(share.ys.iter()) {
+            *byte ^= mul(*y, basis);
+        }
+    }
+    secret
+}
+
+fn checksum(secret: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::digest(secret)[..CHECKSUM_LEN].try_into().unwrap()
+}
+
+/// Interactively split the database's master password into shares.
+pub fn shard(db_path: &Path, threshold: u8, count: u8) -> Result<()> {
+    if threshold < 2 || count < threshold {
+        return Err(anyhow!(
+            "The threshold must be at least 2, and at most the number of shares"
+        ));
+    }
+    warn!(
+        "{}",
+        tr!("shares-unlock", threshold = threshold, count = count)
+    );
+    warn!("{}", tr!("shares-keep-apart"));
+    warn!("{}", tr!("shares-invalidated"));
+    let confirm = Confirm::new(&tr!("split-password"))
+        .with_default(false)
+        .prompt()?;
+    if !confirm {
+        return Ok(());
+    }
+
+    let pass = Secret::new(
+        Password::new(&tr!("confirm-full-password"))
+            .with_display_toggle_enabled()
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .with_formatter(&|_| String::from("🔑"))
+            .without_confirmation()
+            .prompt()?,
+    );
+    let key = database_key(db_path, &pass)?;
+    Database::open(&mut File::open(db_path)?, key).map_err(|_| anyhow!("Incorrect password"))?;
+
+    let mut secret = Zeroizing::new(pass.expose().as_bytes().to_vec());
+    secret.extend(checksum(pass.expose().as_bytes()));
+    println!("-- Shares ---------------");
+    for share in split(&secret, threshold, count) {
+        println!("{}", share.encode());
+    }
+    println!("-------------------------");
+    info!("{}", tr!("shares-use", threshold = threshold));
+    Ok(())
+}
+
+/// Ask for shares until there are enough, and put the password back
+/// together from them.
+pub fn prompt_password() -> Result<Secret> {
+    let mut shares: Vec<Share> = vec![];
+    loop {
+        let message = match shares.first() {
+            Some(first) => tr!(
+                "share-of",
+                number = shares.len() + 1,
+                threshold = first.threshold
+            ),
+            None => tr!("share"),
+        };
+        let text = Zeroizing::new(
+            Password::new(&message)
+                .with_display_toggle_enabled()
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .without_confirmation()
+                .prompt()?,
+        );
+        let share = match Share::decode(&text) {
+            Ok(share) => share,
+            Err(err) => {
+                warn!("{}", err);
+                continue;
+            }
+        };
+        if let Some(first) = shares.first() {
+            if share.threshold != first.threshold || share.ys.len() != first.ys.len() {
+                warn!("{}", tr!("share-of-other-password"));
+                continue;
+            }
+        }
+        if shares.iter().any(|other| other.x == share.x) {
+            warn!("{}", tr!("share-entered"));
+            continue;
+        }
+        shares.push(share);
+        if shares.len() == shares[0].threshold as usize {
+            break;
+        }
+    }
+
+    recover(&shares)
+}
+
+/// The password the shares (as many as the threshold) put back together,
+/// checked against the checksum shared along with it.
+fn recover(shares: &[Share]) -> Result<Secret> {
+    let secret = combine(shares);
+    let (pass, sum) = secret.split_at(secret.len() - CHECKSUM_LEN);
+    if checksum(pass) != sum {
+        return Err(anyhow!("The shares don't belong together"));
+    }
+    let pass =
+        std::str::from_utf8(pass).map_err(|_| anyhow!("The shares don't belong together"))?;
+    Ok(Secret::new(pass.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shares of "correct horse", any 3 of which recover it.
+    const SHARES: [&str; 4] = [
+        "kpass-3-1-8bbd67d41b918175af22a61b360371123b",
+        "kpass-3-2-1d80f86bf36e4f6eb9c24d9e400766999c",
+        "kpass-3-3-f552edcd8d9cba3b7e8f99f613451358c8",
+        "kpass-3-4-7df1cb63e19c0701daf20a4615e8d626e5",
+    ];
+
+    fn decoded(texts: &[&str]) -> Vec<Share> {
+        texts
+            .iter()
+            .map(|text| Share::decode(text).unwrap())
+            .collect()
+    }
+
+    /// The subsets of `0..n` (as bit masks) with `size` elements.
+    fn subsets(n: u8, size: u32) -> impl Iterator<Item = u32> {
+        (0u32..1 << n).filter(move |mask| mask.count_ones() == size)
+    }
+
+    fn pick(shares: &[Share], mask: u32) -> Vec<Share> {
+        shares
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, share)| Share {
+                threshold: share.threshold,
+                x: share.x,
+                ys: share.ys.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn field_arithmetic() {
+        // From FIPS 197.
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        for a in 1..=255 {
+            assert_eq!(mul(a, inv(a)), 1, "{}", a);
+            assert_eq!(mul(a, 1), a);
+            assert_eq!(mul(a, 0), 0);
+        }
+    }
+
+    #[test]
+    fn fixed_shares_recover() {
+        let shares = decoded(&SHARES);
+        for mask in subsets(4, 3) {
+            let pass = recover(&pick(&shares, mask)).unwrap();
+            assert_eq!(pass.expose(), "correct horse");
+        }
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        for text in SHARES {
+            assert_eq!(Share::decode(text).unwrap().encode(), text);
+        }
+        let share = Share::decode(SHARES[1]).unwrap();
+        assert_eq!((share.threshold, share.x, share.ys.len()), (3, 2, 17));
+    }
+
+    #[test]
+    fn decode_rejects_malformed() {
+        for text in [
+            "",
+            "kpass",
+            "other-3-1-8bbd67d41b",
+            "kpass-3-0-8bbd67d41b",
+            "kpass-1-1-8bbd67d41b",
+            "kpass-3-1-8bbd67d4",
+            "kpass-3-1-not-hex",
+            "kpass-x-1-8bbd67d41b",
+        ] {
+            assert!(Share::decode(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn every_threshold_subset_recovers() {
+        let secret = b"a master password, and its checksum";
+        for count in 2..=6 {
+            for threshold in 2..=count {
+                let shares = split(secret, threshold, count);
+                for size in threshold as u32..=count as u32 {
+                    for mask in subsets(count, size) {
+                        let combined = combine(&pick(&shares, mask));
+                        assert_eq!(&combined[..], secret, "{}/{} {:b}", threshold, count, mask);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fewer_shares_than_the_threshold_dont() {
+        let pass = "correct horse battery staple";
+        let mut secret = pass.as_bytes().to_vec();
+        secret.extend(checksum(pass.as_bytes()));
+        for count in 3..=6 {
+            for threshold in 3..=count {
+                let shares = split(&secret, threshold, count);
+                for mask in subsets(count, threshold as u32 - 1) {
+                    let picked = pick(&shares, mask);
+                    assert_ne!(&combine(&picked)[..], &secret[..]);
+                    assert!(recover(&picked).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_shares_are_rejected() {
+        let mut shares = decoded(&SHARES[..2]);
+        let mut secret = b"correct horsf".to_vec();
+        secret.extend(checksum(b"correct horsf"));
+        let other = split(&secret, 3, 3);
+        shares.push(pick(&other, 0b100).remove(0));
+        assert!(recover(&shares).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Sharing single entries as passphrase-encrypted bundles.
+//!
+//! A bundle holds all of an entry's text fields and tags.
+//! Attachments aren't included, as `keepass` doesn't load them.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use cocoon::Cocoon;
+use inquire::{Confirm, Password, PasswordDisplayMode};
+use keepass::db::{Entry as KEntry, Value};
+use passwords::PasswordGenerator;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{error::Error, secret::Secret};
+
+#[derive(Serialize, Deserialize)]
+struct Field {
+    value: String,
+    protected: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    fields: BTreeMap<String, Field>,
+    tags: Vec<String>,
+}
+
+/// Generate a passphrase that's easy to read out or type.
+fn generate_passphrase() -> Result<Secret> {
+    let pg = PasswordGenerator {
+        length: 24,
+        numbers: true,
+        lowercase_letters: true,
+        uppercase_letters: true,
+        symbols: false,
+        spaces: false,
+        exclude_similar_characters: true,
+        strict: true,
+    };
+    Ok(Secret::new(pg.generate_one().map_err(Error::Generator)?))
+}
+
+pub fn prompt_passphrase(message: &str, confirm: bool) -> Result<Secret> {
+    let prompt = Password::new(message)
+        .with_display_toggle_enabled()
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .with_formatter(&|_| String::from("🔑"));
+    let prompt = if confirm {
+        prompt
+    } else {
+        prompt.without_confirmation()
+    };
+    Ok(Secret::new(prompt.prompt()?))
+}
+
+/// Encrypt the entry's bundle with the passphrase.
+fn seal(entry: &KEntry, passphrase: &str, out: &mut impl Write) -> Result<()> {
+    let bundle = Bundle {
+        fields: entry
+            .fields
+            .iter()
+            .filter_map(|(key, value)| {
+                let field = match value {
+                    Value::Unprotected(value) => Field {
+                        value: value.clone(),
+                        protected: false,
+                    },
+                    Value::Protected(data) => Field {
+                        value: std::str::from_utf8(data.unsecure()).ok()?.to_string(),
+                        protected: true,
+                    },
+                    Value::Bytes(_) => return None,
+                };
+                Some((key.clone(), field))
+            })
+            .collect(),
+        tags: entry.tags.clone(),
+    };
+    let data = Zeroizing::new(serde_json::to_vec(&bundle)?);
+    Cocoon::new(passphrase.as_bytes())
+        .dump(data.to_vec(), out)
+        .map_err(|err| anyhow!("Couldn't encrypt entry: {:?}", err))
+}
+
+/// Decrypt a bundle with the passphrase into a new entry.
+fn open(input: &mut impl Read, passphrase: &str) -> Result<KEntry> {
+    let data = Zeroizing::new(
+        Cocoon::parse_only(passphrase.as_bytes())
+            .parse(input)
+            .map_err(|_| anyhow!("Wrong passphrase or not a kpass bundle"))?,
+    );
+    let bundle: Bundle = serde_json::from_slice(&data)?;
+
+    let mut entry = KEntry::new();
+    for (key, field) in bundle.fields {
+        let value = if field.protected {
+            Value::Protected(field.value.as_bytes().into())
+        } else {
+            Value::Unprotected(field.value)
+        };
+        entry.fields.insert(key, value);
+    }
+    entry.tags = bundle.tags;
+    Ok(entry)
+}
+
+/// Encrypt the entry into a bundle at `path`.
+pub fn share(entry: &KEntry, path: &Path) -> Result<()> {
+    let generate = Confirm::new(&tr!("generate-passphrase"))
+        .with_default(true)
+        .prompt()?;
+    let passphrase = if generate {
+        generate_passphrase()?
+    } else {
+        prompt_passphrase(&tr!("passphrase"), true)?
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| anyhow!("Couldn't create {}: {}", path.display(), err))?;
+    seal(entry, passphrase.expose(), &mut file)?;
+
+    if generate {
+        println!("> {} {}", tr!("passphrase"), passphrase.expose());
+    }
+    warn!("{}", tr!("send-passphrase-separately"));
+    Ok(())
+}
+
+/// Decrypt the bundle at `path` into a new entry.
+pub fn receive(path: &Path) -> Result<KEntry> {
+    let passphrase = prompt_passphrase(&tr!("passphrase"), false)?;
+    open(&mut File::open(path)?, passphrase.expose())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_round_trip() {
+        let mut entry = KEntry::new();
+        for (key, value) in [
+            ("Title", Value::Unprotected("GitHub".into())),
+            ("Password", Value::Protected("hunter2".as_bytes().into())),
+            ("Recovery", Value::Protected("1234 5678".as_bytes().into())),
+            ("Avatar", Value::Bytes(vec![1, 2, 3])),
+        ] {
+            entry.fields.insert(key.to_string(), value);
+        }
+        entry.tags = vec!["dev".into()];
+
+        let mut sealed = vec![];
+        seal(&entry, "passphrase", &mut sealed).unwrap();
+        assert!(!sealed.windows(7).any(|w| w == b"hunter2"));
+        let opened = open(&mut sealed.as_slice(), "passphrase").unwrap();
+        assert_eq!(opened.get_title(), Some("GitHub"));
+        assert_eq!(opened.get_password(), Some("hunter2"));
+        assert!(matches!(opened.fields["Recovery"], Value::Protected(_)));
+        assert!(matches!(opened.fields["Title"], Value::Unprotected(_)));
+        // Binary values aren't shared.
+        assert!(!opened.fields.contains_key("Avatar"));
+        assert_eq!(opened.tags, ["dev"]);
+
+        assert!(open(&mut sealed.as_slice(), "wrong").is_err());
+        assert!(open(&mut &b"not a bundle"[..], "passphrase").is_err());
+    }
+
+    #[test]
+    fn generates_readable_passphrases() {
+        let passphrase = generate_passphrase().unwrap();
+        assert_eq!(passphrase.expose().len(), 24);
+        assert!(passphrase
+            .expose()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric()));
+    }
+}
@@ -0,0 +1,56 @@
+//! Showing secrets on screen, e.g. to read them onto another device,
+//! and clearing it away again.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::unix::io::AsRawFd,
+    time::Duration,
+};
+
+use anyhow::Result;
+use inquire::Confirm;
+
+use crate::config;
+
+/// Wait until Enter is pressed, or `timeout` passes.
+fn wait_for_enter(timeout: Duration) -> Result<()> {
+    let tty = File::open("/dev/tty")?;
+    let mut fd = libc::pollfd {
+        fd: tty.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    if unsafe { libc::poll(&mut fd, 1, ms) } > 0 {
+        BufReader::new(tty).read_line(&mut String::new())?;
+    }
+    Ok(())
+}
+
+/// Clear the screen and the scrollback, so the password doesn't linger.
+fn clear_screen() -> Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x1b[2J\x1b[3J\x1b[H")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// After confirming, show `text` until Enter is pressed
+/// or `show_seconds` (see config) pass, then clear the screen.
+/// `what` names what's shown, e.g. `the password`.
+pub fn on_screen(what: &str, text: &str) -> Result<()> {
+    let confirmed = Confirm::new(&tr!("show-on-screen", what = what))
+        .with_default(false)
+        .with_help_message(&tr!("show-on-screen-help"))
+        .prompt()?;
+    if !confirmed {
+        return Ok(());
+    }
+    let seconds = config::get().show_seconds;
+    println!("{}", text);
+    info!("{}", tr!("press-enter-to-hide", seconds = seconds));
+    let result = wait_for_enter(Duration::from_secs(seconds));
+    clear_screen()?;
+    result
+}
@@ -0,0 +1,192 @@
+//! A quick health overview of a database (`kpass stats`).
+
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    path::Path,
+};
+
+use anyhow::Result;
+use chrono::Utc;
+use keepass::{
+    db::{Entry as KEntry, Group, Node, NodeRef},
+    Database,
+};
+use uuid::Uuid;
+
+use crate::{audit, upgrade};
+
+#[derive(Default)]
+pub struct Stats {
+    /// Not counting those in the recycle bin.
+    pub entries: usize,
+    pub recycled: usize,
+    pub groups: usize,
+    pub without_url: usize,
+    pub without_username: usize,
+    pub without_password: usize,
+    /// Average length and age (in days) of the passwords.
+    pub password_length: Option<f64>,
+    pub password_age_days: Option<i64>,
+    pub expired: usize,
+    /// The format, KDF and cipher.
+    pub format: String,
+    pub file_size: u64,
+}
+
+impl Stats {
+    pub fn of(db: &Database, path: &Path) -> Result<Self> {
+        fn walk<'a>(
+            group: &'a Group,
+            bin: Option<Uuid>,
+            stats: &mut Stats,
+            live: &mut Vec<&'a KEntry>,
+        ) {
+            for node in &group.children {
+                match node {
+                    Node::Group(child) if Some(child.uuid) == bin => {
+                        stats.recycled += child
+                            .into_iter()
+                            .filter(|n| matches!(n, NodeRef::Entry(_)))
+                            .count();
+                    }
+                    Node::Group(child) => {
+                        stats.groups += 1;
+                        walk(child, bin, stats, live);
+                    }
+                    Node::Entry(entry) => live.push(entry),
+                }
+            }
+        }
+
+        let mut stats = Stats {
+            format: upgrade::describe(&db.config),
+            file_size: fs::metadata(path)?.len(),
+            ..Default::default()
+        };
+        let bin = db
+            .meta
+            .recyclebin_uuid
+            .filter(|_| db.meta.recyclebin_enabled != Some(false));
+        let mut live = vec![];
+        walk(&db.root, bin, &mut stats, &mut live);
+
+        let now = Utc::now().naive_utc();
+        let missing = |value: Option<&str>| value.is_none_or(str::is_empty);
+        let mut lengths = vec![];
+        let mut ages = vec![];
+        for entry in &live {
+            stats.without_url += missing(entry.get_url()) as usize;
+            stats.without_username += missing(entry.get_username()) as usize;
+            match entry.get_password().filter(|pw| !pw.is_empty()) {
+                Some(password) => {
+                    lengths.push(password.chars().count());
+                    if let Some(changed) = audit::password_changed(entry) {
+                        ages.push((now - changed).num_days());
+                    }
+                }
+                None => stats.without_password += 1,
+            }
+            let expired =
+                entry.times.expires && entry.times.get_expiry().is_some_and(|expiry| *expiry < now);
+            stats.expired += expired as usize;
+        }
+        stats.entries = live.len();
+        if !lengths.is_empty() {
+            stats.password_length =
+                Some(lengths.iter().sum::<usize>() as f64 / lengths.len() as f64);
+        }
+        if !ages.is_empty() {
+            stats.password_age_days = Some(ages.iter().sum::<i64>() / ages.len() as i64);
+        }
+        Ok(stats)
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Entries:             {}", self.entries)?;
+        if self.recycled > 0 {
+            writeln!(f, "In the recycle bin:  {}", self.recycled)?;
+        }
+        writeln!(f, "Groups:              {}", self.groups)?;
+        writeln!(f, "Without a URL:       {}", self.without_url)?;
+        writeln!(f, "Without a username:  {}", self.without_username)?;
+        writeln!(f, "Without a password:  {}", self.without_password)?;
+        if let Some(length) = self.password_length {
+            writeln!(f, "Password length:     {:.1} on average", length)?;
+        }
+        if let Some(age) = self.password_age_days {
+            writeln!(f, "Password age:        {} days on average", age)?;
+        }
+        writeln!(f, "Expired:             {}", self.expired)?;
+        writeln!(f, "Format:              {}", self.format)?;
+        write!(
+            f,
+            "File size:           {} KiB",
+            self.file_size.div_ceil(1024)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use keepass::db::Times;
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(fields: &[(&str, &str)], days_ago: i64) -> KEntry {
+        let mut entry = test_util::entry(fields);
+        entry
+            .times
+            .set_last_modification(Times::now() - TimeDelta::try_days(days_ago).unwrap());
+        entry
+    }
+
+    #[test]
+    fn counts_live_entries() {
+        let mut db = Database::new(Default::default());
+        let mut work = Group::new("Work");
+        work.add_child(entry(
+            &[
+                ("UserName", "alice"),
+                ("Password", "hunter2"),
+                ("URL", "https://a"),
+            ],
+            10,
+        ));
+        let mut expired = entry(&[("Password", "x"), ("URL", "")], 30);
+        expired.times.expires = true;
+        expired
+            .times
+            .set_expiry(Times::now() - TimeDelta::try_days(1).unwrap());
+        work.add_child(expired);
+        work.add_child(entry(&[("Password", "")], 0));
+        let mut bin = Group::new("Recycle Bin");
+        bin.add_child(entry(&[("Password", "old")], 0));
+        let mut nested = Group::new("Nested");
+        nested.add_child(entry(&[], 0));
+        bin.add_child(nested);
+        db.meta.recyclebin_uuid = Some(bin.uuid);
+        db.root.add_child(work);
+        db.root.add_child(bin);
+
+        let path = crate::import::temp_file("stats.kdbx", &"x".repeat(1500));
+        let stats = Stats::of(&db, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((stats.entries, stats.recycled, stats.groups), (3, 2, 1));
+        assert_eq!(stats.without_url, 2);
+        assert_eq!(stats.without_username, 2);
+        assert_eq!(stats.without_password, 1);
+        assert_eq!(stats.password_length, Some(4.));
+        assert_eq!(stats.password_age_days, Some(20));
+        assert_eq!(stats.expired, 1);
+
+        let shown = stats.to_string();
+        assert!(shown.contains("In the recycle bin:  2\n"));
+        assert!(shown.contains("Password length:     4.0 on average\n"));
+        assert!(shown.ends_with("File size:           2 KiB"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Estimating how strong a (manually entered) password is.
+//!
+//! Scores come from the `passwords` crate, which checks a list of
+//! common passwords and penalizes repeated or sequential characters.
+//! The crack time assumes an offline attack on a fast hash.
+
+use std::fmt::Display;
+
+use passwords::{analyzer, scorer};
+
+/// Guesses per second of an offline attack (a GPU rig against a fast hash).
+const GUESSES_PER_SEC: f64 = 1e10;
+
+/// Guesses needed for a password on the common passwords list.
+const COMMON_GUESSES: f64 = 1e6;
+
+/// Scores below this are flagged as very weak.
+const VERY_WEAK_SCORE: f64 = 40.;
+
+pub struct Strength {
+    /// From 0 to 100.
+    pub score: f64,
+    /// Expected time to crack, in seconds.
+    pub crack_secs: f64,
+}
+impl Strength {
+    pub fn of(password: &str) -> Self {
+        let analyzed = analyzer::analyze(password);
+        let score = scorer::score(&analyzed);
+
+        // Characters that repeat or continue a sequence add little.
+        let pool = [
+            (analyzed.numbers_count(), 10),
+            (analyzed.lowercase_letters_count(), 26),
+            (analyzed.uppercase_letters_count(), 26),
+            (analyzed.symbols_count(), 32),
+            (analyzed.spaces_count(), 1),
+            (analyzed.other_characters_count(), 100),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(_, size)| size)
+        .sum::<u32>()
+        .max(1);
+        let effective_len = analyzed
+            .length()
+            .saturating_sub(analyzed.consecutive_count() + analyzed.progressive_count())
+            .max(1);
+        let guesses = if analyzed.is_common() {
+            COMMON_GUESSES
+        } else {
+            (pool as f64).powi(effective_len as i32)
+        };
+        Strength {
+            score,
+            // On average, half the space is searched.
+            crack_secs: guesses / 2. / GUESSES_PER_SEC,
+        }
+    }
+
+    pub fn is_very_weak(&self) -> bool {
+        self.score < VERY_WEAK_SCORE
+    }
+
+    fn label(&self) -> &'static str {
+        match self.score {
+            s if s < 20. => "very dangerous",
+            s if s < 40. => "dangerous",
+            s if s < 60. => "very weak",
+            s if s < 80. => "weak",
+            s if s < 90. => "good",
+            s if s < 95. => "strong",
+            s if s < 99. => "very strong",
+            _ => "invulnerable",
+        }
+    }
+
+    /// How long cracking would take, e.g. `in about 3 days`.
+    fn crack_time(&self) -> String {
+        const MINUTE: f64 = 60.;
+        const HOUR: f64 = 60. * MINUTE;
+        const DAY: f64 = 24. * HOUR;
+        const YEAR: f64 = 365. * DAY;
+        match self.crack_secs {
+            s if s < 1. => "instantly".to_string(),
+            s if s < MINUTE => format!("in about {:.0} seconds", s),
+            s if s < HOUR => format!("in about {:.0} minutes", s / MINUTE),
+            s if s < DAY => format!("in about {:.0} hours", s / HOUR),
+            s if s < YEAR => format!("in about {:.0} days", s / DAY),
+            s if s < 100. * YEAR => format!("in about {:.0} years", s / YEAR),
+            _ => "in centuries".to_string(),
+        }
+    }
+}
+impl Display for Strength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({:.0}/100), cracked {}",
+            self.label(),
+            self.score,
+            self.crack_time()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strength(score: f64, crack_secs: f64) -> Strength {
+        Strength { score, crack_secs }
+    }
+
+    #[test]
+    fn flags_weak_passwords() {
+        let common = Strength::of("password");
+        assert!(common.is_very_weak());
+        assert_eq!(common.crack_time(), "instantly");
+        let repeated = Strength::of("aaaaaaaaaaaa");
+        assert!(repeated.is_very_weak());
+        assert!(repeated.crack_secs < 1.);
+    }
+
+    #[test]
+    fn rates_strong_passwords() {
+        let strong = Strength::of("k8#Vq2!mZr7$Lw4&Xt9p");
+        assert!(!strong.is_very_weak());
+        assert_eq!(strong.crack_time(), "in centuries");
+        assert!(strong.to_string().ends_with("cracked in centuries"));
+    }
+
+    #[test]
+    fn formats_crack_times() {
+        let time = |secs| strength(50., secs).crack_time();
+        assert_eq!(time(0.5), "instantly");
+        assert_eq!(time(30.), "in about 30 seconds");
+        assert_eq!(time(600.), "in about 10 minutes");
+        assert_eq!(time(3. * 3600.), "in about 3 hours");
+        assert_eq!(time(3. * 86400.), "in about 3 days");
+        assert_eq!(time(5. * 365. * 86400.), "in about 5 years");
+        assert_eq!(time(200. * 365. * 86400.), "in centuries");
+    }
+
+    #[test]
+    fn labels_scores() {
+        let label = |score| strength(score, 0.).label();
+        assert_eq!(label(10.), "very dangerous");
+        assert_eq!(label(39.9), "dangerous");
+        assert_eq!(label(40.), "very weak");
+        assert_eq!(label(85.), "good");
+        assert_eq!(label(100.), "invulnerable");
+        assert_eq!(
+            strength(72.4, 0.).to_string(),
+            "weak (72/100), cracked instantly"
+        );
+    }
+}
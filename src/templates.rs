@@ -0,0 +1,159 @@
+//! Templates for entries that aren't (just) logins, e.g. credit cards,
+//! so their details go into consistent custom fields instead of the notes.
+
+use anyhow::Result;
+use inquire::{Password, PasswordDisplayMode, Text};
+use keepass::db::{Entry as KEntry, Value};
+
+use crate::{host, EditEntry};
+
+/// A custom field of a template.
+struct Field {
+    name: &'static str,
+    /// Protected fields are kept encrypted in memory,
+    /// and not previewed when picking fields to copy.
+    protected: bool,
+}
+
+const fn field(name: &'static str) -> Field {
+    Field {
+        name,
+        protected: false,
+    }
+}
+
+const fn secret(name: &'static str) -> Field {
+    Field {
+        name,
+        protected: true,
+    }
+}
+
+pub struct Template {
+    pub name: &'static str,
+    /// Whether it has a username and password, like a login.
+    login: bool,
+    fields: &'static [Field],
+}
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        name: "Credit card",
+        login: false,
+        fields: &[
+            field("Cardholder"),
+            secret("Card number"),
+            field("Expiry (MM/YY)"),
+            secret("CVV"),
+            secret("PIN"),
+            field("Issuer"),
+        ],
+    },
+    Template {
+        name: "Server / SSH",
+        login: true,
+        fields: &[
+            // The same fields as "Host" in the edit menu.
+            field(host::HOST_FIELD),
+            field(host::PORT_FIELD),
+            secret("Private key"),
+            secret("Key passphrase"),
+        ],
+    },
+    Template {
+        name: "Software license",
+        login: false,
+        fields: &[
+            field("Licensed to"),
+            field("Email"),
+            secret("License key"),
+            field("Version"),
+            field("Purchase date"),
+            field("Order number"),
+        ],
+    },
+    Template {
+        name: "Identity",
+        login: false,
+        fields: &[
+            field("Full name"),
+            field("Birth date"),
+            field("Address"),
+            field("Phone"),
+            field("Email"),
+            secret("Passport number"),
+            secret("National ID"),
+            secret("Driver's license"),
+        ],
+    },
+];
+
+/// Create an entry from the template, prompting for its title and fields.
+/// Fields left empty are left out. Suggests the given usernames.
+pub fn new_entry(template: &Template, usernames: &[String]) -> Result<KEntry> {
+    let mut entry = KEntry::new();
+    let mut edit = EditEntry(&mut entry);
+    edit.set_title()?;
+    if template.login {
+        edit.set_username(usernames)?;
+        edit.set_password()?;
+    }
+
+    for field in template.fields {
+        let message = format!("{}:", field.name);
+        let value = if field.protected {
+            Password::new(&message)
+                .with_display_toggle_enabled()
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .with_help_message(&tr!("leave-empty-to-skip"))
+                .without_confirmation()
+                .prompt()?
+        } else {
+            Text::new(&message)
+                .with_help_message(&tr!("leave-empty-to-skip"))
+                .prompt()?
+        };
+        if value.is_empty() {
+            continue;
+        }
+        let value = if field.protected {
+            Value::Protected(value.as_bytes().into())
+        } else {
+            Value::Unprotected(value)
+        };
+        entry.fields.insert(field.name.to_string(), value);
+    }
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn fields_are_custom_and_distinct() {
+        let standard = ["Title", "UserName", "Password", "URL", "Notes", "otp"];
+        for template in TEMPLATES {
+            let mut names = HashSet::new();
+            for field in template.fields {
+                assert!(!standard.contains(&field.name), "{}", field.name);
+                assert!(names.insert(field.name), "{}", field.name);
+            }
+        }
+        let names: HashSet<&str> = TEMPLATES.iter().map(|t| t.name).collect();
+        assert_eq!(names.len(), TEMPLATES.len());
+    }
+
+    #[test]
+    fn servers_use_the_host_fields() {
+        let server = TEMPLATES.iter().find(|t| t.name == "Server / SSH").unwrap();
+        let names: Vec<&str> = server.fields.iter().map(|f| f.name).collect();
+        assert!(names.contains(&host::HOST_FIELD) && names.contains(&host::PORT_FIELD));
+        assert!(server
+            .fields
+            .iter()
+            .any(|f| f.name == "Private key" && f.protected));
+    }
+}
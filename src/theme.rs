@@ -0,0 +1,153 @@
+//! Colors for kpass' own output (field labels in entry views, secret
+//! placeholders, warnings, headings and statuses in reports), set by the
+//! `[theme]` table in the config. Off with `NO_COLOR`, `--plain`, or when
+//! output isn't a terminal.
+
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Deserialize;
+
+use crate::config;
+
+/// Set by `--plain`.
+pub static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Whether to color output.
+pub fn enabled() -> bool {
+    !PLAIN.load(Ordering::Relaxed)
+        && std::env::var_os("NO_COLOR").is_none()
+        && std::io::stdout().is_terminal()
+}
+
+/// A style, from e.g. `bold bright-red` or `#ff8800`: colors (`black`,
+/// `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`, optionally
+/// `bright-`), hex colors, and `bold`, `dim`, `italic` or `underline`.
+/// Empty for none. Kept as ANSI SGR parameters.
+#[derive(Deserialize, Clone, Default)]
+#[serde(try_from = "String")]
+pub struct Style(String);
+impl TryFrom<String> for Style {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        const COLORS: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        let mut codes = vec![];
+        for word in spec.split_whitespace() {
+            let word = word.to_lowercase();
+            let code = match word.as_str() {
+                "bold" => "1".to_string(),
+                "dim" => "2".to_string(),
+                "italic" => "3".to_string(),
+                "underline" => "4".to_string(),
+                hex if hex.starts_with('#') && hex.len() == 7 => {
+                    let rgb =
+                        hex::decode(&hex[1..]).map_err(|_| format!("Invalid color: {}", hex))?;
+                    format!("38;2;{};{};{}", rgb[0], rgb[1], rgb[2])
+                }
+                name => {
+                    let (base, name) = match name.strip_prefix("bright-") {
+                        Some(name) => (90, name),
+                        None => (30, name),
+                    };
+                    let idx = COLORS
+                        .iter()
+                        .position(|color| *color == name)
+                        .ok_or_else(|| format!("Unknown color or style: {}", word))?;
+                    (base + idx).to_string()
+                }
+            };
+            codes.push(code);
+        }
+        Ok(Style(codes.join(";")))
+    }
+}
+
+/// The styles of each kind of output.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Theme {
+    /// Field names in entry views, e.g. `Username:`.
+    pub label: Style,
+    /// Placeholders for hidden secrets, e.g. `••••••`.
+    pub secret: Style,
+    /// Warnings and problems (lines starting with `!`).
+    pub warning: Style,
+    /// Headings, e.g. of audit rules.
+    pub heading: Style,
+    /// Good outcomes, e.g. an audit finding no problems.
+    pub good: Style,
+}
+impl Default for Theme {
+    fn default() -> Self {
+        let style = |spec: &str| Style::try_from(spec.to_string()).unwrap();
+        Theme {
+            label: style("cyan"),
+            secret: style("dim"),
+            warning: style("yellow"),
+            heading: style("bold"),
+            good: style("green"),
+        }
+    }
+}
+
+/// `text` in the style, if colors are enabled.
+pub fn paint(style: &Style, text: &str) -> String {
+    if style.0.is_empty() || !enabled() {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", style.0, text)
+}
+
+pub fn label(text: &str) -> String {
+    paint(&config::get().theme.label, text)
+}
+
+pub fn secret(text: &str) -> String {
+    paint(&config::get().theme.secret, text)
+}
+
+pub fn warning(text: &str) -> String {
+    paint(&config::get().theme.warning, text)
+}
+
+pub fn heading(text: &str) -> String {
+    paint(&config::get().theme.heading, text)
+}
+
+pub fn good(text: &str) -> String {
+    paint(&config::get().theme.good, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(spec: &str) -> Result<String, String> {
+        Style::try_from(spec.to_string()).map(|style| style.0)
+    }
+
+    #[test]
+    fn parses_styles() {
+        assert_eq!(codes("bold bright-red").unwrap(), "1;91");
+        assert_eq!(codes("Cyan underline").unwrap(), "36;4");
+        assert_eq!(codes("#ff8800 italic").unwrap(), "38;2;255;136;0;3");
+        assert_eq!(codes("").unwrap(), "");
+        for invalid in ["purple", "bright-bold", "#ff880", "#gg8800", "#ff88000"] {
+            assert!(codes(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn reads_themes() {
+        let theme: Theme = toml::from_str("warning = \"bold red\"").unwrap();
+        assert_eq!(theme.warning.0, "1;31");
+        // Others keep their defaults.
+        assert_eq!(theme.label.0, "36");
+        assert!(toml::from_str::<Theme>("good = \"mauve\"").is_err());
+    }
+}
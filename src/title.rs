@@ -0,0 +1,76 @@
+//! The terminal's title, showing whether kpass holds databases unlocked,
+//! to tell which terminal has them open at a glance.
+//!
+//! The previous title is saved on the terminal's title stack
+//! (where supported) and restored on exit.
+
+use std::{fs::OpenOptions, io::Write, process::Command, sync::Mutex};
+
+use crate::config;
+
+/// Set when kpass restarts itself to lock (see `idle`),
+/// in which case the title was already saved.
+const SAVED_VAR: &str = "KPASS_TITLE_SAVED";
+
+/// File names of the databases shown in the title.
+static NAMES: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+fn write(escape: &str) {
+    if !config::get().terminal_title {
+        return;
+    }
+    if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(escape.as_bytes());
+    }
+}
+
+/// Set the title (OSC 2), without control characters that could end it early.
+fn set(title: &str) {
+    let title: String = title.chars().filter(|c| !c.is_control()).collect();
+    write(&format!("\x1b]2;{}\x07", title));
+}
+
+/// Show the databases in the given state.
+fn show(state: &str) {
+    set(&format!(
+        "kpass — {} [{}]",
+        NAMES.lock().unwrap().join(", "),
+        state
+    ));
+}
+
+/// Shows the databases' state in the title while it exists.
+pub struct Title;
+impl Title {
+    /// Save the current title and show the databases as locked.
+    pub fn new(names: Vec<String>) -> Self {
+        if std::env::var_os(SAVED_VAR).is_none() {
+            // Push the window title onto the stack.
+            write("\x1b[22;2t");
+        }
+        std::env::remove_var(SAVED_VAR);
+        *NAMES.lock().unwrap() = names;
+        show("locked");
+        Title
+    }
+
+    pub fn unlocked(&self) {
+        show("unlocked");
+    }
+}
+
+impl Drop for Title {
+    fn drop(&mut self) {
+        // Clear the title in case the terminal has no title stack,
+        // then restore the saved one.
+        set("");
+        write("\x1b[23;2t");
+    }
+}
+
+/// Show the databases as locked while kpass restarts itself (see `idle`),
+/// leaving the saved title for the restarted process to restore.
+pub fn restarting(cmd: &mut Command) {
+    show("locked");
+    cmd.env(SAVED_VAR, "1");
+}
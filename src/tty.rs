@@ -0,0 +1,85 @@
+//! Mixing piped input with interactive prompts,
+//! e.g. `echo github | kpass get` still prompting to unlock.
+//!
+//! Prompts already read keys from `/dev/tty`, but programs they spawn
+//! (e.g. `$EDITOR`) inherit stdin. So once the piped input is read,
+//! stdin is pointed at the terminal instead.
+
+use std::{
+    fs::File,
+    io::{IsTerminal, Read},
+    os::unix::io::{AsRawFd, FromRawFd},
+    sync::OnceLock,
+};
+
+use anyhow::Result;
+use zeroize::Zeroizing;
+
+use crate::secret::Secret;
+
+static PIPED: OnceLock<Option<String>> = OnceLock::new();
+
+/// Point stdin at the terminal, if there is one.
+fn attach_terminal() -> Result<()> {
+    // Without a controlling terminal (e.g. run from cron) there's nothing
+    // to attach to, and prompts would fail regardless.
+    if let Ok(tty) = File::open("/dev/tty") {
+        if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
+
+/// Everything piped into stdin, or `None` if stdin is a terminal.
+/// Read on first use, so only call this for commands that take piped input.
+pub fn piped_input() -> Result<Option<&'static str>> {
+    if let Some(piped) = PIPED.get() {
+        return Ok(piped.as_deref());
+    }
+    let piped = if std::io::stdin().is_terminal() {
+        None
+    } else {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        attach_terminal()?;
+        Some(input)
+    };
+    Ok(PIPED.get_or_init(|| piped).as_deref())
+}
+
+/// The first line piped into stdin, if any.
+pub fn piped_line() -> Result<Option<&'static str>> {
+    Ok(piped_input()?
+        .and_then(|input| input.lines().next())
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty()))
+}
+
+/// The first line piped into stdin as a secret (e.g. `--password-stdin`),
+/// or `None` if stdin is a terminal.
+pub fn piped_secret() -> Result<Option<Secret>> {
+    if std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let mut line = Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut line)?;
+    attach_terminal()?;
+    Ok(Some(Secret::new(
+        line.trim_end_matches(['\n', '\r']).to_string(),
+    )))
+}
+
+/// Point stdout at stderr, returning the original stdout, e.g. so only
+/// data reaches a pipe while kpass' messages (when unlocking or saving)
+/// still reach the user.
+pub fn divert_stdout() -> Result<File> {
+    // SAFETY: the duplicated descriptor is owned by the file from here on.
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(File::from_raw_fd(fd))
+    }
+}
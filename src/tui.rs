@@ -0,0 +1,575 @@
+//! A full-screen interface (`kpass tui`) for longer sessions than the
+//! sequential prompts suit: the group tree, the entries in the selected
+//! group (and its subgroups), filtered by typing after `/`, and the
+//! selected entry's details.
+//!
+//! It's drawn with ratatui. Editing and prompts (e.g. for the full
+//! master password) leave the full screen for the usual prompts,
+//! and come back after.
+
+use std::io::{stdout, Stdout};
+
+use anyhow::{anyhow, Result};
+use keepass::db::{Entry as KEntry, Group, Node};
+use ratatui::{
+    crossterm::{
+        cursor::{Hide, Show},
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    prelude::CrosstermBackend,
+    style::{Style, Stylize},
+    widgets::{Block, Borders, List, ListState, Padding, Paragraph},
+    Frame, Terminal,
+};
+use uuid::Uuid;
+
+use crate::{
+    activity, copy_to_clipboard, delete_entry, edit_entry, entries_with_paths, error::is_cancelled,
+    host, passkey::Passkey, protect, reference, vault::Vault, Entry,
+};
+
+const HELP: &str =
+    " ↑↓ move  ←→ pane  / filter  c copy password  u copy username  e edit  d delete  q quit";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Groups,
+    Entries,
+}
+
+/// What keys do.
+enum Mode {
+    Browse,
+    /// Typing the filter.
+    Filter,
+    /// Asking whether to delete the entry.
+    ConfirmDelete(Uuid),
+}
+
+struct Tui<'a> {
+    vault: &'a mut Vault,
+    pane: Pane,
+    mode: Mode,
+    /// The paths of all groups in tree order, the root's (empty) first.
+    groups: Vec<Vec<String>>,
+    group: usize,
+    entry: usize,
+    filter: String,
+    status: String,
+    /// Whether the screen has to be redrawn from scratch, after prompts.
+    redraw: bool,
+}
+
+fn group_paths(group: &Group) -> Vec<Vec<String>> {
+    fn collect(group: &Group, path: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+        out.push(path.clone());
+        for node in &group.children {
+            if let Node::Group(child) = node {
+                path.push(child.name.clone());
+                collect(child, path, out);
+                path.pop();
+            }
+        }
+    }
+
+    let mut out = vec![];
+    collect(group, &mut vec![], &mut out);
+    out
+}
+
+fn group_at<'a>(group: &'a Group, path: &[String]) -> Option<&'a Group> {
+    let Some((name, rest)) = path.split_first() else {
+        return Some(group);
+    };
+    group.children.iter().find_map(|node| match node {
+        Node::Group(child) if child.name == *name => group_at(child, rest),
+        _ => None,
+    })
+}
+
+fn enter() -> Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen, Hide)?;
+    Ok(())
+}
+
+fn leave() -> Result<()> {
+    execute!(stdout(), Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    Ok(())
+}
+
+impl<'a> Tui<'a> {
+    fn new(vault: &'a mut Vault) -> Self {
+        Tui {
+            groups: group_paths(&vault.db.root),
+            vault,
+            pane: Pane::Groups,
+            mode: Mode::Browse,
+            group: 0,
+            entry: 0,
+            filter: String::new(),
+            status: String::new(),
+            redraw: false,
+        }
+    }
+
+    /// The entries in the selected group and its subgroups that match the filter,
+    /// by title, with their paths relative to the group.
+    fn entries(&self) -> Vec<(Vec<String>, &KEntry)> {
+        let Some(group) = group_at(&self.vault.db.root, &self.groups[self.group]) else {
+            return vec![];
+        };
+        let filter = self.filter.to_lowercase();
+        let mut entries: Vec<_> = entries_with_paths(group)
+            .into_iter()
+            .filter(|(_, entry)| {
+                [entry.get_title(), entry.get_username(), entry.get_url()]
+                    .into_iter()
+                    .flatten()
+                    .any(|text| text.to_lowercase().contains(&filter))
+            })
+            .collect();
+        entries.sort_by_key(|(_, entry)| Entry(entry).to_string().to_lowercase());
+        entries
+    }
+
+    fn selected(&self) -> Option<&KEntry> {
+        self.entries().get(self.entry).map(|(_, entry)| *entry)
+    }
+
+    fn details(entry: &KEntry, path: &[String]) -> Vec<String> {
+        let mut lines = vec![Entry(entry).to_string(), String::new()];
+        if !path.is_empty() {
+            lines.push(format!("Group:    {}", path.join("/")));
+        }
+        let fields = [
+            ("Username", entry.get_username()),
+            ("URL", entry.get_url()),
+            ("Password", entry.get_password().map(|_| "••••••")),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                lines.push(format!("{:<9} {}", format!("{}:", name), value));
+            }
+        }
+        if let Some(host) = host::Host::of(entry) {
+            lines.push(format!("Host:     {}", host));
+        }
+        if let Some(passkey) = Passkey::of(entry) {
+            lines.push(format!(
+                "Passkey:  {} ({})",
+                passkey.relying_party, passkey.username
+            ));
+        }
+        if !entry.tags.is_empty() {
+            lines.push(format!("Tags:     {}", entry.tags.join(", ")));
+        }
+        if let Some(warning) = Entry(entry).expiry_warning() {
+            lines.push(format!("Password {}", warning));
+        } else if let Some(expiry) = Entry(entry).expiry() {
+            lines.push(format!("Expires:  {}", expiry.format("%Y-%m-%d")));
+        }
+        if protect::is_required(entry) {
+            lines.push("Requires the master password.".to_string());
+        }
+        if let Some(notes) = Entry(entry).notes().filter(|notes| !notes.is_empty()) {
+            lines.push(String::new());
+            lines.extend(notes.lines().map(str::to_string));
+        }
+        lines
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, body, status, help] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [groups_area, entries_area, details_area] = Layout::horizontal([
+            Constraint::Ratio(1, 4),
+            Constraint::Ratio(3, 8),
+            Constraint::Fill(1),
+        ])
+        .areas(body);
+
+        let filter = match (&self.mode, self.filter.is_empty()) {
+            (Mode::Filter, _) => format!("  /{}▏", self.filter),
+            (_, false) => format!("  /{}", self.filter),
+            (_, true) => String::new(),
+        };
+        frame.render_widget(
+            Paragraph::new(format!(" {}{}", self.vault.name(), filter)),
+            header,
+        );
+
+        // Lists keep the selection in view; only the focused one shows it.
+        let list = |items: Vec<String>, pane: Pane| {
+            let highlight = if self.pane == pane {
+                Style::new().reversed()
+            } else {
+                Style::new()
+            };
+            List::new(items)
+                .block(Block::new().borders(Borders::RIGHT))
+                .highlight_style(highlight)
+        };
+        let groups = self
+            .groups
+            .iter()
+            .map(|path| match path.last() {
+                Some(name) => format!("{}{}", "  ".repeat(path.len()), name),
+                None => format!(" {}", self.vault.db.root.name),
+            })
+            .collect();
+        frame.render_stateful_widget(
+            list(groups, Pane::Groups),
+            groups_area,
+            &mut ListState::default().with_selected(Some(self.group)),
+        );
+
+        let entries = self.entries();
+        let mut labels: Vec<String> = entries
+            .iter()
+            .map(|(_, entry)| format!(" {}", Entry(entry)))
+            .collect();
+        if labels.is_empty() {
+            labels.push(" (no entries)".to_string());
+        }
+        let selected = (!entries.is_empty()).then_some(self.entry);
+        frame.render_stateful_widget(
+            list(labels, Pane::Entries),
+            entries_area,
+            &mut ListState::default().with_selected(selected),
+        );
+
+        if let Some((path, entry)) = entries.get(self.entry) {
+            let entry = reference::resolved(&self.vault.db, entry);
+            let details: Vec<_> = Self::details(&entry, path)
+                .into_iter()
+                .map(Into::into)
+                .collect();
+            frame.render_widget(
+                Paragraph::new(details).block(Block::new().padding(Padding::left(1))),
+                details_area,
+            );
+        }
+
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+        frame.render_widget(Paragraph::new(HELP).reversed(), help);
+    }
+
+    /// Leave the full screen for prompts (e.g. editing), and come back.
+    fn suspended<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        leave()?;
+        let result = f(self);
+        enter()?;
+        self.redraw = true;
+        result
+    }
+
+    /// The selected entry, if it can be used: entries requiring
+    /// the full master password ask for it first.
+    fn released(&mut self) -> Result<Option<KEntry>> {
+        let Some(entry) = self.selected().cloned() else {
+            return Ok(None);
+        };
+        if protect::is_required(&entry)
+            && !self.suspended(|tui| protect::verify(&tui.vault.path))?
+        {
+            return Ok(None);
+        }
+        Ok(Some(entry))
+    }
+
+    fn copy(&mut self, field: &str) -> Result<()> {
+        let Some(entry) = self.released()? else {
+            return Ok(());
+        };
+        let entry = reference::resolved(&self.vault.db, &entry).into_owned();
+        let Some(value) = entry.get(field).filter(|value| !value.is_empty()) else {
+            self.status = format!("{} has no {}.", Entry(&entry), field.to_lowercase());
+            return Ok(());
+        };
+        copy_to_clipboard(value)?;
+        activity::record(&self.vault.path, "viewed", &Entry(&entry).to_string())?;
+        self.status = format!("Copied the {} of {}.", field.to_lowercase(), Entry(&entry));
+        Ok(())
+    }
+
+    fn edit(&mut self) -> Result<()> {
+        let Some(uuid) = self.selected().map(|entry| entry.uuid) else {
+            return Ok(());
+        };
+        self.suspended(|tui| {
+            let vault = &mut *tui.vault;
+            let entry = vault
+                .index
+                .entry_mut(&mut vault.db, &uuid)
+                .ok_or_else(|| anyhow!("Entry not found"))?;
+            edit_entry(entry)?;
+            vault.changed();
+            vault.flush()
+        })?;
+        self.groups = group_paths(&self.vault.db.root);
+        self.status = "Saved.".to_string();
+        Ok(())
+    }
+
+    fn delete(&mut self, uuid: Uuid) -> Result<()> {
+        let title = self.selected().map(|entry| Entry(entry).to_string());
+        let recycled = delete_entry(&mut self.vault.db, &uuid)?;
+        self.vault.changed();
+        self.suspended(|tui| tui.vault.flush())?;
+        self.groups = group_paths(&self.vault.db.root);
+        self.group = self.group.min(self.groups.len() - 1);
+        self.entry = self.entry.saturating_sub(1);
+        let title = title.unwrap_or_default();
+        self.status = if recycled {
+            format!("Moved {} to the recycle bin.", title)
+        } else {
+            format!("Deleted {}.", title)
+        };
+        Ok(())
+    }
+
+    /// Handle a key, returning whether to quit.
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<bool> {
+        if code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(true);
+        }
+        match self.mode {
+            Mode::Filter => {
+                match code {
+                    KeyCode::Char(c) => self.filter.push(c),
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                    }
+                    KeyCode::Esc => {
+                        self.filter.clear();
+                        self.mode = Mode::Browse;
+                    }
+                    KeyCode::Enter => {
+                        self.mode = Mode::Browse;
+                        self.pane = Pane::Entries;
+                    }
+                    _ => {}
+                }
+                self.entry = 0;
+                return Ok(false);
+            }
+            Mode::ConfirmDelete(uuid) => {
+                self.mode = Mode::Browse;
+                self.status.clear();
+                if code == KeyCode::Char('y') {
+                    self.delete(uuid)?;
+                }
+                return Ok(false);
+            }
+            Mode::Browse => {}
+        }
+
+        self.status.clear();
+        let (selected, len) = match self.pane {
+            Pane::Groups => (&mut self.group, self.groups.len()),
+            Pane::Entries => {
+                let len = self.entries().len();
+                (&mut self.entry, len)
+            }
+        };
+        match code {
+            KeyCode::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.entry = 0;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+            KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                *selected = (*selected + 1).min(len.saturating_sub(1))
+            }
+            KeyCode::Home => *selected = 0,
+            KeyCode::End => *selected = len.saturating_sub(1),
+            KeyCode::Left | KeyCode::Char('h') => self.pane = Pane::Groups,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.pane = Pane::Entries,
+            KeyCode::Tab => {
+                self.pane = match self.pane {
+                    Pane::Groups => Pane::Entries,
+                    Pane::Entries => Pane::Groups,
+                }
+            }
+            KeyCode::Char('/') => self.mode = Mode::Filter,
+            KeyCode::Char('c') => self.copy("Password")?,
+            KeyCode::Char('u') => self.copy("UserName")?,
+            KeyCode::Char('e') | KeyCode::Char('d') if self.vault.lock.is_none() => {
+                self.status = format!("{} is open read-only.", self.vault.name());
+            }
+            KeyCode::Char('e') => self.edit()?,
+            KeyCode::Char('d') => {
+                if let Some((title, uuid)) = self
+                    .selected()
+                    .map(|entry| (Entry(entry).to_string(), entry.uuid))
+                {
+                    self.status = format!("Delete {}? (y/n)", title);
+                    self.mode = Mode::ConfirmDelete(uuid);
+                }
+            }
+            _ => {}
+        }
+        if self.pane == Pane::Groups && code != KeyCode::Tab {
+            self.entry = 0;
+        }
+        Ok(false)
+    }
+
+    fn run(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+        loop {
+            // The prompts drew over the screen, which ratatui doesn't know.
+            if std::mem::take(&mut self.redraw) {
+                terminal.clear()?;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            // Errors (e.g. a failed save) are shown, rather than ending the session.
+            match self.key(key.code, key.modifiers) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(err) if is_cancelled(&err) => self.status.clear(),
+                Err(err) => self.status = format!("! {}", err),
+            }
+        }
+    }
+}
+
+pub fn run(vault: &mut Vault) -> Result<()> {
+    let mut tui = Tui::new(vault);
+    enter()?;
+    let result = Terminal::new(CrosstermBackend::new(stdout()))
+        .map_err(Into::into)
+        .and_then(|mut terminal| tui.run(&mut terminal));
+    leave()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::Database;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(title: &str, username: &str) -> KEntry {
+        test_util::entry_protecting(
+            &[
+                ("Title", title),
+                ("UserName", username),
+                ("Password", "hunter2"),
+            ],
+            &["Password"],
+        )
+    }
+
+    fn database() -> Database {
+        let mut db = Database::new(Default::default());
+        db.root.name = "Root".to_string();
+        let mut work = Group::new("Work");
+        work.add_child(entry("GitHub", "alice"));
+        let mut servers = Group::new("Servers");
+        servers.add_child(entry("db1", "root"));
+        work.add_child(servers);
+        db.root.add_child(work);
+        db.root.add_child(entry("Bank", "alice123"));
+        db
+    }
+
+    /// The screen's rows, drawn at 60×8.
+    fn screen(tui: &Tui) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                let row: String = (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect();
+                row.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn press(tui: &mut Tui, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '↓' => KeyCode::Down,
+                '→' => KeyCode::Right,
+                '⏎' => KeyCode::Enter,
+                '⎋' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            assert!(!tui.key(code, KeyModifiers::NONE).unwrap());
+        }
+    }
+
+    #[test]
+    fn draws_groups_entries_and_details() {
+        let mut vault = Vault::in_memory(database());
+        let tui = Tui::new(&mut vault);
+        assert_eq!(
+            screen(&tui),
+            [
+                " vault",
+                " Root         │ Bank                 │ Bank",
+                "  Work        │ db1                  │",
+                "    Servers   │ GitHub               │ Username: alice123",
+                "              │                      │ Password: ••••••",
+                "              │                      │",
+                "",
+                " ↑↓ move  ←→ pane  / filter  c copy password  u copy usernam",
+            ]
+        );
+    }
+
+    #[test]
+    fn browses_groups_and_filters() {
+        let mut vault = Vault::in_memory(database());
+        let mut tui = Tui::new(&mut vault);
+        // Into Work, then its entries.
+        press(&mut tui, "↓→↓");
+        assert!(tui.pane == Pane::Entries);
+        assert_eq!(tui.selected().unwrap().get_title(), Some("GitHub"));
+        assert!(screen(&tui)[3].ends_with("│ Username: alice"));
+
+        press(&mut tui, "/root");
+        assert_eq!(screen(&tui)[0], " vault  /root▏");
+        press(&mut tui, "⏎");
+        assert_eq!(tui.selected().unwrap().get_title(), Some("db1"));
+        // Relative to the selected group.
+        assert!(screen(&tui)[3].ends_with("│ Group:    Servers"));
+        assert_eq!(tui.entries().len(), 1);
+
+        press(&mut tui, "⎋");
+        assert!(tui.filter.is_empty());
+        assert_eq!(tui.entries().len(), 2);
+        assert!(tui.key(KeyCode::Char('q'), KeyModifiers::NONE).unwrap());
+    }
+
+    #[test]
+    fn refuses_changes_when_read_only() {
+        let mut vault = Vault::in_memory(database());
+        let mut tui = Tui::new(&mut vault);
+        press(&mut tui, "→d");
+        assert_eq!(tui.status, "vault is open read-only.");
+        assert!(matches!(tui.mode, Mode::Browse));
+        assert_eq!(screen(&tui)[6], "vault is open read-only.");
+    }
+}
@@ -0,0 +1,123 @@
+//! Upgrading databases in older formats (`kpass upgrade`), e.g. KDBX 3.1
+//! with AES-KDF, to KDBX 4 with Argon2id and the current cipher settings.
+//! kpass can't save older formats at all.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use inquire::Confirm;
+use keepass::config::{DatabaseConfig, DatabaseVersion, KdfConfig};
+
+use crate::{init, kdf, lock_db, save_db, unlock};
+
+/// The format, KDF and cipher, e.g. "KDBX 4.0, Argon2id, ..., AES256 encryption".
+pub(crate) fn describe(config: &DatabaseConfig) -> String {
+    let version = match config.version {
+        DatabaseVersion::KDB(_) => "KeePass 1".to_string(),
+        DatabaseVersion::KDB2(minor) => format!("KDBX 2.{}", minor),
+        DatabaseVersion::KDB3(minor) => format!("KDBX 3.{}", minor),
+        DatabaseVersion::KDB4(minor) => format!("KDBX 4.{}", minor),
+    };
+    format!(
+        "{}, {}, {:?} encryption",
+        version,
+        kdf::describe(&config.kdf_config),
+        config.outer_cipher_config
+    )
+}
+
+/// Where the original is kept, next to it: e.g. `db.kdbx3.kdbx` for `db.kdbx`.
+fn original_path(db_path: &Path, config: &DatabaseConfig) -> std::path::PathBuf {
+    let old = match config.version {
+        DatabaseVersion::KDB(_) => "kdb",
+        DatabaseVersion::KDB2(_) => "kdbx2",
+        DatabaseVersion::KDB3(_) => "kdbx3",
+        DatabaseVersion::KDB4(_) => "kdbx4",
+    };
+    let stem = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    db_path.with_file_name(format!("{}.{}.kdbx", stem, old))
+}
+
+pub fn upgrade(db_path: &Path) -> Result<()> {
+    let _lock = lock_db(db_path)?;
+    let (mut db, key) = unlock(db_path)?;
+    info!("{}", tr!("kdf-current", settings = describe(&db.config)));
+
+    let modern = matches!(db.config.version, DatabaseVersion::KDB4(_))
+        && !matches!(db.config.kdf_config, KdfConfig::Aes { .. });
+    if modern {
+        info!("{}", tr!("up-to-date"));
+        return Ok(());
+    }
+    let upgraded = DatabaseConfig {
+        kdf_config: KdfConfig::Argon2id {
+            iterations: init::ARGON2_ITERATIONS,
+            memory: init::ARGON2_MEMORY,
+            parallelism: init::ARGON2_PARALLELISM,
+            version: argon2::Version::Version13,
+        },
+        ..Default::default()
+    };
+    info!("{}", tr!("upgraded", settings = describe(&upgraded)));
+    let original = original_path(db_path, &db.config);
+    if original.exists() {
+        return Err(anyhow!(
+            "{} exists already; move it out of the way first",
+            original.display()
+        ));
+    }
+    warn!("{}", tr!("upgrade-breaks-old-clients"));
+    let confirm = Confirm::new(&tr!("upgrade-confirm"))
+        .with_default(true)
+        .prompt()?;
+    if !confirm {
+        return Ok(());
+    }
+
+    fs::copy(db_path, &original)?;
+    db.config = upgraded;
+    info!("{}", tr!("saving"));
+    save_db(&mut db, &key, db_path)?;
+    info!("{}", tr!("saved-original-kept", path = original.display()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::config::OuterCipherConfig;
+
+    use super::*;
+
+    fn kdbx3() -> DatabaseConfig {
+        DatabaseConfig {
+            version: DatabaseVersion::KDB3(1),
+            kdf_config: KdfConfig::Aes { rounds: 60_000 },
+            outer_cipher_config: OuterCipherConfig::AES256,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn describes_formats() {
+        assert_eq!(
+            describe(&kdbx3()),
+            "KDBX 3.1, AES-KDF, 60000 rounds, AES256 encryption"
+        );
+    }
+
+    #[test]
+    fn keeps_originals_next_to_the_database() {
+        assert_eq!(
+            original_path(Path::new("/home/alice/db.kdbx"), &kdbx3()),
+            Path::new("/home/alice/db.kdbx3.kdbx")
+        );
+        let kdb = DatabaseConfig {
+            version: DatabaseVersion::KDB(0),
+            ..kdbx3()
+        };
+        assert_eq!(
+            original_path(Path::new("old.kdb"), &kdb),
+            Path::new("old.kdb.kdbx")
+        );
+    }
+}
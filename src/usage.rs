@@ -0,0 +1,172 @@
+//! How often and how recently entries are used ("frecency"),
+//! so the picker can list the entries used most first.
+//!
+//! Each entry's use adds 1 to its score, and scores halve every
+//! `HALF_LIFE_DAYS`, so entries used daily stay on top while
+//! ones used a lot once drift down again.
+//!
+//! The scores are kept in the config dir, encrypted with the local key
+//! (see `cache::key`), one file per database, by entry UUID.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use cocoon::Cocoon;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{cache, config};
+
+const HALF_LIFE_DAYS: f64 = 7.;
+
+/// Scores below this are forgotten.
+const MIN_SCORE: f64 = 0.01;
+
+/// Serializes access to the scores, e.g. between the agent's connections.
+static USAGE: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize)]
+struct Usage {
+    score: f64,
+    /// When the score was last updated.
+    time: NaiveDateTime,
+}
+impl Usage {
+    /// The score decayed until `now`.
+    fn score_at(&self, now: NaiveDateTime) -> f64 {
+        let days = (now - self.time).num_seconds().max(0) as f64 / 86400.;
+        self.score * 0.5_f64.powf(days / HALF_LIFE_DAYS)
+    }
+}
+
+/// The scores live in the config dir, named after a hash of the database path.
+fn usage_path(db_path: &Path) -> Result<PathBuf> {
+    let db_path = db_path.canonicalize()?;
+    let hash = Sha256::digest(db_path.as_os_str().as_encoded_bytes());
+    let name: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(config::config_dir()
+        .ok_or_else(|| anyhow!("Couldn't find the config dir"))?
+        .join("usage")
+        .join(name))
+}
+
+fn read(path: &Path, key: &[u8]) -> Result<HashMap<String, Usage>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let data = Cocoon::new(key)
+        .parse(&mut File::open(path)?)
+        .map_err(|err| anyhow!("Couldn't read usage: {:?}", err))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn write(path: &Path, key: &[u8], usage: &HashMap<String, Usage>) -> Result<()> {
+    fs::create_dir_all(path.parent().expect("Usage is in the usage dir"))?;
+    let data = serde_json::to_vec(usage)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    Cocoon::new(key)
+        .dump(data, &mut file)
+        .map_err(|err| anyhow!("Couldn't write usage: {:?}", err))?;
+    Ok(())
+}
+
+/// Add a use of the entry at `now`, forgetting scores that decayed away.
+fn bump(usage: &mut HashMap<String, Usage>, uuid: &Uuid, now: NaiveDateTime) {
+    let score = usage.get(&uuid.to_string()).map_or(0., |u| u.score_at(now));
+    usage.insert(
+        uuid.to_string(),
+        Usage {
+            score: score + 1.,
+            time: now,
+        },
+    );
+    usage.retain(|_, u| u.score_at(now) >= MIN_SCORE);
+}
+
+/// Record that the entry was used.
+pub fn record(db_path: &Path, uuid: &Uuid) -> Result<()> {
+    let _guard = USAGE.lock().unwrap();
+    let path = usage_path(db_path)?;
+    let key = cache::key()?;
+    let mut usage = read(&path, &key)?;
+    bump(&mut usage, uuid, Local::now().naive_local());
+    write(&path, &key, &usage)
+}
+
+/// The current score of each entry used recently enough, by UUID.
+pub fn scores(db_path: &Path) -> Result<HashMap<Uuid, f64>> {
+    let _guard = USAGE.lock().unwrap();
+    let now = Local::now().naive_local();
+    Ok(read(&usage_path(db_path)?, &cache::key()?)?
+        .into_iter()
+        .filter_map(|(uuid, u)| Some((uuid.parse().ok()?, u.score_at(now))))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    fn days(n: i64) -> TimeDelta {
+        TimeDelta::try_days(n).unwrap()
+    }
+
+    #[test]
+    fn scores_halve_every_half_life() {
+        let now = Local::now().naive_local();
+        let usage = Usage {
+            score: 4.,
+            time: now - days(HALF_LIFE_DAYS as i64),
+        };
+        assert!((usage.score_at(now) - 2.).abs() < 1e-9);
+        assert!((usage.score_at(now + days(7)) - 1.).abs() < 1e-9);
+        // Not from the future.
+        assert_eq!(usage.score_at(now - days(30)), 4.);
+    }
+
+    #[test]
+    fn bumps_and_forgets_scores() {
+        let (github, old) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Local::now().naive_local();
+        let mut usage = HashMap::new();
+        bump(&mut usage, &old, now - days(365));
+        bump(&mut usage, &github, now - days(7));
+        bump(&mut usage, &github, now);
+        assert!((usage[&github.to_string()].score - 1.5).abs() < 1e-9);
+        assert!(!usage.contains_key(&old.to_string()));
+    }
+
+    #[test]
+    fn keeps_scores_encrypted() {
+        let dir = std::env::temp_dir().join(format!("kpass-test-usage-{}", std::process::id()));
+        let path = dir.join("scores");
+        let uuid = Uuid::new_v4();
+        let mut usage = HashMap::new();
+        assert!(read(&path, &[1; 32]).unwrap().is_empty());
+        bump(&mut usage, &uuid, Local::now().naive_local());
+        write(&path, &[1; 32], &usage).unwrap();
+        let contents = fs::read(&path).unwrap();
+        let id = uuid.to_string();
+        assert!(!contents
+            .windows(id.len())
+            .any(|window| window == id.as_bytes()));
+        assert_eq!(read(&path, &[1; 32]).unwrap()[&id].score, 1.);
+        assert!(read(&path, &[2; 32]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
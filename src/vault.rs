@@ -0,0 +1,393 @@
+//! A database unlocked for a session, as in `kpass`'s interactive
+//! mode: changes are saved together, can be undone, and the database
+//! is reloaded when something else changes it.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime};
+use inquire::Confirm;
+use keepass::{db::Times, Database, DatabaseKey};
+use uuid::Uuid;
+
+use crate::{
+    changed_on_disk, changelog, config, get_entry_mut, index, lock, record_opened, save_db,
+    unlock_with, watch,
+};
+
+/// A database unlocked for a session.
+pub struct Vault {
+    pub path: PathBuf,
+    pub db: Database,
+    pub key: DatabaseKey,
+    /// `None` if opened read-only.
+    pub lock: Option<lock::Lock>,
+    /// The database as last saved (or opened).
+    saved: Database,
+    /// The database before each save in this session, and when
+    /// that save was made, most recent last.
+    pub undo: Vec<(DateTime<Local>, Database)>,
+    /// Whether there are changes to save.
+    dirty: bool,
+    /// Notices changes made by something else, see `reload_if_changed`.
+    watcher: Option<watch::Watcher>,
+    /// Rebuilt whenever the database changes.
+    pub index: index::Index,
+    /// When entries were last used, and how often, since the last save.
+    /// Only saved along with changes, as KeePassXC does, rather
+    /// than writing the database each time an entry is viewed.
+    accessed: RefCell<HashMap<Uuid, (NaiveDateTime, usize)>>,
+}
+impl Vault {
+    /// Lock and unlock the database. Returns `None` if it's
+    /// locked by another instance and read-only access is declined.
+    pub fn open(path: &Path, use_cache: bool) -> Result<Option<Self>> {
+        let lock = match lock::acquire(path)? {
+            Ok(lock) => Some(lock),
+            Err(holder) => {
+                warn!(
+                    "{}",
+                    tr!("open-elsewhere", database = path.display(), holder = holder)
+                );
+                warn!("{}", tr!("open-elsewhere-overwrites"));
+                let read_only = Confirm::new(&tr!("open-read-only"))
+                    .with_default(true)
+                    .prompt()?;
+                if !read_only {
+                    return Ok(None);
+                }
+                None
+            }
+        };
+        let (db, key) = unlock_with(path, use_cache)?;
+        let watcher = if config::get().reload_on_change {
+            watch::Watcher::new(path)
+                .map_err(|err| {
+                    warn!(
+                        "{}",
+                        tr!("watch-failed", database = path.display(), error = err)
+                    )
+                })
+                .ok()
+        } else {
+            None
+        };
+        Ok(Some(Vault {
+            path: path.to_path_buf(),
+            index: index::Index::build(&db),
+            saved: db.clone(),
+            db,
+            key,
+            lock,
+            undo: vec![],
+            dirty: false,
+            watcher,
+            accessed: RefCell::default(),
+        }))
+    }
+
+    /// The name given to the database in the config,
+    /// or else its file name.
+    pub fn name(&self) -> String {
+        match config::database_name(&self.path) {
+            Some(name) => name.to_string(),
+            None => self
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// Note that the database changed. Changes are saved together
+    /// (see `flush`), rather than encrypting and writing the whole
+    /// database for each of several changes in a row.
+    pub fn changed(&mut self) {
+        self.dirty = true;
+        self.index = index::Index::build(&self.db);
+    }
+
+    /// Note that an entry was used (viewed, copied, typed, ...),
+    /// to update its access time and usage count when next saving.
+    pub fn accessed(&self, uuid: &Uuid) {
+        let mut accessed = self.accessed.borrow_mut();
+        let (time, count) = accessed.entry(*uuid).or_insert((Times::now(), 0));
+        *time = Times::now();
+        *count += 1;
+    }
+
+    /// Save any changes.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            self.save()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        for (uuid, (time, count)) in self.accessed.take() {
+            if let Some(entry) = get_entry_mut(&mut self.db, uuid.as_u128()) {
+                entry.times.set_last_access(time);
+                entry.times.usage_count += count;
+            }
+        }
+        let pending = changelog::pending(&self.path, &self.db);
+        if !pending.is_empty() {
+            info!("{}", tr!("pending-changes"));
+            for line in pending {
+                println!("  {}", line);
+            }
+        }
+        info!("{}", tr!("saving"));
+        save_db(&mut self.db, &self.key, &self.path)?;
+        info!("{}", tr!("saved"));
+        let before = std::mem::replace(&mut self.saved, self.db.clone());
+        self.undo.push((Local::now(), before));
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        Ok(())
+    }
+
+    /// Reload the database if something else changed it on disk.
+    /// Called with nothing to save, so no changes are lost; changes made
+    /// in the middle of an action are merged on saving instead
+    /// (see `resolve_external_changes`).
+    pub fn reload_if_changed(&mut self) -> Result<()> {
+        let Some(watcher) = &self.watcher else {
+            return Ok(());
+        };
+        // Our own saves are noticed too, but don't change the hash.
+        if !watcher.changed() || !changed_on_disk(&self.path)? {
+            return Ok(());
+        }
+        match Database::open(&mut File::open(&self.path)?, self.key.clone()) {
+            Ok(db) => {
+                record_opened(&self.path)?;
+                changelog::snapshot(&self.path, &db);
+                self.saved = db.clone();
+                self.index = index::Index::build(&db);
+                self.db = db;
+                // Undoing would revert the other changes too.
+                self.undo.clear();
+                info!("{}", tr!("reloaded", database = self.name()));
+            }
+            // E.g. it's only partly written yet, or the master key changed.
+            Err(err) => warn!(
+                "{}",
+                tr!(
+                    "reload-changed-failed",
+                    database = self.name(),
+                    error = format!("{:?}", err)
+                )
+            ),
+        }
+        Ok(())
+    }
+
+    /// Revert the last save in this session.
+    pub fn undo(&mut self) -> Result<()> {
+        let Some((_, before)) = self.undo.pop() else {
+            return Ok(());
+        };
+        self.db = before;
+        self.index = index::Index::build(&self.db);
+        info!("{}", tr!("saving"));
+        save_db(&mut self.db, &self.key, &self.path)?;
+        info!("{}", tr!("saved"));
+        self.saved = self.db.clone();
+        Ok(())
+    }
+}
+
+impl Drop for Vault {
+    /// Save changes still pending when leaving the session early, e.g. on an error.
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            warn!(
+                "{}",
+                tr!("save-failed", database = self.path.display(), error = err)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+impl Vault {
+    /// A read-only session on a database that isn't saved anywhere.
+    pub(crate) fn in_memory(db: Database) -> Self {
+        Vault {
+            path: PathBuf::from("/kpass-test/vault.kdbx"),
+            index: index::Index::build(&db),
+            saved: db.clone(),
+            db,
+            key: DatabaseKey::new().with_password("correct horse"),
+            lock: None,
+            undo: vec![],
+            dirty: false,
+            watcher: None,
+            accessed: RefCell::default(),
+        }
+    }
+}
+
+/// How many saves can be undone per database.
+const UNDO_LIMIT: usize = 20;
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use keepass::{config::KdfConfig, db::Entry as KEntry};
+
+    use super::*;
+    use crate::entries;
+    use crate::test_util;
+
+    fn entry(title: &str) -> KEntry {
+        test_util::entry(&[("Title", title)])
+    }
+
+    /// A saved database with one entry, unlocked and locked for a session.
+    fn vault(name: &str) -> Vault {
+        let dir = env::temp_dir().join(format!("kpass-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.kdbx", name));
+
+        let mut db = Database::new(Default::default());
+        // Cheap to derive, as each save derives the key twice.
+        db.config.kdf_config = KdfConfig::Aes { rounds: 1 };
+        db.root.add_child(entry("GitHub"));
+        let key = DatabaseKey::new().with_password("correct horse");
+        db.save(&mut File::create(&path).unwrap(), key.clone())
+            .unwrap();
+        record_opened(&path).unwrap();
+        let lock = lock::acquire(&path).unwrap().ok();
+        Vault {
+            index: index::Index::build(&db),
+            saved: db.clone(),
+            db,
+            key,
+            lock,
+            path,
+            undo: vec![],
+            dirty: false,
+            watcher: None,
+            accessed: RefCell::default(),
+        }
+    }
+
+    fn on_disk(vault: &Vault) -> Database {
+        Database::open(&mut File::open(&vault.path).unwrap(), vault.key.clone()).unwrap()
+    }
+
+    fn titles(db: &Database) -> Vec<&str> {
+        entries(db).filter_map(|entry| entry.get_title()).collect()
+    }
+
+    fn cleanup(vault: Vault) {
+        let dir = vault.path.parent().unwrap().to_path_buf();
+        drop(vault);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn named_after_the_file() {
+        let vault = vault("named");
+        assert_eq!(vault.name(), "named");
+        cleanup(vault);
+    }
+
+    #[test]
+    fn saves_changes_together() {
+        let mut vault = vault("together");
+        vault.db.root.add_child(entry("VPN"));
+        vault.changed();
+        vault.db.root.add_child(entry("Bank"));
+        vault.changed();
+        assert_eq!(titles(&on_disk(&vault)), ["GitHub"]);
+
+        vault.flush().unwrap();
+        assert_eq!(titles(&on_disk(&vault)), ["GitHub", "VPN", "Bank"]);
+        assert_eq!(vault.undo.len(), 1);
+        // Nothing left to save.
+        vault.flush().unwrap();
+        assert_eq!(vault.undo.len(), 1);
+        cleanup(vault);
+    }
+
+    #[test]
+    fn undoes_saves() {
+        let mut vault = vault("undo");
+        vault.db.root.add_child(entry("VPN"));
+        vault.changed();
+        vault.flush().unwrap();
+        vault.db.root.add_child(entry("Bank"));
+        vault.changed();
+        vault.flush().unwrap();
+
+        vault.undo().unwrap();
+        assert_eq!(titles(&vault.db), ["GitHub", "VPN"]);
+        assert_eq!(titles(&on_disk(&vault)), ["GitHub", "VPN"]);
+        vault.undo().unwrap();
+        assert_eq!(titles(&on_disk(&vault)), ["GitHub"]);
+        assert!(vault.index.search("vpn").is_empty());
+        // Nothing left to undo.
+        vault.undo().unwrap();
+        assert_eq!(titles(&vault.db), ["GitHub"]);
+        cleanup(vault);
+    }
+
+    #[test]
+    fn refuses_to_save_read_only() {
+        let mut vault = vault("read-only");
+        vault.lock = None;
+        vault.db.root.add_child(entry("VPN"));
+        vault.changed();
+        let err = vault.flush().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<crate::Error>(),
+            Some(crate::Error::ReadOnly)
+        ));
+        assert_eq!(titles(&on_disk(&vault)), ["GitHub"]);
+        vault.dirty = false;
+        cleanup(vault);
+    }
+
+    #[test]
+    fn saves_access_times_with_changes() {
+        let mut vault = vault("accessed");
+        let uuid = entries(&vault.db).next().unwrap().uuid;
+        vault.accessed(&uuid);
+        vault.accessed(&uuid);
+        // Viewing alone doesn't save.
+        vault.flush().unwrap();
+        assert_eq!(
+            entries(&on_disk(&vault)).next().unwrap().times.usage_count,
+            0
+        );
+
+        vault.changed();
+        vault.flush().unwrap();
+        let saved = on_disk(&vault);
+        let saved = entries(&saved).next().unwrap();
+        assert_eq!(saved.times.usage_count, 2);
+        assert!(saved.times.get_last_access().is_some());
+        // Counted once.
+        vault.changed();
+        vault.flush().unwrap();
+        assert_eq!(
+            entries(&on_disk(&vault)).next().unwrap().times.usage_count,
+            2
+        );
+        cleanup(vault);
+    }
+}
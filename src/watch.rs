@@ -0,0 +1,114 @@
+//! Noticing when a database file is changed by something else
+//! (e.g. Syncthing, or kpass on another device), so the interactive
+//! session can reload it instead of working on stale data.
+//!
+//! The database's directory is watched with inotify, as saving
+//! (ours or a sync tool's) usually replaces the file rather than
+//! writing to it.
+
+use std::{
+    ffi::{CString, OsString},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+
+/// Size of `struct inotify_event` without its name.
+const EVENT_HEADER: usize = 16;
+
+pub struct Watcher {
+    fd: OwnedFd,
+    name: OsString,
+}
+impl Watcher {
+    pub fn new(path: &Path) -> Result<Self> {
+        let path = path.canonicalize()?;
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(anyhow!("Invalid database path"));
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+        // SAFETY: the descriptor is owned by the watcher from here on.
+        let fd = unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        // SAFETY: the path is a valid C string.
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Watcher {
+            fd,
+            name: name.to_os_string(),
+        })
+    }
+
+    /// Whether the file was written or replaced since the last call.
+    /// This includes our own saves.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            // SAFETY: the buffer's length is passed along.
+            let len =
+                unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            // Nothing more to read (or the watch is gone).
+            if len <= 0 {
+                return changed;
+            }
+            let mut events = &buf[..len as usize];
+            while events.len() >= EVENT_HEADER {
+                let name_len =
+                    u32::from_ne_bytes(events[12..16].try_into().expect("4 bytes")) as usize;
+                let Some(name) = events.get(EVENT_HEADER..EVENT_HEADER + name_len) else {
+                    break;
+                };
+                // Names are padded with NULs.
+                let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                changed |= name[..end] == *self.name.as_bytes();
+                events = &events[EVENT_HEADER + name_len..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn notices_writes_and_replacements() {
+        let dir = env::temp_dir().join(format!("kpass-test-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("db.kdbx");
+        fs::write(&db_path, "v1").unwrap();
+        let watcher = Watcher::new(&db_path).unwrap();
+        assert!(!watcher.changed());
+
+        fs::write(&db_path, "v2").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // Other files in the directory aren't.
+        fs::write(dir.join("other.kdbx"), "v1").unwrap();
+        assert!(!watcher.changed());
+
+        // Saving replaces the file.
+        fs::write(dir.join(".db.kdbx.tmp"), "v3").unwrap();
+        fs::rename(dir.join(".db.kdbx.tmp"), &db_path).unwrap();
+        assert!(watcher.changed());
+
+        assert!(Watcher::new(&dir.join("missing.kdbx")).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}